@@ -8,6 +8,8 @@ export function useDashboard() {
     const logined = ref(false)
     const userData = ref(null)
     const files = ref([])
+    const totalFiles = ref(0)
+    const nextCursor = ref(null)
     const loading = ref(true)
     const error = ref(null)
     const previewFile = ref(null)
@@ -30,13 +32,23 @@ export function useDashboard() {
         return { 'Authorization': `Bearer ${authToken.value}` }
     }
 
-    async function Login(email = "", pw = "") {
+    async function Login(email = "", pw = "", turnstile = null) {
         try {
             if (!email || !pw) {
                 return
             }
 
-            const response = await axios.post(`${API_BASE}/session`, { email, password: pw })
+            let response = await axios.post(`${API_BASE}/session`, { email, password: pw, turnstile })
+
+            if (response.status === 200 && response.data.data?.two_factor_required) {
+                const code = window.prompt(t('two_factor_prompt'))
+                if (!code) return
+                const factor = code.includes('-') ? { recovery_code: code } : { code }
+                response = await axios.post(`${API_BASE}/session/2fa`, {
+                    challenge_token: response.data.data.challenge_token,
+                    ...factor
+                })
+            }
 
             if (response.status === 200 && response.data.data) {
                 userData.value = response.data.data
@@ -46,8 +58,11 @@ export function useDashboard() {
             }
         } catch (err) {
             console.error('Login error:', err)
-            alert(t('login_error', { error: err.response?.data?.error?.detail || err.message }))
             logined.value = false
+            if (err.response?.data?.error?.code === 'CAPTCHA_REQUIRED') {
+                return 'captcha'
+            }
+            alert(t('login_error', { error: err.response?.data?.error?.detail || err.message }))
         }
     }
 
@@ -73,6 +88,8 @@ export function useDashboard() {
             loading.value = true
             const response = await axios.get(`${API_BASE}/content`, { headers: getHeaders() })
             files.value = response.data.data.files || []
+            totalFiles.value = response.data.data.total || 0
+            nextCursor.value = response.data.data.next_cursor || null
             error.value = null
         } catch (err) {
             error.value = err.response?.data?.error?.detail || t('fetch_files_error')
@@ -81,6 +98,24 @@ export function useDashboard() {
         }
     }
 
+    async function loadMoreFiles() {
+        if (!nextCursor.value) return
+        try {
+            loadingAction.value = 'load-more'
+            const response = await axios.get(`${API_BASE}/content`, {
+                headers: getHeaders(),
+                params: { cursor: nextCursor.value }
+            })
+            files.value = [...files.value, ...(response.data.data.files || [])]
+            totalFiles.value = response.data.data.total || 0
+            nextCursor.value = response.data.data.next_cursor || null
+        } catch (err) {
+            error.value = err.response?.data?.error?.detail || t('fetch_files_error')
+        } finally {
+            loadingAction.value = null
+        }
+    }
+
     async function handleSetting() {
         showSetting.value = !showSetting.value
     }
@@ -173,7 +208,6 @@ export function useDashboard() {
 
             if (response.status === 200 && response.data.data) {
                 userData.value = response.data.data
-                authToken.value = response.data.data.session_key
                 logined.value = true
                 await fetchFiles()
             }
@@ -200,6 +234,7 @@ export function useDashboard() {
             uploadProgress.value = 0
 
             const createResponse = await axios.post(`${API_BASE}/content`, { filename: file.name }, { headers: getHeaders() })
+            const fileId = createResponse.data.data.id
             const uploadUrl = createResponse.data.data.url
 
             await axios.put(uploadUrl, file, {
@@ -209,6 +244,8 @@ export function useDashboard() {
                 }
             })
 
+            await axios.post(`${API_BASE}/content/${fileId}/complete`, {}, { headers: getHeaders() })
+
             await fetchFiles()
             showUpload.value = false
         } catch (err) {
@@ -225,11 +262,38 @@ export function useDashboard() {
         headerRef.value?.openFileInput()
     }
 
+    async function handleEmailLinks() {
+        const params = new URLSearchParams(window.location.search)
+        const verifyToken = params.get('verify_token')
+        const resetToken = params.get('reset_token')
+        if (!verifyToken && !resetToken) return
+
+        window.history.replaceState(null, '', window.location.pathname)
+        try {
+            if (verifyToken) {
+                await axios.post(`${API_BASE}/user/email/verify/confirm`, { token: verifyToken })
+                alert(t('email_verified'))
+            } else {
+                const password = window.prompt(t('reset_password_prompt'))
+                if (!password) return
+                await axios.post(`${API_BASE}/user/password/reset/confirm`, { token: resetToken, new_password: password })
+                alert(t('password_reset_done'))
+            }
+        } catch (err) {
+            console.error('Email link error:', err)
+            alert(t('email_link_error', { error: err.response?.data?.error?.detail || err.message }))
+        }
+    }
+
+    onMounted(handleEmailLinks)
+
     return {
         authToken,
         logined,
         userData,
         files,
+        totalFiles,
+        nextCursor,
         loading,
         error,
         previewFile,
@@ -245,6 +309,7 @@ export function useDashboard() {
         Login,
         Signup,
         fetchFiles,
+        loadMoreFiles,
         handleSetting,
         handlePreview,
         handleShareClick,
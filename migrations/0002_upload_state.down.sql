@@ -0,0 +1,7 @@
+ALTER TABLE files
+    DROP INDEX idx_upload_state,
+    DROP COLUMN upload_state,
+    DROP COLUMN sha256,
+    DROP COLUMN etag,
+    DROP COLUMN content_type,
+    DROP COLUMN size_bytes;
@@ -0,0 +1,10 @@
+ALTER TABLE files
+    ADD COLUMN size_bytes BIGINT AFTER accessible_user_ids,
+    ADD COLUMN content_type VARCHAR(255) AFTER size_bytes,
+    ADD COLUMN etag VARCHAR(255) AFTER content_type,
+    ADD COLUMN sha256 CHAR(64) AFTER etag,
+    ADD COLUMN upload_state VARCHAR(16) NOT NULL DEFAULT 'complete' AFTER sha256;
+
+ALTER TABLE files
+    ALTER COLUMN upload_state SET DEFAULT 'pending',
+    ADD INDEX idx_upload_state (upload_state, created_at);
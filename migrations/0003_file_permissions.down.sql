@@ -0,0 +1,9 @@
+UPDATE files f SET f.accessible_user_ids = (
+    SELECT COALESCE(JSON_ARRAYAGG(p.user_id), JSON_ARRAY())
+    FROM file_permissions p
+    WHERE p.file_id = f.id
+);
+
+ALTER TABLE files MODIFY accessible_user_ids JSON NOT NULL;
+
+DROP TABLE file_permissions;
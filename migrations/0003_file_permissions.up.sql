@@ -0,0 +1,22 @@
+CREATE TABLE file_permissions (
+    file_id VARCHAR(255) NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    role VARCHAR(16) NOT NULL DEFAULT 'viewer',
+    granted_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    granted_by VARCHAR(255),
+    PRIMARY KEY (file_id, user_id),
+    FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (granted_by) REFERENCES users(id) ON DELETE SET NULL,
+    INDEX idx_user_id (user_id, file_id)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+
+INSERT IGNORE INTO file_permissions (file_id, user_id, role, granted_at, granted_by)
+SELECT f.id, shared.user_id, 'viewer', f.created_at, f.owner_id
+FROM files f
+CROSS JOIN JSON_TABLE(f.accessible_user_ids, '$[*]' COLUMNS (user_id VARCHAR(255) PATH '$')) AS shared
+JOIN users u ON u.id = shared.user_id;
+
+ALTER TABLE files MODIFY accessible_user_ids JSON NULL;
+
+UPDATE files SET accessible_user_ids = NULL;
@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN accessible_user_ids JSON AFTER owner_id;
@@ -0,0 +1 @@
+ALTER TABLE files DROP COLUMN accessible_user_ids;
@@ -0,0 +1,4 @@
+ALTER TABLE files
+    DROP INDEX idx_owner_size,
+    DROP INDEX idx_owner_filename,
+    DROP INDEX idx_owner_created;
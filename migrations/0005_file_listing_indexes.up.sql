@@ -0,0 +1,4 @@
+ALTER TABLE files
+    ADD INDEX idx_owner_created (owner_id, created_at, id),
+    ADD INDEX idx_owner_filename (owner_id, filename, id),
+    ADD INDEX idx_owner_size (owner_id, size_bytes, id);
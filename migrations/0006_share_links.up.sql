@@ -0,0 +1,16 @@
+CREATE TABLE share_links (
+    id VARCHAR(255) PRIMARY KEY,
+    file_id VARCHAR(255) NOT NULL,
+    token_hash CHAR(64) NOT NULL,
+    password_hash VARCHAR(255),
+    expires_at DATETIME,
+    max_downloads BIGINT,
+    download_count BIGINT NOT NULL DEFAULT 0,
+    created_by VARCHAR(255),
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    revoked_at DATETIME,
+    UNIQUE INDEX idx_token_hash (token_hash),
+    FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE SET NULL,
+    INDEX idx_file_id (file_id, created_at)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
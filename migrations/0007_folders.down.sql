@@ -0,0 +1,7 @@
+ALTER TABLE files
+    DROP FOREIGN KEY fk_files_folder,
+    DROP INDEX idx_folder_id,
+    DROP COLUMN folder_id;
+
+DROP TABLE folder_permissions;
+DROP TABLE folders;
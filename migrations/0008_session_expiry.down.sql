@@ -0,0 +1,2 @@
+ALTER TABLE sessions
+    DROP INDEX idx_last_accessed_at;
@@ -0,0 +1,2 @@
+ALTER TABLE sessions
+    ADD INDEX idx_last_accessed_at (last_accessed_at);
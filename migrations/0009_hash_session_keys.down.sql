@@ -0,0 +1,7 @@
+DELETE FROM sessions;
+
+ALTER TABLE sessions
+    DROP INDEX uq_key_hash,
+    CHANGE COLUMN key_hash session_key VARCHAR(255) NOT NULL,
+    ADD UNIQUE INDEX session_key (session_key),
+    ADD INDEX idx_session_key (session_key);
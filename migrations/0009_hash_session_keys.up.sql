@@ -0,0 +1,9 @@
+-- Existing rows hold plaintext bearer keys; they cannot be rehashed meaningfully
+-- without trusting the leaked values, so every live session is logged out.
+DELETE FROM sessions;
+
+ALTER TABLE sessions
+    DROP INDEX idx_session_key,
+    DROP INDEX session_key,
+    CHANGE COLUMN session_key key_hash CHAR(64) NOT NULL,
+    ADD UNIQUE INDEX uq_key_hash (key_hash);
@@ -0,0 +1,16 @@
+ALTER TABLE users
+    ADD COLUMN email_verified_at DATETIME AFTER email;
+
+-- Accounts created before verification existed keep the sharing rights they already had.
+UPDATE users SET email_verified_at = created_at;
+
+CREATE TABLE email_tokens (
+    token_hash CHAR(64) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL,
+    purpose VARCHAR(32) NOT NULL,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    INDEX idx_user_purpose (user_id, purpose),
+    INDEX idx_expires_at (expires_at)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
@@ -0,0 +1,9 @@
+CREATE TABLE login_failures (
+    scope VARCHAR(16) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    failures INT NOT NULL DEFAULT 0,
+    last_failed_at DATETIME NOT NULL,
+    blocked_until DATETIME,
+    PRIMARY KEY (scope, subject),
+    INDEX idx_last_failed_at (last_failed_at)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
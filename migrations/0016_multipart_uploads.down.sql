@@ -0,0 +1,4 @@
+ALTER TABLE files
+    DROP INDEX idx_multipart_started_at,
+    DROP COLUMN multipart_started_at,
+    DROP COLUMN multipart_upload_id;
@@ -0,0 +1,4 @@
+ALTER TABLE files
+    ADD COLUMN multipart_upload_id VARCHAR(512),
+    ADD COLUMN multipart_started_at DATETIME,
+    ADD INDEX idx_multipart_started_at (multipart_started_at);
@@ -0,0 +1,17 @@
+CREATE TABLE tus_uploads (
+    id VARCHAR(255) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL,
+    folder_id VARCHAR(255),
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255),
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    part_size BIGINT NOT NULL,
+    multipart_upload_id VARCHAR(512) NOT NULL,
+    lock_token CHAR(36),
+    locked_until DATETIME,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (folder_id) REFERENCES folders(id) ON DELETE SET NULL,
+    INDEX idx_created_at (created_at)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
@@ -0,0 +1,4 @@
+ALTER TABLE files
+    DROP FOREIGN KEY fk_files_blob,
+    DROP COLUMN blob_sha256;
+DROP TABLE blobs;
@@ -0,0 +1,12 @@
+CREATE TABLE blobs (
+    sha256 CHAR(64) PRIMARY KEY,
+    size_bytes BIGINT NOT NULL,
+    etag VARCHAR(255),
+    state VARCHAR(16) NOT NULL,
+    ref_count INT NOT NULL DEFAULT 0,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX idx_ref_count_created_at (ref_count, created_at)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+ALTER TABLE files
+    ADD COLUMN blob_sha256 CHAR(64),
+    ADD CONSTRAINT fk_files_blob FOREIGN KEY (blob_sha256) REFERENCES blobs(sha256);
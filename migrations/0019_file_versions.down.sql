@@ -0,0 +1,2 @@
+UPDATE blobs b SET ref_count = (SELECT COUNT(*) FROM files f WHERE f.blob_sha256 = b.sha256);
+DROP TABLE file_versions;
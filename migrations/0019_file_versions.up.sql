@@ -0,0 +1,18 @@
+CREATE TABLE file_versions (
+    file_id VARCHAR(255) NOT NULL,
+    version_number INT NOT NULL,
+    blob_sha256 CHAR(64) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    content_type VARCHAR(255),
+    etag VARCHAR(255),
+    uploaded_by VARCHAR(255),
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (file_id, version_number),
+    FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE,
+    FOREIGN KEY (blob_sha256) REFERENCES blobs(sha256),
+    FOREIGN KEY (uploaded_by) REFERENCES users(id) ON DELETE SET NULL
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+-- Blob references now belong to versions; every deduplicated file becomes its own first version.
+INSERT INTO file_versions (file_id, version_number, blob_sha256, size_bytes, content_type, etag, uploaded_by, created_at)
+SELECT f.id, 1, f.blob_sha256, b.size_bytes, f.content_type, b.etag, f.owner_id, f.created_at
+FROM files f JOIN blobs b ON b.sha256 = f.blob_sha256;
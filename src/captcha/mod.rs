@@ -0,0 +1,5 @@
+pub mod turnstile;
+
+pub use turnstile::Captcha;
+#[cfg(test)]
+pub use turnstile::StaticCaptcha;
\ No newline at end of file
@@ -0,0 +1,44 @@
+use std::error::Error;
+use async_trait::async_trait;
+use cf_turnstile::{SiteVerifyRequest, SiteVerifyResponse, TurnstileClient};
+
+#[async_trait]
+pub trait Captcha: Send + Sync {
+    async fn siteverify(&self, request: SiteVerifyRequest) -> Result<SiteVerifyResponse, Box<dyn Error>>;
+}
+
+#[async_trait]
+impl Captcha for TurnstileClient {
+    async fn siteverify(&self, request: SiteVerifyRequest) -> Result<SiteVerifyResponse, Box<dyn Error>> {
+        match TurnstileClient::siteverify(self, request).await {
+            Ok(response) => Ok(response),
+            Err(e) => Err(e.to_string().into()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub struct StaticCaptcha {
+    success: bool,
+}
+
+#[cfg(test)]
+impl StaticCaptcha {
+    pub fn new(success: bool) -> Self {
+        Self { success }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Captcha for StaticCaptcha {
+    async fn siteverify(&self, _request: SiteVerifyRequest) -> Result<SiteVerifyResponse, Box<dyn Error>> {
+        Ok(SiteVerifyResponse {
+            success: self.success,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            hostname: "localhost".to_string(),
+            action: String::new(),
+            cdata: String::new(),
+        })
+    }
+}
@@ -0,0 +1,98 @@
+use std::error::Error;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use super::model::File;
+use super::repository::Repository;
+use super::storage::{blob_key, ObjectMeta, Storage};
+
+/// Hashes an object by reading it back, for backends that did not report a SHA-256 on upload.
+async fn hash_object(storage: &dyn Storage, key: &str, size: i64) -> Result<Option<String>, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    if size > 0 {
+        let Some(mut stream) = storage.read_object(key, 0..size as u64).await? else {
+            return Ok(None);
+        };
+        while let Some(chunk) = stream.next().await {
+            hasher.update(&chunk?);
+        }
+    }
+    Ok(Some(hex::encode(hasher.finalize())))
+}
+
+/// Moves the object uploaded under the file id into the blob for its contents and records it as
+/// the file's newest version, reusing the blob when the same bytes were stored before.
+///
+/// Returns false when no blob could be used, e.g. while another upload of the same bytes is
+/// still writing it; the object then stays under the file id for the caller to record.
+pub async fn store_as_blob(
+    db: &dyn Repository,
+    storage: &dyn Storage,
+    file: &File,
+    meta: &ObjectMeta,
+    uploaded_by: Option<&str>,
+) -> Result<bool, Box<dyn Error>> {
+    let sha256 = match &meta.sha256 {
+        Some(sha256) => sha256.clone(),
+        None => match hash_object(storage, &file.id, meta.size).await? {
+            Some(sha256) => sha256,
+            None => return Ok(false),
+        },
+    };
+    let content_type = meta.content_type.as_deref();
+
+    if !db.attach_blob(&file.id, &sha256, content_type, uploaded_by).await? {
+        // First time these bytes are seen: the blob only goes live once its object is in place.
+        if db.create_blob(&sha256, meta.size).await? {
+            let key = blob_key(&sha256);
+            storage.copy_object(&file.id, &key).await?;
+            let etag = storage.head_object(&key).await?.and_then(|meta| meta.etag);
+            db.activate_blob(&sha256, etag.as_deref()).await?;
+        }
+        if !db.attach_blob(&file.id, &sha256, content_type, uploaded_by).await? {
+            return Ok(false);
+        }
+    }
+
+    // The file points at the blob now, so a failure here only leaves a stray object behind.
+    if let Err(e) = storage.delete_object(&file.id).await {
+        eprintln!("✗ Failed to delete uploaded object {}: {}", file.id, e);
+    }
+    Ok(true)
+}
+
+/// Deletes the blob and its object once nothing references it; a no-op otherwise.
+pub async fn release_blob(db: &dyn Repository, storage: &dyn Storage, sha256: &str) -> Result<(), Box<dyn Error>> {
+    if db.claim_blob_deletion(sha256, false).await? {
+        storage.delete_object(&blob_key(sha256)).await?;
+        db.delete_blob(sha256).await?;
+        println!("✓ Blob {} deleted", sha256);
+    }
+    Ok(())
+}
+
+/// Releases blobs whose references were just dropped. Failures only leave blobs behind for
+/// the reaper, so they are logged rather than returned.
+pub async fn release_blobs(db: &dyn Repository, storage: &dyn Storage, hashes: &[String]) {
+    let mut hashes: Vec<&String> = hashes.iter().collect();
+    hashes.sort();
+    hashes.dedup();
+    for sha256 in hashes {
+        if let Err(e) = release_blob(db, storage, sha256).await {
+            eprintln!("✗ Failed to release blob {}: {}", sha256, e);
+        }
+    }
+}
+
+/// Cleans up what a deleted file left under its own id: an upload in progress and content that
+/// never made it into a blob. Failures only leave objects behind for the reaper.
+pub async fn remove_file_objects(storage: &dyn Storage, file: &File) {
+    if let Some(upload_id) = &file.multipart_upload_id {
+        if let Err(e) = storage.abort_multipart_upload(&file.id, upload_id).await {
+            eprintln!("✗ Failed to abort multipart upload {} for {}: {}", upload_id, file.id, e);
+        }
+    }
+
+    if let Err(e) = storage.delete_object(&file.id).await {
+        eprintln!("✗ Failed to delete object {}: {}", file.id, e);
+    }
+}
@@ -0,0 +1,478 @@
+use std::env;
+use std::error::Error;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use async_trait::async_trait;
+use actix_web::web::Bytes;
+use futures_util::{Stream, StreamExt};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+use super::storage::{ObjectEntry, ObjectMeta, ObjectPage, ObjectStream, Storage, UploadedPart};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LocalObjectMeta {
+    content_type: Option<String>,
+    etag: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LocalMultipartUpload {
+    key: String,
+    content_type: Option<String>,
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+    public_url: String,
+    secret: Vec<u8>,
+    upload_expire: u64,
+    download_expire: u64,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf, public_url: String, secret: Vec<u8>, upload_expire: u64, download_expire: u64) -> Self {
+        Self {
+            root,
+            public_url: public_url.trim_end_matches('/').to_string(),
+            secret,
+            upload_expire,
+            download_expire,
+        }
+    }
+
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let root = env::var("LOCAL_STORAGE_ROOT")?;
+        let public_url = env::var("LOCAL_STORAGE_PUBLIC_URL")?;
+        let secret = env::var("LOCAL_STORAGE_SECRET")?;
+
+        if secret.is_empty() {
+            return Err("LOCAL_STORAGE_SECRET cannot be empty".into());
+        }
+
+        let upload_expire = env::var("LOCAL_STORAGE_UPLOAD_EXPIRE")?
+            .parse::<u64>()?;
+
+        let download_expire = env::var("LOCAL_STORAGE_DOWNLOAD_EXPIRE")?
+            .parse::<u64>()?;
+
+        Ok(Self::new(PathBuf::from(root), public_url, secret.into_bytes(), upload_expire, download_expire))
+    }
+
+    fn sign(&self, method: &str, key: &str, expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}\n{}\n{}", method, key, expires).as_bytes());
+        mac
+    }
+
+    fn signed_url(&self, method: &str, key: &str, expire: u64) -> Result<String, Box<dyn Error>> {
+        self.object_path(key)?;
+        let expires = chrono::Utc::now().timestamp() + expire as i64;
+        let signature = hex::encode(self.sign(method, key, expires).finalize().into_bytes());
+        Ok(format!("{}/storage/{}?expires={}&signature={}", self.public_url, key, expires, signature))
+    }
+
+    pub fn verify_signature(&self, method: &str, key: &str, expires: i64, signature: &str) -> bool {
+        if expires < chrono::Utc::now().timestamp() {
+            return false;
+        }
+
+        match hex::decode(signature) {
+            Ok(signature) => self.sign(method, key, expires).verify_slice(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn object_path(&self, key: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let relative = Path::new(key);
+        let valid = !key.is_empty()
+            && relative.components().all(|c| matches!(c, Component::Normal(_)))
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'));
+
+        if !valid {
+            return Err(format!("Invalid object key '{}'", key).into());
+        }
+
+        Ok(self.root.join("objects").join(relative))
+    }
+
+    fn meta_path(&self, key: &str) -> Result<PathBuf, Box<dyn Error>> {
+        self.object_path(key)?;
+        Ok(self.root.join("meta").join(format!("{}.json", key)))
+    }
+
+    async fn read_meta(&self, key: &str) -> Result<Option<LocalObjectMeta>, Box<dyn Error>> {
+        let meta_path = self.meta_path(key)?;
+        match tokio::fs::read(meta_path).await {
+            Ok(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn commit(&self, key: &str, temp_path: &Path, meta: LocalObjectMeta) -> Result<(), Box<dyn Error>> {
+        let object_path = self.object_path(key)?;
+        let meta_path = self.meta_path(key)?;
+
+        for path in [&object_path, &meta_path] {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+
+        tokio::fs::write(&meta_path, serde_json::to_vec(&meta)?).await?;
+        tokio::fs::rename(temp_path, &object_path).await?;
+        Ok(())
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        self.root.join("tmp").join(Uuid::new_v4().to_string())
+    }
+
+    /// Part URLs are signed over the upload and part as well, so one cannot be replayed as another.
+    pub fn part_method(upload_id: &str, part_number: i32) -> String {
+        format!("PUT:{}:{}", upload_id, part_number)
+    }
+
+    fn upload_dir(&self, upload_id: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let upload_id = Uuid::parse_str(upload_id).map_err(|_| format!("Invalid upload id '{}'", upload_id))?;
+        Ok(self.root.join("multipart").join(upload_id.to_string()))
+    }
+
+    async fn read_upload(&self, key: &str, upload_id: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let dir = self.upload_dir(upload_id)?;
+        let upload: LocalMultipartUpload = match tokio::fs::read(dir.join("upload.json")).await {
+            Ok(raw) => serde_json::from_slice(&raw)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(format!("No such upload {}", upload_id).into()),
+            Err(e) => return Err(e.into()),
+        };
+        if upload.key != key {
+            return Err(format!("Upload {} is not for object {}", upload_id, key).into());
+        }
+        Ok(dir)
+    }
+
+    /// Stores one part of a multipart upload and returns its ETag.
+    pub async fn write_part<S, E>(&self, key: &str, upload_id: &str, part_number: i32, mut stream: S) -> Result<String, Box<dyn Error>>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Error + 'static,
+    {
+        let dir = self.read_upload(key, upload_id).await?;
+        let temp_path = dir.join(format!("{}.tmp-{}", part_number, Uuid::new_v4()));
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut hasher = Sha256::new();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    drop(file);
+                    let _ = tokio::fs::remove_file(&temp_path).await;
+                    return Err(e.to_string().into());
+                }
+            };
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let etag = hex::encode(hasher.finalize());
+        tokio::fs::write(dir.join(format!("{}.etag", part_number)), &etag).await?;
+        tokio::fs::rename(&temp_path, dir.join(format!("{}.part", part_number))).await?;
+        Ok(etag)
+    }
+
+    pub async fn write_stream<S, E>(&self, key: &str, content_type: Option<&str>, mut stream: S) -> Result<ObjectMeta, Box<dyn Error>>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Error + 'static,
+    {
+        self.object_path(key)?;
+
+        let temp_path = self.temp_path();
+        tokio::fs::create_dir_all(self.root.join("tmp")).await?;
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0i64;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    drop(file);
+                    let _ = tokio::fs::remove_file(&temp_path).await;
+                    return Err(e.to_string().into());
+                }
+            };
+            hasher.update(&chunk);
+            size += chunk.len() as i64;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let meta = LocalObjectMeta {
+            content_type: content_type.map(|s| s.to_string()),
+            etag: hex::encode(hasher.finalize()),
+        };
+        self.commit(key, &temp_path, meta.clone()).await?;
+
+        Ok(ObjectMeta {
+            size,
+            content_type: meta.content_type,
+            etag: Some(meta.etag.clone()),
+            sha256: Some(meta.etag),
+            last_modified: Some(chrono::Utc::now()),
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    fn upload_expire(&self) -> u64 {
+        self.upload_expire
+    }
+
+    async fn upload_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        self.signed_url("PUT", key, self.upload_expire)
+    }
+
+    async fn download_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        self.signed_url("GET", key, self.download_expire)
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        let paths = [self.object_path(key)?, self.meta_path(key)?];
+        for path in paths {
+            match tokio::fs::remove_file(&path).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn head_object(&self, key: &str) -> Result<Option<ObjectMeta>, Box<dyn Error>> {
+        let object_path = self.object_path(key)?;
+        let metadata = match tokio::fs::metadata(object_path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let meta = self.read_meta(key).await?;
+
+        Ok(Some(ObjectMeta {
+            size: metadata.len() as i64,
+            content_type: meta.as_ref().and_then(|m| m.content_type.clone()),
+            etag: meta.as_ref().map(|m| m.etag.clone()),
+            sha256: meta.map(|m| m.etag),
+            last_modified: metadata.modified().ok().map(chrono::DateTime::<chrono::Utc>::from),
+        }))
+    }
+
+    async fn put_object(&self, key: &str, body: Vec<u8>, content_type: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.object_path(key)?;
+
+        let temp_path = self.temp_path();
+        tokio::fs::create_dir_all(self.root.join("tmp")).await?;
+        tokio::fs::write(&temp_path, &body).await?;
+
+        let meta = LocalObjectMeta {
+            content_type: content_type.map(|s| s.to_string()),
+            etag: hex::encode(Sha256::digest(&body)),
+        };
+        self.commit(key, &temp_path, meta).await
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let object_path = self.object_path(key)?;
+        match tokio::fs::read(object_path).await {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn read_object(&self, key: &str, range: Range<u64>) -> Result<Option<ObjectStream>, Box<dyn Error>> {
+        let path = self.object_path(key)?;
+        let mut file = match tokio::fs::File::open(path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        file.seek(SeekFrom::Start(range.start)).await?;
+
+        let reader = file.take(range.end.saturating_sub(range.start));
+        let stream = futures_util::stream::unfold(Some(reader), |reader| async move {
+            let mut reader = reader?;
+            let mut buf = vec![0; READ_CHUNK_SIZE];
+            match reader.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(Bytes::from(buf)), Some(reader)))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        });
+        Ok(Some(Box::pin(stream)))
+    }
+
+    async fn copy_object(&self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        let meta = self.read_meta(from).await?.ok_or_else(|| format!("No such object {}", from))?;
+        let source = self.object_path(from)?;
+        let temp_path = self.temp_path();
+        tokio::fs::create_dir_all(self.root.join("tmp")).await?;
+        tokio::fs::copy(source, &temp_path).await?;
+        self.commit(to, &temp_path, meta).await
+    }
+
+    async fn list_objects(&self, _continuation_token: Option<String>) -> Result<ObjectPage, Box<dyn Error>> {
+        let objects_root = self.root.join("objects");
+        let mut pending = vec![objects_root.clone()];
+        let mut objects = Vec::new();
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                let path = entry.path();
+
+                if metadata.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let Some(key) = path.strip_prefix(&objects_root).ok().and_then(|p| p.to_str()) else {
+                    continue;
+                };
+                objects.push(ObjectEntry {
+                    key: key.replace(std::path::MAIN_SEPARATOR, "/"),
+                    size: metadata.len() as i64,
+                    last_modified: metadata.modified().ok().map(chrono::DateTime::<chrono::Utc>::from),
+                });
+            }
+        }
+
+        Ok(ObjectPage {
+            objects,
+            next_token: None,
+        })
+    }
+
+    async fn create_multipart_upload(&self, key: &str, content_type: Option<&str>) -> Result<String, Box<dyn Error>> {
+        self.object_path(key)?;
+
+        let upload_id = Uuid::new_v4().to_string();
+        let dir = self.upload_dir(&upload_id)?;
+        tokio::fs::create_dir_all(&dir).await?;
+        let upload = LocalMultipartUpload {
+            key: key.to_string(),
+            content_type: content_type.map(|s| s.to_string()),
+        };
+        tokio::fs::write(dir.join("upload.json"), serde_json::to_vec(&upload)?).await?;
+        Ok(upload_id)
+    }
+
+    async fn upload_part_url(&self, key: &str, upload_id: &str, part_number: i32) -> Result<String, Box<dyn Error>> {
+        self.upload_dir(upload_id)?;
+        let url = self.signed_url(&Self::part_method(upload_id, part_number), key, self.upload_expire)?;
+        Ok(format!("{}&upload_id={}&part_number={}", url, upload_id, part_number))
+    }
+
+    async fn put_part(&self, key: &str, upload_id: &str, part_number: i32, body: Vec<u8>) -> Result<String, Box<dyn Error>> {
+        let stream = futures_util::stream::iter([Ok::<_, std::io::Error>(Bytes::from(body))]);
+        self.write_part(key, upload_id, part_number, stream).await
+    }
+
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>, Box<dyn Error>> {
+        let dir = self.read_upload(key, upload_id).await?;
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        let mut parts = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(part_number) = name.to_str().and_then(|n| n.strip_suffix(".part")).and_then(|n| n.parse::<i32>().ok()) else {
+                continue;
+            };
+            let etag = tokio::fs::read_to_string(dir.join(format!("{}.etag", part_number))).await?;
+            parts.push(UploadedPart {
+                part_number,
+                etag,
+                size: entry.metadata().await?.len() as i64,
+            });
+        }
+
+        parts.sort_by_key(|part| part.part_number);
+        Ok(parts)
+    }
+
+    async fn complete_multipart_upload(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<(), Box<dyn Error>> {
+        let dir = self.read_upload(key, upload_id).await?;
+        let upload: LocalMultipartUpload = serde_json::from_slice(&tokio::fs::read(dir.join("upload.json")).await?)?;
+
+        let mut previous = 0;
+        for (part_number, etag) in parts {
+            if *part_number <= previous {
+                return Err("Parts must be listed in ascending order".into());
+            }
+            previous = *part_number;
+            let stored = tokio::fs::read_to_string(dir.join(format!("{}.etag", part_number))).await.unwrap_or_default();
+            if stored != *etag {
+                return Err(format!("Part {} is missing or its ETag does not match", part_number).into());
+            }
+        }
+
+        let temp_path = self.temp_path();
+        tokio::fs::create_dir_all(self.root.join("tmp")).await?;
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+
+        for (part_number, _) in parts {
+            let mut part = tokio::fs::File::open(dir.join(format!("{}.part", part_number))).await?;
+            loop {
+                let read = part.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                file.write_all(&buffer[..read]).await?;
+            }
+        }
+        file.flush().await?;
+        drop(file);
+
+        let meta = LocalObjectMeta {
+            content_type: upload.content_type,
+            etag: hex::encode(hasher.finalize()),
+        };
+        self.commit(key, &temp_path, meta).await?;
+        tokio::fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    async fn abort_multipart_upload(&self, _key: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        let dir = self.upload_dir(upload_id)?;
+        match tokio::fs::remove_dir_all(dir).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
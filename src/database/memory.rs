@@ -0,0 +1,1441 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::ops::Range;
+use actix_web::web::Bytes;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use super::model::{
+    AccessToken, Blob, DeletedFiles, EmailToken, FileVersion, LoginChallenge, LoginFailure, OidcLoginState, TotpEnrollment, TusUpload, UserIdentity, WebauthnChallenge, WebauthnCredential, File, FileGrant, FileListQuery, FilePage, FilePermission, FileScope, FileSort, Folder, FolderFilter, FolderPermission,
+    Role, SearchMode, Session, ShareLink, User, BLOB_DELETING, BLOB_LIVE, BLOB_PENDING, ROLE_VIEWER, UPLOAD_COMPLETE, UPLOAD_PENDING,
+};
+use super::repository::Repository;
+use super::storage::{ObjectEntry, ObjectMeta, ObjectPage, ObjectStream, Storage, UploadedPart};
+
+#[derive(Default)]
+pub struct MemoryRepository {
+    users: Mutex<HashMap<String, User>>,
+    files: Mutex<HashMap<String, File>>,
+    sessions: Mutex<HashMap<String, Session>>,
+    permissions: Mutex<Vec<FilePermission>>,
+    share_links: Mutex<Vec<ShareLink>>,
+    folders: Mutex<HashMap<String, Folder>>,
+    folder_permissions: Mutex<Vec<FolderPermission>>,
+    access_tokens: Mutex<Vec<AccessToken>>,
+    totp: Mutex<HashMap<String, TotpEnrollment>>,
+    recovery_codes: Mutex<Vec<RecoveryCode>>,
+    login_challenges: Mutex<Vec<LoginChallenge>>,
+    webauthn_credentials: Mutex<Vec<WebauthnCredential>>,
+    webauthn_challenges: Mutex<Vec<WebauthnChallenge>>,
+    oidc_login_states: Mutex<Vec<OidcLoginState>>,
+    user_identities: Mutex<Vec<UserIdentity>>,
+    email_tokens: Mutex<Vec<EmailToken>>,
+    login_failures: Mutex<Vec<LoginFailure>>,
+    tus_uploads: Mutex<HashMap<String, TusUpload>>,
+    blobs: Mutex<HashMap<String, Blob>>,
+    file_versions: Mutex<Vec<FileVersion>>,
+}
+
+struct RecoveryCode {
+    user_id: String,
+    code_hash: String,
+    used: bool,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes the file's versions and drops their blob references, returning the blobs they referenced.
+    fn remove_versions(&self, file_id: &str) -> Vec<String> {
+        let mut versions = self.file_versions.lock().unwrap();
+        let hashes: Vec<String> = versions.iter().filter(|v| v.file_id == file_id).map(|v| v.blob_sha256.clone()).collect();
+        versions.retain(|v| v.file_id != file_id);
+        let mut blobs = self.blobs.lock().unwrap();
+        for sha256 in &hashes {
+            if let Some(blob) = blobs.get_mut(sha256) {
+                blob.ref_count -= 1;
+            }
+        }
+        hashes
+    }
+
+    fn with_permissions(&self, mut file: File) -> File {
+        let user_ids: Vec<String> = self.permissions.lock().unwrap()
+            .iter()
+            .filter(|p| p.file_id == file.id)
+            .map(|p| p.user_id.clone())
+            .collect();
+        file.accessible_user_ids = serde_json::json!(user_ids);
+        file
+    }
+
+    /// The folder followed by its ancestors, innermost first.
+    fn ancestors(&self, folder_id: &str) -> Vec<Folder> {
+        let folders = self.folders.lock().unwrap();
+        let mut chain = Vec::new();
+        let mut next = folders.get(folder_id);
+        while let Some(folder) = next {
+            chain.push(folder.clone());
+            next = folder.parent_id.as_ref().and_then(|id| folders.get(id));
+        }
+        chain
+    }
+
+    fn folder_role(&self, folder_id: &str, user_id: &str) -> Option<Role> {
+        let chain = self.ancestors(folder_id);
+        if chain.first()?.owner_id == user_id {
+            return Some(Role::Owner);
+        }
+
+        self.folder_permissions.lock().unwrap()
+            .iter()
+            .filter(|p| p.user_id == user_id && chain.iter().any(|f| f.id == p.folder_id))
+            .map(|p| Role::parse(&p.role).unwrap_or(Role::Viewer))
+            .max()
+    }
+
+    fn subtree(&self, folder_id: &str) -> Vec<String> {
+        let folders = self.folders.lock().unwrap();
+        let mut tree = vec![folder_id.to_string()];
+        let mut index = 0;
+        while index < tree.len() {
+            let parent = tree[index].clone();
+            tree.extend(folders.values().filter(|f| f.parent_id.as_deref() == Some(&parent)).map(|f| f.id.clone()));
+            index += 1;
+        }
+        tree
+    }
+}
+
+#[async_trait]
+impl Repository for MemoryRepository {
+    async fn create_user(&self, id: &str, email: &str, password_hash: &str) -> Result<User, Box<dyn Error>> {
+        let mut users = self.users.lock().unwrap();
+
+        if users.contains_key(id) || users.values().any(|u| u.email == email) {
+            return Err(format!("Duplicate entry for user {} ({})", id, email).into());
+        }
+
+        let user = User {
+            id: id.to_string(),
+            email: email.to_string(),
+            email_verified_at: None,
+            password_hash: password_hash.to_string(),
+            icon_url: None,
+            created_at: chrono::Utc::now(),
+        };
+        users.insert(id.to_string(), user.clone());
+        Ok(user)
+    }
+
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Box<dyn Error>> {
+        Ok(self.users.lock().unwrap().get(id).cloned())
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Box<dyn Error>> {
+        Ok(self.users.lock().unwrap().values().find(|u| u.email == email).cloned())
+    }
+
+    async fn update_user_icon(&self, id: &str, icon_url: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
+            user.icon_url = Some(icon_url.to_string());
+        }
+        Ok(())
+    }
+
+    async fn update_user_password(&self, id: &str, password_hash: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
+            user.password_hash = password_hash.to_string();
+        }
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, id: &str, verified_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
+            user.email_verified_at.get_or_insert(verified_at);
+        }
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.users.lock().unwrap().remove(id);
+        self.folders.lock().unwrap().retain(|_, f| f.owner_id != id);
+        let folders = self.folders.lock().unwrap();
+        self.folder_permissions.lock().unwrap().retain(|p| p.user_id != id && folders.contains_key(&p.folder_id));
+        for file in self.files.lock().unwrap().values_mut() {
+            if file.folder_id.as_ref().is_some_and(|folder_id| !folders.contains_key(folder_id)) {
+                file.folder_id = None;
+            }
+        }
+        drop(folders);
+        let owned: Vec<String> = self.files.lock().unwrap().values().filter(|f| f.owner_id == id).map(|f| f.id.clone()).collect();
+        for file_id in &owned {
+            self.remove_versions(file_id);
+        }
+        self.files.lock().unwrap().retain(|_, f| f.owner_id != id);
+        let files = self.files.lock().unwrap();
+        self.permissions.lock().unwrap().retain(|p| p.user_id != id && files.contains_key(&p.file_id));
+        self.share_links.lock().unwrap().retain(|l| files.contains_key(&l.file_id));
+        self.sessions.lock().unwrap().retain(|_, s| s.user_id != id);
+        self.access_tokens.lock().unwrap().retain(|t| t.user_id != id);
+        self.tus_uploads.lock().unwrap().retain(|_, u| u.user_id != id);
+        self.totp.lock().unwrap().remove(id);
+        self.recovery_codes.lock().unwrap().retain(|c| c.user_id != id);
+        self.login_challenges.lock().unwrap().retain(|c| c.user_id != id);
+        self.webauthn_credentials.lock().unwrap().retain(|c| c.user_id != id);
+        self.webauthn_challenges.lock().unwrap().retain(|c| c.user_id.as_deref() != Some(id));
+        self.user_identities.lock().unwrap().retain(|i| i.user_id != id);
+        self.email_tokens.lock().unwrap().retain(|t| t.user_id != id);
+        Ok(())
+    }
+
+    async fn create_file(&self, id: &str, filename: &str, owner_id: &str, folder_id: Option<&str>) -> Result<File, Box<dyn Error>> {
+        if !self.users.lock().unwrap().contains_key(owner_id) {
+            return Err(format!("Foreign key constraint fails for owner {}", owner_id).into());
+        }
+        if folder_id.is_some_and(|folder_id| !self.folders.lock().unwrap().contains_key(folder_id)) {
+            return Err(format!("Foreign key constraint fails for folder {:?}", folder_id).into());
+        }
+
+        let file = File {
+            id: id.to_string(),
+            filename: filename.to_string(),
+            owner_id: owner_id.to_string(),
+            folder_id: folder_id.map(String::from),
+            accessible_user_ids: serde_json::json!([]),
+            size_bytes: None,
+            content_type: None,
+            etag: None,
+            sha256: None,
+            upload_state: UPLOAD_PENDING.to_string(),
+            created_at: chrono::Utc::now(),
+            multipart_upload_id: None,
+            multipart_started_at: None,
+            blob_sha256: None,
+        };
+        self.files.lock().unwrap().insert(id.to_string(), file.clone());
+        Ok(file)
+    }
+
+    async fn get_file(&self, id: &str) -> Result<Option<File>, Box<dyn Error>> {
+        let file = self.files.lock().unwrap().get(id).cloned();
+        Ok(file.map(|f| self.with_permissions(f)))
+    }
+
+    async fn set_file_permissions(&self, file_id: &str, grants: &[FileGrant], granted_by: &str) -> Result<(), Box<dyn Error>> {
+        let mut permissions = self.permissions.lock().unwrap();
+        permissions.retain(|p| p.file_id != file_id || grants.iter().any(|g| g.user_id == p.user_id));
+
+        for grant in grants {
+            match permissions.iter_mut().find(|p| p.file_id == file_id && p.user_id == grant.user_id) {
+                Some(existing) => existing.role = grant.role.clone(),
+                None => permissions.push(FilePermission {
+                    file_id: file_id.to_string(),
+                    user_id: grant.user_id.clone(),
+                    role: grant.role.clone(),
+                    granted_at: chrono::Utc::now(),
+                    granted_by: Some(granted_by.to_string()),
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_file_permissions(&self, file_id: &str) -> Result<Vec<FilePermission>, Box<dyn Error>> {
+        Ok(self.permissions.lock().unwrap().iter().filter(|p| p.file_id == file_id).cloned().collect())
+    }
+
+    async fn complete_file_upload(
+        &self,
+        id: &str,
+        size_bytes: i64,
+        content_type: Option<&str>,
+        etag: Option<&str>,
+        sha256: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(file) = self.files.lock().unwrap().get_mut(id) {
+            file.size_bytes = Some(size_bytes);
+            file.content_type = content_type.map(|s| s.to_string());
+            file.etag = etag.map(|s| s.to_string());
+            file.sha256 = sha256.map(|s| s.to_string());
+            file.upload_state = UPLOAD_COMPLETE.to_string();
+            file.blob_sha256 = None;
+        }
+        Ok(())
+    }
+
+    async fn rename_file(&self, id: &str, filename: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(file) = self.files.lock().unwrap().get_mut(id) {
+            file.filename = filename.to_string();
+        }
+        Ok(())
+    }
+
+    async fn move_file(&self, id: &str, folder_id: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if let Some(file) = self.files.lock().unwrap().get_mut(id) {
+            file.folder_id = folder_id.map(String::from);
+        }
+        Ok(())
+    }
+
+    async fn delete_file(&self, id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.files.lock().unwrap().remove(id);
+        self.permissions.lock().unwrap().retain(|p| p.file_id != id);
+        self.share_links.lock().unwrap().retain(|l| l.file_id != id);
+        Ok(self.remove_versions(id))
+    }
+
+    async fn list_user_files(&self, user_id: &str, query: &FileListQuery) -> Result<FilePage, Box<dyn Error>> {
+        let shared: Vec<String> = self.permissions.lock().unwrap()
+            .iter()
+            .filter(|p| p.user_id == user_id)
+            .map(|p| p.file_id.clone())
+            .collect();
+
+        let folder_ids: Vec<String> = self.folders.lock().unwrap().keys().cloned().collect();
+        let reachable: Vec<String> = folder_ids
+            .into_iter()
+            .filter(|id| self.folder_role(id, user_id).is_some())
+            .collect();
+        let is_shared = |f: &File| {
+            shared.contains(&f.id) || f.folder_id.as_ref().is_some_and(|id| reachable.contains(id))
+        };
+
+        let search = query.search.as_deref().unwrap_or("").to_lowercase();
+        let mut files: Vec<File> = self.files.lock().unwrap()
+            .values()
+            .filter(|f| f.upload_state == UPLOAD_COMPLETE)
+            .filter(|f| match query.scope {
+                FileScope::All => f.owner_id == user_id || is_shared(f),
+                FileScope::Owned => f.owner_id == user_id,
+                FileScope::Shared => f.owner_id != user_id && is_shared(f),
+            })
+            .filter(|f| match &query.folder {
+                Some(FolderFilter::Root) => f.folder_id.is_none(),
+                Some(FolderFilter::Folder(id)) => f.folder_id.as_ref() == Some(id),
+                None => true,
+            })
+            .filter(|f| match query.search_mode {
+                SearchMode::Prefix => f.filename.to_lowercase().starts_with(&search),
+                SearchMode::Contains => f.filename.to_lowercase().contains(&search),
+            })
+            .cloned()
+            .collect();
+
+        let compare = |a: &File, b: &File| {
+            let ordering = match query.sort {
+                FileSort::Name => a.filename.to_lowercase().cmp(&b.filename.to_lowercase()),
+                FileSort::Date => a.created_at.cmp(&b.created_at),
+                FileSort::Size => a.size_bytes.unwrap_or(0).cmp(&b.size_bytes.unwrap_or(0)),
+            }
+            .then_with(|| a.id.cmp(&b.id));
+            if query.descending { ordering.reverse() } else { ordering }
+        };
+        files.sort_by(compare);
+        let total = files.len() as i64;
+
+        if let Some(after) = &query.after {
+            let mut marker = File {
+                id: after.id.clone(),
+                filename: String::new(),
+                owner_id: String::new(),
+                folder_id: None,
+                accessible_user_ids: serde_json::json!([]),
+                size_bytes: None,
+                content_type: None,
+                etag: None,
+                sha256: None,
+                upload_state: UPLOAD_COMPLETE.to_string(),
+                created_at: chrono::Utc::now(),
+                multipart_upload_id: None,
+                multipart_started_at: None,
+                blob_sha256: None,
+            };
+            match query.sort {
+                FileSort::Name => marker.filename = after.key.clone(),
+                FileSort::Date => marker.created_at = chrono::DateTime::parse_from_rfc3339(&after.key)?.with_timezone(&chrono::Utc),
+                FileSort::Size => marker.size_bytes = Some(after.key.parse()?),
+            }
+            files.retain(|f| compare(f, &marker) == std::cmp::Ordering::Greater);
+        }
+
+        let next = if files.len() as i64 > query.limit {
+            files.truncate(query.limit as usize);
+            files.last().map(|f| query.sort.cursor_for(f))
+        } else {
+            None
+        };
+
+        Ok(FilePage {
+            files: files.into_iter().map(|f| self.with_permissions(f)).collect(),
+            total,
+            next,
+        })
+    }
+
+    async fn list_stale_pending_files(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<File>, Box<dyn Error>> {
+        let mut files: Vec<File> = self.files.lock().unwrap()
+            .values()
+            .filter(|f| f.upload_state == UPLOAD_PENDING && f.created_at < created_before && f.multipart_upload_id.is_none())
+            .cloned()
+            .collect();
+        files.sort_by_key(|f| f.created_at);
+        files.truncate(limit.max(0) as usize);
+        Ok(files)
+    }
+
+    async fn start_multipart_upload(&self, id: &str, upload_id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>> {
+        match self.files.lock().unwrap().get_mut(id) {
+            Some(file) if file.multipart_upload_id.is_none() => {
+                file.multipart_upload_id = Some(upload_id.to_string());
+                file.multipart_started_at = Some(now);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn finish_multipart_upload(&self, id: &str, upload_id: &str) -> Result<bool, Box<dyn Error>> {
+        match self.files.lock().unwrap().get_mut(id) {
+            Some(file) if file.multipart_upload_id.as_deref() == Some(upload_id) => {
+                file.multipart_upload_id = None;
+                file.multipart_started_at = None;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn list_stale_multipart_uploads(&self, started_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<File>, Box<dyn Error>> {
+        let mut files: Vec<File> = self.files.lock().unwrap()
+            .values()
+            .filter(|f| f.multipart_started_at.is_some_and(|started| started < started_before))
+            .cloned()
+            .collect();
+        files.sort_by_key(|f| f.multipart_started_at);
+        files.truncate(limit.max(0) as usize);
+        Ok(files)
+    }
+
+    async fn existing_file_ids(&self, ids: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+        let files = self.files.lock().unwrap();
+        Ok(ids.iter().filter(|id| files.contains_key(*id)).cloned().collect())
+    }
+
+    async fn add_user_to_file(&self, file_id: &str, user_id: &str, granted_by: &str) -> Result<(), Box<dyn Error>> {
+        if !self.files.lock().unwrap().contains_key(file_id) {
+            return Err("File not found".into());
+        }
+
+        let mut permissions = self.permissions.lock().unwrap();
+        if !permissions.iter().any(|p| p.file_id == file_id && p.user_id == user_id) {
+            permissions.push(FilePermission {
+                file_id: file_id.to_string(),
+                user_id: user_id.to_string(),
+                role: ROLE_VIEWER.to_string(),
+                granted_at: chrono::Utc::now(),
+                granted_by: Some(granted_by.to_string()),
+            });
+        }
+        Ok(())
+    }
+
+    async fn remove_user_from_file(&self, file_id: &str, user_id: &str) -> Result<(), Box<dyn Error>> {
+        if !self.files.lock().unwrap().contains_key(file_id) {
+            return Err("File not found".into());
+        }
+
+        self.permissions.lock().unwrap().retain(|p| p.file_id != file_id || p.user_id != user_id);
+        Ok(())
+    }
+
+    async fn check_user_can_access(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        let owner_id = self.files.lock().unwrap()
+            .get(file_id)
+            .map(|f| f.owner_id.clone())
+            .ok_or("File not found")?;
+
+        if owner_id == user_id {
+            return Ok(true);
+        }
+
+        Ok(self.permissions.lock().unwrap().iter().any(|p| p.file_id == file_id && p.user_id == user_id))
+    }
+
+    async fn check_user_is_owner(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        let files = self.files.lock().unwrap();
+        let file = files.get(file_id).ok_or("File not found")?;
+        Ok(file.owner_id == user_id)
+    }
+
+    async fn get_file_role(&self, file_id: &str, user_id: &str) -> Result<Option<Role>, Box<dyn Error>> {
+        let Some(file) = self.files.lock().unwrap().get(file_id).cloned() else {
+            return Ok(None);
+        };
+        if file.owner_id == user_id {
+            return Ok(Some(Role::Owner));
+        }
+
+        let direct = self.permissions.lock().unwrap()
+            .iter()
+            .find(|p| p.file_id == file_id && p.user_id == user_id)
+            .map(|p| Role::parse(&p.role).unwrap_or(Role::Viewer));
+        let inherited = file.folder_id.as_deref()
+            .and_then(|folder_id| self.folder_role(folder_id, user_id))
+            .map(|role| role.min(Role::CoOwner));
+        Ok(direct.max(inherited))
+    }
+
+    async fn create_folder(&self, id: &str, owner_id: &str, parent_id: Option<&str>, name: &str) -> Result<Folder, Box<dyn Error>> {
+        if self.find_folder(owner_id, parent_id, name).await?.is_some() {
+            return Err(format!("Duplicate entry for folder {}", name).into());
+        }
+
+        let folder = Folder {
+            id: id.to_string(),
+            owner_id: owner_id.to_string(),
+            parent_id: parent_id.map(String::from),
+            name: name.to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        self.folders.lock().unwrap().insert(id.to_string(), folder.clone());
+        Ok(folder)
+    }
+
+    async fn get_folder(&self, id: &str) -> Result<Option<Folder>, Box<dyn Error>> {
+        Ok(self.folders.lock().unwrap().get(id).cloned())
+    }
+
+    async fn find_folder(&self, owner_id: &str, parent_id: Option<&str>, name: &str) -> Result<Option<Folder>, Box<dyn Error>> {
+        Ok(self.folders.lock().unwrap()
+            .values()
+            .find(|f| f.owner_id == owner_id && f.parent_id.as_deref() == parent_id && f.name.to_lowercase() == name.to_lowercase())
+            .cloned())
+    }
+
+    async fn list_child_folders(&self, parent_id: &str) -> Result<Vec<Folder>, Box<dyn Error>> {
+        let mut folders: Vec<Folder> = self.folders.lock().unwrap()
+            .values()
+            .filter(|f| f.parent_id.as_deref() == Some(parent_id))
+            .cloned()
+            .collect();
+        folders.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        Ok(folders)
+    }
+
+    async fn list_root_folders(&self, user_id: &str) -> Result<Vec<Folder>, Box<dyn Error>> {
+        let shared: Vec<String> = self.folder_permissions.lock().unwrap()
+            .iter()
+            .filter(|p| p.user_id == user_id)
+            .map(|p| p.folder_id.clone())
+            .collect();
+
+        let mut folders: Vec<Folder> = self.folders.lock().unwrap()
+            .values()
+            .filter(|f| (f.owner_id == user_id && f.parent_id.is_none()) || (f.owner_id != user_id && shared.contains(&f.id)))
+            .cloned()
+            .collect();
+        folders.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        Ok(folders)
+    }
+
+    async fn rename_folder(&self, id: &str, name: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(folder) = self.folders.lock().unwrap().get_mut(id) {
+            folder.name = name.to_string();
+        }
+        Ok(())
+    }
+
+    async fn move_folder(&self, id: &str, parent_id: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if let Some(folder) = self.folders.lock().unwrap().get_mut(id) {
+            folder.parent_id = parent_id.map(String::from);
+        }
+        Ok(())
+    }
+
+    async fn folder_path(&self, id: &str) -> Result<Vec<Folder>, Box<dyn Error>> {
+        let mut path = self.ancestors(id);
+        path.reverse();
+        Ok(path)
+    }
+
+    async fn delete_folder_tree(&self, id: &str, owner_id: &str) -> Result<DeletedFiles, Box<dyn Error>> {
+        let tree = self.subtree(id);
+
+        let mut moved_file_ids = Vec::new();
+        let mut files = Vec::new();
+        for file in self.files.lock().unwrap().values_mut() {
+            if !file.folder_id.as_ref().is_some_and(|folder_id| tree.contains(folder_id)) {
+                continue;
+            }
+            if file.owner_id == owner_id {
+                files.push(file.clone());
+            } else {
+                file.folder_id = None;
+                moved_file_ids.push(file.id.clone());
+            }
+        }
+        let mut blob_hashes = Vec::new();
+        for file in &files {
+            blob_hashes.extend(self.delete_file(&file.id).await?);
+        }
+
+        self.folders.lock().unwrap().retain(|id, _| !tree.contains(id));
+        self.folder_permissions.lock().unwrap().retain(|p| !tree.contains(&p.folder_id));
+        Ok(DeletedFiles { files, blob_hashes, moved_file_ids })
+    }
+
+    async fn set_folder_permissions(&self, folder_id: &str, grants: &[FileGrant], granted_by: &str) -> Result<(), Box<dyn Error>> {
+        let mut permissions = self.folder_permissions.lock().unwrap();
+        permissions.retain(|p| p.folder_id != folder_id || grants.iter().any(|g| g.user_id == p.user_id));
+
+        for grant in grants {
+            match permissions.iter_mut().find(|p| p.folder_id == folder_id && p.user_id == grant.user_id) {
+                Some(existing) => existing.role = grant.role.clone(),
+                None => permissions.push(FolderPermission {
+                    folder_id: folder_id.to_string(),
+                    user_id: grant.user_id.clone(),
+                    role: grant.role.clone(),
+                    granted_at: chrono::Utc::now(),
+                    granted_by: Some(granted_by.to_string()),
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_folder_permissions(&self, folder_id: &str) -> Result<Vec<FolderPermission>, Box<dyn Error>> {
+        Ok(self.folder_permissions.lock().unwrap().iter().filter(|p| p.folder_id == folder_id).cloned().collect())
+    }
+
+    async fn get_folder_role(&self, folder_id: &str, user_id: &str) -> Result<Option<Role>, Box<dyn Error>> {
+        Ok(self.folder_role(folder_id, user_id))
+    }
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<(), Box<dyn Error>> {
+        let mut links = self.share_links.lock().unwrap();
+        if links.iter().any(|l| l.id == link.id || l.token_hash == link.token_hash) {
+            return Err(format!("Duplicate entry for share link {}", link.id).into());
+        }
+        links.push(ShareLink { download_count: 0, revoked_at: None, ..link.clone() });
+        Ok(())
+    }
+
+    async fn get_share_link_by_token(&self, token_hash: &str) -> Result<Option<ShareLink>, Box<dyn Error>> {
+        Ok(self.share_links.lock().unwrap().iter().find(|l| l.token_hash == token_hash).cloned())
+    }
+
+    async fn list_share_links(&self, file_id: &str) -> Result<Vec<ShareLink>, Box<dyn Error>> {
+        Ok(self.share_links.lock().unwrap().iter().filter(|l| l.file_id == file_id).cloned().collect())
+    }
+
+    async fn revoke_share_link(&self, file_id: &str, link_id: &str) -> Result<bool, Box<dyn Error>> {
+        let mut links = self.share_links.lock().unwrap();
+        match links.iter_mut().find(|l| l.id == link_id && l.file_id == file_id && l.revoked_at.is_none()) {
+            Some(link) => {
+                link.revoked_at = Some(chrono::Utc::now());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn consume_share_link(&self, link_id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>> {
+        let mut links = self.share_links.lock().unwrap();
+        match links.iter_mut().find(|l| l.id == link_id) {
+            Some(link) if link.revoked_at.is_none() && !link.is_expired(now) && !link.is_exhausted() => {
+                link.download_count += 1;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn create_session(
+        &self,
+        id: &str,
+        user_id: &str,
+        key_hash: &str,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<Session, Box<dyn Error>> {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        if sessions.contains_key(id) || sessions.values().any(|s| s.key_hash == key_hash) {
+            return Err(format!("Duplicate entry for session {}", id).into());
+        }
+
+        let now = chrono::Utc::now();
+        let session = Session {
+            id: id.to_string(),
+            user_id: user_id.to_string(),
+            key_hash: key_hash.to_string(),
+            ip_address: ip_address.map(|s| s.to_string()),
+            user_agent: user_agent.map(|s| s.to_string()),
+            created_at: now,
+            last_accessed_at: now,
+        };
+        sessions.insert(id.to_string(), session.clone());
+        Ok(session)
+    }
+
+    async fn get_session(&self, key_hash: &str) -> Result<Option<Session>, Box<dyn Error>> {
+        Ok(self.sessions.lock().unwrap().values().find(|s| s.key_hash == key_hash).cloned())
+    }
+
+    async fn update_session_access_time(&self, key_hash: &str, accessed_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        if let Some(session) = self.sessions.lock().unwrap().values_mut().find(|s| s.key_hash == key_hash) {
+            session.last_accessed_at = accessed_at;
+        }
+        Ok(())
+    }
+
+    async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, Box<dyn Error>> {
+        Ok(self.sessions.lock().unwrap().values().filter(|s| s.user_id == user_id).cloned().collect())
+    }
+
+    async fn delete_session(&self, key_hash: &str) -> Result<(), Box<dyn Error>> {
+        self.sessions.lock().unwrap().retain(|_, s| s.key_hash != key_hash);
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.sessions.lock().unwrap().retain(|_, s| s.user_id != user_id);
+        Ok(())
+    }
+
+    async fn delete_expired_sessions(
+        &self,
+        created_before: Option<chrono::DateTime<chrono::Utc>>,
+        idle_before: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<u64, Box<dyn Error>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, s| {
+            created_before.is_none_or(|cutoff| s.created_at >= cutoff)
+                && idle_before.is_none_or(|cutoff| s.last_accessed_at >= cutoff)
+        });
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn create_access_token(&self, token: &AccessToken) -> Result<(), Box<dyn Error>> {
+        let mut tokens = self.access_tokens.lock().unwrap();
+        if tokens.iter().any(|t| t.id == token.id || t.token_hash == token.token_hash) {
+            return Err(format!("Duplicate entry for access token {}", token.id).into());
+        }
+        tokens.push(AccessToken { last_used_at: None, ..token.clone() });
+        Ok(())
+    }
+
+    async fn get_access_token_by_hash(&self, token_hash: &str) -> Result<Option<AccessToken>, Box<dyn Error>> {
+        Ok(self.access_tokens.lock().unwrap().iter().find(|t| t.token_hash == token_hash).cloned())
+    }
+
+    async fn list_access_tokens(&self, user_id: &str) -> Result<Vec<AccessToken>, Box<dyn Error>> {
+        Ok(self.access_tokens.lock().unwrap().iter().filter(|t| t.user_id == user_id).cloned().collect())
+    }
+
+    async fn delete_access_token(&self, user_id: &str, token_id: &str) -> Result<bool, Box<dyn Error>> {
+        let mut tokens = self.access_tokens.lock().unwrap();
+        let before = tokens.len();
+        tokens.retain(|t| !(t.id == token_id && t.user_id == user_id));
+        Ok(tokens.len() < before)
+    }
+
+    async fn update_access_token_last_used(&self, token_id: &str, used_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        if let Some(token) = self.access_tokens.lock().unwrap().iter_mut().find(|t| t.id == token_id) {
+            token.last_used_at = Some(used_at);
+        }
+        Ok(())
+    }
+
+    async fn save_totp_secret(&self, user_id: &str, secret: &str) -> Result<(), Box<dyn Error>> {
+        let mut totp = self.totp.lock().unwrap();
+        if totp.get(user_id).is_some_and(|t| t.enabled_at.is_some()) {
+            return Ok(());
+        }
+        totp.insert(user_id.to_string(), TotpEnrollment {
+            user_id: user_id.to_string(),
+            secret: secret.to_string(),
+            enabled_at: None,
+            last_used_step: None,
+            created_at: chrono::Utc::now(),
+        });
+        Ok(())
+    }
+
+    async fn get_totp(&self, user_id: &str) -> Result<Option<TotpEnrollment>, Box<dyn Error>> {
+        Ok(self.totp.lock().unwrap().get(user_id).cloned())
+    }
+
+    async fn enable_totp(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut totp = self.totp.lock().unwrap();
+        let Some(enrollment) = totp.get_mut(user_id).filter(|t| t.enabled_at.is_none()) else {
+            return Ok(false);
+        };
+        enrollment.enabled_at = Some(now);
+        enrollment.last_used_step = Some(step);
+
+        let mut codes = self.recovery_codes.lock().unwrap();
+        codes.retain(|c| c.user_id != user_id);
+        codes.extend(recovery_code_hashes.iter().map(|hash| RecoveryCode {
+            user_id: user_id.to_string(),
+            code_hash: hash.clone(),
+            used: false,
+        }));
+        Ok(true)
+    }
+
+    async fn use_totp_step(&self, user_id: &str, step: i64) -> Result<bool, Box<dyn Error>> {
+        let mut totp = self.totp.lock().unwrap();
+        match totp.get_mut(user_id) {
+            Some(t) if t.enabled_at.is_some() && t.last_used_step.is_none_or(|last| last < step) => {
+                t.last_used_step = Some(step);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_totp(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        self.totp.lock().unwrap().remove(user_id);
+        self.recovery_codes.lock().unwrap().retain(|c| c.user_id != user_id);
+        Ok(())
+    }
+
+    async fn use_recovery_code(&self, user_id: &str, code_hash: &str, _now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>> {
+        let mut codes = self.recovery_codes.lock().unwrap();
+        match codes.iter_mut().find(|c| c.user_id == user_id && c.code_hash == code_hash && !c.used) {
+            Some(code) => {
+                code.used = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn count_recovery_codes(&self, user_id: &str) -> Result<i64, Box<dyn Error>> {
+        Ok(self.recovery_codes.lock().unwrap().iter().filter(|c| c.user_id == user_id && !c.used).count() as i64)
+    }
+
+    async fn create_login_challenge(&self, challenge: &LoginChallenge) -> Result<(), Box<dyn Error>> {
+        self.login_challenges.lock().unwrap().push(LoginChallenge { attempts: 0, ..challenge.clone() });
+        Ok(())
+    }
+
+    async fn get_login_challenge(&self, token_hash: &str) -> Result<Option<LoginChallenge>, Box<dyn Error>> {
+        Ok(self.login_challenges.lock().unwrap().iter().find(|c| c.token_hash == token_hash).cloned())
+    }
+
+    async fn increment_login_challenge_attempts(&self, id: &str, max_attempts: i32) -> Result<bool, Box<dyn Error>> {
+        match self.login_challenges.lock().unwrap().iter_mut().find(|c| c.id == id && c.attempts < max_attempts) {
+            Some(challenge) => {
+                challenge.attempts += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete_login_challenge(&self, id: &str) -> Result<bool, Box<dyn Error>> {
+        let mut challenges = self.login_challenges.lock().unwrap();
+        let before = challenges.len();
+        challenges.retain(|c| c.id != id);
+        Ok(challenges.len() < before)
+    }
+
+    async fn delete_expired_login_challenges(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let mut challenges = self.login_challenges.lock().unwrap();
+        let before = challenges.len();
+        challenges.retain(|c| c.expires_at > now);
+        Ok((before - challenges.len()) as u64)
+    }
+
+    async fn create_webauthn_credential(&self, credential: &WebauthnCredential) -> Result<(), Box<dyn Error>> {
+        let mut credentials = self.webauthn_credentials.lock().unwrap();
+        if credentials.iter().any(|c| c.credential_id == credential.credential_id) {
+            return Err(format!("Duplicate entry for credential {}", credential.credential_id).into());
+        }
+        credentials.push(credential.clone());
+        Ok(())
+    }
+
+    async fn get_webauthn_credential(&self, credential_id: &str) -> Result<Option<WebauthnCredential>, Box<dyn Error>> {
+        Ok(self.webauthn_credentials.lock().unwrap().iter().find(|c| c.credential_id == credential_id).cloned())
+    }
+
+    async fn list_webauthn_credentials(&self, user_id: &str) -> Result<Vec<WebauthnCredential>, Box<dyn Error>> {
+        let mut credentials: Vec<WebauthnCredential> = self.webauthn_credentials.lock().unwrap()
+            .iter()
+            .filter(|c| c.user_id == user_id)
+            .cloned()
+            .collect();
+        credentials.sort_by_key(|c| std::cmp::Reverse(c.created_at));
+        Ok(credentials)
+    }
+
+    async fn update_webauthn_credential_use(&self, id: &str, sign_count: i64, used_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        if let Some(credential) = self.webauthn_credentials.lock().unwrap().iter_mut().find(|c| c.id == id) {
+            credential.sign_count = sign_count;
+            credential.last_used_at = Some(used_at);
+        }
+        Ok(())
+    }
+
+    async fn delete_webauthn_credential(&self, user_id: &str, id: &str) -> Result<bool, Box<dyn Error>> {
+        let mut credentials = self.webauthn_credentials.lock().unwrap();
+        let before = credentials.len();
+        credentials.retain(|c| !(c.id == id && c.user_id == user_id));
+        Ok(credentials.len() < before)
+    }
+
+    async fn create_webauthn_challenge(&self, challenge: &WebauthnChallenge) -> Result<(), Box<dyn Error>> {
+        self.webauthn_challenges.lock().unwrap().push(challenge.clone());
+        Ok(())
+    }
+
+    async fn take_webauthn_challenge(
+        &self,
+        challenge: &str,
+        purpose: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<WebauthnChallenge>, Box<dyn Error>> {
+        let mut challenges = self.webauthn_challenges.lock().unwrap();
+        let Some(index) = challenges.iter().position(|c| c.challenge == challenge) else {
+            return Ok(None);
+        };
+        let found = challenges.remove(index);
+        Ok((found.purpose == purpose && found.expires_at > now).then_some(found))
+    }
+
+    async fn delete_expired_webauthn_challenges(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let mut challenges = self.webauthn_challenges.lock().unwrap();
+        let before = challenges.len();
+        challenges.retain(|c| c.expires_at > now);
+        Ok((before - challenges.len()) as u64)
+    }
+
+    async fn create_oidc_login_state(&self, state: &OidcLoginState) -> Result<(), Box<dyn Error>> {
+        self.oidc_login_states.lock().unwrap().push(state.clone());
+        Ok(())
+    }
+
+    async fn take_oidc_login_state(&self, state_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Option<OidcLoginState>, Box<dyn Error>> {
+        let mut states = self.oidc_login_states.lock().unwrap();
+        let Some(index) = states.iter().position(|s| s.state_hash == state_hash) else {
+            return Ok(None);
+        };
+        let found = states.remove(index);
+        Ok((found.expires_at > now).then_some(found))
+    }
+
+    async fn delete_expired_oidc_login_states(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let mut states = self.oidc_login_states.lock().unwrap();
+        let before = states.len();
+        states.retain(|s| s.expires_at > now);
+        Ok((before - states.len()) as u64)
+    }
+
+    async fn get_user_identity(&self, issuer: &str, subject: &str) -> Result<Option<UserIdentity>, Box<dyn Error>> {
+        Ok(self.user_identities.lock().unwrap().iter().find(|i| i.issuer == issuer && i.subject == subject).cloned())
+    }
+
+    async fn create_user_identity(&self, identity: &UserIdentity) -> Result<(), Box<dyn Error>> {
+        let mut identities = self.user_identities.lock().unwrap();
+        if identities.iter().any(|i| i.issuer == identity.issuer && i.subject == identity.subject) {
+            return Err(format!("Duplicate entry for identity {}", identity.subject).into());
+        }
+        identities.push(identity.clone());
+        Ok(())
+    }
+
+    async fn create_email_token(&self, token: &EmailToken) -> Result<(), Box<dyn Error>> {
+        self.email_tokens.lock().unwrap().push(token.clone());
+        Ok(())
+    }
+
+    async fn take_email_token(
+        &self,
+        token_hash: &str,
+        purpose: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<EmailToken>, Box<dyn Error>> {
+        let mut tokens = self.email_tokens.lock().unwrap();
+        let Some(index) = tokens.iter().position(|t| t.token_hash == token_hash && t.purpose == purpose) else {
+            return Ok(None);
+        };
+        let found = tokens.remove(index);
+        Ok((found.expires_at > now).then_some(found))
+    }
+
+    async fn delete_email_tokens(&self, user_id: &str, purpose: &str) -> Result<(), Box<dyn Error>> {
+        self.email_tokens.lock().unwrap().retain(|t| !(t.user_id == user_id && t.purpose == purpose));
+        Ok(())
+    }
+
+    async fn delete_expired_email_tokens(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let mut tokens = self.email_tokens.lock().unwrap();
+        let before = tokens.len();
+        tokens.retain(|t| t.expires_at > now);
+        Ok((before - tokens.len()) as u64)
+    }
+
+    async fn get_login_failure(&self, scope: &str, subject: &str) -> Result<Option<LoginFailure>, Box<dyn Error>> {
+        Ok(self.login_failures.lock().unwrap().iter().find(|f| f.scope == scope && f.subject == subject).cloned())
+    }
+
+    async fn record_login_failure(
+        &self,
+        scope: &str,
+        subject: &str,
+        now: chrono::DateTime<chrono::Utc>,
+        window_start: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i32, Box<dyn Error>> {
+        let mut failures = self.login_failures.lock().unwrap();
+        match failures.iter_mut().find(|f| f.scope == scope && f.subject == subject) {
+            Some(failure) => {
+                failure.failures = if failure.last_failed_at < window_start { 1 } else { failure.failures + 1 };
+                failure.last_failed_at = now;
+                Ok(failure.failures)
+            }
+            None => {
+                failures.push(LoginFailure {
+                    scope: scope.to_string(),
+                    subject: subject.to_string(),
+                    failures: 1,
+                    last_failed_at: now,
+                    blocked_until: None,
+                });
+                Ok(1)
+            }
+        }
+    }
+
+    async fn block_login(&self, scope: &str, subject: &str, until: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        if let Some(failure) = self.login_failures.lock().unwrap().iter_mut().find(|f| f.scope == scope && f.subject == subject) {
+            failure.blocked_until = Some(until);
+        }
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, scope: &str, subject: &str) -> Result<(), Box<dyn Error>> {
+        self.login_failures.lock().unwrap().retain(|f| !(f.scope == scope && f.subject == subject));
+        Ok(())
+    }
+
+    async fn delete_stale_login_failures(
+        &self,
+        window_start: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, Box<dyn Error>> {
+        let mut failures = self.login_failures.lock().unwrap();
+        let before = failures.len();
+        failures.retain(|f| f.last_failed_at >= window_start || f.blocked_until.is_some_and(|until| until > now));
+        Ok((before - failures.len()) as u64)
+    }
+
+    async fn create_tus_upload(&self, upload: &TusUpload) -> Result<(), Box<dyn Error>> {
+        self.tus_uploads.lock().unwrap().insert(upload.id.clone(), upload.clone());
+        Ok(())
+    }
+
+    async fn get_tus_upload(&self, id: &str) -> Result<Option<TusUpload>, Box<dyn Error>> {
+        Ok(self.tus_uploads.lock().unwrap().get(id).cloned())
+    }
+
+    async fn lock_tus_upload(
+        &self,
+        id: &str,
+        token: &str,
+        now: chrono::DateTime<chrono::Utc>,
+        until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut uploads = self.tus_uploads.lock().unwrap();
+        let Some(upload) = uploads.get_mut(id) else {
+            return Ok(false);
+        };
+        let free = upload.lock_token.as_deref().is_none_or(|held| held == token)
+            || upload.locked_until.is_some_and(|t| t <= now);
+        if free {
+            upload.lock_token = Some(token.to_string());
+            upload.locked_until = Some(until);
+        }
+        Ok(free)
+    }
+
+    async fn advance_tus_upload(&self, id: &str, token: &str, from_offset: i64, offset: i64) -> Result<bool, Box<dyn Error>> {
+        let mut uploads = self.tus_uploads.lock().unwrap();
+        match uploads.get_mut(id).filter(|u| u.lock_token.as_deref() == Some(token) && u.upload_offset == from_offset) {
+            Some(upload) => {
+                upload.upload_offset = offset;
+                upload.lock_token = None;
+                upload.locked_until = None;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn unlock_tus_upload(&self, id: &str, token: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(upload) = self.tus_uploads.lock().unwrap().get_mut(id).filter(|u| u.lock_token.as_deref() == Some(token)) {
+            upload.lock_token = None;
+            upload.locked_until = None;
+        }
+        Ok(())
+    }
+
+    async fn delete_tus_upload(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.tus_uploads.lock().unwrap().remove(id);
+        Ok(())
+    }
+
+    async fn list_stale_tus_uploads(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<TusUpload>, Box<dyn Error>> {
+        let mut uploads: Vec<TusUpload> = self.tus_uploads.lock().unwrap()
+            .values()
+            .filter(|u| u.created_at < created_before)
+            .cloned()
+            .collect();
+        uploads.sort_by_key(|u| u.created_at);
+        uploads.truncate(limit as usize);
+        Ok(uploads)
+    }
+
+    async fn create_blob(&self, sha256: &str, size_bytes: i64) -> Result<bool, Box<dyn Error>> {
+        let mut blobs = self.blobs.lock().unwrap();
+        if blobs.contains_key(sha256) {
+            return Ok(false);
+        }
+        blobs.insert(sha256.to_string(), Blob {
+            sha256: sha256.to_string(),
+            size_bytes,
+            etag: None,
+            state: BLOB_PENDING.to_string(),
+            ref_count: 0,
+            created_at: chrono::Utc::now(),
+        });
+        Ok(true)
+    }
+
+    async fn get_blob(&self, sha256: &str) -> Result<Option<Blob>, Box<dyn Error>> {
+        Ok(self.blobs.lock().unwrap().get(sha256).cloned())
+    }
+
+    async fn activate_blob(&self, sha256: &str, etag: Option<&str>) -> Result<bool, Box<dyn Error>> {
+        match self.blobs.lock().unwrap().get_mut(sha256) {
+            Some(blob) if blob.state == BLOB_PENDING => {
+                blob.state = BLOB_LIVE.to_string();
+                blob.etag = etag.map(String::from);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn attach_blob(&self, file_id: &str, sha256: &str, content_type: Option<&str>, uploaded_by: Option<&str>) -> Result<bool, Box<dyn Error>> {
+        let mut files = self.files.lock().unwrap();
+        let mut blobs = self.blobs.lock().unwrap();
+        let (Some(file), Some(blob)) = (files.get_mut(file_id), blobs.get_mut(sha256)) else {
+            return Ok(false);
+        };
+        if blob.state != BLOB_LIVE {
+            return Ok(false);
+        }
+
+        file.size_bytes = Some(blob.size_bytes);
+        file.content_type = content_type.map(String::from);
+        file.etag = blob.etag.clone();
+        file.sha256 = Some(sha256.to_string());
+        file.upload_state = UPLOAD_COMPLETE.to_string();
+        if file.blob_sha256.replace(sha256.to_string()).as_deref() != Some(sha256) {
+            let mut versions = self.file_versions.lock().unwrap();
+            let latest = versions.iter().filter(|v| v.file_id == file_id).map(|v| v.version_number).max();
+            versions.push(FileVersion {
+                file_id: file_id.to_string(),
+                version_number: latest.unwrap_or(0) + 1,
+                blob_sha256: sha256.to_string(),
+                size_bytes: blob.size_bytes,
+                content_type: content_type.map(String::from),
+                etag: blob.etag.clone(),
+                uploaded_by: uploaded_by.map(String::from),
+                created_at: chrono::Utc::now(),
+            });
+            blob.ref_count += 1;
+        }
+        Ok(true)
+    }
+
+    async fn claim_blob_deletion(&self, sha256: &str, include_pending: bool) -> Result<bool, Box<dyn Error>> {
+        match self.blobs.lock().unwrap().get_mut(sha256) {
+            Some(blob) if blob.ref_count == 0 && (blob.state == BLOB_LIVE || (include_pending && blob.state == BLOB_PENDING)) => {
+                blob.state = BLOB_DELETING.to_string();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_blob(&self, sha256: &str) -> Result<(), Box<dyn Error>> {
+        self.blobs.lock().unwrap().retain(|key, blob| key != sha256 || blob.ref_count > 0);
+        Ok(())
+    }
+
+    async fn list_unreferenced_blobs(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<Blob>, Box<dyn Error>> {
+        let mut blobs: Vec<Blob> = self.blobs.lock().unwrap()
+            .values()
+            .filter(|b| b.ref_count == 0 && b.created_at < created_before)
+            .cloned()
+            .collect();
+        blobs.sort_by_key(|b| b.created_at);
+        blobs.truncate(limit.max(0) as usize);
+        Ok(blobs)
+    }
+
+    async fn existing_blob_hashes(&self, hashes: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+        let blobs = self.blobs.lock().unwrap();
+        Ok(hashes.iter().filter(|sha256| blobs.contains_key(*sha256)).cloned().collect())
+    }
+
+    async fn list_file_versions(&self, file_id: &str) -> Result<Vec<FileVersion>, Box<dyn Error>> {
+        let mut versions: Vec<FileVersion> = self.file_versions.lock().unwrap()
+            .iter()
+            .filter(|v| v.file_id == file_id)
+            .cloned()
+            .collect();
+        versions.sort_by_key(|v| std::cmp::Reverse(v.version_number));
+        Ok(versions)
+    }
+
+    async fn get_file_version(&self, file_id: &str, version_number: i32) -> Result<Option<FileVersion>, Box<dyn Error>> {
+        Ok(self.file_versions.lock().unwrap()
+            .iter()
+            .find(|v| v.file_id == file_id && v.version_number == version_number)
+            .cloned())
+    }
+
+    async fn delete_file_version(&self, file_id: &str, version_number: i32) -> Result<bool, Box<dyn Error>> {
+        let mut versions = self.file_versions.lock().unwrap();
+        let latest = versions.iter().filter(|v| v.file_id == file_id).map(|v| v.version_number).max();
+        if latest.is_none_or(|latest| version_number >= latest) {
+            return Ok(false);
+        }
+        let Some(index) = versions.iter().position(|v| v.file_id == file_id && v.version_number == version_number) else {
+            return Ok(false);
+        };
+
+        let version = versions.remove(index);
+        if let Some(blob) = self.blobs.lock().unwrap().get_mut(&version.blob_sha256) {
+            blob.ref_count -= 1;
+        }
+        Ok(true)
+    }
+
+    async fn list_excess_file_versions(&self, keep: i64, limit: i64) -> Result<Vec<FileVersion>, Box<dyn Error>> {
+        let versions = self.file_versions.lock().unwrap();
+        let mut excess: Vec<FileVersion> = versions
+            .iter()
+            .filter(|v| {
+                let newer = versions.iter().filter(|o| o.file_id == v.file_id && o.version_number > v.version_number).count();
+                newer as i64 >= keep
+            })
+            .cloned()
+            .collect();
+        excess.sort_by_key(|v| v.created_at);
+        excess.truncate(limit.max(0) as usize);
+        Ok(excess)
+    }
+}
+
+
+struct MemoryObject {
+    body: Vec<u8>,
+    content_type: Option<String>,
+    last_modified: chrono::DateTime<chrono::Utc>,
+}
+
+struct MemoryMultipartUpload {
+    key: String,
+    content_type: Option<String>,
+    parts: BTreeMap<i32, Vec<u8>>,
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: Mutex<HashMap<String, MemoryObject>>,
+    uploads: Mutex<HashMap<String, MemoryMultipartUpload>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stands in for the client's PUT to a presigned part URL and returns the part's ETag.
+    pub fn upload_part(&self, upload_id: &str, part_number: i32, body: Vec<u8>) -> Result<String, Box<dyn Error>> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let upload = uploads.get_mut(upload_id).ok_or("NoSuchUpload")?;
+        let etag = hex::encode(Sha256::digest(&body));
+        upload.parts.insert(part_number, body);
+        Ok(etag)
+    }
+
+    pub fn multipart_upload_count(&self) -> usize {
+        self.uploads.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn upload_expire(&self) -> u64 {
+        300
+    }
+
+    async fn upload_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        Ok(format!("memory://upload/{}", key))
+    }
+
+    async fn download_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        Ok(format!("memory://download/{}", key))
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.objects.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn head_object(&self, key: &str) -> Result<Option<ObjectMeta>, Box<dyn Error>> {
+        Ok(self.objects.lock().unwrap().get(key).map(|object| {
+            let digest = hex::encode(Sha256::digest(&object.body));
+            ObjectMeta {
+                size: object.body.len() as i64,
+                content_type: object.content_type.clone(),
+                etag: Some(digest.clone()),
+                sha256: Some(digest),
+                last_modified: Some(object.last_modified),
+            }
+        }))
+    }
+
+    async fn put_object(&self, key: &str, body: Vec<u8>, content_type: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.objects.lock().unwrap().insert(key.to_string(), MemoryObject {
+            body,
+            content_type: content_type.map(|s| s.to_string()),
+            last_modified: chrono::Utc::now(),
+        });
+        Ok(())
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.objects.lock().unwrap().get(key).map(|object| object.body.clone()))
+    }
+
+    async fn read_object(&self, key: &str, range: Range<u64>) -> Result<Option<ObjectStream>, Box<dyn Error>> {
+        let objects = self.objects.lock().unwrap();
+        let Some(object) = objects.get(key) else {
+            return Ok(None);
+        };
+        let body = object.body.get(range.start as usize..range.end as usize).ok_or("InvalidRange")?.to_vec();
+        Ok(Some(Box::pin(futures_util::stream::once(async move { Ok(Bytes::from(body)) }))))
+    }
+
+    async fn copy_object(&self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        let mut objects = self.objects.lock().unwrap();
+        let object = objects.get(from).ok_or("NoSuchKey")?;
+        let copy = MemoryObject {
+            body: object.body.clone(),
+            content_type: object.content_type.clone(),
+            last_modified: chrono::Utc::now(),
+        };
+        objects.insert(to.to_string(), copy);
+        Ok(())
+    }
+
+    async fn list_objects(&self, _continuation_token: Option<String>) -> Result<ObjectPage, Box<dyn Error>> {
+        let objects = self.objects.lock().unwrap()
+            .iter()
+            .map(|(key, object)| ObjectEntry {
+                key: key.clone(),
+                size: object.body.len() as i64,
+                last_modified: Some(object.last_modified),
+            })
+            .collect();
+
+        Ok(ObjectPage {
+            objects,
+            next_token: None,
+        })
+    }
+
+    async fn create_multipart_upload(&self, key: &str, content_type: Option<&str>) -> Result<String, Box<dyn Error>> {
+        let upload_id = uuid::Uuid::new_v4().to_string();
+        self.uploads.lock().unwrap().insert(upload_id.clone(), MemoryMultipartUpload {
+            key: key.to_string(),
+            content_type: content_type.map(|s| s.to_string()),
+            parts: BTreeMap::new(),
+        });
+        Ok(upload_id)
+    }
+
+    async fn upload_part_url(&self, key: &str, upload_id: &str, part_number: i32) -> Result<String, Box<dyn Error>> {
+        Ok(format!("memory://upload/{}?uploadId={}&partNumber={}", key, upload_id, part_number))
+    }
+
+    async fn put_part(&self, key: &str, upload_id: &str, part_number: i32, body: Vec<u8>) -> Result<String, Box<dyn Error>> {
+        if self.uploads.lock().unwrap().get(upload_id).is_none_or(|u| u.key != key) {
+            return Err("NoSuchUpload".into());
+        }
+        self.upload_part(upload_id, part_number, body)
+    }
+
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>, Box<dyn Error>> {
+        let uploads = self.uploads.lock().unwrap();
+        let upload = uploads.get(upload_id).filter(|u| u.key == key).ok_or("NoSuchUpload")?;
+        Ok(upload.parts
+            .iter()
+            .map(|(part_number, body)| UploadedPart {
+                part_number: *part_number,
+                etag: hex::encode(Sha256::digest(body)),
+                size: body.len() as i64,
+            })
+            .collect())
+    }
+
+    async fn complete_multipart_upload(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<(), Box<dyn Error>> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let upload = uploads.get(upload_id).filter(|u| u.key == key).ok_or("NoSuchUpload")?;
+
+        let mut body = Vec::new();
+        let mut previous = 0;
+        for (part_number, etag) in parts {
+            if *part_number <= previous {
+                return Err("InvalidPartOrder".into());
+            }
+            previous = *part_number;
+            match upload.parts.get(part_number) {
+                Some(part) if hex::encode(Sha256::digest(part)) == *etag => body.extend_from_slice(part),
+                _ => return Err(format!("InvalidPart {}", part_number).into()),
+            }
+        }
+
+        let upload = uploads.remove(upload_id).unwrap();
+        drop(uploads);
+        self.objects.lock().unwrap().insert(upload.key, MemoryObject {
+            body,
+            content_type: upload.content_type,
+            last_modified: chrono::Utc::now(),
+        });
+        Ok(())
+    }
+
+    async fn abort_multipart_upload(&self, _key: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        self.uploads.lock().unwrap().remove(upload_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+use std::error::Error;
+use std::time::Instant;
+use sha2::{Digest, Sha256};
+use sqlx::{query_as, MySql, MySqlConnection, Pool};
+
+const LOCK_NAME: &str = "cloud_file_storage.schema_migrations";
+const LOCK_TIMEOUT_SECS: i64 = 60;
+
+macro_rules! migration {
+    ($version:expr, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../../migrations/", $name, ".up.sql")),
+            down: Some(include_str!(concat!("../../migrations/", $name, ".down.sql"))),
+        }
+    };
+}
+
+pub static MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_initial"),
+    migration!(2, "0002_upload_state"),
+    migration!(3, "0003_file_permissions"),
+    migration!(4, "0004_drop_accessible_user_ids"),
+    migration!(5, "0005_file_listing_indexes"),
+    migration!(6, "0006_share_links"),
+    migration!(7, "0007_folders"),
+    migration!(8, "0008_session_expiry"),
+    migration!(9, "0009_hash_session_keys"),
+    migration!(10, "0010_access_tokens"),
+    migration!(11, "0011_two_factor"),
+    migration!(12, "0012_webauthn"),
+    migration!(13, "0013_oidc"),
+    migration!(14, "0014_email_verification"),
+    migration!(15, "0015_login_failures"),
+    migration!(16, "0016_multipart_uploads"),
+    migration!(17, "0017_tus_uploads"),
+    migration!(18, "0018_blobs"),
+    migration!(19, "0019_file_versions"),
+];
+
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: Option<&'static str>,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        hex::encode(Sha256::digest(self.up.as_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Checks that every applied migration is known to this binary and unchanged since it ran.
+pub fn verify(migrations: &[Migration], applied: &[AppliedMigration]) -> Result<(), Box<dyn Error>> {
+    for record in applied {
+        let Some(migration) = migrations.iter().find(|m| m.version == record.version) else {
+            return Err(format!(
+                "Database has migration {} ({}) which is unknown to this build",
+                record.version, record.name
+            ).into());
+        };
+
+        if migration.checksum() != record.checksum {
+            return Err(format!(
+                "Checksum mismatch for migration {} ({}): applied {}, embedded {}",
+                record.version, record.name, record.checksum, migration.checksum()
+            ).into());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn pending<'a>(migrations: &'a [Migration], applied: &[AppliedMigration], target: Option<i64>) -> Vec<&'a Migration> {
+    migrations
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .filter(|m| target.is_none_or(|t| m.version <= t))
+        .collect()
+}
+
+pub fn rollback_plan<'a>(migrations: &'a [Migration], applied: &[AppliedMigration], target: i64) -> Result<Vec<&'a Migration>, Box<dyn Error>> {
+    let mut plan: Vec<&Migration> = migrations
+        .iter()
+        .filter(|m| m.version > target && applied.iter().any(|a| a.version == m.version))
+        .collect();
+    plan.sort_by_key(|m| std::cmp::Reverse(m.version));
+
+    if let Some(migration) = plan.iter().find(|m| m.down.is_none()) {
+        return Err(format!("Migration {} ({}) cannot be reverted", migration.version, migration.name).into());
+    }
+
+    Ok(plan)
+}
+
+pub struct Migrator<'a> {
+    pool: &'a Pool<MySql>,
+    migrations: &'a [Migration],
+}
+
+impl<'a> Migrator<'a> {
+    pub fn new(pool: &'a Pool<MySql>, migrations: &'a [Migration]) -> Self {
+        Self { pool, migrations }
+    }
+
+    pub async fn run_command(&self, args: &[String]) -> Result<(), Box<dyn Error>> {
+        let parse_version = |arg: Option<&String>| -> Result<Option<i64>, Box<dyn Error>> {
+            match arg {
+                Some(value) => Ok(Some(value.parse::<i64>()?)),
+                None => Ok(None),
+            }
+        };
+
+        match args.first().map(String::as_str) {
+            None | Some("up") => {
+                self.up(parse_version(args.get(1))?).await?;
+            }
+            Some("down") => {
+                let target = parse_version(args.get(1))?.ok_or("Usage: migrate down <version>")?;
+                self.down(target).await?;
+            }
+            Some("baseline") => {
+                let version = parse_version(args.get(1))?.ok_or("Usage: migrate baseline <version>")?;
+                self.baseline(version).await?;
+            }
+            Some("status") => {
+                let applied = self.status().await?;
+                for migration in self.migrations {
+                    match applied.iter().find(|a| a.version == migration.version) {
+                        Some(record) => println!("✓ {} applied at {}", migration.name, record.applied_at),
+                        None => println!("· {} pending", migration.name),
+                    }
+                }
+            }
+            Some(other) => {
+                return Err(format!("Unknown migrate command '{}' (expected up, down, status or baseline)", other).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn status(&self) -> Result<Vec<AppliedMigration>, Box<dyn Error>> {
+        let mut conn = self.pool.acquire().await?;
+        Self::ensure_table(&mut conn).await?;
+        let applied = Self::applied(&mut conn).await?;
+        verify(self.migrations, &applied)?;
+        Ok(applied)
+    }
+
+    pub async fn up(&self, target: Option<i64>) -> Result<Vec<i64>, Box<dyn Error>> {
+        let mut conn = self.pool.acquire().await?;
+        Self::lock(&mut conn).await?;
+        let result = self.up_locked(&mut conn, target).await;
+        Self::unlock(&mut conn).await;
+        result
+    }
+
+    pub async fn down(&self, target: i64) -> Result<Vec<i64>, Box<dyn Error>> {
+        let mut conn = self.pool.acquire().await?;
+        Self::lock(&mut conn).await?;
+        let result = self.down_locked(&mut conn, target).await;
+        Self::unlock(&mut conn).await;
+        result
+    }
+
+    pub async fn baseline(&self, version: i64) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.pool.acquire().await?;
+        Self::lock(&mut conn).await?;
+        let result = self.baseline_locked(&mut conn, version).await;
+        Self::unlock(&mut conn).await;
+        result
+    }
+
+    async fn up_locked(&self, conn: &mut MySqlConnection, target: Option<i64>) -> Result<Vec<i64>, Box<dyn Error>> {
+        Self::ensure_table(conn).await?;
+        let mut applied = Self::applied(conn).await?;
+
+        if applied.is_empty() {
+            let legacy_version = Self::detect_legacy_version(conn).await?;
+            if legacy_version > 0 {
+                println!("✓ Existing schema detected, baselining at migration {}", legacy_version);
+                self.baseline_locked(conn, legacy_version).await?;
+                applied = Self::applied(conn).await?;
+            }
+        }
+
+        verify(self.migrations, &applied)?;
+
+        let mut ran = Vec::new();
+        for migration in pending(self.migrations, &applied, target) {
+            let started = Instant::now();
+            sqlx::raw_sql(migration.up).execute(&mut *conn).await
+                .map_err(|e| format!("Migration {} failed: {}", migration.name, e))?;
+
+            sqlx::query(
+                "INSERT INTO schema_migrations (version, name, checksum, applied_at, execution_ms) VALUES (?, ?, ?, ?, ?)"
+            )
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .bind(chrono::Utc::now())
+            .bind(started.elapsed().as_millis() as i64)
+            .execute(&mut *conn)
+            .await?;
+
+            println!("✓ Applied migration {}", migration.name);
+            ran.push(migration.version);
+        }
+
+        Ok(ran)
+    }
+
+    async fn down_locked(&self, conn: &mut MySqlConnection, target: i64) -> Result<Vec<i64>, Box<dyn Error>> {
+        Self::ensure_table(conn).await?;
+        let applied = Self::applied(conn).await?;
+        verify(self.migrations, &applied)?;
+
+        let mut reverted = Vec::new();
+        for migration in rollback_plan(self.migrations, &applied, target)? {
+            let down = migration.down.unwrap_or_default();
+            sqlx::raw_sql(down).execute(&mut *conn).await
+                .map_err(|e| format!("Reverting migration {} failed: {}", migration.name, e))?;
+
+            sqlx::query("DELETE FROM schema_migrations WHERE version = ?")
+                .bind(migration.version)
+                .execute(&mut *conn)
+                .await?;
+
+            println!("✓ Reverted migration {}", migration.name);
+            reverted.push(migration.version);
+        }
+
+        Ok(reverted)
+    }
+
+    async fn baseline_locked(&self, conn: &mut MySqlConnection, version: i64) -> Result<(), Box<dyn Error>> {
+        Self::ensure_table(conn).await?;
+
+        if !self.migrations.iter().any(|m| m.version == version) {
+            return Err(format!("Unknown migration version {}", version).into());
+        }
+
+        for migration in self.migrations.iter().filter(|m| m.version <= version) {
+            sqlx::query(
+                "INSERT IGNORE INTO schema_migrations (version, name, checksum, applied_at, execution_ms) VALUES (?, ?, ?, ?, 0)"
+            )
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .bind(chrono::Utc::now())
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        println!("✓ Baselined schema at migration {}", version);
+        Ok(())
+    }
+
+    async fn ensure_table(conn: &mut MySqlConnection) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                name VARCHAR(255) NOT NULL,
+                checksum CHAR(64) NOT NULL,
+                applied_at DATETIME NOT NULL,
+                execution_ms BIGINT NOT NULL
+            ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci"
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn applied(conn: &mut MySqlConnection) -> Result<Vec<AppliedMigration>, Box<dyn Error>> {
+        let applied = query_as::<_, AppliedMigration>(
+            "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version"
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(applied)
+    }
+
+    /// Maps a database created by the old `schema.sql` bootstrap to the migration it matches.
+    async fn detect_legacy_version(conn: &mut MySqlConnection) -> Result<i64, Box<dyn Error>> {
+        let (tables,): (i64,) = query_as(
+            "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name IN ('files', 'file_permissions')"
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let (upload_state,): (i64,) = query_as(
+            "SELECT COUNT(*) FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = 'files' AND column_name = 'upload_state'"
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(match (tables, upload_state) {
+            (2, _) => 3,
+            (1, 1) => 2,
+            (1, _) => 1,
+            _ => 0,
+        })
+    }
+
+    async fn lock(conn: &mut MySqlConnection) -> Result<(), Box<dyn Error>> {
+        let (acquired,): (Option<i64>,) = query_as("SELECT GET_LOCK(?, ?)")
+            .bind(LOCK_NAME)
+            .bind(LOCK_TIMEOUT_SECS)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        if acquired != Some(1) {
+            return Err(format!("Timed out waiting for migration lock after {}s", LOCK_TIMEOUT_SECS).into());
+        }
+
+        Ok(())
+    }
+
+    async fn unlock(conn: &mut MySqlConnection) {
+        if let Err(e) = sqlx::query("SELECT RELEASE_LOCK(?)").bind(LOCK_NAME).execute(&mut *conn).await {
+            eprintln!("✗ Failed to release migration lock: {}", e);
+        }
+    }
+}
@@ -1,5 +1,19 @@
+pub mod model;
+pub mod repository;
 pub mod mysql;
+pub mod migration;
+#[cfg(test)]
+pub mod memory;
 pub mod s3client;
+pub mod storage;
+pub mod local;
+pub mod blob;
 
-pub use mysql::{Session, MySQLClient};
-pub use s3client::S3Client;
\ No newline at end of file
+pub use model::Session;
+pub use repository::Repository;
+pub use mysql::MySQLClient;
+#[cfg(test)]
+pub use memory::{MemoryRepository, MemoryStorage};
+pub use s3client::S3Client;
+pub use storage::Storage;
+pub use local::LocalStorage;
\ No newline at end of file
@@ -0,0 +1,500 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::storage::{blob_key, STAGING_PREFIX};
+
+pub const UPLOAD_PENDING: &str = "pending";
+pub const UPLOAD_COMPLETE: &str = "complete";
+
+pub const ROLE_VIEWER: &str = "viewer";
+pub const ROLE_EDITOR: &str = "editor";
+pub const ROLE_CO_OWNER: &str = "co_owner";
+pub const ROLE_OWNER: &str = "owner";
+
+/// What a user may do with a file, from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    CoOwner,
+    Owner,
+}
+
+impl Role {
+    /// Parses a grantable role; ownership itself can never be granted.
+    pub fn parse(value: &str) -> Option<Role> {
+        match value {
+            ROLE_VIEWER => Some(Role::Viewer),
+            ROLE_EDITOR => Some(Role::Editor),
+            ROLE_CO_OWNER => Some(Role::CoOwner),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => ROLE_VIEWER,
+            Role::Editor => ROLE_EDITOR,
+            Role::CoOwner => ROLE_CO_OWNER,
+            Role::Owner => ROLE_OWNER,
+        }
+    }
+
+    pub fn can_edit(&self) -> bool {
+        *self >= Role::Editor
+    }
+
+    pub fn can_share(&self) -> bool {
+        *self >= Role::CoOwner
+    }
+
+    pub fn can_delete(&self) -> bool {
+        *self == Role::Owner
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    pub email_verified_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub password_hash: String,
+    pub icon_url: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct File {
+    pub id: String,
+    pub filename: String,
+    pub owner_id: String,
+    pub folder_id: Option<String>,
+    #[sqlx(json)]
+    pub accessible_user_ids: Value,
+    pub size_bytes: Option<i64>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub sha256: Option<String>,
+    pub upload_state: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Backend id of the multipart upload in progress for this file, if any.
+    pub multipart_upload_id: Option<String>,
+    pub multipart_started_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Content-addressed blob holding this file's bytes, once deduplicated; the blob of its newest version.
+    pub blob_sha256: Option<String>,
+}
+
+impl File {
+    /// Storage key of the file's contents; files that were never deduplicated keep theirs under the file id.
+    pub fn object_key(&self) -> String {
+        match &self.blob_sha256 {
+            Some(sha256) => blob_key(sha256),
+            None => self.id.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FilePermission {
+    pub file_id: String,
+    pub user_id: String,
+    pub role: String,
+    pub granted_at: chrono::DateTime<chrono::Utc>,
+    pub granted_by: Option<String>,
+}
+
+/// Folders form one tree per owner: subfolders always share the owner of their parent.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Folder {
+    pub id: String,
+    pub owner_id: String,
+    pub parent_id: Option<String>,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FolderPermission {
+    pub folder_id: String,
+    pub user_id: String,
+    pub role: String,
+    pub granted_at: chrono::DateTime<chrono::Utc>,
+    pub granted_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileGrant {
+    pub user_id: String,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ShareLink {
+    pub id: String,
+    pub file_id: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub max_downloads: Option<i64>,
+    pub download_count: i64,
+    pub created_by: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ShareLink {
+    pub fn is_expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.max_downloads.is_some_and(|max| self.download_count >= max)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    pub key_hash: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_accessed_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TotpEnrollment {
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Unset while the user has not yet confirmed the secret with a first code.
+    pub enabled_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Last time step a code was accepted for, so a code cannot be replayed.
+    pub last_used_step: Option<i64>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The password step of a two-factor login, exchanged for a session once the second factor checks out.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LoginChallenge {
+    pub id: String,
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub attempts: i32,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub const WEBAUTHN_REGISTER: &str = "register";
+pub const WEBAUTHN_LOGIN: &str = "login";
+
+/// A registered passkey. `credential_id` is base64url, `public_key` an uncompressed SEC1 P-256 point.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WebauthnCredential {
+    pub id: String,
+    pub user_id: String,
+    pub credential_id: String,
+    #[serde(skip_serializing)]
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A challenge handed to the browser for one registration or login ceremony.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WebauthnChallenge {
+    pub challenge: String,
+    /// Set for registrations and for logins that named an account; unset for discoverable logins.
+    pub user_id: Option<String>,
+    pub purpose: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A single-sign-on attempt between the redirect to the provider and its callback.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OidcLoginState {
+    #[serde(skip_serializing)]
+    pub state_hash: String,
+    #[serde(skip_serializing)]
+    pub nonce: String,
+    #[serde(skip_serializing)]
+    pub code_verifier: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Links a provider account (`issuer` + `subject`) to a local user.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub user_id: String,
+    pub email: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub const EMAIL_TOKEN_PASSWORD_RESET: &str = "password_reset";
+pub const EMAIL_TOKEN_VERIFY: &str = "verify_email";
+
+/// A single-use secret mailed to the user, stored as a digest like session keys.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EmailToken {
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub user_id: String,
+    pub purpose: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub const LOGIN_SCOPE_ACCOUNT: &str = "account";
+pub const LOGIN_SCOPE_IP: &str = "ip";
+
+/// Recent failed logins for one email address or one client address.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LoginFailure {
+    pub scope: String,
+    pub subject: String,
+    pub failures: i32,
+    pub last_failed_at: chrono::DateTime<chrono::Utc>,
+    pub blocked_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A tus upload still receiving bytes; its id becomes the file id once the last byte arrives.
+///
+/// Bytes are stored as parts of `part_size` in a backend multipart upload, so the first
+/// `upload_offset / part_size` parts are complete and the remainder waits in a staging object.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TusUpload {
+    pub id: String,
+    pub user_id: String,
+    pub folder_id: Option<String>,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    pub part_size: i64,
+    pub multipart_upload_id: String,
+    /// Held by the PATCH currently writing to the upload; expired leases may be taken over.
+    #[serde(skip_serializing)]
+    pub lock_token: Option<String>,
+    pub locked_until: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TusUpload {
+    /// Parts already filled to `part_size`.
+    pub fn completed_parts(&self) -> i32 {
+        (self.upload_offset / self.part_size) as i32
+    }
+
+    /// Bytes past the last completed part, held in the staging object.
+    pub fn tail_size(&self) -> i64 {
+        self.upload_offset % self.part_size
+    }
+
+    /// Staging key for the bytes that will open `part_number`.
+    pub fn tail_key(&self, part_number: i32) -> String {
+        format!("{}tus/{}/{}", STAGING_PREFIX, self.id, part_number)
+    }
+}
+
+pub const BLOB_PENDING: &str = "pending";
+pub const BLOB_LIVE: &str = "live";
+pub const BLOB_DELETING: &str = "deleting";
+
+/// Stored bytes shared by every file with the same SHA-256.
+///
+/// A blob is `pending` while its object is being written, `live` once files may point at it,
+/// and `deleting` after the last reference went away and its object is being removed.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Blob {
+    pub sha256: String,
+    pub size_bytes: i64,
+    pub etag: Option<String>,
+    pub state: String,
+    pub ref_count: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Blob {
+    pub fn key(&self) -> String {
+        blob_key(&self.sha256)
+    }
+}
+
+/// One stored revision of a file's contents; the highest number is the current one.
+///
+/// Versions hold the references that keep blobs alive, so old contents survive a new upload
+/// until the version is deleted or falls out of retention.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FileVersion {
+    pub file_id: String,
+    pub version_number: i32,
+    pub blob_sha256: String,
+    pub size_bytes: i64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub uploaded_by: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl FileVersion {
+    pub fn object_key(&self) -> String {
+        blob_key(&self.blob_sha256)
+    }
+}
+
+pub const SCOPE_FILES_READ: &str = "files:read";
+pub const SCOPE_FILES_WRITE: &str = "files:write";
+pub const SCOPE_SHARE_MANAGE: &str = "share:manage";
+
+/// What a personal access token may do; interactive sessions hold every scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    FilesRead,
+    FilesWrite,
+    ShareManage,
+}
+
+impl Scope {
+    pub fn parse(value: &str) -> Option<Scope> {
+        match value {
+            SCOPE_FILES_READ => Some(Scope::FilesRead),
+            SCOPE_FILES_WRITE => Some(Scope::FilesWrite),
+            SCOPE_SHARE_MANAGE => Some(Scope::ShareManage),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::FilesRead => SCOPE_FILES_READ,
+            Scope::FilesWrite => SCOPE_FILES_WRITE,
+            Scope::ShareManage => SCOPE_SHARE_MANAGE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AccessToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    /// Space-separated scope names, as in OAuth.
+    pub scopes: String,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl AccessToken {
+    pub fn scopes(&self) -> Vec<Scope> {
+        self.scopes.split_whitespace().filter_map(Scope::parse).collect()
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes().contains(&scope)
+    }
+
+    pub fn is_expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileScope {
+    All,
+    Owned,
+    Shared,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSort {
+    Name,
+    Date,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    Prefix,
+    Contains,
+}
+
+/// Position of the last row of a page: its sort key (filename, RFC 3339 timestamp or size) and id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileCursor {
+    pub key: String,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FolderFilter {
+    Root,
+    Folder(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FileListQuery {
+    pub scope: FileScope,
+    pub folder: Option<FolderFilter>,
+    pub sort: FileSort,
+    pub descending: bool,
+    pub search: Option<String>,
+    pub search_mode: SearchMode,
+    pub after: Option<FileCursor>,
+    pub limit: i64,
+}
+
+impl Default for FileListQuery {
+    fn default() -> Self {
+        Self {
+            scope: FileScope::All,
+            folder: None,
+            sort: FileSort::Date,
+            descending: true,
+            search: None,
+            search_mode: SearchMode::Contains,
+            after: None,
+            limit: 50,
+        }
+    }
+}
+
+/// Files removed with a folder tree, the blobs their versions referenced, and the other users'
+/// files that were moved out of the tree instead.
+#[derive(Debug, Clone, Default)]
+pub struct DeletedFiles {
+    pub files: Vec<File>,
+    pub blob_hashes: Vec<String>,
+    pub moved_file_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilePage {
+    pub files: Vec<File>,
+    pub total: i64,
+    pub next: Option<FileCursor>,
+}
+
+impl FileSort {
+    pub fn cursor_for(&self, file: &File) -> FileCursor {
+        let key = match self {
+            FileSort::Name => file.filename.clone(),
+            FileSort::Date => file.created_at.to_rfc3339(),
+            FileSort::Size => file.size_bytes.unwrap_or(0).to_string(),
+        };
+        FileCursor { key, id: file.id.clone() }
+    }
+}
@@ -1,39 +1,163 @@
 use std::env;
 use std::error::Error;
-use sqlx::{query_as, MySql, Pool};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
-pub struct User {
-    pub id: String,
-    pub email: String,
-    pub password_hash: String,
-    pub icon_url: Option<String>,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+use async_trait::async_trait;
+use sqlx::{query_as, MySql, Pool, QueryBuilder};
+use super::model::{
+    AccessToken, Blob, DeletedFiles, EmailToken, File, FileVersion, FileGrant, LoginChallenge, LoginFailure, OidcLoginState, TotpEnrollment, TusUpload, UserIdentity, WebauthnChallenge, WebauthnCredential, FileListQuery, FilePage, FilePermission, FileScope, FileSort, Folder, FolderFilter, FolderPermission,
+    Role, SearchMode, Session, ShareLink, User, BLOB_DELETING, BLOB_LIVE, BLOB_PENDING, ROLE_VIEWER, UPLOAD_COMPLETE, UPLOAD_PENDING,
+};
+use super::repository::Repository;
+use super::migration::{Migrator, MIGRATIONS};
+
+const FILE_COLUMNS: &str = "f.id, f.filename, f.owner_id, f.folder_id, \
+    (SELECT COALESCE(JSON_ARRAYAGG(p.user_id), JSON_ARRAY()) FROM file_permissions p WHERE p.file_id = f.id) AS accessible_user_ids, \
+    f.size_bytes, f.content_type, f.etag, f.sha256, f.upload_state, f.created_at, \
+    f.multipart_upload_id, f.multipart_started_at, f.blob_sha256";
+
+const FILE_VERSION_COLUMNS: &str = "file_id, version_number, blob_sha256, size_bytes, content_type, etag, uploaded_by, created_at";
+
+const BLOB_COLUMNS: &str = "sha256, size_bytes, etag, state, ref_count, created_at";
+
+const TUS_UPLOAD_COLUMNS: &str = "id, user_id, folder_id, filename, content_type, upload_length, upload_offset, part_size, \
+    multipart_upload_id, lock_token, locked_until, created_at";
+
+fn sort_column(sort: FileSort) -> &'static str {
+    match sort {
+        FileSort::Name => "f.filename",
+        FileSort::Date => "f.created_at",
+        FileSort::Size => "COALESCE(f.size_bytes, 0)",
+    }
+}
+
+fn push_sort_key(builder: &mut QueryBuilder<'_, MySql>, sort: FileSort, key: &str) -> Result<(), Box<dyn Error>> {
+    match sort {
+        FileSort::Name => builder.push_bind(key.to_string()),
+        FileSort::Date => builder.push_bind(chrono::DateTime::parse_from_rfc3339(key)?.with_timezone(&chrono::Utc)),
+        FileSort::Size => builder.push_bind(key.parse::<i64>()?),
+    };
+    Ok(())
+}
+
+/// Escapes LIKE wildcards so user input only ever matches literally.
+fn like_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Drops one reference per listed version from each blob, inside the caller's transaction.
+async fn release_blobs(tx: &mut sqlx::Transaction<'_, MySql>, hashes: impl IntoIterator<Item = &String>) -> Result<(), Box<dyn Error>> {
+    let mut counts = std::collections::BTreeMap::<&String, i64>::new();
+    for sha256 in hashes {
+        *counts.entry(sha256).or_default() += 1;
+    }
+    for (sha256, count) in counts {
+        sqlx::query("UPDATE blobs SET ref_count = ref_count - ? WHERE sha256 = ?")
+            .bind(count)
+            .bind(sha256)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Descendants of a folder (itself included) with their distance from it.
+const FOLDER_TREE_CTE: &str = "WITH RECURSIVE tree (id, depth) AS ( \
+    SELECT id, 0 FROM folders WHERE id = ? \
+    UNION ALL SELECT c.id, t.depth + 1 FROM folders c JOIN tree t ON c.parent_id = t.id) ";
+
+/// Ancestors of a folder (itself included) with their distance from it.
+const FOLDER_PATH_CTE: &str = "WITH RECURSIVE path (id, owner_id, parent_id, name, created_at, depth) AS ( \
+    SELECT id, owner_id, parent_id, name, created_at, 0 FROM folders WHERE id = ? \
+    UNION ALL SELECT p.id, p.owner_id, p.parent_id, p.name, p.created_at, path.depth + 1 \
+    FROM folders p JOIN path ON p.id = path.parent_id) ";
+
+const FOLDER_COLUMNS: &str = "id, owner_id, parent_id, name, created_at";
+
+/// Builds `select` over the files a user can see, with the filters shared by the page query and its total count.
+fn file_list_query(select: &str, user_id: &str, query: &FileListQuery) -> QueryBuilder<'static, MySql> {
+    // Every folder the user can reach: their own tree plus anything below a folder shared with them.
+    let mut builder = QueryBuilder::<MySql>::new("WITH RECURSIVE accessible_folders (id) AS (SELECT id FROM folders WHERE owner_id = ");
+    builder.push_bind(user_id.to_string());
+    builder.push(" UNION SELECT folder_id FROM folder_permissions WHERE user_id = ");
+    builder.push_bind(user_id.to_string());
+    builder.push(" UNION SELECT c.id FROM folders c JOIN accessible_folders a ON c.parent_id = a.id) ");
+
+    builder.push(select);
+    builder.push(" FROM files f LEFT JOIN file_permissions fp ON fp.file_id = f.id AND fp.user_id = ");
+    builder.push_bind(user_id.to_string());
+    builder.push(" WHERE f.upload_state = ");
+    builder.push_bind(UPLOAD_COMPLETE);
+
+    let shared = "(fp.user_id IS NOT NULL OR f.folder_id IN (SELECT id FROM accessible_folders))";
+    match query.scope {
+        FileScope::All => {
+            builder.push(" AND (f.owner_id = ");
+            builder.push_bind(user_id.to_string());
+            builder.push(format!(" OR {})", shared));
+        }
+        FileScope::Owned => {
+            builder.push(" AND f.owner_id = ");
+            builder.push_bind(user_id.to_string());
+        }
+        FileScope::Shared => {
+            builder.push(format!(" AND {} AND f.owner_id <> ", shared));
+            builder.push_bind(user_id.to_string());
+        }
+    }
+
+    match &query.folder {
+        Some(FolderFilter::Root) => {
+            builder.push(" AND f.folder_id IS NULL");
+        }
+        Some(FolderFilter::Folder(folder_id)) => {
+            builder.push(" AND f.folder_id = ");
+            builder.push_bind(folder_id.clone());
+        }
+        None => {}
+    }
+
+    if let Some(search) = query.search.as_deref().filter(|s| !s.is_empty()) {
+        let pattern = match query.search_mode {
+            SearchMode::Prefix => format!("{}%", like_escape(search)),
+            SearchMode::Contains => format!("%{}%", like_escape(search)),
+        };
+        builder.push(" AND f.filename LIKE ");
+        builder.push_bind(pattern);
+    }
+
+    builder
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
-pub struct File {
-    pub id: String,
-    pub filename: String,
-    pub owner_id: String,
-    #[sqlx(json)]
-    pub accessible_user_ids: Value,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+/// Highest role among a comma-separated list of grants.
+fn strongest_role(roles: Option<&str>) -> Option<Role> {
+    roles?.split(',').map(|role| Role::parse(role).unwrap_or(Role::Viewer)).max()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
-pub struct Session {
-    pub id: String,
-    pub user_id: String,
-    pub session_key: String,
-    pub ip_address: Option<String>,
-    pub user_agent: Option<String>,
-    pub created_at: chrono::DateTime<chrono::Utc>,
-    pub last_accessed_at: chrono::DateTime<chrono::Utc>,
+async fn delete_folders_deepest_first(
+    tx: &mut sqlx::Transaction<'_, MySql>,
+    mut tree: Vec<(String, i64)>,
+) -> Result<(), Box<dyn Error>> {
+    tree.sort_by_key(|(_, depth)| std::cmp::Reverse(*depth));
+
+    for level in tree.chunk_by(|a, b| a.1 == b.1) {
+        let mut builder = QueryBuilder::<MySql>::new("DELETE FROM folders WHERE id IN (");
+        let mut separated = builder.separated(", ");
+        for (folder_id, _) in level {
+            separated.push_bind(folder_id.clone());
+        }
+        separated.push_unseparated(")");
+        builder.build().execute(&mut **tx).await?;
+    }
+
+    Ok(())
 }
 
+const SHARE_LINK_COLUMNS: &str = "id, file_id, token_hash, password_hash, expires_at, max_downloads, download_count, created_by, created_at, revoked_at";
+
+const WEBAUTHN_CREDENTIAL_COLUMNS: &str = "id, user_id, credential_id, public_key, sign_count, name, created_at, last_used_at";
+const LOGIN_CHALLENGE_COLUMNS: &str = "id, user_id, token_hash, ip_address, user_agent, attempts, expires_at, created_at";
+
+const ACCESS_TOKEN_COLUMNS: &str = "id, user_id, name, token_hash, scopes, expires_at, last_used_at, created_at";
+
 pub struct MySQLClient {
     pool: Pool<MySql>,
 }
@@ -45,24 +169,14 @@ impl MySQLClient {
         Ok(Self { pool })
     }
 
-    pub async fn init_database(&self, filename: &str) -> Result<(), Box<dyn Error>> {
-        let schema = std::fs::read_to_string(filename)?;
-        
-        for statement in schema.split(';').filter(|s| !s.trim().is_empty()) {
-            match sqlx::raw_sql(statement).execute(&self.pool).await {
-                Ok(_) => println!("✓ Executed: {}", statement.trim().lines().next().unwrap_or("")),
-                Err(e) => {
-                    if !e.to_string().contains("database exists") && !e.to_string().contains("already exists") {
-                        return Err(Box::new(e));
-                    }
-                }
-            }
-        }
-        
-        Ok(())
+    pub fn migrator(&self) -> Migrator<'_> {
+        Migrator::new(&self.pool, MIGRATIONS)
     }
+}
 
-    pub async fn create_user(
+#[async_trait]
+impl Repository for MySQLClient {
+    async fn create_user(
         &self,
         id: &str,
         email: &str,
@@ -81,7 +195,7 @@ impl MySQLClient {
         .await?;
 
         let user = query_as::<_, User>(
-            "SELECT id, email, password_hash, icon_url, created_at FROM users WHERE id = ?"
+            "SELECT id, email, email_verified_at, password_hash, icon_url, created_at FROM users WHERE id = ?"
         )
         .bind(id)
         .fetch_one(&self.pool)
@@ -91,9 +205,9 @@ impl MySQLClient {
         Ok(user)
     }
 
-    pub async fn get_user(&self, id: &str) -> Result<Option<User>, Box<dyn Error>> {
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Box<dyn Error>> {
         let user = query_as::<_, User>(
-            "SELECT id, email, password_hash, icon_url, created_at FROM users WHERE id = ?"
+            "SELECT id, email, email_verified_at, password_hash, icon_url, created_at FROM users WHERE id = ?"
         )
         .bind(id)
         .fetch_optional(&self.pool)
@@ -102,9 +216,9 @@ impl MySQLClient {
         Ok(user)
     }
 
-    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Box<dyn Error>> {
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Box<dyn Error>> {
         let user = query_as::<_, User>(
-            "SELECT id, email, password_hash, icon_url, created_at FROM users WHERE email = ?"
+            "SELECT id, email, email_verified_at, password_hash, icon_url, created_at FROM users WHERE email = ?"
         )
         .bind(email)
         .fetch_optional(&self.pool)
@@ -113,7 +227,7 @@ impl MySQLClient {
         Ok(user)
     }
 
-    pub async fn update_user_icon(&self, id: &str, icon_url: &str) -> Result<(), Box<dyn Error>> {
+    async fn update_user_icon(&self, id: &str, icon_url: &str) -> Result<(), Box<dyn Error>> {
         sqlx::query("UPDATE users SET icon_url = ? WHERE id = ?")
             .bind(icon_url)
             .bind(id)
@@ -123,8 +237,19 @@ impl MySQLClient {
         Ok(())
     }
 
-    pub async fn delete_user(&self, id: &str) -> Result<(), Box<dyn Error>> {
-        sqlx::query("DELETE FROM users WHERE id = ?")
+    async fn update_user_password(&self, id: &str, password_hash: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+            .bind(password_hash)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, id: &str, verified_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE users SET email_verified_at = COALESCE(email_verified_at, ?) WHERE id = ?")
+            .bind(verified_at)
             .bind(id)
             .execute(&self.pool)
             .await?;
@@ -132,254 +257,1718 @@ impl MySQLClient {
         Ok(())
     }
 
-    pub async fn create_file(
+    async fn delete_user(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        // Folders nest through a plain foreign key, so they have to go bottom-up before the cascade from users.
+        let roots: Vec<(String,)> = query_as("SELECT id FROM folders WHERE owner_id = ? AND parent_id IS NULL")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+        for (root_id,) in roots {
+            let tree: Vec<(String, i64)> = query_as(&format!("{} SELECT id, depth FROM tree", FOLDER_TREE_CTE))
+                .bind(&root_id)
+                .fetch_all(&mut *tx)
+                .await?;
+            delete_folders_deepest_first(&mut tx, tree).await?;
+        }
+
+        // Files and their versions go with the cascade too, but blob references have to be dropped by hand.
+        let hashes: Vec<(String,)> = query_as(
+            "SELECT v.blob_sha256 FROM files f JOIN file_versions v ON v.file_id = f.id WHERE f.owner_id = ? FOR UPDATE"
+        )
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+        release_blobs(&mut tx, hashes.iter().map(|(sha256,)| sha256)).await?;
+
+        sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn create_file(
         &self,
         id: &str,
         filename: &str,
         owner_id: &str,
-        accessible_user_ids: &str,
+        folder_id: Option<&str>,
     ) -> Result<File, Box<dyn Error>> {
         let now = chrono::Utc::now();
         
         sqlx::query(
-            "INSERT INTO files (id, filename, owner_id, accessible_user_ids, created_at) VALUES (?, ?, ?, CAST(? AS JSON), ?)"
+            "INSERT INTO files (id, filename, owner_id, folder_id, upload_state, created_at) VALUES (?, ?, ?, ?, ?, ?)"
         )
         .bind(id)
         .bind(filename)
         .bind(owner_id)
-        .bind(accessible_user_ids)
+        .bind(folder_id)
+        .bind(UPLOAD_PENDING)
         .bind(now)
         .execute(&self.pool)
         .await?;
 
-        let file = query_as::<_, File>(
-            "SELECT id, filename, owner_id, accessible_user_ids, created_at FROM files WHERE id = ?"
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
+        let file = query_as::<_, File>(&format!("SELECT {} FROM files f WHERE f.id = ?", FILE_COLUMNS))
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
 
         println!("✓ File created: {} ({}) by {}", id, filename, owner_id);
         Ok(file)
     }
 
-    pub async fn get_file(&self, id: &str) -> Result<Option<File>, Box<dyn Error>> {
-        let file = query_as::<_, File>(
-            "SELECT id, filename, owner_id, accessible_user_ids, created_at FROM files WHERE id = ?"
+    async fn get_file(&self, id: &str) -> Result<Option<File>, Box<dyn Error>> {
+        let file = query_as::<_, File>(&format!("SELECT {} FROM files f WHERE f.id = ?", FILE_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(file)
+    }
+
+    async fn set_file_permissions(
+        &self,
+        file_id: &str,
+        grants: &[FileGrant],
+        granted_by: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let now = chrono::Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let mut builder = QueryBuilder::<MySql>::new("DELETE FROM file_permissions WHERE file_id = ");
+        builder.push_bind(file_id);
+        if !grants.is_empty() {
+            builder.push(" AND user_id NOT IN (");
+            let mut separated = builder.separated(", ");
+            for grant in grants {
+                separated.push_bind(&grant.user_id);
+            }
+            separated.push_unseparated(")");
+        }
+        builder.build().execute(&mut *tx).await?;
+
+        for grant in grants {
+            sqlx::query(
+                "INSERT INTO file_permissions (file_id, user_id, role, granted_at, granted_by) VALUES (?, ?, ?, ?, ?) \
+                 ON DUPLICATE KEY UPDATE role = VALUES(role)"
+            )
+            .bind(file_id)
+            .bind(&grant.user_id)
+            .bind(&grant.role)
+            .bind(now)
+            .bind(granted_by)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_file_permissions(&self, file_id: &str) -> Result<Vec<FilePermission>, Box<dyn Error>> {
+        let permissions = query_as::<_, FilePermission>(
+            "SELECT file_id, user_id, role, granted_at, granted_by FROM file_permissions WHERE file_id = ? ORDER BY granted_at, user_id"
         )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(file_id)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(file)
+        Ok(permissions)
     }
 
-    pub async fn update_file_access(
+    async fn complete_file_upload(
         &self,
         id: &str,
-        accessible_user_ids: &str,
+        size_bytes: i64,
+        content_type: Option<&str>,
+        etag: Option<&str>,
+        sha256: Option<&str>,
     ) -> Result<(), Box<dyn Error>> {
-        sqlx::query("UPDATE files SET accessible_user_ids = CAST(? AS JSON) WHERE id = ?")
-            .bind(accessible_user_ids)
+        sqlx::query(
+            "UPDATE files SET size_bytes = ?, content_type = ?, etag = ?, sha256 = ?, upload_state = ?, blob_sha256 = NULL WHERE id = ?"
+        )
+        .bind(size_bytes)
+        .bind(content_type)
+        .bind(etag)
+        .bind(sha256)
+        .bind(UPLOAD_COMPLETE)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        println!("✓ File upload completed: {} ({} bytes)", id, size_bytes);
+        Ok(())
+    }
+
+    async fn rename_file(&self, id: &str, filename: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE files SET filename = ? WHERE id = ?")
+            .bind(filename)
             .bind(id)
             .execute(&self.pool)
             .await?;
 
+        println!("✓ File {} renamed", id);
         Ok(())
     }
 
-    pub async fn delete_file(&self, id: &str) -> Result<(), Box<dyn Error>> {
-        sqlx::query("DELETE FROM files WHERE id = ?")
+    async fn move_file(&self, id: &str, folder_id: Option<&str>) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE files SET folder_id = ? WHERE id = ?")
+            .bind(folder_id)
             .bind(id)
             .execute(&self.pool)
             .await?;
 
+        println!("✓ File {} moved", id);
         Ok(())
     }
 
-    pub async fn list_user_files(&self, user_id: &str) -> Result<Vec<File>, Box<dyn Error>> {
-        let files = query_as::<_, File>(
-            "SELECT id, filename, owner_id, accessible_user_ids, created_at FROM files"
-        )
+    async fn delete_file(&self, id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("SELECT id FROM files WHERE id = ? FOR UPDATE")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let hashes: Vec<(String,)> = query_as("SELECT blob_sha256 FROM file_versions WHERE file_id = ? FOR UPDATE")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM files WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        let hashes: Vec<String> = hashes.into_iter().map(|(sha256,)| sha256).collect();
+        release_blobs(&mut tx, &hashes).await?;
+        tx.commit().await?;
+
+        Ok(hashes)
+    }
+
+    async fn list_user_files(&self, user_id: &str, query: &FileListQuery) -> Result<FilePage, Box<dyn Error>> {
+        let mut count = file_list_query("SELECT COUNT(*)", user_id, query);
+        let (total,): (i64,) = count.build_query_as().fetch_one(&self.pool).await?;
+
+        let mut builder = file_list_query(&format!("SELECT {}", FILE_COLUMNS), user_id, query);
+
+        let sort_column = sort_column(query.sort);
+        if let Some(after) = &query.after {
+            let op = if query.descending { "<" } else { ">" };
+            builder.push(format!(" AND ({} {} ", sort_column, op));
+            push_sort_key(&mut builder, query.sort, &after.key)?;
+            builder.push(format!(" OR ({} = ", sort_column));
+            push_sort_key(&mut builder, query.sort, &after.key)?;
+            builder.push(format!(" AND f.id {} ", op));
+            builder.push_bind(after.id.clone());
+            builder.push("))");
+        }
+
+        let direction = if query.descending { "DESC" } else { "ASC" };
+        builder.push(format!(" ORDER BY {} {}, f.id {} LIMIT ", sort_column, direction, direction));
+        builder.push_bind(query.limit + 1);
+
+        let mut files = builder.build_query_as::<File>().fetch_all(&self.pool).await?;
+
+        let next = if files.len() as i64 > query.limit {
+            files.truncate(query.limit as usize);
+            files.last().map(|f| query.sort.cursor_for(f))
+        } else {
+            None
+        };
+
+        println!("✓ Listed {} of {} files for user {}", files.len(), total, user_id);
+        Ok(FilePage { files, total, next })
+    }
+
+    async fn list_stale_pending_files(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<File>, Box<dyn Error>> {
+        let files = query_as::<_, File>(&format!(
+            "SELECT {} FROM files f WHERE f.upload_state = ? AND f.created_at < ? AND f.multipart_upload_id IS NULL \
+             ORDER BY f.created_at LIMIT ?",
+            FILE_COLUMNS
+        ))
+        .bind(UPLOAD_PENDING)
+        .bind(created_before)
+        .bind(limit)
         .fetch_all(&self.pool)
         .await?;
 
-        let user_files: Vec<File> = files
-            .into_iter()
-            .filter(|f| {
-                f.owner_id == user_id || 
-                if let Ok(user_ids) = serde_json::from_value::<Vec<String>>(f.accessible_user_ids.clone()) {
-                    user_ids.contains(&user_id.to_string())
-                } else {
-                    false
-                }
-            })
-            .collect();
+        Ok(files)
+    }
+
+    async fn start_multipart_upload(&self, id: &str, upload_id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE files SET multipart_upload_id = ?, multipart_started_at = ? WHERE id = ? AND multipart_upload_id IS NULL"
+        )
+        .bind(upload_id)
+        .bind(now)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
 
-        println!("✓ Listed {} files for user {}", user_files.len(), user_id);
-        Ok(user_files)
+        Ok(result.rows_affected() == 1)
     }
 
-    pub async fn add_user_to_file(&self, file_id: &str, user_id: &str) -> Result<(), Box<dyn Error>> {
-        let file = self.get_file(file_id).await?;
-        if file.is_none() {
-            return Err("File not found".into());
-        }
-        let file = file.unwrap();
-        
-        let mut user_ids: Vec<String> = serde_json::from_value(file.accessible_user_ids)
-            .unwrap_or_default();
-        
-        if !user_ids.contains(&user_id.to_string()) {
-            user_ids.push(user_id.to_string());
-        }
-        
-        let updated_ids = serde_json::to_string(&user_ids)?;
-        self.update_file_access(file_id, &updated_ids).await?;
-        
-        Ok(())
+    async fn finish_multipart_upload(&self, id: &str, upload_id: &str) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE files SET multipart_upload_id = NULL, multipart_started_at = NULL WHERE id = ? AND multipart_upload_id = ?"
+        )
+        .bind(id)
+        .bind(upload_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
     }
 
-    pub async fn remove_user_from_file(&self, file_id: &str, user_id: &str) -> Result<(), Box<dyn Error>> {
-        let file = self.get_file(file_id).await?;
-        if file.is_none() {
-            return Err("File not found".into());
-        }
-        let file = file.unwrap();
-        
-        let mut user_ids: Vec<String> = serde_json::from_value(file.accessible_user_ids)
-            .unwrap_or_default();
-        
-        user_ids.retain(|id| id != user_id);
-        
-        let updated_ids = serde_json::to_string(&user_ids)?;
-        self.update_file_access(file_id, &updated_ids).await?;
-        
-        Ok(())
+    async fn list_stale_multipart_uploads(&self, started_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<File>, Box<dyn Error>> {
+        let files = query_as::<_, File>(&format!(
+            "SELECT {} FROM files f WHERE f.multipart_started_at < ? ORDER BY f.multipart_started_at LIMIT ?",
+            FILE_COLUMNS
+        ))
+        .bind(started_before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
     }
 
-    pub async fn check_user_can_access(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>> {
-        let file = self.get_file(file_id).await?;
-        if file.is_none() {
-            return Err("File not found".into());
+    async fn existing_file_ids(&self, ids: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
         }
-        let file = file.unwrap();
-        
-        if file.owner_id == user_id {
-            return Ok(true);
+
+        let mut builder = QueryBuilder::<MySql>::new("SELECT id FROM files WHERE id IN (");
+        let mut separated = builder.separated(", ");
+        for id in ids {
+            separated.push_bind(id);
         }
-        
-        let user_ids: Vec<String> = serde_json::from_value(file.accessible_user_ids)
-            .unwrap_or_default();
-        
-        Ok(user_ids.contains(&user_id.to_string()))
+        separated.push_unseparated(")");
+
+        let existing: Vec<(String,)> = builder
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(existing.into_iter().map(|(id,)| id).collect())
     }
 
-    pub async fn check_user_is_owner(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>> {
-        let file = self.get_file(file_id).await?;
-        if file.is_none() {
+    async fn add_user_to_file(&self, file_id: &str, user_id: &str, granted_by: &str) -> Result<(), Box<dyn Error>> {
+        if self.get_file(file_id).await?.is_none() {
             return Err("File not found".into());
         }
-        let file = file.unwrap();
-        
-        Ok(file.owner_id == user_id)
-    }
 
-    pub async fn create_session(
-        &self,
-        id: &str,
-        user_id: &str,
-        session_key: &str,
-        ip_address: Option<&str>,
-        user_agent: Option<&str>,
-    ) -> Result<Session, Box<dyn Error>> {
-        let now = chrono::Utc::now();
-        
         sqlx::query(
-            "INSERT INTO sessions (id, user_id, session_key, ip_address, user_agent, created_at, last_accessed_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+            "INSERT IGNORE INTO file_permissions (file_id, user_id, role, granted_at, granted_by) VALUES (?, ?, ?, ?, ?)"
         )
-        .bind(id)
+        .bind(file_id)
         .bind(user_id)
-        .bind(session_key)
-        .bind(ip_address)
-        .bind(user_agent)
-        .bind(now)
-        .bind(now)
+        .bind(ROLE_VIEWER)
+        .bind(chrono::Utc::now())
+        .bind(granted_by)
         .execute(&self.pool)
         .await?;
 
-        let session = query_as::<_, Session>(
-            "SELECT id, user_id, session_key, ip_address, user_agent, created_at, last_accessed_at FROM sessions WHERE id = ?"
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
+        Ok(())
+    }
 
-        println!("✓ Session created for user: {}", user_id);
-        Ok(session)
+    async fn remove_user_from_file(&self, file_id: &str, user_id: &str) -> Result<(), Box<dyn Error>> {
+        if self.get_file(file_id).await?.is_none() {
+            return Err("File not found".into());
+        }
+
+        sqlx::query("DELETE FROM file_permissions WHERE file_id = ? AND user_id = ?")
+            .bind(file_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
     }
 
-    pub async fn get_session(&self, session_key: &str) -> Result<Option<Session>, Box<dyn Error>> {
-        let session = query_as::<_, Session>(
-            "SELECT id, user_id, session_key, ip_address, user_agent, created_at, last_accessed_at FROM sessions WHERE session_key = ?"
+    async fn check_user_can_access(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        let row: Option<(String, Option<String>)> = query_as(
+            "SELECT f.owner_id, fp.user_id FROM files f \
+             LEFT JOIN file_permissions fp ON fp.file_id = f.id AND fp.user_id = ? \
+             WHERE f.id = ?"
         )
-        .bind(session_key)
+        .bind(user_id)
+        .bind(file_id)
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(session)
+        match row {
+            Some((owner_id, grantee)) => Ok(owner_id == user_id || grantee.is_some()),
+            None => Err("File not found".into()),
+        }
     }
 
-    pub async fn verify_session(&self, session_key: &str, ip_address: &str) -> Result<Option<Session>, Box<dyn Error>> {
-        if let Some(session) = self.get_session(session_key).await? {
-            if let Some(stored_ip) = &session.ip_address {
-                if stored_ip == ip_address {
-                    return Ok(Some(session.clone()));
-                }
-            }
+    async fn check_user_is_owner(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>> {
+        let owner: Option<(String,)> = query_as("SELECT owner_id FROM files WHERE id = ?")
+            .bind(file_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match owner {
+            Some((owner_id,)) => Ok(owner_id == user_id),
+            None => Err("File not found".into()),
         }
-        
-        Ok(None)
     }
 
-    pub async fn update_session_access_time(&self, session_key: &str) -> Result<(), Box<dyn Error>> {
-        let now = chrono::Utc::now();
-        
-        sqlx::query("UPDATE sessions SET last_accessed_at = ? WHERE session_key = ?")
-            .bind(now)
-            .bind(session_key)
+    async fn get_file_role(&self, file_id: &str, user_id: &str) -> Result<Option<Role>, Box<dyn Error>> {
+        let row: Option<(String, Option<String>, Option<String>, Option<String>)> = query_as(
+            "WITH RECURSIVE ancestors (id, parent_id) AS ( \
+                SELECT fo.id, fo.parent_id FROM folders fo JOIN files f ON f.folder_id = fo.id WHERE f.id = ? \
+                UNION ALL SELECT p.id, p.parent_id FROM folders p JOIN ancestors a ON p.id = a.parent_id) \
+             SELECT f.owner_id, fp.role, \
+                (SELECT owner_id FROM folders WHERE id = f.folder_id), \
+                (SELECT GROUP_CONCAT(p.role) FROM folder_permissions p \
+                 WHERE p.user_id = ? AND p.folder_id IN (SELECT id FROM ancestors)) \
+             FROM files f LEFT JOIN file_permissions fp ON fp.file_id = f.id AND fp.user_id = ? \
+             WHERE f.id = ?"
+        )
+        .bind(file_id)
+        .bind(user_id)
+        .bind(user_id)
+        .bind(file_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match row {
+            None => None,
+            Some((owner_id, _, _, _)) if owner_id == user_id => Some(Role::Owner),
+            Some((_, file_role, folder_owner, folder_roles)) => {
+                let file_role = file_role.map(|role| Role::parse(&role).unwrap_or(Role::Viewer));
+                // Owning the folder is worth the strongest grant on other users' files in it, not ownership.
+                let folder_owner = (folder_owner.as_deref() == Some(user_id)).then_some(Role::CoOwner);
+                file_role.max(strongest_role(folder_roles.as_deref())).max(folder_owner)
+            }
+        })
+    }
+
+    async fn create_folder(&self, id: &str, owner_id: &str, parent_id: Option<&str>, name: &str) -> Result<Folder, Box<dyn Error>> {
+        sqlx::query("INSERT INTO folders (id, owner_id, parent_id, name, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(id)
+            .bind(owner_id)
+            .bind(parent_id)
+            .bind(name)
+            .bind(chrono::Utc::now())
             .execute(&self.pool)
             .await?;
 
-        Ok(())
+        let folder = query_as::<_, Folder>(&format!("SELECT {} FROM folders WHERE id = ?", FOLDER_COLUMNS))
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        println!("✓ Folder created: {} ({}) by {}", id, name, owner_id);
+        Ok(folder)
     }
 
-    pub async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, Box<dyn Error>> {
-        let sessions = query_as::<_, Session>(
-            "SELECT id, user_id, session_key, ip_address, user_agent, created_at, last_accessed_at FROM sessions WHERE user_id = ?"
+    async fn get_folder(&self, id: &str) -> Result<Option<Folder>, Box<dyn Error>> {
+        let folder = query_as::<_, Folder>(&format!("SELECT {} FROM folders WHERE id = ?", FOLDER_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(folder)
+    }
+
+    async fn find_folder(&self, owner_id: &str, parent_id: Option<&str>, name: &str) -> Result<Option<Folder>, Box<dyn Error>> {
+        let folder = query_as::<_, Folder>(&format!(
+            "SELECT {} FROM folders WHERE owner_id = ? AND parent_key = ? AND name = ?",
+            FOLDER_COLUMNS
+        ))
+        .bind(owner_id)
+        .bind(parent_id.unwrap_or(""))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(folder)
+    }
+
+    async fn list_child_folders(&self, parent_id: &str) -> Result<Vec<Folder>, Box<dyn Error>> {
+        let folders = query_as::<_, Folder>(&format!(
+            "SELECT {} FROM folders WHERE parent_id = ? ORDER BY name, id",
+            FOLDER_COLUMNS
+        ))
+        .bind(parent_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(folders)
+    }
+
+    async fn list_root_folders(&self, user_id: &str) -> Result<Vec<Folder>, Box<dyn Error>> {
+        let folders = query_as::<_, Folder>(
+            "SELECT fo.id, fo.owner_id, fo.parent_id, fo.name, fo.created_at FROM folders fo \
+             WHERE fo.owner_id = ? AND fo.parent_id IS NULL \
+             UNION \
+             SELECT fo.id, fo.owner_id, fo.parent_id, fo.name, fo.created_at FROM folders fo \
+             JOIN folder_permissions p ON p.folder_id = fo.id \
+             WHERE p.user_id = ? AND fo.owner_id <> ? \
+             ORDER BY name, id"
         )
         .bind(user_id)
+        .bind(user_id)
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(sessions)
+        Ok(folders)
     }
 
-    pub async fn delete_session(&self, session_key: &str) -> Result<(), Box<dyn Error>> {
-        sqlx::query("DELETE FROM sessions WHERE session_key = ?")
-            .bind(session_key)
+    async fn rename_folder(&self, id: &str, name: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE folders SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(id)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
 
-    pub async fn delete_user_sessions(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
-        sqlx::query("DELETE FROM sessions WHERE user_id = ?")
-            .bind(user_id)
+    async fn move_folder(&self, id: &str, parent_id: Option<&str>) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE folders SET parent_id = ? WHERE id = ?")
+            .bind(parent_id)
+            .bind(id)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn folder_path(&self, id: &str) -> Result<Vec<Folder>, Box<dyn Error>> {
+        let path = query_as::<_, Folder>(&format!(
+            "{} SELECT {} FROM path ORDER BY depth DESC",
+            FOLDER_PATH_CTE, FOLDER_COLUMNS
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(path)
+    }
+
+    async fn delete_folder_tree(&self, id: &str, owner_id: &str) -> Result<DeletedFiles, Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        let tree: Vec<(String, i64)> = query_as(&format!("{} SELECT id, depth FROM tree", FOLDER_TREE_CTE))
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        let moved: Vec<(String,)> = query_as(&format!(
+            "{} SELECT f.id FROM files f JOIN tree t ON f.folder_id = t.id WHERE f.owner_id <> ? FOR UPDATE",
+            FOLDER_TREE_CTE
+        ))
+        .bind(id)
+        .bind(owner_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        sqlx::query(&format!(
+            "{} UPDATE files f JOIN tree t ON f.folder_id = t.id SET f.folder_id = NULL WHERE f.owner_id <> ?",
+            FOLDER_TREE_CTE
+        ))
+        .bind(id)
+        .bind(owner_id)
+        .execute(&mut *tx)
+        .await?;
+
+        let files = query_as::<_, File>(&format!(
+            "{} SELECT {} FROM files f JOIN tree t ON f.folder_id = t.id WHERE f.owner_id = ? FOR UPDATE",
+            FOLDER_TREE_CTE, FILE_COLUMNS
+        ))
+        .bind(id)
+        .bind(owner_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let hashes: Vec<(String,)> = query_as(&format!(
+            "{} SELECT v.blob_sha256 FROM files f JOIN tree t ON f.folder_id = t.id JOIN file_versions v ON v.file_id = f.id \
+             WHERE f.owner_id = ? FOR UPDATE",
+            FOLDER_TREE_CTE
+        ))
+        .bind(id)
+        .bind(owner_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for chunk in files.chunks(500) {
+            let mut builder = QueryBuilder::<MySql>::new("DELETE FROM files WHERE id IN (");
+            let mut separated = builder.separated(", ");
+            for file in chunk {
+                separated.push_bind(&file.id);
+            }
+            separated.push_unseparated(")");
+            builder.build().execute(&mut *tx).await?;
+        }
+
+        let blob_hashes: Vec<String> = hashes.into_iter().map(|(sha256,)| sha256).collect();
+        release_blobs(&mut tx, &blob_hashes).await?;
+        delete_folders_deepest_first(&mut tx, tree).await?;
+        tx.commit().await?;
+
+        println!("✓ Folder {} deleted with {} files, {} moved to their owners", id, files.len(), moved.len());
+        let moved_file_ids = moved.into_iter().map(|(file_id,)| file_id).collect();
+        Ok(DeletedFiles { files, blob_hashes, moved_file_ids })
+    }
+
+    async fn set_folder_permissions(&self, folder_id: &str, grants: &[FileGrant], granted_by: &str) -> Result<(), Box<dyn Error>> {
+        let now = chrono::Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let mut builder = QueryBuilder::<MySql>::new("DELETE FROM folder_permissions WHERE folder_id = ");
+        builder.push_bind(folder_id);
+        if !grants.is_empty() {
+            builder.push(" AND user_id NOT IN (");
+            let mut separated = builder.separated(", ");
+            for grant in grants {
+                separated.push_bind(&grant.user_id);
+            }
+            separated.push_unseparated(")");
+        }
+        builder.build().execute(&mut *tx).await?;
+
+        for grant in grants {
+            sqlx::query(
+                "INSERT INTO folder_permissions (folder_id, user_id, role, granted_at, granted_by) VALUES (?, ?, ?, ?, ?) \
+                 ON DUPLICATE KEY UPDATE role = VALUES(role)"
+            )
+            .bind(folder_id)
+            .bind(&grant.user_id)
+            .bind(&grant.role)
+            .bind(now)
+            .bind(granted_by)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_folder_permissions(&self, folder_id: &str) -> Result<Vec<FolderPermission>, Box<dyn Error>> {
+        let permissions = query_as::<_, FolderPermission>(
+            "SELECT folder_id, user_id, role, granted_at, granted_by FROM folder_permissions WHERE folder_id = ? ORDER BY granted_at, user_id"
+        )
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(permissions)
+    }
+
+    async fn get_folder_role(&self, folder_id: &str, user_id: &str) -> Result<Option<Role>, Box<dyn Error>> {
+        let row: Option<(String, Option<String>)> = query_as(&format!(
+            "{} SELECT fo.owner_id, \
+                (SELECT GROUP_CONCAT(p.role) FROM folder_permissions p \
+                 WHERE p.user_id = ? AND p.folder_id IN (SELECT id FROM path)) \
+             FROM folders fo WHERE fo.id = ?",
+            FOLDER_PATH_CTE
+        ))
+        .bind(folder_id)
+        .bind(user_id)
+        .bind(folder_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match row {
+            None => None,
+            Some((owner_id, _)) if owner_id == user_id => Some(Role::Owner),
+            Some((_, roles)) => strongest_role(roles.as_deref()),
+        })
+    }
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO share_links (id, file_id, token_hash, password_hash, expires_at, max_downloads, download_count, created_by, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, 0, ?, ?)"
+        )
+        .bind(&link.id)
+        .bind(&link.file_id)
+        .bind(&link.token_hash)
+        .bind(&link.password_hash)
+        .bind(link.expires_at)
+        .bind(link.max_downloads)
+        .bind(&link.created_by)
+        .bind(link.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        println!("✓ Share link {} created for file {}", link.id, link.file_id);
+        Ok(())
+    }
+
+    async fn get_share_link_by_token(&self, token_hash: &str) -> Result<Option<ShareLink>, Box<dyn Error>> {
+        let link = query_as::<_, ShareLink>(&format!(
+            "SELECT {} FROM share_links WHERE token_hash = ?",
+            SHARE_LINK_COLUMNS
+        ))
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    async fn list_share_links(&self, file_id: &str) -> Result<Vec<ShareLink>, Box<dyn Error>> {
+        let links = query_as::<_, ShareLink>(&format!(
+            "SELECT {} FROM share_links WHERE file_id = ? ORDER BY created_at, id",
+            SHARE_LINK_COLUMNS
+        ))
+        .bind(file_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(links)
+    }
+
+    async fn revoke_share_link(&self, file_id: &str, link_id: &str) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE share_links SET revoked_at = ? WHERE id = ? AND file_id = ? AND revoked_at IS NULL"
+        )
+        .bind(chrono::Utc::now())
+        .bind(link_id)
+        .bind(file_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn consume_share_link(&self, link_id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE share_links SET download_count = download_count + 1 \
+             WHERE id = ? AND revoked_at IS NULL \
+             AND (expires_at IS NULL OR expires_at > ?) \
+             AND (max_downloads IS NULL OR download_count < max_downloads)"
+        )
+        .bind(link_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_session(
+        &self,
+        id: &str,
+        user_id: &str,
+        key_hash: &str,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<Session, Box<dyn Error>> {
+        let now = chrono::Utc::now();
+        
+        sqlx::query(
+            "INSERT INTO sessions (id, user_id, key_hash, ip_address, user_agent, created_at, last_accessed_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(key_hash)
+        .bind(ip_address)
+        .bind(user_agent)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        let session = query_as::<_, Session>(
+            "SELECT id, user_id, key_hash, ip_address, user_agent, created_at, last_accessed_at FROM sessions WHERE id = ?"
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        println!("✓ Session created for user: {}", user_id);
+        Ok(session)
+    }
+
+    async fn get_session(&self, key_hash: &str) -> Result<Option<Session>, Box<dyn Error>> {
+        let session = query_as::<_, Session>(
+            "SELECT id, user_id, key_hash, ip_address, user_agent, created_at, last_accessed_at FROM sessions WHERE key_hash = ?"
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn update_session_access_time(&self, key_hash: &str, accessed_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE sessions SET last_accessed_at = ? WHERE key_hash = ?")
+            .bind(accessed_at)
+            .bind(key_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, Box<dyn Error>> {
+        let sessions = query_as::<_, Session>(
+            "SELECT id, user_id, key_hash, ip_address, user_agent, created_at, last_accessed_at FROM sessions WHERE user_id = ?"
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn delete_session(&self, key_hash: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("DELETE FROM sessions WHERE key_hash = ?")
+            .bind(key_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_expired_sessions(
+        &self,
+        created_before: Option<chrono::DateTime<chrono::Utc>>,
+        idle_before: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<u64, Box<dyn Error>> {
+        if created_before.is_none() && idle_before.is_none() {
+            return Ok(0);
+        }
+
+        let result = sqlx::query(
+            "DELETE FROM sessions WHERE (? IS NOT NULL AND created_at < ?) OR (? IS NOT NULL AND last_accessed_at < ?)"
+        )
+        .bind(created_before)
+        .bind(created_before)
+        .bind(idle_before)
+        .bind(idle_before)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn create_access_token(&self, token: &AccessToken) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO access_tokens (id, user_id, name, token_hash, scopes, expires_at, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&token.id)
+        .bind(&token.user_id)
+        .bind(&token.name)
+        .bind(&token.token_hash)
+        .bind(&token.scopes)
+        .bind(token.expires_at)
+        .bind(token.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        println!("✓ Access token {} created for user: {}", token.id, token.user_id);
+        Ok(())
+    }
+
+    async fn get_access_token_by_hash(&self, token_hash: &str) -> Result<Option<AccessToken>, Box<dyn Error>> {
+        let token = query_as::<_, AccessToken>(&format!(
+            "SELECT {} FROM access_tokens WHERE token_hash = ?",
+            ACCESS_TOKEN_COLUMNS
+        ))
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    async fn list_access_tokens(&self, user_id: &str) -> Result<Vec<AccessToken>, Box<dyn Error>> {
+        let tokens = query_as::<_, AccessToken>(&format!(
+            "SELECT {} FROM access_tokens WHERE user_id = ? ORDER BY created_at, id",
+            ACCESS_TOKEN_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tokens)
+    }
+
+    async fn delete_access_token(&self, user_id: &str, token_id: &str) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query("DELETE FROM access_tokens WHERE id = ? AND user_id = ?")
+            .bind(token_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_access_token_last_used(&self, token_id: &str, used_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE access_tokens SET last_used_at = ? WHERE id = ?")
+            .bind(used_at)
+            .bind(token_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn save_totp_secret(&self, user_id: &str, secret: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO user_totp (user_id, secret, created_at) VALUES (?, ?, ?) \
+             ON DUPLICATE KEY UPDATE \
+                secret = IF(enabled_at IS NULL, VALUES(secret), secret), \
+                created_at = IF(enabled_at IS NULL, VALUES(created_at), created_at)"
+        )
+        .bind(user_id)
+        .bind(secret)
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_totp(&self, user_id: &str) -> Result<Option<TotpEnrollment>, Box<dyn Error>> {
+        let totp = query_as::<_, TotpEnrollment>(
+            "SELECT user_id, secret, enabled_at, last_used_step, created_at FROM user_totp WHERE user_id = ?"
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(totp)
+    }
+
+    async fn enable_totp(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE user_totp SET enabled_at = ?, last_used_step = ? WHERE user_id = ? AND enabled_at IS NULL"
+        )
+        .bind(now)
+        .bind(step)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if !recovery_code_hashes.is_empty() {
+            let mut builder = QueryBuilder::<MySql>::new("INSERT INTO recovery_codes (user_id, code_hash, created_at) ");
+            builder.push_values(recovery_code_hashes, |mut row, hash| {
+                row.push_bind(user_id).push_bind(hash).push_bind(now);
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        println!("✓ Two-factor authentication enabled for user: {}", user_id);
+        Ok(true)
+    }
+
+    async fn use_totp_step(&self, user_id: &str, step: i64) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE user_totp SET last_used_step = ? \
+             WHERE user_id = ? AND enabled_at IS NOT NULL AND (last_used_step IS NULL OR last_used_step < ?)"
+        )
+        .bind(step)
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_totp(&self, user_id: &str) -> Result<(), Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM user_totp WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        println!("✓ Two-factor authentication disabled for user: {}", user_id);
+        Ok(())
+    }
+
+    async fn use_recovery_code(&self, user_id: &str, code_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE recovery_codes SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL"
+        )
+        .bind(now)
+        .bind(user_id)
+        .bind(code_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count_recovery_codes(&self, user_id: &str) -> Result<i64, Box<dyn Error>> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ? AND used_at IS NULL"
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn create_login_challenge(&self, challenge: &LoginChallenge) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO login_challenges (id, user_id, token_hash, ip_address, user_agent, attempts, expires_at, created_at) \
+             VALUES (?, ?, ?, ?, ?, 0, ?, ?)"
+        )
+        .bind(&challenge.id)
+        .bind(&challenge.user_id)
+        .bind(&challenge.token_hash)
+        .bind(&challenge.ip_address)
+        .bind(&challenge.user_agent)
+        .bind(challenge.expires_at)
+        .bind(challenge.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_login_challenge(&self, token_hash: &str) -> Result<Option<LoginChallenge>, Box<dyn Error>> {
+        let challenge = query_as::<_, LoginChallenge>(&format!(
+            "SELECT {} FROM login_challenges WHERE token_hash = ?",
+            LOGIN_CHALLENGE_COLUMNS
+        ))
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    async fn increment_login_challenge_attempts(&self, id: &str, max_attempts: i32) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query("UPDATE login_challenges SET attempts = attempts + 1 WHERE id = ? AND attempts < ?")
+            .bind(id)
+            .bind(max_attempts)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_login_challenge(&self, id: &str) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query("DELETE FROM login_challenges WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_expired_login_challenges(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let result = sqlx::query("DELETE FROM login_challenges WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn create_webauthn_credential(&self, credential: &WebauthnCredential) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO webauthn_credentials (id, user_id, credential_id, public_key, sign_count, name, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&credential.id)
+        .bind(&credential.user_id)
+        .bind(&credential.credential_id)
+        .bind(&credential.public_key)
+        .bind(credential.sign_count)
+        .bind(&credential.name)
+        .bind(credential.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_webauthn_credential(&self, credential_id: &str) -> Result<Option<WebauthnCredential>, Box<dyn Error>> {
+        let credential = query_as::<_, WebauthnCredential>(&format!(
+            "SELECT {} FROM webauthn_credentials WHERE credential_id = ?",
+            WEBAUTHN_CREDENTIAL_COLUMNS
+        ))
+        .bind(credential_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn list_webauthn_credentials(&self, user_id: &str) -> Result<Vec<WebauthnCredential>, Box<dyn Error>> {
+        let credentials = query_as::<_, WebauthnCredential>(&format!(
+            "SELECT {} FROM webauthn_credentials WHERE user_id = ? ORDER BY created_at DESC",
+            WEBAUTHN_CREDENTIAL_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(credentials)
+    }
+
+    async fn update_webauthn_credential_use(&self, id: &str, sign_count: i64, used_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE webauthn_credentials SET sign_count = ?, last_used_at = ? WHERE id = ?")
+            .bind(sign_count)
+            .bind(used_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_webauthn_credential(&self, user_id: &str, id: &str) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query("DELETE FROM webauthn_credentials WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_webauthn_challenge(&self, challenge: &WebauthnChallenge) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO webauthn_challenges (challenge, user_id, purpose, expires_at, created_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(&challenge.challenge)
+        .bind(&challenge.user_id)
+        .bind(&challenge.purpose)
+        .bind(challenge.expires_at)
+        .bind(challenge.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn take_webauthn_challenge(
+        &self,
+        challenge: &str,
+        purpose: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<WebauthnChallenge>, Box<dyn Error>> {
+        let found = query_as::<_, WebauthnChallenge>(
+            "SELECT challenge, user_id, purpose, expires_at, created_at FROM webauthn_challenges \
+             WHERE challenge = ? AND purpose = ? AND expires_at > ?"
+        )
+        .bind(challenge)
+        .bind(purpose)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(found) = found else {
+            return Ok(None);
+        };
+
+        // Only the request whose delete lands gets the challenge.
+        let result = sqlx::query("DELETE FROM webauthn_challenges WHERE challenge = ?")
+            .bind(challenge)
+            .execute(&self.pool)
+            .await?;
+
+        Ok((result.rows_affected() > 0).then_some(found))
+    }
+
+    async fn delete_expired_webauthn_challenges(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let result = sqlx::query("DELETE FROM webauthn_challenges WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn create_oidc_login_state(&self, state: &OidcLoginState) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO oidc_login_states (state_hash, nonce, code_verifier, expires_at, created_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(&state.state_hash)
+        .bind(&state.nonce)
+        .bind(&state.code_verifier)
+        .bind(state.expires_at)
+        .bind(state.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn take_oidc_login_state(&self, state_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Option<OidcLoginState>, Box<dyn Error>> {
+        let found = query_as::<_, OidcLoginState>(
+            "SELECT state_hash, nonce, code_verifier, expires_at, created_at FROM oidc_login_states \
+             WHERE state_hash = ? AND expires_at > ?"
+        )
+        .bind(state_hash)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(found) = found else {
+            return Ok(None);
+        };
+
+        let result = sqlx::query("DELETE FROM oidc_login_states WHERE state_hash = ?")
+            .bind(state_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok((result.rows_affected() > 0).then_some(found))
+    }
+
+    async fn delete_expired_oidc_login_states(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let result = sqlx::query("DELETE FROM oidc_login_states WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_user_identity(&self, issuer: &str, subject: &str) -> Result<Option<UserIdentity>, Box<dyn Error>> {
+        let identity = query_as::<_, UserIdentity>(
+            "SELECT issuer, subject, user_id, email, created_at FROM user_identities WHERE issuer = ? AND subject = ?"
+        )
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn create_user_identity(&self, identity: &UserIdentity) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO user_identities (issuer, subject, user_id, email, created_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(&identity.issuer)
+        .bind(&identity.subject)
+        .bind(&identity.user_id)
+        .bind(&identity.email)
+        .bind(identity.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_email_token(&self, token: &EmailToken) -> Result<(), Box<dyn Error>> {
+        sqlx::query(
+            "INSERT INTO email_tokens (token_hash, user_id, purpose, expires_at, created_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(&token.token_hash)
+        .bind(&token.user_id)
+        .bind(&token.purpose)
+        .bind(token.expires_at)
+        .bind(token.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn take_email_token(
+        &self,
+        token_hash: &str,
+        purpose: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<EmailToken>, Box<dyn Error>> {
+        let found = query_as::<_, EmailToken>(
+            "SELECT token_hash, user_id, purpose, expires_at, created_at FROM email_tokens \
+             WHERE token_hash = ? AND purpose = ? AND expires_at > ?"
+        )
+        .bind(token_hash)
+        .bind(purpose)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(found) = found else {
+            return Ok(None);
+        };
+
+        let result = sqlx::query("DELETE FROM email_tokens WHERE token_hash = ?")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok((result.rows_affected() > 0).then_some(found))
+    }
+
+    async fn delete_email_tokens(&self, user_id: &str, purpose: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("DELETE FROM email_tokens WHERE user_id = ? AND purpose = ?")
+            .bind(user_id)
+            .bind(purpose)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_expired_email_tokens(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>> {
+        let result = sqlx::query("DELETE FROM email_tokens WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_login_failure(&self, scope: &str, subject: &str) -> Result<Option<LoginFailure>, Box<dyn Error>> {
+        let failure = query_as::<_, LoginFailure>(
+            "SELECT scope, subject, failures, last_failed_at, blocked_until FROM login_failures WHERE scope = ? AND subject = ?"
+        )
+        .bind(scope)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(failure)
+    }
+
+    async fn record_login_failure(
+        &self,
+        scope: &str,
+        subject: &str,
+        now: chrono::DateTime<chrono::Utc>,
+        window_start: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i32, Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        // The increment happens in SQL so concurrent failures are all counted.
+        sqlx::query(
+            "INSERT INTO login_failures (scope, subject, failures, last_failed_at) VALUES (?, ?, 1, ?) \
+             ON DUPLICATE KEY UPDATE \
+                failures = IF(last_failed_at < ?, 1, failures + 1), \
+                last_failed_at = VALUES(last_failed_at)"
+        )
+        .bind(scope)
+        .bind(subject)
+        .bind(now)
+        .bind(window_start)
+        .execute(&mut *tx)
+        .await?;
+
+        let failures: i32 = sqlx::query_scalar("SELECT failures FROM login_failures WHERE scope = ? AND subject = ?")
+            .bind(scope)
+            .bind(subject)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(failures)
+    }
+
+    async fn block_login(&self, scope: &str, subject: &str, until: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE login_failures SET blocked_until = ? WHERE scope = ? AND subject = ?")
+            .bind(until)
+            .bind(scope)
+            .bind(subject)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, scope: &str, subject: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("DELETE FROM login_failures WHERE scope = ? AND subject = ?")
+            .bind(scope)
+            .bind(subject)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_stale_login_failures(
+        &self,
+        window_start: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, Box<dyn Error>> {
+        let result = sqlx::query(
+            "DELETE FROM login_failures WHERE last_failed_at < ? AND (blocked_until IS NULL OR blocked_until <= ?)"
+        )
+        .bind(window_start)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn create_tus_upload(&self, upload: &TusUpload) -> Result<(), Box<dyn Error>> {
+        sqlx::query(&format!(
+            "INSERT INTO tus_uploads ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            TUS_UPLOAD_COLUMNS
+        ))
+        .bind(&upload.id)
+        .bind(&upload.user_id)
+        .bind(&upload.folder_id)
+        .bind(&upload.filename)
+        .bind(&upload.content_type)
+        .bind(upload.upload_length)
+        .bind(upload.upload_offset)
+        .bind(upload.part_size)
+        .bind(&upload.multipart_upload_id)
+        .bind(&upload.lock_token)
+        .bind(upload.locked_until)
+        .bind(upload.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_tus_upload(&self, id: &str) -> Result<Option<TusUpload>, Box<dyn Error>> {
+        let upload = query_as::<_, TusUpload>(&format!("SELECT {} FROM tus_uploads WHERE id = ?", TUS_UPLOAD_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(upload)
+    }
+
+    async fn lock_tus_upload(
+        &self,
+        id: &str,
+        token: &str,
+        now: chrono::DateTime<chrono::Utc>,
+        until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE tus_uploads SET lock_token = ?, locked_until = ? \
+             WHERE id = ? AND (lock_token IS NULL OR lock_token = ? OR locked_until <= ?)"
+        )
+        .bind(token)
+        .bind(until)
+        .bind(id)
+        .bind(token)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn advance_tus_upload(&self, id: &str, token: &str, from_offset: i64, offset: i64) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE tus_uploads SET upload_offset = ?, lock_token = NULL, locked_until = NULL \
+             WHERE id = ? AND lock_token = ? AND upload_offset = ?"
+        )
+        .bind(offset)
+        .bind(id)
+        .bind(token)
+        .bind(from_offset)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn unlock_tus_upload(&self, id: &str, token: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("UPDATE tus_uploads SET lock_token = NULL, locked_until = NULL WHERE id = ? AND lock_token = ?")
+            .bind(id)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_tus_upload(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("DELETE FROM tus_uploads WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_stale_tus_uploads(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<TusUpload>, Box<dyn Error>> {
+        let uploads = query_as::<_, TusUpload>(&format!(
+            "SELECT {} FROM tus_uploads WHERE created_at < ? ORDER BY created_at LIMIT ?",
+            TUS_UPLOAD_COLUMNS
+        ))
+        .bind(created_before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(uploads)
+    }
+
+    async fn create_blob(&self, sha256: &str, size_bytes: i64) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query("INSERT IGNORE INTO blobs (sha256, size_bytes, state, ref_count, created_at) VALUES (?, ?, ?, 0, ?)")
+            .bind(sha256)
+            .bind(size_bytes)
+            .bind(BLOB_PENDING)
+            .bind(chrono::Utc::now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_blob(&self, sha256: &str) -> Result<Option<Blob>, Box<dyn Error>> {
+        let blob = query_as::<_, Blob>(&format!("SELECT {} FROM blobs WHERE sha256 = ?", BLOB_COLUMNS))
+            .bind(sha256)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(blob)
+    }
+
+    async fn activate_blob(&self, sha256: &str, etag: Option<&str>) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query("UPDATE blobs SET state = ?, etag = ? WHERE sha256 = ? AND state = ?")
+            .bind(BLOB_LIVE)
+            .bind(etag)
+            .bind(sha256)
+            .bind(BLOB_PENDING)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn attach_blob(&self, file_id: &str, sha256: &str, content_type: Option<&str>, uploaded_by: Option<&str>) -> Result<bool, Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        // Files are locked before blobs everywhere, so deletes and attaches cannot deadlock.
+        let previous: Option<(Option<String>,)> = query_as("SELECT blob_sha256 FROM files WHERE id = ? FOR UPDATE")
+            .bind(file_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some((previous,)) = previous else {
+            return Ok(false);
+        };
+
+        let blob: Option<(i64, Option<String>)> = query_as("SELECT size_bytes, etag FROM blobs WHERE sha256 = ? AND state = ? FOR UPDATE")
+            .bind(sha256)
+            .bind(BLOB_LIVE)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some((size_bytes, etag)) = blob else {
+            return Ok(false);
+        };
+
+        sqlx::query(
+            "UPDATE files SET size_bytes = ?, content_type = ?, etag = ?, sha256 = ?, upload_state = ?, blob_sha256 = ? WHERE id = ?"
+        )
+        .bind(size_bytes)
+        .bind(content_type)
+        .bind(&etag)
+        .bind(sha256)
+        .bind(UPLOAD_COMPLETE)
+        .bind(sha256)
+        .bind(file_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if previous.as_deref() != Some(sha256) {
+            let (latest,): (Option<i32>,) = query_as("SELECT MAX(version_number) FROM file_versions WHERE file_id = ?")
+                .bind(file_id)
+                .fetch_one(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO file_versions (file_id, version_number, blob_sha256, size_bytes, content_type, etag, uploaded_by, created_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+            )
+            .bind(file_id)
+            .bind(latest.unwrap_or(0) + 1)
+            .bind(sha256)
+            .bind(size_bytes)
+            .bind(content_type)
+            .bind(&etag)
+            .bind(uploaded_by)
+            .bind(chrono::Utc::now())
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("UPDATE blobs SET ref_count = ref_count + 1 WHERE sha256 = ?")
+                .bind(sha256)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        println!("✓ File {} stored as blob {} ({} bytes)", file_id, sha256, size_bytes);
+        Ok(true)
+    }
+
+    async fn claim_blob_deletion(&self, sha256: &str, include_pending: bool) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query("UPDATE blobs SET state = ? WHERE sha256 = ? AND ref_count = 0 AND (state = ? OR (? AND state = ?))")
+            .bind(BLOB_DELETING)
+            .bind(sha256)
+            .bind(BLOB_LIVE)
+            .bind(include_pending)
+            .bind(BLOB_PENDING)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_blob(&self, sha256: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("DELETE FROM blobs WHERE sha256 = ? AND ref_count = 0")
+            .bind(sha256)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_unreferenced_blobs(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<Blob>, Box<dyn Error>> {
+        let blobs = query_as::<_, Blob>(&format!(
+            "SELECT {} FROM blobs WHERE ref_count = 0 AND created_at < ? ORDER BY created_at LIMIT ?",
+            BLOB_COLUMNS
+        ))
+        .bind(created_before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(blobs)
+    }
+
+    async fn existing_blob_hashes(&self, hashes: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut builder = QueryBuilder::<MySql>::new("SELECT sha256 FROM blobs WHERE sha256 IN (");
+        let mut separated = builder.separated(", ");
+        for sha256 in hashes {
+            separated.push_bind(sha256);
+        }
+        separated.push_unseparated(")");
+
+        let existing: Vec<(String,)> = builder
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(existing.into_iter().map(|(sha256,)| sha256).collect())
+    }
+
+    async fn list_file_versions(&self, file_id: &str) -> Result<Vec<FileVersion>, Box<dyn Error>> {
+        let versions = query_as::<_, FileVersion>(&format!(
+            "SELECT {} FROM file_versions WHERE file_id = ? ORDER BY version_number DESC",
+            FILE_VERSION_COLUMNS
+        ))
+        .bind(file_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+
+    async fn get_file_version(&self, file_id: &str, version_number: i32) -> Result<Option<FileVersion>, Box<dyn Error>> {
+        let version = query_as::<_, FileVersion>(&format!(
+            "SELECT {} FROM file_versions WHERE file_id = ? AND version_number = ?",
+            FILE_VERSION_COLUMNS
+        ))
+        .bind(file_id)
+        .bind(version_number)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(version)
+    }
+
+    async fn delete_file_version(&self, file_id: &str, version_number: i32) -> Result<bool, Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("SELECT id FROM files WHERE id = ? FOR UPDATE")
+            .bind(file_id)
+            .execute(&mut *tx)
+            .await?;
+        let (latest,): (Option<i32>,) = query_as("SELECT MAX(version_number) FROM file_versions WHERE file_id = ?")
+            .bind(file_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if latest.is_none_or(|latest| version_number >= latest) {
+            return Ok(false);
+        }
+
+        let version: Option<(String,)> = query_as("SELECT blob_sha256 FROM file_versions WHERE file_id = ? AND version_number = ? FOR UPDATE")
+            .bind(file_id)
+            .bind(version_number)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some((sha256,)) = version else {
+            return Ok(false);
+        };
+
+        sqlx::query("DELETE FROM file_versions WHERE file_id = ? AND version_number = ?")
+            .bind(file_id)
+            .bind(version_number)
+            .execute(&mut *tx)
+            .await?;
+        release_blobs(&mut tx, [&sha256]).await?;
+        tx.commit().await?;
+
+        println!("✓ Version {} of file {} deleted", version_number, file_id);
+        Ok(true)
+    }
+
+    async fn list_excess_file_versions(&self, keep: i64, limit: i64) -> Result<Vec<FileVersion>, Box<dyn Error>> {
+        let versions = query_as::<_, FileVersion>(&format!(
+            "SELECT {} FROM ( \
+                SELECT {}, ROW_NUMBER() OVER (PARTITION BY file_id ORDER BY version_number DESC) AS newer \
+                FROM file_versions \
+             ) ranked WHERE newer > ? ORDER BY created_at LIMIT ?",
+            FILE_VERSION_COLUMNS, FILE_VERSION_COLUMNS
+        ))
+        .bind(keep)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+}
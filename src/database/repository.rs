@@ -0,0 +1,322 @@
+use std::error::Error;
+use async_trait::async_trait;
+use super::model::{
+    AccessToken, Blob, DeletedFiles, EmailToken, File, FileVersion, FileGrant, LoginChallenge, LoginFailure, OidcLoginState, TotpEnrollment, TusUpload, UserIdentity, WebauthnChallenge, WebauthnCredential, FileListQuery, FilePage, FilePermission, Folder, FolderPermission, Role, Session, ShareLink, User,
+};
+
+#[async_trait]
+pub trait Repository: Send + Sync {
+    async fn create_user(&self, id: &str, email: &str, password_hash: &str) -> Result<User, Box<dyn Error>>;
+
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Box<dyn Error>>;
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Box<dyn Error>>;
+
+    async fn update_user_icon(&self, id: &str, icon_url: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn update_user_password(&self, id: &str, password_hash: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Records the first time the user proved control of their address; later calls keep that time.
+    async fn mark_email_verified(&self, id: &str, verified_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>>;
+
+    async fn delete_user(&self, id: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn create_file(&self, id: &str, filename: &str, owner_id: &str, folder_id: Option<&str>) -> Result<File, Box<dyn Error>>;
+
+    async fn get_file(&self, id: &str) -> Result<Option<File>, Box<dyn Error>>;
+
+    /// Replaces the file's grants: users not listed lose access, listed users get exactly the given role.
+    async fn set_file_permissions(&self, file_id: &str, grants: &[FileGrant], granted_by: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn list_file_permissions(&self, file_id: &str) -> Result<Vec<FilePermission>, Box<dyn Error>>;
+
+    /// Marks the upload complete with its contents stored under the file id rather than in a blob.
+    async fn complete_file_upload(
+        &self,
+        id: &str,
+        size_bytes: i64,
+        content_type: Option<&str>,
+        etag: Option<&str>,
+        sha256: Option<&str>,
+    ) -> Result<(), Box<dyn Error>>;
+
+    async fn rename_file(&self, id: &str, filename: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn move_file(&self, id: &str, folder_id: Option<&str>) -> Result<(), Box<dyn Error>>;
+
+    /// Deletes the file and its versions, dropping their blob references; returns the blobs they referenced.
+    async fn delete_file(&self, id: &str) -> Result<Vec<String>, Box<dyn Error>>;
+
+    async fn list_user_files(&self, user_id: &str, query: &FileListQuery) -> Result<FilePage, Box<dyn Error>>;
+
+    async fn list_stale_pending_files(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<File>, Box<dyn Error>>;
+
+    /// Records a multipart upload on the file; false when another one is already in progress.
+    async fn start_multipart_upload(&self, id: &str, upload_id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>>;
+
+    /// Clears the multipart upload if it is still `upload_id`; false when it was not.
+    async fn finish_multipart_upload(&self, id: &str, upload_id: &str) -> Result<bool, Box<dyn Error>>;
+
+    async fn list_stale_multipart_uploads(&self, started_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<File>, Box<dyn Error>>;
+
+    async fn existing_file_ids(&self, ids: &[String]) -> Result<Vec<String>, Box<dyn Error>>;
+
+    async fn add_user_to_file(&self, file_id: &str, user_id: &str, granted_by: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn remove_user_from_file(&self, file_id: &str, user_id: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn check_user_can_access(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>>;
+
+    async fn check_user_is_owner(&self, file_id: &str, user_id: &str) -> Result<bool, Box<dyn Error>>;
+
+    /// The user's effective role on the file, including roles inherited from its folders,
+    /// or `None` when the file is missing or not shared with them.
+    async fn get_file_role(&self, file_id: &str, user_id: &str) -> Result<Option<Role>, Box<dyn Error>>;
+
+    async fn create_folder(&self, id: &str, owner_id: &str, parent_id: Option<&str>, name: &str) -> Result<Folder, Box<dyn Error>>;
+
+    async fn get_folder(&self, id: &str) -> Result<Option<Folder>, Box<dyn Error>>;
+
+    /// Looks up a sibling by name within one owner's tree; names compare case-insensitively.
+    async fn find_folder(&self, owner_id: &str, parent_id: Option<&str>, name: &str) -> Result<Option<Folder>, Box<dyn Error>>;
+
+    async fn list_child_folders(&self, parent_id: &str) -> Result<Vec<Folder>, Box<dyn Error>>;
+
+    /// The user's own top-level folders plus every folder shared with them directly.
+    async fn list_root_folders(&self, user_id: &str) -> Result<Vec<Folder>, Box<dyn Error>>;
+
+    async fn rename_folder(&self, id: &str, name: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn move_folder(&self, id: &str, parent_id: Option<&str>) -> Result<(), Box<dyn Error>>;
+
+    /// Ancestors of the folder from the top of its tree down to and including the folder itself.
+    async fn folder_path(&self, id: &str) -> Result<Vec<Folder>, Box<dyn Error>>;
+
+    /// Deletes the folder, every subfolder and every contained file of `owner_id`, returning the
+    /// removed files. Other users' files move to their owners' top level instead, and blob
+    /// references held by the removed versions are dropped in the same transaction.
+    async fn delete_folder_tree(&self, id: &str, owner_id: &str) -> Result<DeletedFiles, Box<dyn Error>>;
+
+    async fn set_folder_permissions(&self, folder_id: &str, grants: &[FileGrant], granted_by: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn list_folder_permissions(&self, folder_id: &str) -> Result<Vec<FolderPermission>, Box<dyn Error>>;
+
+    /// The user's effective role on the folder, taking grants on any ancestor into account.
+    async fn get_folder_role(&self, folder_id: &str, user_id: &str) -> Result<Option<Role>, Box<dyn Error>>;
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<(), Box<dyn Error>>;
+
+    async fn get_share_link_by_token(&self, token_hash: &str) -> Result<Option<ShareLink>, Box<dyn Error>>;
+
+    async fn list_share_links(&self, file_id: &str) -> Result<Vec<ShareLink>, Box<dyn Error>>;
+
+    /// Marks a link revoked; returns false when no active link with that id belongs to the file.
+    async fn revoke_share_link(&self, file_id: &str, link_id: &str) -> Result<bool, Box<dyn Error>>;
+
+    /// Atomically counts one download, failing when the link was revoked, expired or used up in the meantime.
+    async fn consume_share_link(&self, link_id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>>;
+
+    async fn create_session(
+        &self,
+        id: &str,
+        user_id: &str,
+        key_hash: &str,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<Session, Box<dyn Error>>;
+
+    /// Sessions are looked up by the SHA-256 digest of their bearer key; the raw key is never stored.
+    async fn get_session(&self, key_hash: &str) -> Result<Option<Session>, Box<dyn Error>>;
+
+    async fn update_session_access_time(&self, key_hash: &str, accessed_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>>;
+
+    async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, Box<dyn Error>>;
+
+    async fn delete_session(&self, key_hash: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn delete_user_sessions(&self, user_id: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Deletes sessions created before `created_before` or idle since before `idle_before`; returns how many went.
+    async fn delete_expired_sessions(
+        &self,
+        created_before: Option<chrono::DateTime<chrono::Utc>>,
+        idle_before: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<u64, Box<dyn Error>>;
+
+    async fn create_access_token(&self, token: &AccessToken) -> Result<(), Box<dyn Error>>;
+
+    async fn get_access_token_by_hash(&self, token_hash: &str) -> Result<Option<AccessToken>, Box<dyn Error>>;
+
+    async fn list_access_tokens(&self, user_id: &str) -> Result<Vec<AccessToken>, Box<dyn Error>>;
+
+    /// Deletes one of the user's tokens; returns false when the user has no token with that id.
+    async fn delete_access_token(&self, user_id: &str, token_id: &str) -> Result<bool, Box<dyn Error>>;
+
+    async fn update_access_token_last_used(&self, token_id: &str, used_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>>;
+
+    /// Stores a fresh, unconfirmed TOTP secret; an already enabled enrollment is left untouched.
+    async fn save_totp_secret(&self, user_id: &str, secret: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn get_totp(&self, user_id: &str) -> Result<Option<TotpEnrollment>, Box<dyn Error>>;
+
+    /// Confirms a pending enrollment at `step` and replaces the recovery codes; false when nothing was pending.
+    async fn enable_totp(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool, Box<dyn Error>>;
+
+    /// Records `step` as used; false when that step (or a later one) was already accepted.
+    async fn use_totp_step(&self, user_id: &str, step: i64) -> Result<bool, Box<dyn Error>>;
+
+    /// Removes the enrollment together with its recovery codes.
+    async fn delete_totp(&self, user_id: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Marks an unused recovery code as used; false when no such unused code exists.
+    async fn use_recovery_code(&self, user_id: &str, code_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>>;
+
+    async fn count_recovery_codes(&self, user_id: &str) -> Result<i64, Box<dyn Error>>;
+
+    async fn create_login_challenge(&self, challenge: &LoginChallenge) -> Result<(), Box<dyn Error>>;
+
+    async fn get_login_challenge(&self, token_hash: &str) -> Result<Option<LoginChallenge>, Box<dyn Error>>;
+
+    /// Spends one of the challenge's attempts; false when `max_attempts` were already spent.
+    async fn increment_login_challenge_attempts(&self, id: &str, max_attempts: i32) -> Result<bool, Box<dyn Error>>;
+
+    /// Deletes the challenge; false when it was already gone, so only one exchange can win.
+    async fn delete_login_challenge(&self, id: &str) -> Result<bool, Box<dyn Error>>;
+
+    async fn delete_expired_login_challenges(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>>;
+
+    async fn create_webauthn_credential(&self, credential: &WebauthnCredential) -> Result<(), Box<dyn Error>>;
+
+    async fn get_webauthn_credential(&self, credential_id: &str) -> Result<Option<WebauthnCredential>, Box<dyn Error>>;
+
+    async fn list_webauthn_credentials(&self, user_id: &str) -> Result<Vec<WebauthnCredential>, Box<dyn Error>>;
+
+    /// Stores the authenticator's new signature counter after a successful login.
+    async fn update_webauthn_credential_use(&self, id: &str, sign_count: i64, used_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>>;
+
+    /// Deletes one of the user's passkeys; false when the user has no passkey with that id.
+    async fn delete_webauthn_credential(&self, user_id: &str, id: &str) -> Result<bool, Box<dyn Error>>;
+
+    async fn create_webauthn_challenge(&self, challenge: &WebauthnChallenge) -> Result<(), Box<dyn Error>>;
+
+    /// Removes and returns an unexpired challenge issued for `purpose`, so each can be answered once.
+    async fn take_webauthn_challenge(
+        &self,
+        challenge: &str,
+        purpose: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<WebauthnChallenge>, Box<dyn Error>>;
+
+    async fn delete_expired_webauthn_challenges(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>>;
+
+    async fn create_oidc_login_state(&self, state: &OidcLoginState) -> Result<(), Box<dyn Error>>;
+
+    /// Removes and returns an unexpired login state, so each callback can be redeemed once.
+    async fn take_oidc_login_state(&self, state_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Option<OidcLoginState>, Box<dyn Error>>;
+
+    async fn delete_expired_oidc_login_states(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>>;
+
+    async fn get_user_identity(&self, issuer: &str, subject: &str) -> Result<Option<UserIdentity>, Box<dyn Error>>;
+
+    async fn create_user_identity(&self, identity: &UserIdentity) -> Result<(), Box<dyn Error>>;
+
+    async fn create_email_token(&self, token: &EmailToken) -> Result<(), Box<dyn Error>>;
+
+    /// Removes and returns an unexpired token issued for `purpose`, so each link works once.
+    async fn take_email_token(
+        &self,
+        token_hash: &str,
+        purpose: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<EmailToken>, Box<dyn Error>>;
+
+    /// Invalidates every outstanding token of one kind for the user.
+    async fn delete_email_tokens(&self, user_id: &str, purpose: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn delete_expired_email_tokens(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64, Box<dyn Error>>;
+
+    async fn get_login_failure(&self, scope: &str, subject: &str) -> Result<Option<LoginFailure>, Box<dyn Error>>;
+
+    /// Counts one more failure and returns the new total; a count last bumped before `window_start` restarts at one.
+    async fn record_login_failure(
+        &self,
+        scope: &str,
+        subject: &str,
+        now: chrono::DateTime<chrono::Utc>,
+        window_start: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i32, Box<dyn Error>>;
+
+    async fn block_login(&self, scope: &str, subject: &str, until: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>>;
+
+    async fn clear_login_failures(&self, scope: &str, subject: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Drops counters that fell out of the window and are no longer blocking anything.
+    async fn delete_stale_login_failures(
+        &self,
+        window_start: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, Box<dyn Error>>;
+
+    async fn create_tus_upload(&self, upload: &TusUpload) -> Result<(), Box<dyn Error>>;
+
+    async fn get_tus_upload(&self, id: &str) -> Result<Option<TusUpload>, Box<dyn Error>>;
+
+    /// Takes or renews the write lease; fails while another token holds an unexpired one.
+    async fn lock_tus_upload(
+        &self,
+        id: &str,
+        token: &str,
+        now: chrono::DateTime<chrono::Utc>,
+        until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool, Box<dyn Error>>;
+
+    /// Moves the offset from `from_offset` to `offset` and releases the lease, provided `token`
+    /// still holds it and no other request moved the offset in between.
+    async fn advance_tus_upload(&self, id: &str, token: &str, from_offset: i64, offset: i64) -> Result<bool, Box<dyn Error>>;
+
+    async fn unlock_tus_upload(&self, id: &str, token: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn delete_tus_upload(&self, id: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn list_stale_tus_uploads(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<TusUpload>, Box<dyn Error>>;
+
+    /// Records a pending blob about to be written; false when one with this hash already exists.
+    async fn create_blob(&self, sha256: &str, size_bytes: i64) -> Result<bool, Box<dyn Error>>;
+
+    async fn get_blob(&self, sha256: &str) -> Result<Option<Blob>, Box<dyn Error>>;
+
+    /// Makes a pending blob available to files once its object is in place.
+    async fn activate_blob(&self, sha256: &str, etag: Option<&str>) -> Result<bool, Box<dyn Error>>;
+
+    /// Points the file at a live blob and marks its upload complete. Unless the file already points
+    /// at it, a new version referencing the blob is recorded. False when the blob is not live or the file is gone.
+    async fn attach_blob(&self, file_id: &str, sha256: &str, content_type: Option<&str>, uploaded_by: Option<&str>) -> Result<bool, Box<dyn Error>>;
+
+    /// Moves an unreferenced blob to `deleting` so no file can attach to it while its object is removed.
+    /// Pending blobs may still be being written, so they are only claimed with `include_pending`.
+    async fn claim_blob_deletion(&self, sha256: &str, include_pending: bool) -> Result<bool, Box<dyn Error>>;
+
+    async fn delete_blob(&self, sha256: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Blobs without references created before the cutoff, including pending ones never activated.
+    async fn list_unreferenced_blobs(&self, created_before: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<Blob>, Box<dyn Error>>;
+
+    async fn existing_blob_hashes(&self, hashes: &[String]) -> Result<Vec<String>, Box<dyn Error>>;
+
+    /// The file's versions, newest first.
+    async fn list_file_versions(&self, file_id: &str) -> Result<Vec<FileVersion>, Box<dyn Error>>;
+
+    async fn get_file_version(&self, file_id: &str, version_number: i32) -> Result<Option<FileVersion>, Box<dyn Error>>;
+
+    /// Deletes a version and drops its blob reference; the newest version is never deleted.
+    async fn delete_file_version(&self, file_id: &str, version_number: i32) -> Result<bool, Box<dyn Error>>;
+
+    /// Versions beyond the newest `keep` of each file, oldest first.
+    async fn list_excess_file_versions(&self, keep: i64, limit: i64) -> Result<Vec<FileVersion>, Box<dyn Error>>;
+}
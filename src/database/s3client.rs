@@ -1,9 +1,20 @@
 use std::env;
 use std::error::Error;
+use std::ops::Range;
 use std::time::Duration;
+use async_trait::async_trait;
 use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{ChecksumMode, CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::presigning::PresigningConfig;
 use aws_config::SdkConfig;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use super::storage::{ObjectEntry, ObjectMeta, ObjectPage, ObjectStream, Storage, UploadedPart};
+
+/// Largest object a single CopyObject call accepts.
+const MAX_COPY_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+const COPY_PART_SIZE: i64 = 512 * 1024 * 1024;
 
 pub struct S3Client {
     client: Client,
@@ -15,7 +26,7 @@ pub struct S3Client {
 impl S3Client {
     pub fn new_from_env(config: SdkConfig) -> Result<Self, Box<dyn std::error::Error>> {
         let bucket = env::var("AWS_S3_BUCKET")?;
-        
+
         let upload_expire = env::var("AWS_S3_UPLOAD_EXPIRE")?
             .parse::<u64>()?;
 
@@ -30,8 +41,15 @@ impl S3Client {
             download_expire,
         })
     }
+}
+
+#[async_trait]
+impl Storage for S3Client {
+    fn upload_expire(&self) -> u64 {
+        self.upload_expire
+    }
 
-    pub async fn upload_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
+    async fn upload_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
         let presign_config = PresigningConfig::expires_in(Duration::from_secs(self.upload_expire))?;
         let presigned_req = self.client
             .put_object()
@@ -42,7 +60,7 @@ impl S3Client {
         Ok(presigned_req.uri().to_string())
     }
 
-    pub async fn download_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
+    async fn download_url(&self, key: &str) -> Result<String, Box<dyn Error>> {
         let presign_config = PresigningConfig::expires_in(Duration::from_secs(self.download_expire))?;
         let presigned_req = self.client
             .get_object()
@@ -53,7 +71,7 @@ impl S3Client {
         Ok(presigned_req.uri().to_string())
     }
 
-    pub async fn delete_object(&self, key: &str) -> Result<(), Box<dyn Error>> {
+    async fn delete_object(&self, key: &str) -> Result<(), Box<dyn Error>> {
         self.client.delete_object()
             .bucket(&self.bucket)
             .key(key)
@@ -61,4 +79,255 @@ impl S3Client {
             .await?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn head_object(&self, key: &str) -> Result<Option<ObjectMeta>, Box<dyn Error>> {
+        let output = match self.client.head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .checksum_mode(ChecksumMode::Enabled)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(ObjectMeta {
+            size: output.content_length.unwrap_or(0),
+            content_type: output.content_type,
+            etag: output.e_tag.map(|tag| tag.trim_matches('"').to_string()),
+            sha256: output.checksum_sha256
+                .filter(|checksum| !checksum.contains('-'))
+                .and_then(|checksum| STANDARD.decode(checksum).ok())
+                .map(hex::encode),
+            last_modified: output.last_modified
+                .and_then(|t| chrono::DateTime::from_timestamp(t.secs(), t.subsec_nanos())),
+        }))
+    }
+
+    async fn put_object(&self, key: &str, body: Vec<u8>, content_type: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.client.put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_content_type(content_type.map(|s| s.to_string()))
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let output = match self.client.get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let body = output.body.collect().await?;
+        Ok(Some(body.into_bytes().to_vec()))
+    }
+
+    async fn read_object(&self, key: &str, range: Range<u64>) -> Result<Option<ObjectStream>, Box<dyn Error>> {
+        let output = match self.client.get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .range(format!("bytes={}-{}", range.start, range.end.saturating_sub(1)))
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // The stream ends after the first error rather than polling a failed body again.
+        let stream = futures_util::stream::unfold(Some(output.body), |body| async move {
+            let mut body = body?;
+            match body.next().await? {
+                Ok(bytes) => Some((Ok(bytes), Some(body))),
+                Err(e) => Some((Err(std::io::Error::other(e)), None)),
+            }
+        });
+        Ok(Some(Box::pin(stream)))
+    }
+
+    async fn copy_object(&self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        let source = format!("{}/{}", self.bucket, from);
+        let meta = self.head_object(from).await?.ok_or_else(|| format!("No such object {}", from))?;
+
+        if meta.size <= MAX_COPY_SIZE {
+            self.client.copy_object()
+                .bucket(&self.bucket)
+                .key(to)
+                .copy_source(&source)
+                .send()
+                .await?;
+            return Ok(());
+        }
+
+        // CopyObject stops at 5 GiB; larger objects are copied range by range.
+        let upload_id = self.create_multipart_upload(to, meta.content_type.as_deref()).await?;
+        let mut parts = Vec::new();
+        let mut start = 0;
+        while start < meta.size {
+            let end = (start + COPY_PART_SIZE).min(meta.size) - 1;
+            let part_number = parts.len() as i32 + 1;
+            let result = self.client.upload_part_copy()
+                .bucket(&self.bucket)
+                .key(to)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .copy_source(&source)
+                .copy_source_range(format!("bytes={}-{}", start, end))
+                .send()
+                .await;
+            let etag = match result {
+                Ok(output) => output.copy_part_result.and_then(|r| r.e_tag),
+                Err(e) => {
+                    self.abort_multipart_upload(to, &upload_id).await?;
+                    return Err(e.into());
+                }
+            };
+            let Some(etag) = etag else {
+                self.abort_multipart_upload(to, &upload_id).await?;
+                return Err("S3 did not return a copied part ETag".into());
+            };
+            parts.push((part_number, etag.trim_matches('"').to_string()));
+            start = end + 1;
+        }
+
+        self.complete_multipart_upload(to, &upload_id, &parts).await
+    }
+
+    async fn list_objects(&self, continuation_token: Option<String>) -> Result<ObjectPage, Box<dyn Error>> {
+        let output = self.client.list_objects_v2()
+            .bucket(&self.bucket)
+            .set_continuation_token(continuation_token)
+            .send()
+            .await?;
+
+        let objects = output.contents
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|object| Some(ObjectEntry {
+                key: object.key?,
+                size: object.size.unwrap_or(0),
+                last_modified: object.last_modified
+                    .and_then(|t| chrono::DateTime::from_timestamp(t.secs(), t.subsec_nanos())),
+            }))
+            .collect();
+
+        Ok(ObjectPage {
+            objects,
+            next_token: output.next_continuation_token,
+        })
+    }
+
+    async fn create_multipart_upload(&self, key: &str, content_type: Option<&str>) -> Result<String, Box<dyn Error>> {
+        let output = self.client.create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_content_type(content_type.map(|s| s.to_string()))
+            .send()
+            .await?;
+
+        output.upload_id.ok_or_else(|| "S3 did not return an upload id".into())
+    }
+
+    async fn upload_part_url(&self, key: &str, upload_id: &str, part_number: i32) -> Result<String, Box<dyn Error>> {
+        let presign_config = PresigningConfig::expires_in(Duration::from_secs(self.upload_expire))?;
+        let presigned_req = self.client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .presigned(presign_config)
+            .await?;
+        Ok(presigned_req.uri().to_string())
+    }
+
+    async fn put_part(&self, key: &str, upload_id: &str, part_number: i32, body: Vec<u8>) -> Result<String, Box<dyn Error>> {
+        let output = self.client.upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+
+        output.e_tag
+            .map(|etag| etag.trim_matches('"').to_string())
+            .ok_or_else(|| "S3 did not return a part ETag".into())
+    }
+
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>, Box<dyn Error>> {
+        let mut parts = Vec::new();
+        let mut marker = None;
+
+        loop {
+            let output = self.client.list_parts()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker)
+                .send()
+                .await?;
+
+            parts.extend(output.parts().iter().filter_map(|part| Some(UploadedPart {
+                part_number: part.part_number()?,
+                etag: part.e_tag()?.trim_matches('"').to_string(),
+                size: part.size().unwrap_or(0),
+            })));
+
+            match output.next_part_number_marker().filter(|_| output.is_truncated().unwrap_or(false)) {
+                Some(next) => marker = Some(next.to_string()),
+                None => break,
+            }
+        }
+
+        Ok(parts)
+    }
+
+    async fn complete_multipart_upload(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<(), Box<dyn Error>> {
+        let completed = CompletedMultipartUpload::builder()
+            .set_parts(Some(parts
+                .iter()
+                .map(|(part_number, etag)| CompletedPart::builder()
+                    .part_number(*part_number)
+                    .e_tag(format!("\"{}\"", etag.trim_matches('"')))
+                    .build())
+                .collect()))
+            .build();
+
+        self.client.complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(completed)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), Box<dyn Error>> {
+        match self.client.abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_upload()) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
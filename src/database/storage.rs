@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::ops::Range;
+use std::pin::Pin;
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use futures_util::Stream;
+
+#[derive(Debug, Clone)]
+pub struct ObjectMeta {
+    pub size: i64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub sha256: Option<String>,
+    pub last_modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectEntry {
+    pub key: String,
+    pub size: i64,
+    pub last_modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ObjectPage {
+    pub objects: Vec<ObjectEntry>,
+    pub next_token: Option<String>,
+}
+
+/// Object contents read incrementally, so large downloads never sit in memory whole.
+pub type ObjectStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// One part already stored for a multipart upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: i64,
+}
+
+/// Part numbers follow the S3 limits, which every backend enforces the same way.
+pub const MIN_PART_NUMBER: i32 = 1;
+pub const MAX_PART_NUMBER: i32 = 10000;
+/// Smallest size S3 accepts for any part but the last.
+pub const MIN_PART_SIZE: i64 = 5 * 1024 * 1024;
+/// Deduplicated contents live under this prefix, keyed by their SHA-256.
+pub const BLOB_PREFIX: &str = "blobs/";
+pub fn blob_key(sha256: &str) -> String {
+    format!("{}{}", BLOB_PREFIX, sha256)
+}
+
+/// Keys under this prefix hold server-side scratch data rather than file contents.
+pub const STAGING_PREFIX: &str = "staging/";
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    fn upload_expire(&self) -> u64;
+
+    async fn upload_url(&self, key: &str) -> Result<String, Box<dyn Error>>;
+
+    async fn download_url(&self, key: &str) -> Result<String, Box<dyn Error>>;
+
+    async fn delete_object(&self, key: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn head_object(&self, key: &str) -> Result<Option<ObjectMeta>, Box<dyn Error>>;
+
+    async fn put_object(&self, key: &str, body: Vec<u8>, content_type: Option<&str>) -> Result<(), Box<dyn Error>>;
+
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+
+    /// Streams the bytes of `key` within `range`, which must lie inside the object.
+    async fn read_object(&self, key: &str, range: Range<u64>) -> Result<Option<ObjectStream>, Box<dyn Error>>;
+
+    /// Copies an object within the store, keeping its content type.
+    async fn copy_object(&self, from: &str, to: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn list_objects(&self, continuation_token: Option<String>) -> Result<ObjectPage, Box<dyn Error>>;
+
+    /// Starts a multipart upload for `key` and returns the backend's upload id.
+    async fn create_multipart_upload(&self, key: &str, content_type: Option<&str>) -> Result<String, Box<dyn Error>>;
+
+    /// Presigns a PUT for one part; the response to that PUT carries the part's ETag.
+    async fn upload_part_url(&self, key: &str, upload_id: &str, part_number: i32) -> Result<String, Box<dyn Error>>;
+
+    /// Uploads one part from the server itself and returns its ETag.
+    async fn put_part(&self, key: &str, upload_id: &str, part_number: i32, body: Vec<u8>) -> Result<String, Box<dyn Error>>;
+
+    /// Parts stored so far, in part-number order.
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>, Box<dyn Error>>;
+
+    /// Assembles the listed parts, in order, into the object at `key`.
+    async fn complete_multipart_upload(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<(), Box<dyn Error>>;
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), Box<dyn Error>>;
+}
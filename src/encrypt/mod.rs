@@ -1,3 +1,9 @@
-pub mod bcrypt;
+pub mod password;
+pub mod token;
+pub mod totp;
+pub mod webauthn;
 
-pub use bcrypt::Bcrypt;
\ No newline at end of file
+pub use password::PasswordHasher;
+pub use token::SecretToken;
+pub use totp::Totp;
+pub use webauthn::RelyingParty;
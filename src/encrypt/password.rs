@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::sync::OnceLock;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordVerifier, Version};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHasher as _, SaltString};
+
+/// Why a new password was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordRejection {
+    TooShort { length: usize, min: usize },
+    TooLong { length: usize, max: usize },
+    Breached,
+}
+
+impl PasswordRejection {
+    pub fn message(&self) -> String {
+        match self {
+            Self::TooShort { min, .. } => format!("Password must be at least {} characters", min),
+            Self::TooLong { max, .. } => format!("Password must be at most {} characters", max),
+            Self::Breached => "Password appears in a list of breached passwords".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PasswordRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort { length, min } => write!(f, "Password length is {}, minimum required is {}", length, min),
+            Self::TooLong { length, max } => write!(f, "Password length is {}, maximum allowed is {}", length, max),
+            Self::Breached => write!(f, "Choose a password that has not been exposed in a data breach"),
+        }
+    }
+}
+
+impl Error for PasswordRejection {}
+
+/// Rules a password must meet when it is set; existing hashes are never re-checked.
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// Bounds the hashing work a single request can ask for.
+    pub max_length: usize,
+    breached: HashSet<String>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self { min_length: 8, max_length: 1024, breached: HashSet::new() }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let default = Self::default();
+        let mut policy = Self {
+            min_length: match env::var("PASSWORD_MIN_LENGTH") {
+                Ok(value) => value.parse()?,
+                Err(_) => default.min_length,
+            },
+            max_length: match env::var("PASSWORD_MAX_LENGTH") {
+                Ok(value) => value.parse()?,
+                Err(_) => default.max_length,
+            },
+            breached: HashSet::new(),
+        };
+
+        if let Ok(path) = env::var("PASSWORD_BREACHED_LIST").map(|p| p.trim().to_string()) {
+            if !path.is_empty() {
+                let list = fs::read_to_string(&path).map_err(|e| format!("Failed to read PASSWORD_BREACHED_LIST {}: {}", path, e))?;
+                policy = policy.with_breached(list.lines());
+                println!("✓ Loaded {} breached passwords from {}", policy.breached.len(), path);
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Adds passwords that must never be chosen, one entry per item; blank entries are skipped.
+    pub fn with_breached<'a>(mut self, passwords: impl IntoIterator<Item = &'a str>) -> Self {
+        self.breached.extend(
+            passwords
+                .into_iter()
+                .map(|p| p.trim_end_matches('\r'))
+                .filter(|p| !p.is_empty())
+                .map(|p| p.to_string()),
+        );
+        self
+    }
+
+    pub fn check(&self, plain: &str) -> Result<(), PasswordRejection> {
+        let length = plain.chars().count();
+        if length < self.min_length {
+            return Err(PasswordRejection::TooShort { length, min: self.min_length });
+        }
+        if length > self.max_length {
+            return Err(PasswordRejection::TooLong { length, max: self.max_length });
+        }
+        if self.breached.contains(plain) {
+            return Err(PasswordRejection::Breached);
+        }
+        Ok(())
+    }
+}
+
+/// Hashes new passwords with Argon2id and verifies both Argon2id and legacy bcrypt hashes.
+pub struct PasswordHasher {
+    params: Params,
+    policy: PasswordPolicy,
+    /// Hash of a throwaway password, verified against when there is no real hash so that
+    /// unknown accounts take as long as known ones.
+    dummy_hash: OnceLock<String>,
+}
+
+impl Default for PasswordHasher {
+    fn default() -> Self {
+        Self::new(Params::default(), PasswordPolicy::default())
+    }
+}
+
+impl PasswordHasher {
+    pub fn new(params: Params, policy: PasswordPolicy) -> Self {
+        Self { params, policy, dummy_hash: OnceLock::new() }
+    }
+
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let default = Params::default();
+        let read = |name: &str, default: u32| -> Result<u32, Box<dyn Error>> {
+            match env::var(name) {
+                Ok(value) => Ok(value.parse()?),
+                Err(_) => Ok(default),
+            }
+        };
+
+        let params = Params::new(
+            read("ARGON2_MEMORY_KIB", default.m_cost())?,
+            read("ARGON2_ITERATIONS", default.t_cost())?,
+            read("ARGON2_PARALLELISM", default.p_cost())?,
+            None,
+        )
+        .map_err(|e| format!("Invalid Argon2 parameters: {}", e))?;
+
+        Ok(Self::new(params, PasswordPolicy::new_from_env()?))
+    }
+
+    pub fn policy(&self) -> &PasswordPolicy {
+        &self.policy
+    }
+
+    fn argon2(&self) -> Argon2<'_> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Checks the policy and hashes a password that is about to be stored.
+    pub fn hash(&self, plain: &str) -> Result<String, Box<dyn Error>> {
+        self.policy.check(plain)?;
+        self.rehash(plain)
+    }
+
+    /// Hashes a password that already verified, without holding it to the current policy.
+    pub fn rehash(&self, plain: &str) -> Result<String, Box<dyn Error>> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.argon2().hash_password(plain.as_bytes(), &salt).map_err(|e| e.to_string())?;
+        Ok(hash.to_string())
+    }
+
+    /// Verifies against either hash format; anything unrecognised (such as an account without a
+    /// password) never matches.
+    pub fn verify(&self, plain: &str, hash: &str) -> Result<bool, Box<dyn Error>> {
+        if plain.is_empty() || hash.is_empty() {
+            return Err("plain and hash cannot be empty".into());
+        }
+
+        if is_bcrypt(hash) {
+            return Ok(bcrypt::verify(plain, hash)?);
+        }
+
+        let parsed = match PasswordHash::new(hash) {
+            Ok(parsed) => parsed,
+            Err(_) => return Ok(false),
+        };
+        match self.argon2().verify_password(plain.as_bytes(), &parsed) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(e.to_string().into()),
+        }
+    }
+
+    /// Spends the same effort as a real verification and always fails.
+    pub fn verify_dummy(&self, plain: &str) -> bool {
+        let hash = self.dummy_hash.get_or_init(|| {
+            self.rehash("dummy-password-for-timing").unwrap_or_default()
+        });
+        let _ = self.verify(plain, hash);
+        false
+    }
+
+    /// Whether a hash that just verified should be replaced: it is bcrypt, or Argon2 with
+    /// parameters other than the configured ones.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        if is_bcrypt(hash) {
+            return true;
+        }
+
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return false;
+        };
+        if parsed.algorithm != Algorithm::Argon2id.ident() || parsed.version != Some(Version::V0x13.into()) {
+            return true;
+        }
+        match Params::try_from(&parsed) {
+            Ok(params) => {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix))
+}
@@ -0,0 +1,19 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+const TOKEN_BYTES: usize = 32;
+
+/// Bearer-style secrets that are handed to the client once and only ever stored as a digest.
+pub struct SecretToken;
+
+impl SecretToken {
+    pub fn generate() -> String {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        rand::rng().fill_bytes(&mut bytes);
+        hex::encode(bytes)
+    }
+
+    pub fn digest(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+}
@@ -0,0 +1,220 @@
+use std::env;
+use std::error::Error;
+use std::io::Cursor;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ciborium::Value;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::ecdsa::signature::Verifier;
+use p256::EncodedPoint;
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const CHALLENGE_BYTES: usize = 32;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+const COSE_KTY: i128 = 1;
+const COSE_ALG: i128 = 3;
+const COSE_EC2_CRV: i128 = -1;
+const COSE_EC2_X: i128 = -2;
+const COSE_EC2_Y: i128 = -3;
+const COSE_KTY_EC2: i128 = 2;
+const COSE_CRV_P256: i128 = 1;
+/// ES256, the only algorithm offered in `pubKeyCredParams`.
+pub const COSE_ALG_ES256: i64 = -7;
+
+/// The site passkeys are scoped to.
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origin: String,
+}
+
+impl Default for RelyingParty {
+    fn default() -> Self {
+        Self {
+            id: "localhost".to_string(),
+            name: "Cloud File Storage".to_string(),
+            origin: "http://localhost:9000".to_string(),
+        }
+    }
+}
+
+impl RelyingParty {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let default = Self::default();
+
+        Ok(Self {
+            id: env::var("WEBAUTHN_RP_ID").unwrap_or(default.id),
+            name: env::var("WEBAUTHN_RP_NAME").unwrap_or(default.name),
+            origin: env::var("WEBAUTHN_ORIGIN").unwrap_or(default.origin),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// The fields of `authenticatorData` this server looks at.
+pub struct AuthenticatorData {
+    pub flags: u8,
+    pub sign_count: u32,
+    /// Credential id and SEC1-encoded public key, present on registration only.
+    pub attested: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl AuthenticatorData {
+    pub fn user_verified(&self) -> bool {
+        self.flags & FLAG_USER_VERIFIED != 0
+    }
+}
+
+/// A checked registration response.
+pub struct NewCredential {
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+/// A fresh ceremony challenge, base64url-encoded as it appears in `clientDataJSON`.
+pub fn generate_challenge() -> String {
+    let mut bytes = [0u8; CHALLENGE_BYTES];
+    rand::rng().fill_bytes(&mut bytes);
+    encode_b64url(&bytes)
+}
+
+pub fn decode_b64url(value: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(URL_SAFE_NO_PAD.decode(value.trim_end_matches('='))?)
+}
+
+pub fn encode_b64url(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+impl RelyingParty {
+    /// Parses `clientDataJSON`, checks its ceremony type and origin, and returns it with the challenge it signs.
+    pub fn client_data(&self, client_data_json: &[u8], kind: &str) -> Result<String, Box<dyn Error>> {
+        let client_data: ClientData = serde_json::from_slice(client_data_json)?;
+
+        if client_data.kind != kind {
+            return Err(format!("Expected a {} ceremony, got {}", kind, client_data.kind).into());
+        }
+        if client_data.origin != self.origin {
+            return Err(format!("Origin {} is not {}", client_data.origin, self.origin).into());
+        }
+
+        Ok(client_data.challenge)
+    }
+
+    /// Parses `authenticatorData`, requiring it to be scoped to this relying party and made with the user present.
+    pub fn authenticator_data(&self, data: &[u8]) -> Result<AuthenticatorData, Box<dyn Error>> {
+        if data.len() < 37 {
+            return Err("authenticatorData is truncated".into());
+        }
+        if data[..32] != Sha256::digest(self.id.as_bytes())[..] {
+            return Err("authenticatorData belongs to a different relying party".into());
+        }
+
+        let flags = data[32];
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err("The user was not present".into());
+        }
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            // aaguid (16) | credentialIdLength (2) | credentialId | credentialPublicKey (COSE)
+            let rest = data.get(37 + 16..).ok_or("Attested credential data is truncated")?;
+            let id_len = u16::from_be_bytes([*rest.first().ok_or("Truncated")?, *rest.get(1).ok_or("Truncated")?]) as usize;
+            let credential_id = rest.get(2..2 + id_len).ok_or("Credential id is truncated")?.to_vec();
+            let key: Value = ciborium::from_reader(Cursor::new(&rest[2 + id_len..]))?;
+            Some((credential_id, cose_to_sec1(&key)?))
+        } else {
+            None
+        };
+
+        Ok(AuthenticatorData { flags, sign_count, attested })
+    }
+
+    /// Verifies a `navigator.credentials.create()` response and returns the challenge it answered.
+    ///
+    /// Attestation statements are not checked: registration asks for `"none"` attestation, so the
+    /// authenticator's make and model are taken on trust, as most relying parties do.
+    pub fn verify_registration(
+        &self,
+        client_data_json: &[u8],
+        attestation_object: &[u8],
+    ) -> Result<(String, NewCredential), Box<dyn Error>> {
+        let challenge = self.client_data(client_data_json, "webauthn.create")?;
+
+        let attestation: Value = ciborium::from_reader(attestation_object)?;
+        let auth_data = map_get(&attestation, |k| k.as_text() == Some("authData"))
+            .and_then(Value::as_bytes)
+            .ok_or("attestationObject has no authData")?;
+
+        let data = self.authenticator_data(auth_data)?;
+        let (credential_id, public_key) = data.attested.ok_or("Registration carries no credential")?;
+
+        Ok((challenge, NewCredential { credential_id, public_key, sign_count: data.sign_count }))
+    }
+
+    /// Verifies a `navigator.credentials.get()` response against the stored public key.
+    pub fn verify_assertion(
+        &self,
+        public_key: &[u8],
+        client_data_json: &[u8],
+        authenticator_data: &[u8],
+        signature: &[u8],
+    ) -> Result<(String, AuthenticatorData), Box<dyn Error>> {
+        let challenge = self.client_data(client_data_json, "webauthn.get")?;
+        let data = self.authenticator_data(authenticator_data)?;
+
+        let key = VerifyingKey::from_sec1_bytes(public_key)?;
+        let signature = Signature::from_der(signature)?;
+        let mut signed = authenticator_data.to_vec();
+        signed.extend_from_slice(&Sha256::digest(client_data_json));
+        key.verify(&signed, &signature).map_err(|_| "Assertion signature is invalid")?;
+
+        Ok((challenge, data))
+    }
+}
+
+fn map_get(map: &Value, key: impl Fn(&Value) -> bool) -> Option<&Value> {
+    map.as_map()?.iter().find(|(k, _)| key(k)).map(|(_, v)| v)
+}
+
+fn cose_int(map: &Value, label: i128) -> Option<&Value> {
+    map_get(map, |k| k.as_integer().is_some_and(|i| i128::from(i) == label))
+}
+
+/// Converts an ES256 COSE_Key into an uncompressed SEC1 point.
+fn cose_to_sec1(key: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    let int = |label| cose_int(key, label).and_then(Value::as_integer).map(i128::from);
+
+    if int(COSE_KTY) != Some(COSE_KTY_EC2) || int(COSE_EC2_CRV) != Some(COSE_CRV_P256) {
+        return Err("Only P-256 EC2 keys are supported".into());
+    }
+    if int(COSE_ALG) != Some(COSE_ALG_ES256 as i128) {
+        return Err("Only ES256 credentials are supported".into());
+    }
+
+    let x = cose_int(key, COSE_EC2_X).and_then(Value::as_bytes).ok_or("COSE key has no x coordinate")?;
+    let y = cose_int(key, COSE_EC2_Y).and_then(Value::as_bytes).ok_or("COSE key has no y coordinate")?;
+    if x.len() != 32 || y.len() != 32 {
+        return Err("COSE key coordinates must be 32 bytes".into());
+    }
+
+    let point = EncodedPoint::from_affine_coordinates(x.as_slice().into(), y.as_slice().into(), false);
+    // Reject points that are not on the curve now rather than at login.
+    VerifyingKey::from_encoded_point(&point)?;
+    Ok(point.as_bytes().to_vec())
+}
@@ -0,0 +1,139 @@
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tokio::io::AsyncWriteExt;
+
+const DEFAULT_FROM: &str = "Cloud File Storage <no-reply@localhost>";
+
+/// A plain-text message to a single recipient.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), Box<dyn Error>>;
+}
+
+/// Picks the transport from `MAIL_TRANSPORT`: `smtp`, or `log` (the default) for development.
+pub fn mailer_from_env() -> Result<Arc<dyn Mailer>, Box<dyn Error>> {
+    let transport = env::var("MAIL_TRANSPORT").unwrap_or_else(|_| "log".to_string());
+
+    match transport.as_str() {
+        "smtp" => Ok(Arc::new(SmtpMailer::new_from_env()?)),
+        "log" => Ok(Arc::new(LogMailer::new(env::var("MAIL_LOG_PATH").ok().filter(|p| !p.is_empty())))),
+        other => Err(format!("MAIL_TRANSPORT must be smtp or log, got {}", other).into()),
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let host = env::var("SMTP_HOST").map_err(|_| "SMTP_HOST is required when MAIL_TRANSPORT=smtp")?;
+        let tls = env::var("SMTP_TLS").unwrap_or_else(|_| "starttls".to_string());
+
+        let mut builder = match tls.as_str() {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)?,
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+            other => return Err(format!("SMTP_TLS must be starttls, tls or none, got {}", other).into()),
+        };
+        if let Ok(port) = env::var("SMTP_PORT") {
+            builder = builder.port(port.parse()?);
+        }
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: env::var("MAIL_FROM").unwrap_or_else(|_| DEFAULT_FROM.to_string()).parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), Box<dyn Error>> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(&email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+/// Writes messages to a file, or to stdout when no path is set, instead of delivering them.
+pub struct LogMailer {
+    path: Option<String>,
+}
+
+impl LogMailer {
+    pub fn new(path: Option<String>) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<(), Box<dyn Error>> {
+        let entry = format!(
+            "--- {}\nTo: {}\nSubject: {}\n\n{}\n\n",
+            chrono::Utc::now().to_rfc3339(),
+            email.to,
+            email.subject,
+            email.body
+        );
+
+        match &self.path {
+            Some(path) => {
+                let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+                file.write_all(entry.as_bytes()).await?;
+            }
+            None => println!("✓ Mail (not delivered)\n{}", entry),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryMailer {
+    sent: std::sync::Mutex<Vec<Email>>,
+}
+
+#[cfg(test)]
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, email: &Email) -> Result<(), Box<dyn Error>> {
+        self.sent.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+pub mod mailer;
+
+pub use mailer::{mailer_from_env, Email, LogMailer, Mailer};
+#[cfg(test)]
+pub use mailer::MemoryMailer;
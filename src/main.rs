@@ -1,17 +1,32 @@
 mod router;
+mod captcha;
 mod unique;
 mod encrypt;
 mod service;
 mod database;
 mod middleware;
+mod task;
+mod oidc;
+mod mail;
+
+#[cfg(test)]
+mod tests;
 
 use std::env;
+use std::sync::Arc;
 use dotenv::dotenv;
 use std::error::Error;
 use unique::Snowflake;
 use actix_cors::Cors;
 use router::{configure, AppState};
-use database::{S3Client, MySQLClient};
+use task::{Reaper, ReaperConfig, SessionPurger};
+use middleware::{LoginPolicy, SessionPolicy, TrustedProxies};
+use encrypt::{PasswordHasher, RelyingParty};
+use oidc::{HyperHttpClient, OidcConfig, OidcProvider};
+use mail::mailer_from_env;
+use service::download::DownloadMode;
+use service::tus::TusConfig;
+use database::{S3Client, LocalStorage, Storage, MySQLClient, Repository};
 use cf_turnstile::TurnstileClient;
 use actix_web::{web, App, HttpServer, middleware as actix_middleware};
 use rustls::crypto::CryptoProvider;
@@ -23,28 +38,96 @@ async fn main() -> Result<(), Box<dyn Error>> {
     CryptoProvider::install_default(ring::default_provider()).unwrap();
     dotenv().ok();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
-    let s3client = S3Client::new_from_env(config)?;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        let mysql = MySQLClient::new_from_env().await?;
+        return mysql.migrator().run_command(&args[1..]).await;
+    }
+
+    let mut local_storage = None;
+    let storage: Arc<dyn Storage> = match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("local") => {
+            let local = Arc::new(LocalStorage::new_from_env()?);
+            local_storage = Some(web::Data::from(local.clone()));
+            local
+        }
+        Ok("s3") | Err(_) => {
+            let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+            Arc::new(S3Client::new_from_env(config)?)
+        }
+        Ok(other) => return Err(format!("Unknown STORAGE_BACKEND '{}'", other).into()),
+    };
+
     let snowflake = Snowflake::new_from_env()?;
     let mysql = MySQLClient::new_from_env().await?;
-    mysql.init_database("schema.sql").await?;
+    if env::var("MIGRATE_ON_START").map(|v| v != "false").unwrap_or(true) {
+        mysql.migrator().up(None).await?;
+    }
+    let db: Arc<dyn Repository> = Arc::new(mysql);
+
+    Reaper::new(db.clone(), storage.clone(), ReaperConfig::new_from_env()?).spawn();
+
+    let session_policy = SessionPolicy::new_from_env()?;
+    let login_policy = LoginPolicy::new_from_env()?;
+    SessionPurger::new(db.clone(), session_policy.clone())
+        .with_login_window(login_policy.window)
+        .spawn();
 
     let secret = env::var("CLOUDFLARE_TURNSTILE_SECRET")?;
     let sitekey = env::var("CLOUDFLARE_TURNSTILE_SITE_KEY")?;
     
-    let turnstile = TurnstileClient::new(secret.into());
+    let turnstile = Arc::new(TurnstileClient::new(secret.into()));
+
+    let mut app_state = AppState::new(db, storage, snowflake, turnstile, sitekey)
+        .with_session_policy(session_policy)
+        .with_login_policy(login_policy)
+        .with_trusted_proxies(TrustedProxies::new_from_env()?)
+        .with_relying_party(RelyingParty::new_from_env()?)
+        .with_password_hasher(PasswordHasher::new_from_env()?)
+        .with_tus(TusConfig::new_from_env()?)
+        .with_download_mode(DownloadMode::new_from_env()?)
+        .with_mailer(mailer_from_env()?, env::var("PUBLIC_URL").unwrap_or_else(|_| "http://localhost:9000".to_string()))
+        .with_email_verification(match env::var("REQUIRE_EMAIL_VERIFICATION") {
+            Ok(v) => v.parse()?,
+            Err(_) => true,
+        });
+
+    if let Some(config) = OidcConfig::new_from_env()? {
+        println!("✓ Single sign-on enabled for {}", config.issuer);
+        app_state = app_state.with_oidc(OidcProvider::new(config, Arc::new(HyperHttpClient::new()?)));
+    }
 
-    let app_state = web::Data::new(AppState::new(mysql, s3client, snowflake, turnstile, sitekey));
+    let app_state = web::Data::new(app_state);
 
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
             .allow_any_header()
+            .expose_headers([
+                "ETag",
+                "Content-Disposition",
+                "Content-Range",
+                "Accept-Ranges",
+                "Location",
+                "Upload-Offset",
+                "Upload-Length",
+                "Tus-Resumable",
+                "Tus-Version",
+                "Tus-Extension",
+                "Tus-Max-Size",
+                "Tus-Checksum-Algorithm",
+            ])
             .max_age(3600);
 
         App::new()
             .app_data(app_state.clone())
+            .configure(|cfg| {
+                if let Some(local) = &local_storage {
+                    cfg.app_data(local.clone());
+                }
+            })
             .wrap(cors)
             .wrap(actix_middleware::Logger::default())
             .configure(configure)
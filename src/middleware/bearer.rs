@@ -1,49 +1,204 @@
 use crate::router::AppState;
 use std::error::Error;
+use std::fmt;
 use crate::database::Session;
-use actix_web::{web, HttpRequest};
+use crate::database::model::{AccessToken, Scope};
+use crate::encrypt::SecretToken;
+use super::session::SessionExpiry;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
 
-pub fn get_client_ip(req: &HttpRequest) -> String {
-    if let Some(forwarded) = req.headers().get("X-Forwarded-For") {
-        if let Ok(forwarded_str) = forwarded.to_str() {
-            if let Some(ip) = forwarded_str.split(',').next() {
-                return ip.trim().to_string();
-            }
+#[derive(Debug)]
+pub enum AuthError {
+    Invalid(String),
+    Expired(SessionExpiry),
+    TokenExpired,
+    MissingScope(Scope),
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::Invalid(_) => "AUTH_FAILED",
+            AuthError::Expired(_) => "SESSION_EXPIRED",
+            AuthError::TokenExpired => "TOKEN_EXPIRED",
+            AuthError::MissingScope(_) => "INSUFFICIENT_SCOPE",
         }
     }
-    if let Some(real_ip) = req.headers().get("X-Real-IP") {
-        if let Ok(ip_str) = real_ip.to_str() {
-            return ip_str.to_string();
+
+    pub fn to_response(&self) -> HttpResponse {
+        let message = match self {
+            AuthError::Invalid(_) => "Authentication failed",
+            AuthError::Expired(_) => "Session has expired",
+            AuthError::TokenExpired => "Access token has expired",
+            AuthError::MissingScope(_) => "Access token lacks the required scope",
+        };
+
+        let mut status = match self {
+            AuthError::MissingScope(_) => HttpResponse::Forbidden(),
+            _ => HttpResponse::Unauthorized(),
+        };
+
+        status.json(json!({
+            "error": {
+                "code": self.code(),
+                "message": message,
+                "detail": self.to_string()
+            }
+        }))
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Invalid(detail) => f.write_str(detail),
+            AuthError::Expired(expiry) => write!(f, "Session exceeded its {}, please log in again", expiry.as_str()),
+            AuthError::TokenExpired => f.write_str("The access token is past its expiry date"),
+            AuthError::MissingScope(scope) => write!(f, "This endpoint requires the {} scope", scope.as_str()),
         }
     }
-    req.peer_addr()
-        .map(|addr| addr.ip().to_string())
+}
+
+impl Error for AuthError {}
+
+pub fn get_client_ip(state: &AppState, req: &HttpRequest) -> String {
+    state.trusted_proxies
+        .client_ip(req)
+        .map(|ip| ip.to_string())
         .unwrap_or_else(|| "unknown".to_string())
 }
 
-pub async fn bearer_auth(
-    state: web::Data<AppState>,
-    req: &HttpRequest,
-) -> Result<Session, Box<dyn Error>> {
-    let session_key = if let Some(auth_header) = req.headers().get("Authorization") {
+/// Prefix that tells personal access tokens apart from interactive session keys.
+pub const ACCESS_TOKEN_PREFIX: &str = "cfs_pat_";
+
+/// The credential a request authenticated with.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    Session(Session),
+    AccessToken(AccessToken),
+}
+
+/// The user a request acts for.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user_id: String,
+    pub credential: Credential,
+}
+
+impl Principal {
+    /// Sessions may do anything their user can; access tokens only what their scopes allow.
+    pub fn allows(&self, scope: Scope) -> bool {
+        match &self.credential {
+            Credential::Session(_) => true,
+            Credential::AccessToken(token) => token.has_scope(scope),
+        }
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Result<String, AuthError> {
+    if let Some(auth_header) = req.headers().get("Authorization") {
         if let Ok(auth_str) = auth_header.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                auth_str[7..].to_string()
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                Ok(token.to_string())
             } else {
-                return Err("Missing Bearer token".into());
+                Err(AuthError::Invalid("Missing Bearer token".to_string()))
             }
         } else {
-            return Err("Invalid Authorization header format".into());
+            Err(AuthError::Invalid("Invalid Authorization header format".to_string()))
         }
     } else {
-        return Err("Missing Authorization header".into());
+        Err(AuthError::Invalid("Missing Authorization header".to_string()))
+    }
+}
+
+/// Authenticates a session or a personal access token carrying `scope`.
+pub async fn bearer_auth(
+    state: web::Data<AppState>,
+    req: &HttpRequest,
+    scope: Scope,
+) -> Result<Principal, AuthError> {
+    let token = bearer_token(req)?;
+
+    let principal = if token.starts_with(ACCESS_TOKEN_PREFIX) {
+        let access_token = verify_access_token(&state, &token).await?;
+        Principal { user_id: access_token.user_id.clone(), credential: Credential::AccessToken(access_token) }
+    } else {
+        let session = verify_session(&state, req, &token).await?;
+        Principal { user_id: session.user_id.clone(), credential: Credential::Session(session) }
+    };
+
+    if !principal.allows(scope) {
+        return Err(AuthError::MissingScope(scope));
+    }
+
+    Ok(principal)
+}
+
+/// Authenticates an interactive session only, for endpoints that manage credentials themselves.
+pub async fn session_auth(
+    state: web::Data<AppState>,
+    req: &HttpRequest,
+) -> Result<Session, AuthError> {
+    let session_key = bearer_token(req)?;
+    if session_key.starts_with(ACCESS_TOKEN_PREFIX) {
+        return Err(AuthError::Invalid("Access tokens cannot be used for this endpoint".to_string()));
+    }
+
+    verify_session(&state, req, &session_key).await
+}
+
+async fn verify_access_token(state: &AppState, token: &str) -> Result<AccessToken, AuthError> {
+    let mut access_token = match state.db.get_access_token_by_hash(&SecretToken::digest(token)).await {
+        Ok(Some(access_token)) => access_token,
+        Ok(None) => return Err(AuthError::Invalid("Invalid access token".to_string())),
+        Err(e) => return Err(AuthError::Invalid(e.to_string())),
+    };
+
+    let now = chrono::Utc::now();
+    if access_token.is_expired(now) {
+        return Err(AuthError::TokenExpired);
+    }
+
+    if access_token.last_used_at.is_none_or(|used_at| used_at + state.session_policy.touch_interval <= now) {
+        if let Err(e) = state.db.update_access_token_last_used(&access_token.id, now).await {
+            eprintln!("✗ Failed to touch access token {}: {}", access_token.id, e);
+        } else {
+            access_token.last_used_at = Some(now);
+        }
+    }
+
+    Ok(access_token)
+}
+
+async fn verify_session(state: &AppState, req: &HttpRequest, session_key: &str) -> Result<Session, AuthError> {
+    let ip_address = get_client_ip(state, req);
+    let policy = &state.session_policy;
+
+    let mut session = match state.db.get_session(&SecretToken::digest(session_key)).await {
+        Ok(Some(session)) if policy.ip_allowed(session.ip_address.as_deref(), &ip_address) => session,
+        Ok(_) => return Err(AuthError::Invalid("Invalid session or IP mismatch".to_string())),
+        Err(e) => return Err(AuthError::Invalid(e.to_string())),
     };
 
-    let ip_address = get_client_ip(req);
-    
-    match state.mysql.verify_session(&session_key, &ip_address).await {
-        Ok(Some(session)) => Ok(session),
-        Ok(None) => Err("Invalid session or IP mismatch".into()),
-        Err(e) => Err(e.to_string().into()),
+    let now = chrono::Utc::now();
+
+    if let Some(expiry) = policy.expiry(&session, now) {
+        // The row is useless from here on; the purge task would remove it later anyway.
+        if let Err(e) = state.db.delete_session(&session.key_hash).await {
+            eprintln!("✗ Failed to delete expired session {}: {}", session.id, e);
+        }
+        return Err(AuthError::Expired(expiry));
+    }
+
+    // Only write when the stored timestamp is stale, so busy clients cost one UPDATE per interval.
+    if policy.needs_touch(&session, now) {
+        if let Err(e) = state.db.update_session_access_time(&session.key_hash, now).await {
+            eprintln!("✗ Failed to touch session {}: {}", session.id, e);
+        } else {
+            session.last_accessed_at = now;
+        }
     }
-}
\ No newline at end of file
+
+    Ok(session)
+}
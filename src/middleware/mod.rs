@@ -1,3 +1,9 @@
 pub mod bearer;
+pub mod session;
+pub mod proxy;
+pub mod throttle;
 
-pub use bearer::{get_client_ip, bearer_auth};
\ No newline at end of file
+pub use bearer::{get_client_ip, bearer_auth, session_auth, ACCESS_TOKEN_PREFIX};
+pub use session::SessionPolicy;
+pub use proxy::TrustedProxies;
+pub use throttle::LoginPolicy;
@@ -0,0 +1,164 @@
+use std::env;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use actix_web::HttpRequest;
+
+/// An address block such as `10.0.0.0/8` or `2001:db8::/32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, Box<dyn Error>> {
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(format!("Prefix /{} is too long for {}", prefix, addr).into());
+        }
+
+        Ok(Self { network: mask(addr, prefix), prefix })
+    }
+
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        addr.is_ipv4() == self.network.is_ipv4() && mask(addr, self.prefix) == self.network
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        let prefix = prefix.unwrap_or(if addr.to_canonical().is_ipv4() { 32 } else { 128 });
+
+        Cidr::new(addr, prefix)
+    }
+}
+
+/// Zeroes every bit of `addr` past the first `prefix` bits.
+pub fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let bits = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix as u32) };
+            IpAddr::V4((u32::from(v4) & bits).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix as u32) };
+            IpAddr::V6((u128::from(v6) & bits).into())
+        }
+    }
+}
+
+/// Proxies whose forwarding headers are believed when resolving the client address.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+}
+
+impl TrustedProxies {
+    pub fn new(ranges: Vec<Cidr>) -> Self {
+        Self { ranges }
+    }
+
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let ranges = match env::var("TRUSTED_PROXIES") {
+            Ok(value) => value
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.parse::<Cidr>().map_err(|e| format!("Invalid TRUSTED_PROXIES entry '{}': {}", s.trim(), e)))
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(_) => Vec::new(),
+        };
+
+        Ok(Self { ranges })
+    }
+
+    pub fn is_trusted(&self, addr: &IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(addr))
+    }
+
+    /// Resolves the originating client of `req`.
+    ///
+    /// Forwarding headers are only read when the direct peer is a trusted proxy, and the
+    /// chain is walked right to left so a client cannot pick its own address by prepending
+    /// entries. `Forwarded` (RFC 7239) wins over `X-Forwarded-For`, which wins over `X-Real-IP`.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip().to_canonical();
+        if !self.is_trusted(&peer) {
+            return Some(peer);
+        }
+
+        let chain = forwarded_chain(req)
+            .or_else(|| header_values(req, "X-Forwarded-For").map(|values| {
+                values.iter().flat_map(|v| v.split(',')).map(parse_node).collect()
+            }))
+            .or_else(|| header_values(req, "X-Real-IP").map(|values| {
+                values.last().map(|v| parse_node(v)).into_iter().collect()
+            }));
+
+        let mut client = peer;
+        for hop in chain.unwrap_or_default().into_iter().rev() {
+            // An obfuscated or malformed hop ends what we can vouch for.
+            let Some(hop) = hop else { break };
+            client = hop;
+            if !self.is_trusted(&hop) {
+                break;
+            }
+        }
+
+        Some(client)
+    }
+}
+
+fn header_values(req: &HttpRequest, name: &str) -> Option<Vec<String>> {
+    let values: Vec<String> = req
+        .headers()
+        .get_all(name)
+        .filter_map(|h| h.to_str().ok())
+        .map(|s| s.to_string())
+        .collect();
+
+    if values.is_empty() { None } else { Some(values) }
+}
+
+/// Collects the `for=` node of every element across all `Forwarded` headers, in order.
+fn forwarded_chain(req: &HttpRequest) -> Option<Vec<Option<IpAddr>>> {
+    let values = header_values(req, "Forwarded")?;
+
+    Some(values
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                .and_then(|(_, node)| parse_node(node))
+        })
+        .collect())
+}
+
+/// Parses a forwarding node: `192.0.2.1`, `192.0.2.1:8080`, `[2001:db8::1]:443`, optionally quoted.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(addr) = node.parse::<IpAddr>() {
+        return Some(addr.to_canonical());
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip().to_canonical());
+    }
+
+    node.strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .and_then(|(addr, _)| addr.parse::<IpAddr>().ok())
+        .map(|addr| addr.to_canonical())
+}
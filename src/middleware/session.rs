@@ -0,0 +1,167 @@
+use std::env;
+use std::error::Error;
+use std::net::IpAddr;
+use std::str::FromStr;
+use chrono::{DateTime, Duration, Utc};
+use crate::database::Session;
+use super::proxy::mask;
+
+/// Which lifetime a session ran out of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionExpiry {
+    Absolute,
+    Idle,
+}
+
+impl SessionExpiry {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionExpiry::Absolute => "absolute lifetime",
+            SessionExpiry::Idle => "idle timeout",
+        }
+    }
+}
+
+/// How closely a request's address has to match the one the session was created from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpBinding {
+    Strict,
+    SameSubnet,
+    Off,
+}
+
+impl FromStr for IpBinding {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "strict" => Ok(IpBinding::Strict),
+            "same-subnet" => Ok(IpBinding::SameSubnet),
+            "off" => Ok(IpBinding::Off),
+            other => Err(format!("Unknown IP binding '{}', expected strict, same-subnet or off", other).into()),
+        }
+    }
+}
+
+/// Lifetimes and address binding applied to bearer sessions. A `None` lifetime never expires.
+#[derive(Debug, Clone)]
+pub struct SessionPolicy {
+    pub absolute_ttl: Option<Duration>,
+    pub idle_ttl: Option<Duration>,
+    /// Minimum gap between two `last_accessed_at` writes for the same session.
+    pub touch_interval: Duration,
+    pub purge_interval: std::time::Duration,
+    pub ip_binding: IpBinding,
+    /// Prefix lengths compared under `IpBinding::SameSubnet`.
+    pub ipv4_subnet: u8,
+    pub ipv6_subnet: u8,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        Self {
+            absolute_ttl: Some(Duration::days(30)),
+            idle_ttl: Some(Duration::days(7)),
+            touch_interval: Duration::seconds(60),
+            purge_interval: std::time::Duration::from_secs(3600),
+            ip_binding: IpBinding::Strict,
+            ipv4_subnet: 24,
+            ipv6_subnet: 64,
+        }
+    }
+}
+
+fn lifetime_from_env(name: &str, default: Option<Duration>) -> Result<Option<Duration>, Box<dyn Error>> {
+    match env::var(name) {
+        Ok(value) => match value.parse::<i64>()? {
+            0 => Ok(None),
+            secs if secs > 0 => Ok(Some(Duration::seconds(secs))),
+            secs => Err(format!("{} must not be negative, got {}", name, secs).into()),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+impl SessionPolicy {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let default = Self::default();
+
+        let absolute_ttl = lifetime_from_env("SESSION_ABSOLUTE_TTL", default.absolute_ttl)?;
+        let idle_ttl = lifetime_from_env("SESSION_IDLE_TTL", default.idle_ttl)?;
+
+        let touch_interval = match env::var("SESSION_TOUCH_INTERVAL") {
+            Ok(value) => Duration::seconds(value.parse::<u32>()? as i64),
+            Err(_) => default.touch_interval,
+        };
+
+        let purge_interval = match env::var("SESSION_PURGE_INTERVAL") {
+            Ok(value) => std::time::Duration::from_secs(value.parse::<u64>()?),
+            Err(_) => default.purge_interval,
+        };
+
+        let ip_binding = match env::var("SESSION_IP_BINDING") {
+            Ok(value) => value.parse::<IpBinding>()?,
+            Err(_) => default.ip_binding,
+        };
+
+        let ipv4_subnet = match env::var("SESSION_IPV4_SUBNET") {
+            Ok(value) => value.parse::<u8>().ok().filter(|p| *p <= 32).ok_or("SESSION_IPV4_SUBNET must be 0-32")?,
+            Err(_) => default.ipv4_subnet,
+        };
+
+        let ipv6_subnet = match env::var("SESSION_IPV6_SUBNET") {
+            Ok(value) => value.parse::<u8>().ok().filter(|p| *p <= 128).ok_or("SESSION_IPV6_SUBNET must be 0-128")?,
+            Err(_) => default.ipv6_subnet,
+        };
+
+        Ok(Self {
+            absolute_ttl,
+            idle_ttl,
+            touch_interval,
+            purge_interval,
+            ip_binding,
+            ipv4_subnet,
+            ipv6_subnet,
+        })
+    }
+
+    /// Returns the lifetime the session has exceeded as of `now`, if any.
+    pub fn expiry(&self, session: &Session, now: DateTime<Utc>) -> Option<SessionExpiry> {
+        if self.absolute_ttl.is_some_and(|ttl| session.created_at + ttl <= now) {
+            return Some(SessionExpiry::Absolute);
+        }
+        if self.idle_ttl.is_some_and(|ttl| session.last_accessed_at + ttl <= now) {
+            return Some(SessionExpiry::Idle);
+        }
+        None
+    }
+
+    /// Whether `last_accessed_at` is stale enough to be worth another write.
+    pub fn needs_touch(&self, session: &Session, now: DateTime<Utc>) -> bool {
+        session.last_accessed_at + self.touch_interval <= now
+    }
+
+    /// Sessions created before the first cutoff or last used before the second are expired.
+    pub fn cutoffs(&self, now: DateTime<Utc>) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        (self.absolute_ttl.map(|ttl| now - ttl), self.idle_ttl.map(|ttl| now - ttl))
+    }
+
+    /// Whether a request from `current` may use a session bound to `bound`.
+    pub fn ip_allowed(&self, bound: Option<&str>, current: &str) -> bool {
+        match self.ip_binding {
+            IpBinding::Off => true,
+            IpBinding::Strict => bound == Some(current),
+            IpBinding::SameSubnet => {
+                let Some(bound) = bound else { return false };
+                match (bound.parse::<IpAddr>(), current.parse::<IpAddr>()) {
+                    (Ok(bound), Ok(current)) => {
+                        let (bound, current) = (bound.to_canonical(), current.to_canonical());
+                        let prefix = if bound.is_ipv4() { self.ipv4_subnet } else { self.ipv6_subnet };
+                        bound.is_ipv4() == current.is_ipv4() && mask(bound, prefix) == mask(current, prefix)
+                    }
+                    _ => bound == current,
+                }
+            }
+        }
+    }
+}
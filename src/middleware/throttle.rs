@@ -0,0 +1,100 @@
+use std::env;
+use std::error::Error;
+use chrono::{DateTime, Duration, Utc};
+
+/// When repeated failures start costing a delay, and when they lock the key out entirely.
+#[derive(Debug, Clone, Copy)]
+pub struct FailureLimits {
+    pub backoff_after: i32,
+    pub lockout_after: i32,
+}
+
+/// Failed-login handling, tracked separately per account (by email) and per client address.
+#[derive(Debug, Clone)]
+pub struct LoginPolicy {
+    pub account: FailureLimits,
+    /// Looser than the account limits, since many users can share one address behind NAT.
+    pub ip: FailureLimits,
+    /// Delay after the first failure past `backoff_after`; each further failure doubles it.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub lockout_duration: Duration,
+    /// Failures after which a Turnstile token is required to try again; `None` never asks.
+    pub captcha_after: Option<i32>,
+    /// A key with no failure for this long starts counting from zero again.
+    pub window: Duration,
+}
+
+impl Default for LoginPolicy {
+    fn default() -> Self {
+        Self {
+            account: FailureLimits { backoff_after: 3, lockout_after: 10 },
+            ip: FailureLimits { backoff_after: 10, lockout_after: 50 },
+            base_delay: Duration::seconds(1),
+            max_delay: Duration::minutes(5),
+            lockout_duration: Duration::minutes(15),
+            captcha_after: Some(5),
+            window: Duration::hours(1),
+        }
+    }
+}
+
+fn count_from_env(name: &str, default: i32) -> Result<i32, Box<dyn Error>> {
+    match env::var(name) {
+        Ok(value) => value.parse::<u16>().map(i32::from).map_err(|_| format!("{} must be a whole number", name).into()),
+        Err(_) => Ok(default),
+    }
+}
+
+fn seconds_from_env(name: &str, default: Duration) -> Result<Duration, Box<dyn Error>> {
+    match env::var(name) {
+        Ok(value) => Ok(Duration::seconds(value.parse::<u32>()? as i64)),
+        Err(_) => Ok(default),
+    }
+}
+
+impl LoginPolicy {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let default = Self::default();
+
+        Ok(Self {
+            account: FailureLimits {
+                backoff_after: count_from_env("LOGIN_BACKOFF_AFTER", default.account.backoff_after)?,
+                lockout_after: count_from_env("LOGIN_LOCKOUT_AFTER", default.account.lockout_after)?,
+            },
+            ip: FailureLimits {
+                backoff_after: count_from_env("LOGIN_IP_BACKOFF_AFTER", default.ip.backoff_after)?,
+                lockout_after: count_from_env("LOGIN_IP_LOCKOUT_AFTER", default.ip.lockout_after)?,
+            },
+            base_delay: seconds_from_env("LOGIN_BACKOFF_BASE", default.base_delay)?,
+            max_delay: seconds_from_env("LOGIN_BACKOFF_MAX", default.max_delay)?,
+            lockout_duration: seconds_from_env("LOGIN_LOCKOUT_DURATION", default.lockout_duration)?,
+            captcha_after: match count_from_env("LOGIN_CAPTCHA_AFTER", default.captcha_after.unwrap_or(0))? {
+                0 => None,
+                n => Some(n),
+            },
+            window: seconds_from_env("LOGIN_FAILURE_WINDOW", default.window)?,
+        })
+    }
+
+    /// How long a key stays blocked after its `failures`-th consecutive failure at `now`.
+    pub fn blocked_until(&self, limits: FailureLimits, failures: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if limits.lockout_after > 0 && failures >= limits.lockout_after {
+            return Some(now + self.lockout_duration);
+        }
+        if limits.backoff_after > 0 && failures >= limits.backoff_after {
+            let doublings = (failures - limits.backoff_after).min(30) as u32;
+            let delay = self.base_delay.num_seconds().saturating_mul(1i64 << doublings);
+            return Some(now + Duration::seconds(delay).min(self.max_delay));
+        }
+        None
+    }
+
+    pub fn locked_out(&self, limits: FailureLimits, failures: i32) -> bool {
+        limits.lockout_after > 0 && failures >= limits.lockout_after
+    }
+
+    pub fn needs_captcha(&self, failures: i32) -> bool {
+        self.captcha_after.is_some_and(|after| failures >= after)
+    }
+}
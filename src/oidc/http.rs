@@ -0,0 +1,77 @@
+use std::error::Error;
+use async_trait::async_trait;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{header, Method, Request};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde_json::Value;
+
+/// The two kinds of request an OIDC relying party makes: fetching discovery documents and
+/// JWKS, and posting to the token endpoint.
+#[async_trait]
+pub trait HttpClient: Send + Sync {
+    async fn get_json(&self, url: &str) -> Result<Value, Box<dyn Error>>;
+
+    async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Value, Box<dyn Error>>;
+}
+
+pub struct HyperHttpClient {
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+}
+
+impl HyperHttpClient {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()?
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        Ok(Self {
+            client: Client::builder(TokioExecutor::new()).build(connector),
+        })
+    }
+
+    async fn send(&self, request: Request<Full<Bytes>>) -> Result<Value, Box<dyn Error>> {
+        let uri = request.uri().to_string();
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+
+        if !status.is_success() {
+            return Err(format!("{} answered {}: {}", uri, status, String::from_utf8_lossy(&body)).into());
+        }
+
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+#[async_trait]
+impl HttpClient for HyperHttpClient {
+    async fn get_json(&self, url: &str) -> Result<Value, Box<dyn Error>> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .header(header::ACCEPT, "application/json")
+            .body(Full::new(Bytes::new()))?;
+
+        self.send(request).await
+    }
+
+    async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Value, Box<dyn Error>> {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form)
+            .finish();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Full::new(Bytes::from(body)))?;
+
+        self.send(request).await
+    }
+}
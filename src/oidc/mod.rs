@@ -0,0 +1,5 @@
+pub mod http;
+pub mod provider;
+
+pub use http::{HttpClient, HyperHttpClient};
+pub use provider::{IdentityClaims, OidcConfig, OidcProvider};
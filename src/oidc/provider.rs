@@ -0,0 +1,226 @@
+use std::env;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use jsonwebtoken::jwk::JwkSet;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use url::Url;
+use super::HttpClient;
+
+const DEFAULT_SCOPES: &str = "openid email profile";
+
+/// Asymmetric algorithms accepted for ID tokens; HMAC would let anyone holding the client secret mint tokens.
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: String,
+    /// Create an account for a verified email that has none yet.
+    pub auto_provision: bool,
+}
+
+impl OidcConfig {
+    /// Reads the provider from `OIDC_*`; single sign-on stays off while `OIDC_ISSUER` is unset.
+    pub fn new_from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        let issuer = match env::var("OIDC_ISSUER") {
+            Ok(issuer) if !issuer.is_empty() => issuer,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            issuer,
+            client_id: env::var("OIDC_CLIENT_ID").map_err(|_| "OIDC_CLIENT_ID is required when OIDC_ISSUER is set")?,
+            client_secret: env::var("OIDC_CLIENT_SECRET").ok().filter(|s| !s.is_empty()),
+            redirect_uri: env::var("OIDC_REDIRECT_URI").map_err(|_| "OIDC_REDIRECT_URI is required when OIDC_ISSUER is set")?,
+            scopes: env::var("OIDC_SCOPES").unwrap_or_else(|_| DEFAULT_SCOPES.to_string()),
+            auto_provision: match env::var("OIDC_AUTO_PROVISION") {
+                Ok(v) => v.parse()?,
+                Err(_) => true,
+            },
+        }))
+    }
+}
+
+/// The parts of the discovery document this relying party uses.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    #[serde(default)]
+    email: Option<String>,
+    /// Some providers send the string "true" rather than a boolean.
+    #[serde(default)]
+    email_verified: Option<Value>,
+    #[serde(default)]
+    nonce: Option<String>,
+}
+
+/// Who the provider says signed in, taken from a verified ID token.
+#[derive(Debug, Clone)]
+pub struct IdentityClaims {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+/// PKCE `S256` challenge for a code verifier.
+pub fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// Authorization-code client for one provider. Discovery and JWKS are fetched lazily and cached;
+/// an ID token signed with an unknown key id triggers one JWKS refresh to pick up rotated keys.
+pub struct OidcProvider {
+    pub config: OidcConfig,
+    http: Arc<dyn HttpClient>,
+    metadata: Mutex<Option<ProviderMetadata>>,
+    jwks: Mutex<Option<JwkSet>>,
+}
+
+impl OidcProvider {
+    pub fn new(config: OidcConfig, http: Arc<dyn HttpClient>) -> Self {
+        Self {
+            config,
+            http,
+            metadata: Mutex::new(None),
+            jwks: Mutex::new(None),
+        }
+    }
+
+    pub async fn metadata(&self) -> Result<ProviderMetadata, Box<dyn Error>> {
+        if let Some(metadata) = self.metadata.lock().unwrap().clone() {
+            return Ok(metadata);
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", self.config.issuer.trim_end_matches('/'));
+        let metadata: ProviderMetadata = serde_json::from_value(self.http.get_json(&url).await?)?;
+        if metadata.issuer != self.config.issuer {
+            return Err(format!("Discovery issuer {} does not match {}", metadata.issuer, self.config.issuer).into());
+        }
+
+        *self.metadata.lock().unwrap() = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    pub async fn authorization_url(&self, state: &str, nonce: &str, code_verifier: &str) -> Result<String, Box<dyn Error>> {
+        let metadata = self.metadata().await?;
+        let url = Url::parse_with_params(&metadata.authorization_endpoint, &[
+            ("response_type", "code"),
+            ("client_id", self.config.client_id.as_str()),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("scope", self.config.scopes.as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge(code_verifier).as_str()),
+            ("code_challenge_method", "S256"),
+        ])?;
+
+        Ok(url.into())
+    }
+
+    /// Redeems an authorization code and returns the verified identity from its ID token.
+    pub async fn exchange_code(&self, code: &str, code_verifier: &str, nonce: &str) -> Result<IdentityClaims, Box<dyn Error>> {
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = self.config.client_secret.as_deref() {
+            form.push(("client_secret", secret));
+        }
+
+        let response: TokenResponse = serde_json::from_value(self.http.post_form(&metadata.token_endpoint, &form).await?)?;
+        self.verify_id_token(&response.id_token, nonce).await
+    }
+
+    pub async fn verify_id_token(&self, id_token: &str, nonce: &str) -> Result<IdentityClaims, Box<dyn Error>> {
+        let header = decode_header(id_token)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(format!("ID token algorithm {:?} is not allowed", header.alg).into());
+        }
+
+        let key = self.decoding_key(header.kid.as_deref()).await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)?.claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err("ID token nonce does not match the login request".into());
+        }
+
+        let email_verified = match claims.email_verified {
+            Some(Value::Bool(verified)) => verified,
+            Some(Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+
+        Ok(IdentityClaims {
+            issuer: self.config.issuer.clone(),
+            subject: claims.sub,
+            email: claims.email.map(|e| e.trim().to_string()),
+            email_verified,
+        })
+    }
+
+    async fn decoding_key(&self, kid: Option<&str>) -> Result<DecodingKey, Box<dyn Error>> {
+        let cached = self.jwks.lock().unwrap().clone();
+        if let Some(key) = cached.as_ref().and_then(|jwks| find_key(jwks, kid)) {
+            return Ok(key);
+        }
+
+        let metadata = self.metadata().await?;
+        let jwks: JwkSet = serde_json::from_value(self.http.get_json(&metadata.jwks_uri).await?)?;
+        let key = find_key(&jwks, kid);
+        *self.jwks.lock().unwrap() = Some(jwks);
+
+        key.ok_or_else(|| format!("No signing key {} in the provider's JWKS", kid.unwrap_or("(none)")).into())
+    }
+}
+
+fn find_key(jwks: &JwkSet, kid: Option<&str>) -> Option<DecodingKey> {
+    let jwk = match kid {
+        Some(kid) => jwks.find(kid),
+        // Without a key id the set must be unambiguous.
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }?;
+
+    DecodingKey::from_jwk(jwk).ok()
+}
@@ -1,3 +1,4 @@
+use actix_web::http::Method;
 use actix_web::web::{self, ServiceConfig};
 use actix_files as fs;
 use crate::service::{
@@ -9,10 +10,64 @@ use crate::service::{
     login_handler,
     get_file_details_handler, 
     create_file_handler, 
+    complete_upload_handler,
     list_files_handler, 
     delete_file_handler, 
     update_file_access_handler, 
-    get_download_url_handler
+    get_download_url_handler,
+    rename_file_handler,
+    move_file_handler,
+    replace_content_handler,
+    initiate_multipart_handler,
+    multipart_part_urls_handler,
+    list_multipart_parts_handler,
+    complete_multipart_handler,
+    abort_multipart_handler,
+    download_file_handler,
+    list_versions_handler,
+    download_version_handler,
+    restore_version_handler,
+    delete_version_handler,
+    tus_options_handler,
+    tus_create_handler,
+    tus_head_handler,
+    tus_patch_handler,
+    tus_delete_handler,
+    create_folder_handler,
+    list_root_folders_handler,
+    get_folder_handler,
+    rename_folder_handler,
+    move_folder_handler,
+    delete_folder_handler,
+    update_folder_access_handler,
+    create_share_link_handler,
+    list_share_links_handler,
+    revoke_share_link_handler,
+    open_share_link_handler,
+    unlock_share_link_handler,
+    create_access_token_handler,
+    list_access_tokens_handler,
+    revoke_access_token_handler,
+    complete_login_handler,
+    two_factor_status_handler,
+    enroll_totp_handler,
+    confirm_totp_handler,
+    disable_totp_handler,
+    passkey_register_options_handler,
+    passkey_register_handler,
+    passkey_login_options_handler,
+    passkey_login_handler,
+    list_passkeys_handler,
+    delete_passkey_handler,
+    oidc_authorize_handler,
+    oidc_callback_handler,
+    change_password_handler,
+    request_password_reset_handler,
+    confirm_password_reset_handler,
+    resend_verification_handler,
+    confirm_email_handler,
+    local_upload_handler,
+    local_download_handler
 };
 
 pub fn configure(cfg: &mut ServiceConfig) {
@@ -22,15 +77,71 @@ pub fn configure(cfg: &mut ServiceConfig) {
             .route("/user", web::post().to(create_user_handler))
             .route("/session", web::get().to(session_info_handler))
             .route("/session", web::post().to(login_handler))
+            .route("/session/2fa", web::post().to(complete_login_handler))
+            .route("/session/passkey/register/options", web::post().to(passkey_register_options_handler))
+            .route("/session/passkey/register", web::post().to(passkey_register_handler))
+            .route("/session/passkey/login/options", web::post().to(passkey_login_options_handler))
+            .route("/session/passkey/login", web::post().to(passkey_login_handler))
+            .route("/session/passkeys", web::get().to(list_passkeys_handler))
+            .route("/session/passkeys/{passkey_id}", web::delete().to(delete_passkey_handler))
+            .route("/session/oidc/authorize", web::get().to(oidc_authorize_handler))
+            .route("/session/oidc/callback", web::get().to(oidc_callback_handler))
             .route("/session/{session_id}", web::delete().to(logout_handler))
+            .route("/user/password", web::put().to(change_password_handler))
+            .route("/user/password/reset", web::post().to(request_password_reset_handler))
+            .route("/user/password/reset/confirm", web::post().to(confirm_password_reset_handler))
+            .route("/user/email/verify", web::post().to(resend_verification_handler))
+            .route("/user/email/verify/confirm", web::post().to(confirm_email_handler))
+            .route("/user/2fa", web::get().to(two_factor_status_handler))
+            .route("/user/2fa/totp", web::post().to(enroll_totp_handler))
+            .route("/user/2fa/totp", web::delete().to(disable_totp_handler))
+            .route("/user/2fa/totp/confirm", web::post().to(confirm_totp_handler))
+            .route("/tokens", web::get().to(list_access_tokens_handler))
+            .route("/tokens", web::post().to(create_access_token_handler))
+            .route("/tokens/{token_id}", web::delete().to(revoke_access_token_handler))
             .route("/content", web::get().to(list_files_handler))
             .route("/content", web::post().to(create_file_handler))
             .route("/content/{file_id}", web::get().to(get_file_details_handler))
+            .route("/content/{file_id}", web::patch().to(rename_file_handler))
             .route("/content/{file_id}", web::delete().to(delete_file_handler))
+            .route("/content/{file_id}/complete", web::post().to(complete_upload_handler))
+            .route("/content/{file_id}/replace", web::post().to(replace_content_handler))
+            .route("/content/{file_id}/multipart", web::post().to(initiate_multipart_handler))
+            .route("/content/{file_id}/multipart", web::get().to(list_multipart_parts_handler))
+            .route("/content/{file_id}/multipart", web::delete().to(abort_multipart_handler))
+            .route("/content/{file_id}/multipart/parts", web::post().to(multipart_part_urls_handler))
+            .route("/content/{file_id}/multipart/complete", web::post().to(complete_multipart_handler))
+            .route("/tus", web::method(Method::OPTIONS).to(tus_options_handler))
+            .route("/tus", web::post().to(tus_create_handler))
+            .route("/tus/{upload_id}", web::head().to(tus_head_handler))
+            .route("/tus/{upload_id}", web::patch().to(tus_patch_handler))
+            .route("/tus/{upload_id}", web::delete().to(tus_delete_handler))
+            .route("/content/{file_id}/move", web::post().to(move_file_handler))
+            .route("/content/{file_id}/data", web::get().to(download_file_handler))
+            .route("/content/{file_id}/data", web::head().to(download_file_handler))
+            .route("/content/{file_id}/versions", web::get().to(list_versions_handler))
+            .route("/content/{file_id}/versions/{version}", web::delete().to(delete_version_handler))
+            .route("/content/{file_id}/versions/{version}/data", web::get().to(download_version_handler))
+            .route("/content/{file_id}/versions/{version}/data", web::head().to(download_version_handler))
+            .route("/content/{file_id}/versions/{version}/restore", web::post().to(restore_version_handler))
             .route("/content/{file_id}/share", web::get().to(get_download_url_handler))
             .route("/content/{file_id}/share", web::put().to(update_file_access_handler))
+            .route("/content/{file_id}/links", web::get().to(list_share_links_handler))
+            .route("/content/{file_id}/links", web::post().to(create_share_link_handler))
+            .route("/content/{file_id}/links/{link_id}", web::delete().to(revoke_share_link_handler))
+            .route("/folders", web::get().to(list_root_folders_handler))
+            .route("/folders", web::post().to(create_folder_handler))
+            .route("/folders/{folder_id}", web::get().to(get_folder_handler))
+            .route("/folders/{folder_id}", web::patch().to(rename_folder_handler))
+            .route("/folders/{folder_id}", web::delete().to(delete_folder_handler))
+            .route("/folders/{folder_id}/move", web::post().to(move_folder_handler))
+            .route("/folders/{folder_id}/share", web::put().to(update_folder_access_handler))
             .route("/turnstile", web::get().to(get_turnstile))
     )
+    .route("/s/{token}", web::get().to(open_share_link_handler))
+    .route("/s/{token}", web::post().to(unlock_share_link_handler))
+    .route("/storage/{key:.*}", web::put().to(local_upload_handler))
+    .route("/storage/{key:.*}", web::get().to(local_download_handler))
     .route("/dashboard", web::get().to(index_handler))
     .service(
         fs::Files::new("/dashboard", "./dist").show_files_listing()
@@ -1,24 +1,105 @@
 use std::sync::Arc;
 use crate::unique::Snowflake;
-use cf_turnstile::TurnstileClient;
-use crate::database::{S3Client, MySQLClient};
+use crate::captcha::Captcha;
+use crate::database::{Storage, Repository};
+use crate::middleware::{LoginPolicy, SessionPolicy, TrustedProxies};
+use crate::encrypt::{PasswordHasher, RelyingParty};
+use crate::oidc::OidcProvider;
+use crate::mail::{LogMailer, Mailer};
+use crate::service::download::DownloadMode;
+use crate::service::tus::TusConfig;
 
 pub struct AppState {
-    pub mysql: Arc<MySQLClient>,
-    pub s3client: Arc<S3Client>,
+    pub db: Arc<dyn Repository>,
+    pub storage: Arc<dyn Storage>,
     pub snowflake: Arc<Snowflake>,
-    pub turnstile_client: Arc<TurnstileClient>,
+    pub turnstile_client: Arc<dyn Captcha>,
     pub turnstile_sitekey: String,
+    pub session_policy: SessionPolicy,
+    pub login_policy: LoginPolicy,
+    pub password_hasher: PasswordHasher,
+    pub trusted_proxies: TrustedProxies,
+    pub relying_party: RelyingParty,
+    pub oidc: Option<Arc<OidcProvider>>,
+    pub mailer: Arc<dyn Mailer>,
+    pub tus: TusConfig,
+    pub download_mode: DownloadMode,
+    /// Base URL that links in outgoing mail and proxied download URLs point at.
+    pub public_url: String,
+    /// Whether sharing is held back until the account's email is verified.
+    pub require_verified_email: bool,
 }
 
 impl AppState {
-    pub fn new(mysql_client: MySQLClient, s3_client: S3Client, snowflake: Snowflake, turnstile_client: TurnstileClient, turnstile_sitekey: String) -> Self {
+    pub fn new(db: Arc<dyn Repository>, storage: Arc<dyn Storage>, snowflake: Snowflake, turnstile_client: Arc<dyn Captcha>, turnstile_sitekey: String) -> Self {
         Self {
-            mysql: Arc::new(mysql_client),
-            s3client: Arc::new(s3_client),
+            db,
+            storage,
             snowflake: Arc::new(snowflake),
-            turnstile_client: Arc::new(turnstile_client),
-            turnstile_sitekey: turnstile_sitekey,
+            turnstile_client,
+            turnstile_sitekey,
+            session_policy: SessionPolicy::default(),
+            login_policy: LoginPolicy::default(),
+            password_hasher: PasswordHasher::default(),
+            trusted_proxies: TrustedProxies::default(),
+            relying_party: RelyingParty::default(),
+            oidc: None,
+            mailer: Arc::new(LogMailer::new(None)),
+            tus: TusConfig::default(),
+            download_mode: DownloadMode::default(),
+            public_url: "http://localhost:9000".to_string(),
+            require_verified_email: true,
         }
     }
-}
\ No newline at end of file
+
+    pub fn with_session_policy(mut self, session_policy: SessionPolicy) -> Self {
+        self.session_policy = session_policy;
+        self
+    }
+
+    pub fn with_login_policy(mut self, login_policy: LoginPolicy) -> Self {
+        self.login_policy = login_policy;
+        self
+    }
+
+    pub fn with_password_hasher(mut self, password_hasher: PasswordHasher) -> Self {
+        self.password_hasher = password_hasher;
+        self
+    }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    pub fn with_relying_party(mut self, relying_party: RelyingParty) -> Self {
+        self.relying_party = relying_party;
+        self
+    }
+
+    pub fn with_oidc(mut self, provider: OidcProvider) -> Self {
+        self.oidc = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>, public_url: String) -> Self {
+        self.mailer = mailer;
+        self.public_url = public_url;
+        self
+    }
+
+    pub fn with_tus(mut self, tus: TusConfig) -> Self {
+        self.tus = tus;
+        self
+    }
+
+    pub fn with_download_mode(mut self, download_mode: DownloadMode) -> Self {
+        self.download_mode = download_mode;
+        self
+    }
+
+    pub fn with_email_verification(mut self, required: bool) -> Self {
+        self.require_verified_email = required;
+        self
+    }
+}
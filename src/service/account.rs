@@ -0,0 +1,296 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::router::AppState;
+use crate::middleware::session_auth;
+use crate::encrypt::SecretToken;
+use crate::mail::Email;
+use crate::database::model::{EmailToken, User, EMAIL_TOKEN_PASSWORD_RESET, EMAIL_TOKEN_VERIFY};
+use super::session::issue_session;
+use serde::Deserialize;
+use serde_json::json;
+
+const RESET_TOKEN_TTL_SECONDS: i64 = 3600;
+const VERIFY_TOKEN_TTL_SECONDS: i64 = 86400;
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmPasswordResetRequest {
+    token: String,
+    new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmailRequest {
+    token: String,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+fn weak_password(state: &AppState, password: &str) -> Option<HttpResponse> {
+    state.password_hasher.policy().check(password).err().map(|e| error_response!(
+        HttpResponse::BadRequest(),
+        "WEAK_PASSWORD",
+        e.message(),
+        e.to_string()
+    ))
+}
+
+fn invalid_token() -> HttpResponse {
+    error_response!(
+        HttpResponse::BadRequest(),
+        "TOKEN_INVALID",
+        "The link is invalid or has expired",
+        "Request a new email and use the latest link"
+    )
+}
+
+/// Replaces any outstanding token of this kind with a fresh one and mails the link.
+async fn send_token(state: &web::Data<AppState>, user: &User, purpose: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (ttl, param, subject, action) = match purpose {
+        EMAIL_TOKEN_PASSWORD_RESET => (RESET_TOKEN_TTL_SECONDS, "reset_token", "Reset your password", "choose a new password"),
+        _ => (VERIFY_TOKEN_TTL_SECONDS, "verify_token", "Verify your email address", "confirm this email address"),
+    };
+
+    let token = SecretToken::generate();
+    let now = chrono::Utc::now();
+    state.db.delete_email_tokens(&user.id, purpose).await?;
+    state.db.create_email_token(&EmailToken {
+        token_hash: SecretToken::digest(&token),
+        user_id: user.id.clone(),
+        purpose: purpose.to_string(),
+        expires_at: now + chrono::Duration::seconds(ttl),
+        created_at: now,
+    }).await?;
+
+    let link = format!("{}/dashboard?{}={}", state.public_url.trim_end_matches('/'), param, token);
+    state.mailer.send(&Email {
+        to: user.email.clone(),
+        subject: subject.to_string(),
+        body: format!(
+            "Open this link to {}:\n\n{}\n\nThe link expires in {} minutes. If you did not ask for this, ignore this email.",
+            action,
+            link,
+            ttl / 60
+        ),
+    }).await
+}
+
+/// Mails a verification link to a new account; failure is logged so signup itself still succeeds.
+pub(crate) async fn send_verification_email(state: &web::Data<AppState>, user: &User) {
+    if let Err(e) = send_token(state, user, EMAIL_TOKEN_VERIFY).await {
+        eprintln!("✗ Failed to send verification email to {}: {}", user.id, e);
+    }
+}
+
+/// Rejects the request unless the user's email is verified or the deployment does not require it.
+pub(crate) async fn require_verified_email(state: &web::Data<AppState>, user_id: &str) -> Result<(), HttpResponse> {
+    if !state.require_verified_email {
+        return Ok(());
+    }
+
+    match state.db.get_user(user_id).await {
+        Ok(Some(user)) if user.email_verified_at.is_some() => Ok(()),
+        Ok(_) => Err(error_response!(
+            HttpResponse::Forbidden(),
+            "EMAIL_NOT_VERIFIED",
+            "Verify your email address before sharing",
+            "Use the link sent to your inbox, or request a new one"
+        )),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve user", e)),
+    }
+}
+
+/// Changes the password and signs out every other session; the caller gets a fresh session in the response.
+pub async fn change_password_handler(
+    state: web::Data<AppState>,
+    body: web::Json<ChangePasswordRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let user = match state.db.get_user(&session.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response!(HttpResponse::NotFound(), "USER_NOT_FOUND", "User not found", session.user_id),
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve user", e),
+    };
+
+    if !state.password_hasher.verify(&body.current_password, &user.password_hash).unwrap_or(false) {
+        return error_response!(
+            HttpResponse::Unauthorized(),
+            "INVALID_PASSWORD",
+            "Current password is incorrect",
+            "Enter the password you currently sign in with"
+        );
+    }
+
+    if let Some(response) = weak_password(&state, &body.new_password) {
+        return response;
+    }
+
+    let hashed_password = match state.password_hasher.hash(&body.new_password) {
+        Ok(hash) => hash,
+        Err(e) => return db_error!("HASH_FAILED", "Failed to hash password", e),
+    };
+
+    if let Err(e) = state.db.update_user_password(&user.id, &hashed_password).await {
+        return db_error!("DB_ERROR", "Failed to update password", e);
+    }
+    if let Err(e) = state.db.delete_email_tokens(&user.id, EMAIL_TOKEN_PASSWORD_RESET).await {
+        return db_error!("DB_ERROR", "Failed to invalidate reset links", e);
+    }
+    if let Err(e) = state.db.delete_user_sessions(&user.id).await {
+        return db_error!("DB_ERROR", "Failed to revoke sessions", e);
+    }
+
+    issue_session(&state, &req, &user).await
+}
+
+/// Always answers the same way, so the endpoint cannot be used to find out which emails have accounts.
+pub async fn request_password_reset_handler(
+    state: web::Data<AppState>,
+    body: web::Json<PasswordResetRequest>,
+) -> HttpResponse {
+    match state.db.get_user_by_email(&body.email).await {
+        Ok(Some(user)) => {
+            if let Err(e) = send_token(&state, &user, EMAIL_TOKEN_PASSWORD_RESET).await {
+                eprintln!("✗ Failed to send password reset email to {}: {}", user.id, e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => return db_error!("DB_ERROR", "Failed to query user", e),
+    }
+
+    HttpResponse::Accepted().json(json!({
+        "data": {
+            "message": "If an account uses this email, a reset link has been sent"
+        }
+    }))
+}
+
+pub async fn confirm_password_reset_handler(
+    state: web::Data<AppState>,
+    body: web::Json<ConfirmPasswordResetRequest>,
+) -> HttpResponse {
+    // Check the new password first so a typo does not burn the link.
+    if let Some(response) = weak_password(&state, &body.new_password) {
+        return response;
+    }
+
+    let now = chrono::Utc::now();
+    let token = match state.db.take_email_token(&SecretToken::digest(&body.token), EMAIL_TOKEN_PASSWORD_RESET, now).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return invalid_token(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to load reset link", e),
+    };
+
+    let hashed_password = match state.password_hasher.hash(&body.new_password) {
+        Ok(hash) => hash,
+        Err(e) => return db_error!("HASH_FAILED", "Failed to hash password", e),
+    };
+
+    if let Err(e) = state.db.update_user_password(&token.user_id, &hashed_password).await {
+        return db_error!("DB_ERROR", "Failed to update password", e);
+    }
+    if let Err(e) = state.db.delete_user_sessions(&token.user_id).await {
+        return db_error!("DB_ERROR", "Failed to revoke sessions", e);
+    }
+    // Following the mailed link proves the address as well.
+    if let Err(e) = state.db.mark_email_verified(&token.user_id, now).await {
+        return db_error!("DB_ERROR", "Failed to verify email", e);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "message": "Password has been reset; sign in with the new password"
+        }
+    }))
+}
+
+pub async fn resend_verification_handler(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let user = match state.db.get_user(&session.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response!(HttpResponse::NotFound(), "USER_NOT_FOUND", "User not found", session.user_id),
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve user", e),
+    };
+
+    if user.email_verified_at.is_some() {
+        return error_response!(
+            HttpResponse::Conflict(),
+            "EMAIL_ALREADY_VERIFIED",
+            "Email address is already verified",
+            user.email
+        );
+    }
+
+    match send_token(&state, &user, EMAIL_TOKEN_VERIFY).await {
+        Ok(_) => HttpResponse::Accepted().json(json!({
+            "data": {
+                "message": "Verification email sent",
+                "email": user.email
+            }
+        })),
+        Err(e) => error_response!(
+            HttpResponse::BadGateway(),
+            "MAIL_FAILED",
+            "Failed to send verification email",
+            e.to_string()
+        ),
+    }
+}
+
+pub async fn confirm_email_handler(
+    state: web::Data<AppState>,
+    body: web::Json<ConfirmEmailRequest>,
+) -> HttpResponse {
+    let now = chrono::Utc::now();
+    let token = match state.db.take_email_token(&SecretToken::digest(&body.token), EMAIL_TOKEN_VERIFY, now).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return invalid_token(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to load verification link", e),
+    };
+
+    match state.db.mark_email_verified(&token.user_id, now).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "data": {
+                "email_verified": true
+            }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to verify email", e),
+    }
+}
@@ -1,31 +1,163 @@
 use actix_web::{web, HttpRequest, HttpResponse};
 use crate::router::AppState;
 use crate::middleware::bearer_auth;
-use serde::Deserialize;
+use crate::database::blob::{release_blobs, remove_file_objects, store_as_blob};
+use super::account::require_verified_email;
+use super::download::{data_url, DownloadMode};
+use super::folder::require_folder_role;
+use crate::database::model::{
+    File, FileCursor, FileGrant, FileListQuery, FileScope, FileSort, FolderFilter, Role, Scope, SearchMode, UPLOAD_COMPLETE,
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::fs;
 
 #[derive(Deserialize)]
 pub struct UpdateAccessRequest {
-    accessible_user_ids: Vec<String>,
+    #[serde(default)]
+    accessible_user_ids: Option<Vec<String>>,
+    #[serde(default)]
+    permissions: Option<Vec<FileGrant>>,
+}
+
+#[derive(Deserialize)]
+pub struct RenameFileRequest {
+    filename: String,
 }
 
 #[derive(Deserialize)]
 pub struct FileUploadRequest {
     #[serde(default)]
     filename: Option<String>,
+    #[serde(default)]
+    folder_id: Option<String>,
 }
 
-macro_rules! auth_error {
-    ($e:expr) => {
-        HttpResponse::Unauthorized().json(json!({
-            "error": {
-                "code": "AUTH_FAILED",
-                "message": "Authentication failed",
-                "detail": $e.to_string()
+#[derive(Deserialize)]
+pub struct MoveFileRequest {
+    #[serde(default)]
+    folder_id: Option<String>,
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_FILENAME_LENGTH: usize = 255;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct ListFilesQuery {
+    #[serde(default)]
+    cursor: Option<String>,
+    #[serde(default)]
+    limit: Option<i64>,
+    #[serde(default)]
+    sort: Option<String>,
+    #[serde(default)]
+    order: Option<String>,
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(default)]
+    q: Option<String>,
+    #[serde(default, rename = "match")]
+    match_mode: Option<String>,
+    #[serde(default)]
+    folder: Option<String>,
+}
+
+/// Opaque page token; it remembers the ordering so it cannot be replayed against a different sort.
+#[derive(Serialize, Deserialize)]
+struct PageToken {
+    sort: String,
+    desc: bool,
+    key: String,
+    id: String,
+}
+
+impl ListFilesQuery {
+    fn parse(&self) -> Result<FileListQuery, String> {
+        let sort_name = self.sort.as_deref().unwrap_or("date");
+        let sort = match sort_name {
+            "name" => FileSort::Name,
+            "date" => FileSort::Date,
+            "size" => FileSort::Size,
+            other => return Err(format!("Unknown sort '{}' (expected name, date or size)", other)),
+        };
+
+        let descending = match self.order.as_deref() {
+            Some("asc") => false,
+            Some("desc") => true,
+            None => sort != FileSort::Name,
+            Some(other) => return Err(format!("Unknown order '{}' (expected asc or desc)", other)),
+        };
+
+        let scope = match self.scope.as_deref().unwrap_or("all") {
+            "all" => FileScope::All,
+            "owned" => FileScope::Owned,
+            "shared" => FileScope::Shared,
+            other => return Err(format!("Unknown scope '{}' (expected all, owned or shared)", other)),
+        };
+
+        let search_mode = match self.match_mode.as_deref().unwrap_or("contains") {
+            "contains" => SearchMode::Contains,
+            "prefix" => SearchMode::Prefix,
+            other => return Err(format!("Unknown match '{}' (expected contains or prefix)", other)),
+        };
+
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(format!("limit must be between 1 and {}", MAX_PAGE_SIZE));
+        }
+
+        let after = match &self.cursor {
+            Some(cursor) => {
+                let token: PageToken = URL_SAFE_NO_PAD
+                    .decode(cursor)
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                    .ok_or("Malformed cursor")?;
+                if token.sort != sort_name || token.desc != descending {
+                    return Err("Cursor was issued for a different sort order".to_string());
+                }
+                let key_valid = match sort {
+                    FileSort::Name => true,
+                    FileSort::Date => chrono::DateTime::parse_from_rfc3339(&token.key).is_ok(),
+                    FileSort::Size => token.key.parse::<i64>().is_ok(),
+                };
+                if !key_valid {
+                    return Err("Malformed cursor".to_string());
+                }
+                Some(FileCursor { key: token.key, id: token.id })
             }
-        }))
-    };
+            None => None,
+        };
+
+        let folder = self.folder.as_deref().map(|folder| match folder {
+            "root" => FolderFilter::Root,
+            id => FolderFilter::Folder(id.to_string()),
+        });
+
+        Ok(FileListQuery {
+            scope,
+            folder,
+            sort,
+            descending,
+            search: self.q.clone().filter(|q| !q.is_empty()),
+            search_mode,
+            after,
+            limit,
+        })
+    }
+
+    fn encode_cursor(&self, query: &FileListQuery, cursor: FileCursor) -> String {
+        let token = PageToken {
+            sort: self.sort.clone().unwrap_or_else(|| "date".to_string()),
+            desc: query.descending,
+            key: cursor.key,
+            id: cursor.id,
+        };
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&token).unwrap_or_default())
+    }
 }
 
 macro_rules! db_error {
@@ -40,6 +172,99 @@ macro_rules! db_error {
     };
 }
 
+/// Resolves the caller's role on a file and rejects the request unless `allowed` accepts it.
+pub(crate) async fn require_role(
+    state: &web::Data<AppState>,
+    file_id: &str,
+    user_id: &str,
+    allowed: fn(&Role) -> bool,
+    action: &str,
+) -> Result<Role, HttpResponse> {
+    let role = match state.db.get_file_role(file_id, user_id).await {
+        Ok(Some(role)) => role,
+        Ok(None) => {
+            return Err(HttpResponse::Forbidden().json(json!({
+                "error": {
+                    "code": "ACCESS_DENIED",
+                    "message": "You don't have permission to access this file",
+                    "detail": format!("User {} cannot access file {}", user_id, file_id)
+                }
+            })));
+        }
+        Err(e) => return Err(db_error!("DB_ERROR", "Failed to check file access", e)),
+    };
+
+    if !allowed(&role) {
+        return Err(HttpResponse::Forbidden().json(json!({
+            "error": {
+                "code": "ACCESS_DENIED",
+                "message": format!("Your role does not allow you to {}", action),
+                "detail": format!("User {} is {} of file {}", user_id, role.as_str(), file_id)
+            }
+        })));
+    }
+
+    Ok(role)
+}
+
+/// Checks a replacement grant list: unique existing users, grantable roles, and never the owner.
+pub(crate) async fn validate_grants(
+    state: &web::Data<AppState>,
+    grants: &[FileGrant],
+    owner_id: &str,
+) -> Result<(), HttpResponse> {
+    let mut seen = std::collections::HashSet::with_capacity(grants.len());
+    for grant in grants {
+        if !seen.insert(grant.user_id.clone()) {
+            return Err(HttpResponse::BadRequest().json(json!({
+                "error": {
+                    "code": "DUPLICATE_USER_ID",
+                    "message": "Duplicate user ID in accessible users",
+                    "detail": format!("User ID '{}' appears more than once", grant.user_id)
+                }
+            })));
+        }
+
+        if Role::parse(&grant.role).is_none() {
+            return Err(HttpResponse::BadRequest().json(json!({
+                "error": {
+                    "code": "INVALID_ROLE",
+                    "message": "Unknown sharing role",
+                    "detail": format!("Role '{}' is not one of viewer, editor or co_owner", grant.role)
+                }
+            })));
+        }
+
+        if grant.user_id == owner_id {
+            return Err(HttpResponse::BadRequest().json(json!({
+                "error": {
+                    "code": "INVALID_INPUT",
+                    "message": "The owner cannot be given a sharing role",
+                    "detail": format!("User {} is the owner", grant.user_id)
+                }
+            })));
+        }
+    }
+
+    for grant in grants {
+        match state.db.get_user(&grant.user_id).await {
+            Ok(Some(_)) => {},
+            Ok(None) => {
+                return Err(HttpResponse::BadRequest().json(json!({
+                    "error": {
+                        "code": "USER_NOT_FOUND",
+                        "message": "One or more users do not exist",
+                        "detail": format!("User with ID '{}' not found", grant.user_id)
+                    }
+                })));
+            }
+            Err(e) => return Err(db_error!("DB_ERROR", "Failed to verify user existence", e)),
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn index_handler() -> HttpResponse {
     match fs::read_to_string("./dist/index.html") {
         Ok(html) => HttpResponse::Ok()
@@ -62,98 +287,232 @@ pub async fn create_file_handler(
     req: HttpRequest,
     body: web::Json<FileUploadRequest>,
 ) -> HttpResponse {
-    let session = match bearer_auth(state.clone(), &req).await {
-        Ok(id) => id,
-        Err(e) => return auth_error!(e),
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
     };
 
+    if let Some(folder_id) = body.folder_id.as_deref() {
+        if let Err(response) = require_folder_role(&state, folder_id, &principal.user_id, Role::can_edit, "upload into this folder").await {
+            return response;
+        }
+    }
+
     let file_id = state.snowflake.generate().await.to_string();
     let filename = body.filename.as_deref().unwrap_or(&file_id).to_string();
 
-    if let Err(e) = state.mysql.create_file(&file_id, &filename, &session.user_id, "[]").await {
+    if let Err(e) = state.db.create_file(&file_id, &filename, &principal.user_id, body.folder_id.as_deref()).await {
         return db_error!("DB_ERROR", "Failed to create file record in database", e);
     }
 
-    match state.s3client.upload_url(&file_id).await {
+    match state.storage.upload_url(&file_id).await {
         Ok(url) => HttpResponse::Created().json(json!({
             "data": {
                 "id": file_id,
                 "filename": filename,
                 "url": url,
-                "user_id": session.user_id,
-                "owner_id": session.user_id
+                "user_id": principal.user_id,
+                "owner_id": principal.user_id,
+                "folder_id": body.folder_id,
+                "upload_state": "pending"
             }
         })),
         Err(e) => db_error!("UPLOAD_FAILED", "Failed to generate upload URL", e),
     }
 }
 
-pub async fn update_file_access_handler(
+pub async fn complete_upload_handler(
     state: web::Data<AppState>,
     path: web::Path<String>,
     req: HttpRequest,
-    body: web::Json<UpdateAccessRequest>,
 ) -> HttpResponse {
-    let session = match bearer_auth(state.clone(), &req).await {
-        Ok(id) => id,
-        Err(e) => return auth_error!(e),
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
     };
 
     let file_id = path.into_inner();
 
-    let is_owner = match state.mysql.check_user_is_owner(&file_id, &session.user_id).await {
-        Ok(owner) => owner,
-        Err(e) => return db_error!("DB_ERROR", "Failed to verify file ownership", e),
+    let file = match state.db.get_file(&file_id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": {
+                    "code": "FILE_NOT_FOUND",
+                    "message": "File not found"
+                }
+            }));
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
     };
 
-    if !is_owner {
-        return HttpResponse::Forbidden().json(json!({
-            "error": {
-                "code": "ACCESS_DENIED",
-                "message": "Only file owner can update access permissions",
-                "detail": format!("User {} is not the owner of file {}", session.user_id, file_id)
-            }
-        }));
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, Role::can_edit, "upload content").await {
+        return response;
     }
 
-    let mut seen = std::collections::HashSet::with_capacity(body.accessible_user_ids.len());
-    for uid in &body.accessible_user_ids {
-        if !seen.insert(uid.clone()) {
-            return HttpResponse::BadRequest().json(json!({
+    finalize_upload(&state, &file, &principal.user_id).await
+}
+
+/// Content that never made it into a blob sits under the file id, where a new upload would
+/// overwrite it, so it becomes a version before the upload starts.
+pub(crate) async fn preserve_current_content(state: &web::Data<AppState>, file: &File) -> Result<(), HttpResponse> {
+    if file.upload_state != UPLOAD_COMPLETE || file.blob_sha256.is_some() {
+        return Ok(());
+    }
+
+    let stored = match state.storage.head_object(&file.id).await {
+        Ok(Some(meta)) => store_as_blob(state.db.as_ref(), state.storage.as_ref(), file, &meta, None).await,
+        Ok(None) => Ok(false),
+        Err(e) => Err(e),
+    };
+    match stored {
+        Ok(_) => Ok(()),
+        Err(e) => Err(db_error!("STORAGE_ERROR", "Failed to preserve current content", e)),
+    }
+}
+
+/// Moves the uploaded object into its content-addressed blob as a new version, records its size
+/// and checksums on the file row and answers with the file.
+pub(crate) async fn finalize_upload(state: &web::Data<AppState>, file: &File, uploaded_by: &str) -> HttpResponse {
+    let file_id = &file.id;
+
+    // Content replaced through a new upload URL shows up under the file id again.
+    let meta = match state.storage.head_object(file_id).await {
+        Ok(Some(meta)) => meta,
+        // Already moved into a blob by an earlier call.
+        Ok(None) if file.blob_sha256.is_some() && file.upload_state == UPLOAD_COMPLETE => return file_response(state, file_id).await,
+        Ok(None) => {
+            return HttpResponse::Conflict().json(json!({
                 "error": {
-                    "code": "DUPLICATE_USER_ID",
-                    "message": "Duplicate user ID in accessible users",
-                    "detail": format!("User ID '{}' appears more than once", uid)
+                    "code": "UPLOAD_NOT_FOUND",
+                    "message": "File content has not been uploaded yet",
+                    "detail": format!("No stored object for file {}", file_id)
                 }
             }));
         }
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to inspect uploaded object", e),
+    };
+
+    let deduplicated = match store_as_blob(state.db.as_ref(), state.storage.as_ref(), file, &meta, Some(uploaded_by)).await {
+        Ok(deduplicated) => deduplicated,
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to store uploaded object", e),
+    };
+
+    if !deduplicated {
+        if let Err(e) = state.db.complete_file_upload(
+            file_id,
+            meta.size,
+            meta.content_type.as_deref(),
+            meta.etag.as_deref(),
+            meta.sha256.as_deref(),
+        ).await {
+            return db_error!("DB_ERROR", "Failed to record uploaded file", e);
+        }
     }
 
-    for user_id_item in &body.accessible_user_ids {
-        match state.mysql.get_user(user_id_item).await {
-            Ok(Some(_)) => {},
-            Ok(None) => {
-                return HttpResponse::BadRequest().json(json!({
-                    "error": {
-                        "code": "USER_NOT_FOUND",
-                        "message": "One or more users do not exist",
-                        "detail": format!("User with ID '{}' not found", user_id_item)
-                    }
-                }));
+    file_response(state, file_id).await
+}
+
+/// Answers with the upload-related fields of the file as stored now.
+pub(crate) async fn file_response(state: &web::Data<AppState>, file_id: &str) -> HttpResponse {
+    match state.db.get_file(file_id).await {
+        Ok(Some(file)) => HttpResponse::Ok().json(json!({
+            "data": {
+                "id": file.id,
+                "filename": file.filename,
+                "owner_id": file.owner_id,
+                "size_bytes": file.size_bytes,
+                "content_type": file.content_type,
+                "etag": file.etag,
+                "sha256": file.sha256,
+                "upload_state": file.upload_state
             }
-            Err(e) => return db_error!("DB_ERROR", "Failed to verify user existence", e),
-        }
+        })),
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "error": {
+                "code": "FILE_NOT_FOUND",
+                "message": "File not found"
+            }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to retrieve file", e),
     }
+}
+
+pub async fn update_file_access_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+    body: web::Json<UpdateAccessRequest>,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::ShareManage).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let file_id = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, Role::can_share, "update access permissions").await {
+        return response;
+    }
+
+    if let Err(response) = require_verified_email(&state, &principal.user_id).await {
+        return response;
+    }
+
+    let file = match state.db.get_file(&file_id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": {
+                    "code": "FILE_NOT_FOUND",
+                    "message": "File not found"
+                }
+            }));
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
+    };
+
+    let existing = match state.db.list_file_permissions(&file_id).await {
+        Ok(permissions) => permissions,
+        Err(e) => return db_error!("DB_ERROR", "Failed to list file permissions", e),
+    };
+
+    // The plain id list predates roles: listed users keep their current role, new ones become viewers.
+    let grants: Vec<FileGrant> = match (&body.permissions, &body.accessible_user_ids) {
+        (Some(permissions), None) => permissions.clone(),
+        (None, Some(user_ids)) => user_ids
+            .iter()
+            .map(|user_id| FileGrant {
+                user_id: user_id.clone(),
+                role: existing
+                    .iter()
+                    .find(|p| &p.user_id == user_id)
+                    .map(|p| p.role.clone())
+                    .unwrap_or_else(|| Role::Viewer.as_str().to_string()),
+            })
+            .collect(),
+        _ => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": {
+                    "code": "INVALID_INPUT",
+                    "message": "Provide either permissions or accessible_user_ids",
+                    "detail": "Exactly one of the two fields must be present"
+                }
+            }));
+        }
+    };
 
-    let updated_ids = serde_json::to_string(&body.accessible_user_ids)
-        .unwrap_or_else(|_| "[]".to_string());
+    if let Err(response) = validate_grants(&state, &grants, &file.owner_id).await {
+        return response;
+    }
 
-    match state.mysql.update_file_access(&file_id, &updated_ids).await {
+    match state.db.set_file_permissions(&file_id, &grants, &principal.user_id).await {
         Ok(_) => HttpResponse::Ok().json(json!({
             "data": {
                 "message": "File access updated successfully",
                 "file_id": file_id,
-                "accessible_user_ids": body.accessible_user_ids
+                "accessible_user_ids": grants.iter().map(|g| g.user_id.clone()).collect::<Vec<_>>(),
+                "permissions": grants
             }
         })),
         Err(e) => db_error!("UPDATE_FAILED", "Failed to update file access", e),
@@ -165,36 +524,43 @@ pub async fn get_file_details_handler(
     path: web::Path<String>,
     req: HttpRequest,
 ) -> HttpResponse {
-    let session = match bearer_auth(state.clone(), &req).await {
-        Ok(id) => id,
-        Err(e) => return auth_error!(e),
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
     };
 
     let file_id = path.into_inner();
 
-    let has_access = match state.mysql.check_user_can_access(&file_id, &session.user_id).await {
-        Ok(access) => access,
-        Err(e) => return db_error!("DB_ERROR", "Failed to check file access", e),
+    let role = match require_role(&state, &file_id, &principal.user_id, |_| true, "view this file").await {
+        Ok(role) => role,
+        Err(response) => return response,
     };
 
-    if !has_access {
-        return HttpResponse::Forbidden().json(json!({
-            "error": {
-                "code": "ACCESS_DENIED",
-                "message": "You don't have permission to access this file",
-                "detail": format!("User {} cannot access file {}", session.user_id, file_id)
-            }
-        }));
-    }
+    let permissions = match state.db.list_file_permissions(&file_id).await {
+        Ok(permissions) => permissions,
+        Err(e) => return db_error!("DB_ERROR", "Failed to list file permissions", e),
+    };
 
-    match state.mysql.get_file(&file_id).await {
+    match state.db.get_file(&file_id).await {
         Ok(Some(file)) => HttpResponse::Ok().json(json!({
             "data": {
                 "id": file.id,
                 "filename": file.filename,
                 "owner_id": file.owner_id,
                 "created_at": file.created_at,
-                "accessible_user_ids": file.accessible_user_ids
+                "accessible_user_ids": file.accessible_user_ids,
+                "permissions": permissions.iter().map(|p| json!({
+                    "user_id": p.user_id,
+                    "role": p.role,
+                    "granted_at": p.granted_at,
+                    "granted_by": p.granted_by
+                })).collect::<Vec<_>>(),
+                "role": role.as_str(),
+                "size_bytes": file.size_bytes,
+                "content_type": file.content_type,
+                "etag": file.etag,
+                "sha256": file.sha256,
+                "upload_state": file.upload_state
             }
         })),
         Ok(None) => HttpResponse::NotFound().json(json!({
@@ -207,102 +573,294 @@ pub async fn get_file_details_handler(
     }
 }
 
-pub async fn get_download_url_handler(
+pub async fn rename_file_handler(
     state: web::Data<AppState>,
     path: web::Path<String>,
     req: HttpRequest,
+    body: web::Json<RenameFileRequest>,
 ) -> HttpResponse {
-    let session = match bearer_auth(state.clone(), &req).await {
-        Ok(id) => id,
-        Err(e) => return auth_error!(e),
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
     };
 
     let file_id = path.into_inner();
 
-    let has_access = match state.mysql.check_user_can_access(&file_id, &session.user_id).await {
-        Ok(access) => access,
-        Err(e) => return db_error!("DB_ERROR", "Failed to check file access", e),
-    };
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, Role::can_edit, "rename this file").await {
+        return response;
+    }
 
-    if !has_access {
-        return HttpResponse::Forbidden().json(json!({
+    let filename = body.filename.trim();
+    if filename.is_empty() || filename.chars().count() > MAX_FILENAME_LENGTH {
+        return HttpResponse::BadRequest().json(json!({
             "error": {
-                "code": "ACCESS_DENIED",
-                "message": "You don't have permission to access this file",
-                "detail": format!("User {} cannot access file {}", session.user_id, file_id)
+                "code": "INVALID_INPUT",
+                "message": "Invalid filename",
+                "detail": format!("Filename must be between 1 and {} characters", MAX_FILENAME_LENGTH)
             }
         }));
     }
 
-    match state.s3client.download_url(&file_id).await {
-        Ok(url) => HttpResponse::Ok().json(json!({
+    match state.db.rename_file(&file_id, filename).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
             "data": {
-                "url": url,
-                "user_id": session.user_id,
-                "file_id": file_id
+                "id": file_id,
+                "filename": filename
             }
         })),
-        Err(e) => db_error!("DOWNLOAD_FAILED", "Failed to generate download URL", e),
+        Err(e) => db_error!("DB_ERROR", "Failed to rename file", e),
     }
 }
 
-pub async fn list_files_handler(
+pub async fn move_file_handler(
     state: web::Data<AppState>,
+    path: web::Path<String>,
     req: HttpRequest,
+    body: web::Json<MoveFileRequest>,
 ) -> HttpResponse {
-    let session = match bearer_auth(state.clone(), &req).await {
-        Ok(id) => id,
-        Err(e) => return auth_error!(e),
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
     };
 
-    match state.mysql.list_user_files(&session.user_id).await {
-        Ok(files) => HttpResponse::Ok().json(json!({
+    let file_id = path.into_inner();
+
+    let role = match require_role(&state, &file_id, &principal.user_id, Role::can_edit, "move this file").await {
+        Ok(role) => role,
+        Err(response) => return response,
+    };
+
+    let file = match state.db.get_file(&file_id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": {
+                    "code": "FILE_NOT_FOUND",
+                    "message": "File not found"
+                }
+            }));
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
+    };
+
+    match body.folder_id.as_deref() {
+        Some(folder_id) => {
+            let target = match require_folder_role(&state, folder_id, &principal.user_id, Role::can_edit, "move files here").await {
+                Ok((folder, _)) => folder,
+                Err(response) => return response,
+            };
+
+            // Whoever owns the folder controls what is in it, so only the file's owner may hand
+            // it to another user's folder.
+            let current_owner = match file.folder_id.as_deref() {
+                Some(current) => match state.db.get_folder(current).await {
+                    Ok(folder) => folder.map(|folder| folder.owner_id),
+                    Err(e) => return db_error!("DB_ERROR", "Failed to retrieve folder", e),
+                },
+                None => Some(file.owner_id.clone()),
+            };
+            if role != Role::Owner && target.owner_id != file.owner_id && current_owner.as_deref() != Some(target.owner_id.as_str()) {
+                return HttpResponse::Forbidden().json(json!({
+                    "error": {
+                        "code": "ACCESS_DENIED",
+                        "message": "Only the file owner can move it into another user's folder",
+                        "detail": format!("User {} is {} of file {}", principal.user_id, role.as_str(), file_id)
+                    }
+                }));
+            }
+        }
+        None => {
+            if role != Role::Owner {
+                return HttpResponse::Forbidden().json(json!({
+                    "error": {
+                        "code": "ACCESS_DENIED",
+                        "message": "Only the file owner can move it to the top level",
+                        "detail": format!("User {} is {} of file {}", principal.user_id, role.as_str(), file_id)
+                    }
+                }));
+            }
+        }
+    }
+
+    match state.db.move_file(&file_id, body.folder_id.as_deref()).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
             "data": {
-                "files": files
+                "id": file_id,
+                "folder_id": body.folder_id
             }
         })),
-        Err(e) => db_error!("LIST_FAILED", "Failed to get file list", e),
+        Err(e) => db_error!("DB_ERROR", "Failed to move file", e),
     }
 }
 
-pub async fn delete_file_handler(
+pub async fn replace_content_handler(
     state: web::Data<AppState>,
     path: web::Path<String>,
     req: HttpRequest,
 ) -> HttpResponse {
-    let session = match bearer_auth(state.clone(), &req).await {
-        Ok(id) => id,
-        Err(e) => return auth_error!(e),
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
     };
 
     let file_id = path.into_inner();
 
-    let is_owner = match state.mysql.check_user_is_owner(&file_id, &session.user_id).await {
-        Ok(owner) => owner,
-        Err(e) => return db_error!("DB_ERROR", "Failed to check file ownership", e),
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, Role::can_edit, "replace file content").await {
+        return response;
+    }
+
+    let file = match state.db.get_file(&file_id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": {
+                    "code": "FILE_NOT_FOUND",
+                    "message": "File not found"
+                }
+            }));
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
     };
 
-    if !is_owner {
-        return HttpResponse::Forbidden().json(json!({
-            "error": {
-                "code": "ACCESS_DENIED",
-                "message": "Only file owner can delete this file",
-                "detail": format!("User {} is not the owner of file {}", session.user_id, file_id)
+    if let Err(response) = preserve_current_content(&state, &file).await {
+        return response;
+    }
+
+    match state.storage.upload_url(&file_id).await {
+        Ok(url) => HttpResponse::Ok().json(json!({
+            "data": {
+                "id": file_id,
+                "url": url,
+                "expires_in": state.storage.upload_expire()
             }
-        }));
+        })),
+        Err(e) => db_error!("UPLOAD_FAILED", "Failed to generate upload URL", e),
     }
+}
+
+pub async fn get_download_url_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
 
-    if let Err(e) = state.s3client.delete_object(&file_id).await {
-        return db_error!("DELETE_FAILED", "Failed to delete file from S3", e);
+    let file_id = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, |_| true, "download this file").await {
+        return response;
     }
 
-    match state.mysql.delete_file(&file_id).await {
-        Ok(_) => HttpResponse::Ok().json(json!({
+    let file = match state.db.get_file(&file_id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": {
+                    "code": "FILE_NOT_FOUND",
+                    "message": "File not found"
+                }
+            }));
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
+    };
+
+    let url = match state.download_mode {
+        DownloadMode::Presigned => state.storage.download_url(&file.object_key()).await,
+        DownloadMode::Proxy => Ok(data_url(&state, &file_id)),
+    };
+
+    match url {
+        Ok(url) => HttpResponse::Ok().json(json!({
             "data": {
-                "message": "File deleted successfully",
+                "url": url,
+                "user_id": principal.user_id,
                 "file_id": file_id
             }
         })),
-        Err(e) => db_error!("DB_ERROR", "Failed to delete file from database", e),
+        Err(e) => db_error!("DOWNLOAD_FAILED", "Failed to generate download URL", e),
+    }
+}
+
+pub async fn list_files_handler(
+    state: web::Data<AppState>,
+    params: web::Query<ListFilesQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let query = match params.parse() {
+        Ok(query) => query,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": {
+                    "code": "INVALID_QUERY",
+                    "message": "Invalid list parameters",
+                    "detail": e
+                }
+            }));
+        }
+    };
+
+    if let Some(FolderFilter::Folder(folder_id)) = &query.folder {
+        if let Err(response) = require_folder_role(&state, folder_id, &principal.user_id, |_| true, "view this folder").await {
+            return response;
+        }
+    }
+
+    match state.db.list_user_files(&principal.user_id, &query).await {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "data": {
+                "files": page.files,
+                "total": page.total,
+                "next_cursor": page.next.map(|cursor| params.encode_cursor(&query, cursor))
+            }
+        })),
+        Err(e) => db_error!("LIST_FAILED", "Failed to get file list", e),
     }
+}
+
+pub async fn delete_file_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let file_id = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, Role::can_delete, "delete this file").await {
+        return response;
+    }
+
+    let file = match state.db.get_file(&file_id).await {
+        Ok(file) => file,
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
+    };
+
+    let blob_hashes = match state.db.delete_file(&file_id).await {
+        Ok(blob_hashes) => blob_hashes,
+        Err(e) => return db_error!("DB_ERROR", "Failed to delete file from database", e),
+    };
+
+    // A blob's object only goes with its last reference.
+    if let Some(file) = file {
+        remove_file_objects(state.storage.as_ref(), &file).await;
+    }
+    release_blobs(state.db.as_ref(), state.storage.as_ref(), &blob_hashes).await;
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "message": "File deleted successfully",
+            "file_id": file_id
+        }
+    }))
 }
\ No newline at end of file
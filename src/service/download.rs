@@ -0,0 +1,240 @@
+use std::env;
+use std::error::Error;
+use std::ops::Range;
+use actix_web::http::header::{
+    self, Charset, ContentDisposition, DispositionParam, DispositionType, EntityTag, ExtendedValue, HttpDate, IfNoneMatch, IfRange, LastModified,
+};
+use actix_web::http::Method;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde_json::json;
+use crate::router::AppState;
+use crate::middleware::bearer_auth;
+use crate::database::model::{File, Scope, UPLOAD_COMPLETE};
+use super::content::require_role;
+
+/// How downloads are handed to clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownloadMode {
+    /// Clients fetch objects straight from storage through presigned URLs.
+    #[default]
+    Presigned,
+    /// Every download is streamed through this server; storage URLs are never handed out.
+    Proxy,
+}
+
+impl DownloadMode {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        match env::var("DOWNLOAD_MODE").as_deref() {
+            Ok("presigned") | Err(_) => Ok(Self::Presigned),
+            Ok("proxy") => Ok(Self::Proxy),
+            Ok(other) => Err(format!("DOWNLOAD_MODE must be presigned or proxy, got '{}'", other).into()),
+        }
+    }
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+/// Where the proxied contents of a file can be fetched.
+pub(crate) fn data_url(state: &AppState, file_id: &str) -> String {
+    format!("{}/api/v1/content/{}/data", state.public_url.trim_end_matches('/'), file_id)
+}
+
+enum RangeRequest {
+    Full,
+    Partial(Range<u64>),
+    Unsatisfiable,
+}
+
+/// Reads a single `bytes=` range; anything else, multiple ranges included, falls back to the whole object.
+fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => match start < size {
+            true => RangeRequest::Partial(start..size.min(end + 1)),
+            false => RangeRequest::Unsatisfiable,
+        },
+        (Ok(start), Err(_)) if end.is_empty() => match start < size {
+            true => RangeRequest::Partial(start..size),
+            false => RangeRequest::Unsatisfiable,
+        },
+        (Err(_), Ok(suffix)) if start.is_empty() => match suffix > 0 && size > 0 {
+            true => RangeRequest::Partial(size.saturating_sub(suffix)..size),
+            false => RangeRequest::Unsatisfiable,
+        },
+        _ => RangeRequest::Full,
+    }
+}
+
+/// A Range only applies while the representation still matches `If-Range`, so a resumed
+/// download never stitches together two different versions of a file.
+fn range_applies(req: &HttpRequest, etag: Option<&EntityTag>, last_modified: Option<HttpDate>) -> bool {
+    match req.get_header::<IfRange>() {
+        None => true,
+        Some(IfRange::EntityTag(expected)) => etag.is_some_and(|etag| etag.strong_eq(&expected)),
+        Some(IfRange::Date(expected)) => last_modified.is_some_and(|date| date == expected),
+    }
+}
+
+fn not_modified(req: &HttpRequest, etag: Option<&EntityTag>) -> bool {
+    match (req.get_header::<IfNoneMatch>(), etag) {
+        (Some(IfNoneMatch::Any), Some(_)) => true,
+        (Some(IfNoneMatch::Items(items)), Some(etag)) => items.iter().any(|item| item.weak_eq(etag)),
+        _ => false,
+    }
+}
+
+/// Non-ASCII names travel in `filename*`; older clients get this approximation in `filename`.
+fn ascii_filename(filename: &str) -> String {
+    filename
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' { c } else { '_' })
+        .collect()
+}
+
+/// Streams a finished file's object, honouring `Range`, `If-Range` and `If-None-Match`.
+/// HEAD requests get the same headers without the body.
+pub(crate) async fn stream_file(state: &web::Data<AppState>, req: &HttpRequest, file: &File) -> HttpResponse {
+    let key = file.object_key();
+    let meta = match state.storage.head_object(&key).await {
+        Ok(Some(meta)) => meta,
+        Ok(None) => {
+            return error_response!(
+                HttpResponse::NotFound(),
+                "OBJECT_NOT_FOUND",
+                "File content is missing from storage",
+                format!("No stored object for file {}", file.id)
+            );
+        }
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to inspect stored object", e),
+    };
+
+    let size = meta.size.max(0) as u64;
+    let etag = meta.etag.as_deref().map(|etag| EntityTag::new_strong(etag.trim_matches('"').to_string()));
+    let last_modified = meta.last_modified.map(|t| HttpDate::from(std::time::SystemTime::from(t)));
+
+    let mut builder = HttpResponse::Ok();
+    builder
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::CACHE_CONTROL, "private, no-cache"));
+    if let Some(etag) = &etag {
+        builder.insert_header(header::ETag(etag.clone()));
+    }
+    if let Some(last_modified) = last_modified {
+        builder.insert_header(LastModified(last_modified));
+    }
+
+    if not_modified(req, etag.as_ref()) {
+        return builder.status(actix_web::http::StatusCode::NOT_MODIFIED).finish();
+    }
+
+    let range = match req.headers().get(header::RANGE).and_then(|value| value.to_str().ok()) {
+        Some(value) if range_applies(req, etag.as_ref(), last_modified) => parse_range(value, size),
+        _ => RangeRequest::Full,
+    };
+    let range = match range {
+        RangeRequest::Full => 0..size,
+        RangeRequest::Partial(range) => {
+            builder
+                .status(actix_web::http::StatusCode::PARTIAL_CONTENT)
+                .insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, range.end - 1, size)));
+            range
+        }
+        RangeRequest::Unsatisfiable => {
+            return builder
+                .status(actix_web::http::StatusCode::RANGE_NOT_SATISFIABLE)
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{}", size)))
+                .finish();
+        }
+    };
+
+    builder
+        // A shared blob keeps the first uploader's content type; the file row has this file's.
+        .content_type(file.content_type.as_deref().or(meta.content_type.as_deref()).unwrap_or("application/octet-stream"))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![
+                DispositionParam::Filename(ascii_filename(&file.filename)),
+                DispositionParam::FilenameExt(ExtendedValue {
+                    charset: Charset::Ext("UTF-8".to_string()),
+                    language_tag: None,
+                    value: file.filename.as_bytes().to_vec(),
+                }),
+            ],
+        })
+        .no_chunking(range.end - range.start);
+
+    if req.method() == Method::HEAD || range.is_empty() {
+        return builder.streaming(futures_util::stream::empty::<Result<web::Bytes, std::io::Error>>());
+    }
+
+    match state.storage.read_object(&key, range).await {
+        Ok(Some(stream)) => builder.streaming(stream),
+        Ok(None) => error_response!(
+            HttpResponse::NotFound(),
+            "OBJECT_NOT_FOUND",
+            "File content is missing from storage",
+            format!("No stored object for file {}", file.id)
+        ),
+        Err(e) => db_error!("STORAGE_ERROR", "Failed to read stored object", e),
+    }
+}
+
+/// Serves GET and HEAD for a file's contents through the server.
+pub async fn download_file_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let file_id = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, |_| true, "download this file").await {
+        return response;
+    }
+
+    match state.db.get_file(&file_id).await {
+        Ok(Some(file)) if file.upload_state == UPLOAD_COMPLETE => stream_file(&state, &req, &file).await,
+        Ok(Some(_)) => error_response!(
+            HttpResponse::Conflict(),
+            "UPLOAD_NOT_FOUND",
+            "File content has not been uploaded yet",
+            format!("File {} is still pending", file_id)
+        ),
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "error": {
+                "code": "FILE_NOT_FOUND",
+                "message": "File not found"
+            }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to retrieve file", e),
+    }
+}
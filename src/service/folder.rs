@@ -0,0 +1,425 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::router::AppState;
+use crate::middleware::bearer_auth;
+use crate::database::blob::{release_blobs, remove_file_objects};
+use crate::database::model::{FileGrant, Folder, Role, Scope};
+use super::content::validate_grants;
+use super::account::require_verified_email;
+use serde::Deserialize;
+use serde_json::json;
+
+const MAX_FOLDER_NAME_LENGTH: usize = 255;
+
+#[derive(Deserialize)]
+pub struct CreateFolderRequest {
+    name: String,
+    #[serde(default)]
+    parent_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RenameFolderRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct MoveFolderRequest {
+    #[serde(default)]
+    parent_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateFolderAccessRequest {
+    permissions: Vec<FileGrant>,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+/// Loads a folder and the caller's role on it, rejecting the request unless `allowed` accepts that role.
+pub(crate) async fn require_folder_role(
+    state: &web::Data<AppState>,
+    folder_id: &str,
+    user_id: &str,
+    allowed: fn(&Role) -> bool,
+    action: &str,
+) -> Result<(Folder, Role), HttpResponse> {
+    let role = match state.db.get_folder_role(folder_id, user_id).await {
+        Ok(Some(role)) => role,
+        Ok(None) => {
+            return Err(error_response!(
+                HttpResponse::Forbidden(),
+                "ACCESS_DENIED",
+                "You don't have permission to access this folder",
+                format!("User {} cannot access folder {}", user_id, folder_id)
+            ));
+        }
+        Err(e) => return Err(db_error!("DB_ERROR", "Failed to check folder access", e)),
+    };
+
+    if !allowed(&role) {
+        return Err(error_response!(
+            HttpResponse::Forbidden(),
+            "ACCESS_DENIED",
+            format!("Your role does not allow you to {}", action),
+            format!("User {} is {} of folder {}", user_id, role.as_str(), folder_id)
+        ));
+    }
+
+    match state.db.get_folder(folder_id).await {
+        Ok(Some(folder)) => Ok((folder, role)),
+        Ok(None) => Err(error_response!(
+            HttpResponse::NotFound(),
+            "FOLDER_NOT_FOUND",
+            "Folder not found",
+            format!("Folder {} does not exist", folder_id)
+        )),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve folder", e)),
+    }
+}
+
+fn validate_folder_name(name: &str) -> Result<&str, HttpResponse> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_FOLDER_NAME_LENGTH || name.contains('/') {
+        return Err(error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "Invalid folder name",
+            format!("Folder names must be 1 to {} characters and cannot contain '/'", MAX_FOLDER_NAME_LENGTH)
+        ));
+    }
+    Ok(name)
+}
+
+async fn ensure_name_free(
+    state: &web::Data<AppState>,
+    owner_id: &str,
+    parent_id: Option<&str>,
+    name: &str,
+    except: Option<&str>,
+) -> Result<(), HttpResponse> {
+    match state.db.find_folder(owner_id, parent_id, name).await {
+        Ok(Some(existing)) if Some(existing.id.as_str()) != except => Err(error_response!(
+            HttpResponse::Conflict(),
+            "FOLDER_EXISTS",
+            "A folder with this name already exists here",
+            format!("Folder '{}' already exists as {}", name, existing.id)
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to check folder name", e)),
+    }
+}
+
+/// The folder's path trimmed to the part the user can see, so a shared folder never reveals its parents.
+async fn breadcrumbs(state: &web::Data<AppState>, folder_id: &str, user_id: &str) -> Result<Vec<Folder>, HttpResponse> {
+    let path = match state.db.folder_path(folder_id).await {
+        Ok(path) => path,
+        Err(e) => return Err(db_error!("DB_ERROR", "Failed to resolve folder path", e)),
+    };
+
+    for (index, folder) in path.iter().enumerate() {
+        match state.db.get_folder_role(&folder.id, user_id).await {
+            Ok(Some(_)) => return Ok(path[index..].to_vec()),
+            Ok(None) => {}
+            Err(e) => return Err(db_error!("DB_ERROR", "Failed to check folder access", e)),
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+pub async fn create_folder_handler(
+    state: web::Data<AppState>,
+    body: web::Json<CreateFolderRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let name = match validate_folder_name(&body.name) {
+        Ok(name) => name,
+        Err(response) => return response,
+    };
+
+    // A subfolder always belongs to the owner of the tree it is created in.
+    let owner_id = match body.parent_id.as_deref() {
+        Some(parent_id) => {
+            match require_folder_role(&state, parent_id, &principal.user_id, Role::can_edit, "create folders here").await {
+                Ok((parent, _)) => parent.owner_id,
+                Err(response) => return response,
+            }
+        }
+        None => principal.user_id.clone(),
+    };
+
+    if let Err(response) = ensure_name_free(&state, &owner_id, body.parent_id.as_deref(), name, None).await {
+        return response;
+    }
+
+    let folder_id = state.snowflake.generate().await.to_string();
+    match state.db.create_folder(&folder_id, &owner_id, body.parent_id.as_deref(), name).await {
+        Ok(folder) => HttpResponse::Created().json(json!({ "data": folder })),
+        Err(e) => db_error!("DB_ERROR", "Failed to create folder", e),
+    }
+}
+
+pub async fn list_root_folders_handler(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    match state.db.list_root_folders(&principal.user_id).await {
+        Ok(folders) => HttpResponse::Ok().json(json!({
+            "data": {
+                "folders": folders
+            }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to list folders", e),
+    }
+}
+
+pub async fn get_folder_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let folder_id = path.into_inner();
+    let (folder, role) = match require_folder_role(&state, &folder_id, &principal.user_id, |_| true, "view this folder").await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let breadcrumbs = match breadcrumbs(&state, &folder_id, &principal.user_id).await {
+        Ok(breadcrumbs) => breadcrumbs,
+        Err(response) => return response,
+    };
+
+    let children = match state.db.list_child_folders(&folder_id).await {
+        Ok(children) => children,
+        Err(e) => return db_error!("DB_ERROR", "Failed to list folders", e),
+    };
+
+    let permissions = match state.db.list_folder_permissions(&folder_id).await {
+        Ok(permissions) => permissions,
+        Err(e) => return db_error!("DB_ERROR", "Failed to list folder permissions", e),
+    };
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "folder": folder,
+            "role": role.as_str(),
+            "breadcrumbs": breadcrumbs,
+            "folders": children,
+            "permissions": permissions.iter().map(|p| json!({
+                "user_id": p.user_id,
+                "role": p.role,
+                "granted_at": p.granted_at,
+                "granted_by": p.granted_by
+            })).collect::<Vec<_>>()
+        }
+    }))
+}
+
+pub async fn rename_folder_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<RenameFolderRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let folder_id = path.into_inner();
+    let (folder, _) = match require_folder_role(&state, &folder_id, &principal.user_id, Role::can_edit, "rename this folder").await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let name = match validate_folder_name(&body.name) {
+        Ok(name) => name,
+        Err(response) => return response,
+    };
+
+    if let Err(response) = ensure_name_free(&state, &folder.owner_id, folder.parent_id.as_deref(), name, Some(&folder.id)).await {
+        return response;
+    }
+
+    match state.db.rename_folder(&folder_id, name).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "data": Folder { name: name.to_string(), ..folder }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to rename folder", e),
+    }
+}
+
+pub async fn move_folder_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<MoveFolderRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let folder_id = path.into_inner();
+    let (folder, role) = match require_folder_role(&state, &folder_id, &principal.user_id, Role::can_edit, "move this folder").await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    match body.parent_id.as_deref() {
+        Some(parent_id) => {
+            let (parent, _) = match require_folder_role(&state, parent_id, &principal.user_id, Role::can_edit, "move folders here").await {
+                Ok(found) => found,
+                Err(response) => return response,
+            };
+
+            if parent.owner_id != folder.owner_id {
+                return error_response!(
+                    HttpResponse::BadRequest(),
+                    "INVALID_MOVE",
+                    "Folders cannot be moved into another user's folders",
+                    format!("Folder {} belongs to {}, target {} to {}", folder.id, folder.owner_id, parent.id, parent.owner_id)
+                );
+            }
+
+            match state.db.folder_path(parent_id).await {
+                Ok(path) if path.iter().any(|f| f.id == folder.id) => {
+                    return error_response!(
+                        HttpResponse::BadRequest(),
+                        "INVALID_MOVE",
+                        "A folder cannot be moved into itself",
+                        format!("Folder {} contains {}", folder.id, parent_id)
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => return db_error!("DB_ERROR", "Failed to resolve folder path", e),
+            }
+        }
+        None => {
+            if role != Role::Owner {
+                return error_response!(
+                    HttpResponse::Forbidden(),
+                    "ACCESS_DENIED",
+                    "Only the folder owner can move it to the top level",
+                    format!("User {} is {} of folder {}", principal.user_id, role.as_str(), folder.id)
+                );
+            }
+        }
+    }
+
+    if let Err(response) = ensure_name_free(&state, &folder.owner_id, body.parent_id.as_deref(), &folder.name, Some(&folder.id)).await {
+        return response;
+    }
+
+    match state.db.move_folder(&folder_id, body.parent_id.as_deref()).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "data": Folder { parent_id: body.parent_id.clone(), ..folder }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to move folder", e),
+    }
+}
+
+pub async fn delete_folder_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let folder_id = path.into_inner();
+    if let Err(response) = require_folder_role(&state, &folder_id, &principal.user_id, Role::can_delete, "delete this folder").await {
+        return response;
+    }
+
+    // Files other users put in the folder are theirs; they move to their owners' top level.
+    let deleted = match state.db.delete_folder_tree(&folder_id, &principal.user_id).await {
+        Ok(deleted) => deleted,
+        Err(e) => return db_error!("DB_ERROR", "Failed to delete folder", e),
+    };
+
+    for file in &deleted.files {
+        remove_file_objects(state.storage.as_ref(), file).await;
+    }
+    release_blobs(state.db.as_ref(), state.storage.as_ref(), &deleted.blob_hashes).await;
+    let file_ids: Vec<&str> = deleted.files.iter().map(|file| file.id.as_str()).collect();
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "message": "Folder deleted successfully",
+            "folder_id": folder_id,
+            "deleted_file_ids": file_ids,
+            "moved_file_ids": deleted.moved_file_ids
+        }
+    }))
+}
+
+pub async fn update_folder_access_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<UpdateFolderAccessRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::ShareManage).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    if let Err(response) = require_verified_email(&state, &principal.user_id).await {
+        return response;
+    }
+
+    let folder_id = path.into_inner();
+    let (folder, _) = match require_folder_role(&state, &folder_id, &principal.user_id, Role::can_share, "update access permissions").await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    if let Err(response) = validate_grants(&state, &body.permissions, &folder.owner_id).await {
+        return response;
+    }
+
+    match state.db.set_folder_permissions(&folder_id, &body.permissions, &principal.user_id).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "data": {
+                "message": "Folder access updated successfully",
+                "folder_id": folder_id,
+                "permissions": body.permissions
+            }
+        })),
+        Err(e) => db_error!("UPDATE_FAILED", "Failed to update folder access", e),
+    }
+}
@@ -1,6 +1,18 @@
 pub mod user;
 pub mod content;
+pub mod multipart;
 pub mod session;
+pub mod share;
+pub mod folder;
+pub mod token;
+pub mod two_factor;
+pub mod passkey;
+pub mod oidc;
+pub mod account;
+pub mod storage;
+pub mod tus;
+pub mod download;
+pub mod version;
 
 pub use user::{
     create_user_handler
@@ -11,14 +23,102 @@ pub use content::{
     get_turnstile,
     get_file_details_handler, 
     create_file_handler, 
+    complete_upload_handler,
     list_files_handler, 
     delete_file_handler, 
     update_file_access_handler, 
-    get_download_url_handler
+    get_download_url_handler,
+    rename_file_handler,
+    move_file_handler,
+    replace_content_handler
+};
+
+pub use multipart::{
+    initiate_multipart_handler,
+    multipart_part_urls_handler,
+    list_multipart_parts_handler,
+    complete_multipart_handler,
+    abort_multipart_handler,
+};
+
+pub use folder::{
+    create_folder_handler,
+    list_root_folders_handler,
+    get_folder_handler,
+    rename_folder_handler,
+    move_folder_handler,
+    delete_folder_handler,
+    update_folder_access_handler,
 };
 
 pub use session::{
     session_info_handler,
     logout_handler,
     login_handler,
+};
+
+pub use share::{
+    create_share_link_handler,
+    list_share_links_handler,
+    revoke_share_link_handler,
+    open_share_link_handler,
+    unlock_share_link_handler,
+};
+
+pub use token::{
+    create_access_token_handler,
+    list_access_tokens_handler,
+    revoke_access_token_handler,
+};
+
+pub use two_factor::{
+    complete_login_handler,
+    two_factor_status_handler,
+    enroll_totp_handler,
+    confirm_totp_handler,
+    disable_totp_handler,
+};
+
+pub use passkey::{
+    passkey_register_options_handler,
+    passkey_register_handler,
+    passkey_login_options_handler,
+    passkey_login_handler,
+    list_passkeys_handler,
+    delete_passkey_handler,
+};
+
+pub use oidc::{
+    oidc_authorize_handler,
+    oidc_callback_handler,
+};
+
+pub use account::{
+    change_password_handler,
+    request_password_reset_handler,
+    confirm_password_reset_handler,
+    resend_verification_handler,
+    confirm_email_handler,
+};
+
+pub use download::download_file_handler;
+
+pub use version::{
+    list_versions_handler,
+    download_version_handler,
+    restore_version_handler,
+    delete_version_handler,
+};
+
+pub use tus::{
+    tus_options_handler,
+    tus_create_handler,
+    tus_head_handler,
+    tus_patch_handler,
+    tus_delete_handler,
+};
+
+pub use storage::{
+    local_upload_handler,
+    local_download_handler,
 };
\ No newline at end of file
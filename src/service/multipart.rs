@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use crate::router::AppState;
+use crate::middleware::bearer_auth;
+use crate::middleware::bearer::Principal;
+use crate::database::model::{File, Role, Scope};
+use crate::database::storage::{MAX_PART_NUMBER, MIN_PART_NUMBER, MIN_PART_SIZE};
+use super::content::{finalize_upload, preserve_current_content, require_role};
+
+/// Caps how many URLs one request can presign, so clients fetch them as they go.
+const MAX_PART_URLS_PER_REQUEST: usize = 100;
+
+#[derive(Deserialize)]
+pub struct InitiateMultipartRequest {
+    #[serde(default)]
+    content_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PartUrlsRequest {
+    part_numbers: Vec<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct CompletedPartRequest {
+    part_number: i32,
+    etag: String,
+}
+
+#[derive(Deserialize)]
+pub struct CompleteMultipartRequest {
+    parts: Vec<CompletedPartRequest>,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+/// Loads a file the caller may upload into, along with the multipart upload it has in progress.
+async fn editable_file(
+    state: &web::Data<AppState>,
+    req: &HttpRequest,
+    file_id: &str,
+) -> Result<(Principal, File), HttpResponse> {
+    let principal = bearer_auth(state.clone(), req, Scope::FilesWrite).await.map_err(|e| e.to_response())?;
+
+    let file = match state.db.get_file(file_id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(HttpResponse::NotFound().json(json!({
+                "error": {
+                    "code": "FILE_NOT_FOUND",
+                    "message": "File not found"
+                }
+            })));
+        }
+        Err(e) => return Err(db_error!("DB_ERROR", "Failed to retrieve file", e)),
+    };
+
+    require_role(state, file_id, &principal.user_id, Role::can_edit, "upload content").await?;
+    Ok((principal, file))
+}
+
+fn upload_id(file: &File) -> Result<&str, HttpResponse> {
+    file.multipart_upload_id.as_deref().ok_or_else(|| error_response!(
+        HttpResponse::NotFound(),
+        "MULTIPART_NOT_FOUND",
+        "No multipart upload is in progress for this file",
+        format!("Start one with POST /api/v1/content/{}/multipart", file.id)
+    ))
+}
+
+fn invalid_part_number(part_number: i32) -> Option<HttpResponse> {
+    (!(MIN_PART_NUMBER..=MAX_PART_NUMBER).contains(&part_number)).then(|| error_response!(
+        HttpResponse::BadRequest(),
+        "INVALID_PART",
+        format!("Part numbers must be between {} and {}", MIN_PART_NUMBER, MAX_PART_NUMBER),
+        format!("Part number {} is out of range", part_number)
+    ))
+}
+
+pub async fn initiate_multipart_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<InitiateMultipartRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let file_id = path.into_inner();
+    let (_, file) = match editable_file(&state, &req, &file_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    if let Some(upload_id) = &file.multipart_upload_id {
+        return error_response!(
+            HttpResponse::Conflict(),
+            "MULTIPART_IN_PROGRESS",
+            "A multipart upload is already in progress for this file",
+            format!("Resume upload {} or abort it first", upload_id)
+        );
+    }
+
+    if let Err(response) = preserve_current_content(&state, &file).await {
+        return response;
+    }
+
+    let upload_id = match state.storage.create_multipart_upload(&file_id, body.content_type.as_deref()).await {
+        Ok(upload_id) => upload_id,
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to start multipart upload", e),
+    };
+
+    match state.db.start_multipart_upload(&file_id, &upload_id, chrono::Utc::now()).await {
+        Ok(true) => {}
+        Ok(false) => {
+            // Another request started one first; drop ours rather than leave its parts behind.
+            if let Err(e) = state.storage.abort_multipart_upload(&file_id, &upload_id).await {
+                eprintln!("✗ Failed to abort multipart upload {}: {}", upload_id, e);
+            }
+            return error_response!(
+                HttpResponse::Conflict(),
+                "MULTIPART_IN_PROGRESS",
+                "A multipart upload is already in progress for this file",
+                format!("File {} was claimed by a concurrent upload", file_id)
+            );
+        }
+        Err(e) => {
+            if let Err(e) = state.storage.abort_multipart_upload(&file_id, &upload_id).await {
+                eprintln!("✗ Failed to abort multipart upload {}: {}", upload_id, e);
+            }
+            return db_error!("DB_ERROR", "Failed to record multipart upload", e);
+        }
+    }
+
+    HttpResponse::Created().json(json!({
+        "data": {
+            "id": file_id,
+            "upload_id": upload_id,
+            "min_part_size": MIN_PART_SIZE,
+            "max_parts": MAX_PART_NUMBER,
+            "expires_in": state.storage.upload_expire()
+        }
+    }))
+}
+
+pub async fn multipart_part_urls_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<PartUrlsRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let file_id = path.into_inner();
+    let (_, file) = match editable_file(&state, &req, &file_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let upload_id = match upload_id(&file) {
+        Ok(upload_id) => upload_id,
+        Err(response) => return response,
+    };
+
+    if body.part_numbers.is_empty() || body.part_numbers.len() > MAX_PART_URLS_PER_REQUEST {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            format!("Request between 1 and {} part URLs at a time", MAX_PART_URLS_PER_REQUEST),
+            format!("{} part numbers were sent", body.part_numbers.len())
+        );
+    }
+    if let Some(response) = body.part_numbers.iter().find_map(|n| invalid_part_number(*n)) {
+        return response;
+    }
+
+    let mut parts = Vec::with_capacity(body.part_numbers.len());
+    for part_number in &body.part_numbers {
+        match state.storage.upload_part_url(&file_id, upload_id, *part_number).await {
+            Ok(url) => parts.push(json!({ "part_number": part_number, "url": url })),
+            Err(e) => return db_error!("UPLOAD_FAILED", "Failed to generate part upload URL", e),
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "id": file_id,
+            "upload_id": upload_id,
+            "expires_in": state.storage.upload_expire(),
+            "parts": parts
+        }
+    }))
+}
+
+/// Lists the parts already stored, so an interrupted client can upload only what is missing.
+pub async fn list_multipart_parts_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let file_id = path.into_inner();
+    let (_, file) = match editable_file(&state, &req, &file_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let upload_id = match upload_id(&file) {
+        Ok(upload_id) => upload_id,
+        Err(response) => return response,
+    };
+
+    let parts = match state.storage.list_parts(&file_id, upload_id).await {
+        Ok(parts) => parts,
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to list uploaded parts", e),
+    };
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "id": file_id,
+            "upload_id": upload_id,
+            "started_at": file.multipart_started_at,
+            "uploaded_bytes": parts.iter().map(|p| p.size).sum::<i64>(),
+            "parts": parts.iter().map(|p| json!({
+                "part_number": p.part_number,
+                "etag": p.etag,
+                "size": p.size
+            })).collect::<Vec<_>>()
+        }
+    }))
+}
+
+pub async fn complete_multipart_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<CompleteMultipartRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let file_id = path.into_inner();
+    let (principal, file) = match editable_file(&state, &req, &file_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let upload_id = match upload_id(&file) {
+        Ok(upload_id) => upload_id.to_string(),
+        Err(response) => return response,
+    };
+
+    if body.parts.is_empty() {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "At least one part is required",
+            "parts must list every uploaded part with its ETag"
+        );
+    }
+
+    let mut previous = 0;
+    for part in &body.parts {
+        if let Some(response) = invalid_part_number(part.part_number) {
+            return response;
+        }
+        if part.part_number <= previous {
+            return error_response!(
+                HttpResponse::BadRequest(),
+                "INVALID_PART",
+                "Parts must be listed in ascending order without repeats",
+                format!("Part {} follows part {}", part.part_number, previous)
+            );
+        }
+        previous = part.part_number;
+    }
+
+    // Checking against the stored parts gives a precise error instead of an opaque backend failure.
+    let stored: HashMap<i32, String> = match state.storage.list_parts(&file_id, &upload_id).await {
+        Ok(parts) => parts.into_iter().map(|p| (p.part_number, p.etag)).collect(),
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to list uploaded parts", e),
+    };
+    for part in &body.parts {
+        if stored.get(&part.part_number).map(String::as_str) != Some(part.etag.trim_matches('"')) {
+            return error_response!(
+                HttpResponse::BadRequest(),
+                "INVALID_PART",
+                "A listed part was not uploaded or its ETag does not match",
+                format!("Part {} with ETag {}", part.part_number, part.etag)
+            );
+        }
+    }
+
+    let parts: Vec<(i32, String)> = body.parts
+        .iter()
+        .map(|p| (p.part_number, p.etag.trim_matches('"').to_string()))
+        .collect();
+    if let Err(e) = state.storage.complete_multipart_upload(&file_id, &upload_id, &parts).await {
+        return db_error!("STORAGE_ERROR", "Failed to complete multipart upload", e);
+    }
+
+    if let Err(e) = state.db.finish_multipart_upload(&file_id, &upload_id).await {
+        return db_error!("DB_ERROR", "Failed to record completed upload", e);
+    }
+
+    finalize_upload(&state, &file, &principal.user_id).await
+}
+
+pub async fn abort_multipart_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let file_id = path.into_inner();
+    let (_, file) = match editable_file(&state, &req, &file_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let upload_id = match upload_id(&file) {
+        Ok(upload_id) => upload_id,
+        Err(response) => return response,
+    };
+
+    if let Err(e) = state.storage.abort_multipart_upload(&file_id, upload_id).await {
+        return db_error!("STORAGE_ERROR", "Failed to abort multipart upload", e);
+    }
+
+    if let Err(e) = state.db.finish_multipart_upload(&file_id, upload_id).await {
+        return db_error!("DB_ERROR", "Failed to clear multipart upload", e);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "message": "Multipart upload aborted",
+            "id": file_id,
+            "upload_id": upload_id
+        }
+    }))
+}
@@ -0,0 +1,227 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::router::AppState;
+use crate::encrypt::SecretToken;
+use crate::oidc::IdentityClaims;
+use crate::database::model::{OidcLoginState, User, UserIdentity};
+use super::session::issue_session;
+use super::two_factor::start_login_challenge;
+use serde::Deserialize;
+use serde_json::json;
+
+const LOGIN_STATE_TTL_SECONDS: i64 = 600;
+/// Stored for accounts created through single sign-on; no password ever verifies against it.
+const SSO_ONLY_PASSWORD_HASH: &str = "!";
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+fn oidc_disabled() -> HttpResponse {
+    error_response!(
+        HttpResponse::NotFound(),
+        "OIDC_DISABLED",
+        "Single sign-on is not configured",
+        "Set OIDC_ISSUER to enable it"
+    )
+}
+
+/// Starts a login: stores the state, nonce and PKCE verifier, and returns where to send the browser.
+pub async fn oidc_authorize_handler(
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(provider) = state.oidc.clone() else {
+        return oidc_disabled();
+    };
+
+    let login_state = SecretToken::generate();
+    let now = chrono::Utc::now();
+    let pending = OidcLoginState {
+        state_hash: SecretToken::digest(&login_state),
+        nonce: SecretToken::generate(),
+        code_verifier: SecretToken::generate(),
+        expires_at: now + chrono::Duration::seconds(LOGIN_STATE_TTL_SECONDS),
+        created_at: now,
+    };
+
+    let authorization_url = match provider.authorization_url(&login_state, &pending.nonce, &pending.code_verifier).await {
+        Ok(url) => url,
+        Err(e) => {
+            return error_response!(
+                HttpResponse::BadGateway(),
+                "OIDC_PROVIDER_ERROR",
+                "Failed to reach the identity provider",
+                e.to_string()
+            );
+        }
+    };
+
+    if let Err(e) = state.db.create_oidc_login_state(&pending).await {
+        return db_error!("DB_ERROR", "Failed to store login state", e);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "authorization_url": authorization_url,
+            "expires_in": LOGIN_STATE_TTL_SECONDS
+        }
+    }))
+}
+
+/// Finishes a login at the provider's redirect and answers like a password login.
+pub async fn oidc_callback_handler(
+    state: web::Data<AppState>,
+    query: web::Query<CallbackQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(provider) = state.oidc.clone() else {
+        return oidc_disabled();
+    };
+
+    if let Some(error) = query.error.as_deref() {
+        return error_response!(
+            HttpResponse::Unauthorized(),
+            "OIDC_DENIED",
+            "The identity provider did not sign you in",
+            query.error_description.clone().unwrap_or_else(|| error.to_string())
+        );
+    }
+
+    let (Some(code), Some(login_state)) = (query.code.as_deref(), query.state.as_deref()) else {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "code and state are required",
+            "The callback must carry the provider's code and state parameters"
+        );
+    };
+
+    let pending = match state.db.take_oidc_login_state(&SecretToken::digest(login_state), chrono::Utc::now()).await {
+        Ok(Some(pending)) => pending,
+        Ok(None) => {
+            return error_response!(
+                HttpResponse::BadRequest(),
+                "OIDC_STATE_INVALID",
+                "Login state is invalid or has expired",
+                "Start the sign-in again"
+            );
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to load login state", e),
+    };
+
+    let claims = match provider.exchange_code(code, &pending.code_verifier, &pending.nonce).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            return error_response!(
+                HttpResponse::Unauthorized(),
+                "OIDC_TOKEN_INVALID",
+                "The identity provider's answer could not be verified",
+                e.to_string()
+            );
+        }
+    };
+
+    let user = match resolve_user(&state, &claims, provider.config.auto_provision).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    // The provider vouches for the first factor only; an enrolled second factor is still asked for.
+    match state.db.get_totp(&user.id).await {
+        Ok(Some(totp)) if totp.enabled_at.is_some() => return start_login_challenge(&state, &req, &user).await,
+        Ok(_) => {}
+        Err(e) => return db_error!("DB_ERROR", "Failed to check two-factor status", e),
+    }
+
+    issue_session(&state, &req, &user).await
+}
+
+/// Finds the local user for a provider identity, linking by verified email on first sign-in and
+/// creating an account when none exists and provisioning is enabled.
+async fn resolve_user(state: &web::Data<AppState>, claims: &IdentityClaims, auto_provision: bool) -> Result<User, HttpResponse> {
+    match state.db.get_user_identity(&claims.issuer, &claims.subject).await {
+        Ok(Some(identity)) => {
+            return match state.db.get_user(&identity.user_id).await {
+                Ok(Some(user)) => Ok(user),
+                Ok(None) => Err(error_response!(HttpResponse::NotFound(), "USER_NOT_FOUND", "User not found", identity.user_id)),
+                Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve user", e)),
+            };
+        }
+        Ok(None) => {}
+        Err(e) => return Err(db_error!("DB_ERROR", "Failed to look up identity", e)),
+    }
+
+    let email = match claims.email.as_deref() {
+        Some(email) if claims.email_verified && !email.is_empty() => email,
+        _ => {
+            return Err(error_response!(
+                HttpResponse::Forbidden(),
+                "OIDC_EMAIL_UNVERIFIED",
+                "The identity provider did not vouch for an email address",
+                "A verified email claim is required to sign in"
+            ));
+        }
+    };
+
+    let user = match state.db.get_user_by_email(email).await {
+        Ok(Some(user)) => user,
+        Ok(None) if auto_provision => {
+            let user_id = state.snowflake.generate().await.to_string();
+            match state.db.create_user(&user_id, email, SSO_ONLY_PASSWORD_HASH).await {
+                Ok(user) => user,
+                Err(e) => return Err(db_error!("USER_CREATE_FAILED", "Failed to create user", e)),
+            }
+        }
+        Ok(None) => {
+            return Err(error_response!(
+                HttpResponse::Forbidden(),
+                "OIDC_NO_ACCOUNT",
+                "No account exists for this email",
+                email
+            ));
+        }
+        Err(e) => return Err(db_error!("DB_ERROR", "Failed to query user", e)),
+    };
+
+    let identity = UserIdentity {
+        issuer: claims.issuer.clone(),
+        subject: claims.subject.clone(),
+        user_id: user.id.clone(),
+        email: Some(email.to_string()),
+        created_at: chrono::Utc::now(),
+    };
+    if let Err(e) = state.db.create_user_identity(&identity).await {
+        return Err(db_error!("DB_ERROR", "Failed to link identity", e));
+    }
+    // The provider vouched for the address, which is as good as our own verification link.
+    if let Err(e) = state.db.mark_email_verified(&user.id, identity.created_at).await {
+        return Err(db_error!("DB_ERROR", "Failed to verify email", e));
+    }
+
+    Ok(User { email_verified_at: user.email_verified_at.or(Some(identity.created_at)), ..user })
+}
@@ -0,0 +1,375 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::router::AppState;
+use crate::middleware::session_auth;
+use crate::encrypt::webauthn::{decode_b64url, encode_b64url, generate_challenge, COSE_ALG_ES256};
+use crate::database::model::{WebauthnChallenge, WebauthnCredential, WEBAUTHN_LOGIN, WEBAUTHN_REGISTER};
+use super::session::issue_session;
+use serde::Deserialize;
+use serde_json::json;
+
+const CHALLENGE_TTL_SECONDS: i64 = 300;
+const MAX_PASSKEY_NAME_LENGTH: usize = 255;
+
+#[derive(Deserialize)]
+pub struct RegisterPasskeyRequest {
+    #[serde(default)]
+    name: Option<String>,
+    /// base64url, as returned by `navigator.credentials.create()`.
+    client_data_json: String,
+    attestation_object: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct LoginOptionsRequest {
+    #[serde(default)]
+    email: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginRequest {
+    /// base64url, as returned by `navigator.credentials.get()`.
+    credential_id: String,
+    client_data_json: String,
+    authenticator_data: String,
+    signature: String,
+    #[serde(default)]
+    user_handle: Option<String>,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+fn invalid_passkey(detail: impl ToString) -> HttpResponse {
+    error_response!(
+        HttpResponse::Unauthorized(),
+        "PASSKEY_INVALID",
+        "Passkey authentication failed",
+        detail.to_string()
+    )
+}
+
+fn invalid_challenge() -> HttpResponse {
+    error_response!(
+        HttpResponse::BadRequest(),
+        "CHALLENGE_INVALID",
+        "Passkey challenge is invalid or has expired",
+        "Request new options and try again"
+    )
+}
+
+fn passkey_json(credential: &WebauthnCredential) -> serde_json::Value {
+    json!({
+        "id": credential.id,
+        "name": credential.name,
+        "credential_id": credential.credential_id,
+        "created_at": credential.created_at,
+        "last_used_at": credential.last_used_at
+    })
+}
+
+fn descriptors(credentials: &[WebauthnCredential]) -> Vec<serde_json::Value> {
+    credentials
+        .iter()
+        .map(|c| json!({ "type": "public-key", "id": c.credential_id }))
+        .collect()
+}
+
+async fn issue_challenge(state: &web::Data<AppState>, user_id: Option<&str>, purpose: &str) -> Result<String, HttpResponse> {
+    let now = chrono::Utc::now();
+    let challenge = WebauthnChallenge {
+        challenge: generate_challenge(),
+        user_id: user_id.map(str::to_string),
+        purpose: purpose.to_string(),
+        expires_at: now + chrono::Duration::seconds(CHALLENGE_TTL_SECONDS),
+        created_at: now,
+    };
+
+    match state.db.create_webauthn_challenge(&challenge).await {
+        Ok(_) => Ok(challenge.challenge),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to store passkey challenge", e)),
+    }
+}
+
+/// Returns `PublicKeyCredentialCreationOptions` for the signed-in user.
+pub async fn passkey_register_options_handler(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let user = match state.db.get_user(&session.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response!(HttpResponse::NotFound(), "USER_NOT_FOUND", "User not found", session.user_id),
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve user", e),
+    };
+
+    let existing = match state.db.list_webauthn_credentials(&user.id).await {
+        Ok(credentials) => credentials,
+        Err(e) => return db_error!("DB_ERROR", "Failed to list passkeys", e),
+    };
+
+    let challenge = match issue_challenge(&state, Some(&user.id), WEBAUTHN_REGISTER).await {
+        Ok(challenge) => challenge,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "challenge": challenge,
+            "rp": { "id": state.relying_party.id, "name": state.relying_party.name },
+            "user": {
+                "id": encode_b64url(user.id.as_bytes()),
+                "name": user.email,
+                "displayName": user.email
+            },
+            "pubKeyCredParams": [{ "type": "public-key", "alg": COSE_ALG_ES256 }],
+            "timeout": CHALLENGE_TTL_SECONDS * 1000,
+            "attestation": "none",
+            "excludeCredentials": descriptors(&existing),
+            "authenticatorSelection": {
+                "residentKey": "preferred",
+                "userVerification": "required"
+            }
+        }
+    }))
+}
+
+pub async fn passkey_register_handler(
+    state: web::Data<AppState>,
+    body: web::Json<RegisterPasskeyRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let name = body.name.as_deref().map(str::trim).filter(|n| !n.is_empty()).unwrap_or("Passkey");
+    if name.len() > MAX_PASSKEY_NAME_LENGTH {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "Passkey name is too long",
+            format!("name must be at most {} bytes", MAX_PASSKEY_NAME_LENGTH)
+        );
+    }
+
+    let decoded = decode_b64url(&body.client_data_json)
+        .and_then(|client_data| Ok((client_data, decode_b64url(&body.attestation_object)?)));
+    let verified = decoded.and_then(|(client_data, attestation)| {
+        state.relying_party.verify_registration(&client_data, &attestation)
+    });
+    let (challenge, credential) = match verified {
+        Ok(verified) => verified,
+        Err(e) => {
+            return error_response!(
+                HttpResponse::BadRequest(),
+                "PASSKEY_INVALID",
+                "Passkey registration could not be verified",
+                e.to_string()
+            );
+        }
+    };
+
+    match state.db.take_webauthn_challenge(&challenge, WEBAUTHN_REGISTER, chrono::Utc::now()).await {
+        Ok(Some(issued)) if issued.user_id.as_deref() == Some(session.user_id.as_str()) => {}
+        Ok(_) => return invalid_challenge(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to load passkey challenge", e),
+    }
+
+    let credential_id = encode_b64url(&credential.credential_id);
+    match state.db.get_webauthn_credential(&credential_id).await {
+        Ok(Some(_)) => {
+            return error_response!(
+                HttpResponse::Conflict(),
+                "PASSKEY_EXISTS",
+                "This passkey is already registered",
+                credential_id
+            );
+        }
+        Ok(None) => {}
+        Err(e) => return db_error!("DB_ERROR", "Failed to check passkey", e),
+    }
+
+    let passkey = WebauthnCredential {
+        id: state.snowflake.generate().await.to_string(),
+        user_id: session.user_id.clone(),
+        credential_id,
+        public_key: credential.public_key,
+        sign_count: i64::from(credential.sign_count),
+        name: name.to_string(),
+        created_at: chrono::Utc::now(),
+        last_used_at: None,
+    };
+
+    match state.db.create_webauthn_credential(&passkey).await {
+        Ok(_) => HttpResponse::Created().json(json!({ "data": passkey_json(&passkey) })),
+        Err(e) => db_error!("DB_ERROR", "Failed to store passkey", e),
+    }
+}
+
+/// Returns `PublicKeyCredentialRequestOptions`. Without an email the browser offers any
+/// discoverable passkey for this site; an unknown email gets the same answer so accounts
+/// cannot be probed through this endpoint.
+pub async fn passkey_login_options_handler(
+    state: web::Data<AppState>,
+    body: Option<web::Json<LoginOptionsRequest>>,
+) -> HttpResponse {
+    let email = body.and_then(|b| b.into_inner().email);
+
+    let user = match email {
+        Some(email) => match state.db.get_user_by_email(&email).await {
+            Ok(user) => user,
+            Err(e) => return db_error!("DB_ERROR", "Failed to query user", e),
+        },
+        None => None,
+    };
+
+    let allowed = match &user {
+        Some(user) => match state.db.list_webauthn_credentials(&user.id).await {
+            Ok(credentials) => credentials,
+            Err(e) => return db_error!("DB_ERROR", "Failed to list passkeys", e),
+        },
+        None => Vec::new(),
+    };
+
+    let challenge = match issue_challenge(&state, user.as_ref().map(|u| u.id.as_str()), WEBAUTHN_LOGIN).await {
+        Ok(challenge) => challenge,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "challenge": challenge,
+            "rpId": state.relying_party.id,
+            "timeout": CHALLENGE_TTL_SECONDS * 1000,
+            "allowCredentials": descriptors(&allowed),
+            "userVerification": "required"
+        }
+    }))
+}
+
+/// Exchanges a verified assertion for a session. A passkey proves possession and, with user
+/// verification required, a PIN or biometric, so the TOTP step of a password login is not asked for.
+pub async fn passkey_login_handler(
+    state: web::Data<AppState>,
+    body: web::Json<PasskeyLoginRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let credential = match state.db.get_webauthn_credential(&body.credential_id).await {
+        Ok(Some(credential)) => credential,
+        Ok(None) => return invalid_passkey("Unknown passkey"),
+        Err(e) => return db_error!("DB_ERROR", "Failed to load passkey", e),
+    };
+
+    if let Some(user_handle) = body.user_handle.as_deref().filter(|h| !h.is_empty()) {
+        if decode_b64url(user_handle).ok().as_deref() != Some(credential.user_id.as_bytes()) {
+            return invalid_passkey("The user handle does not belong to this passkey");
+        }
+    }
+
+    let decoded = (|| Ok::<_, Box<dyn std::error::Error>>((
+        decode_b64url(&body.client_data_json)?,
+        decode_b64url(&body.authenticator_data)?,
+        decode_b64url(&body.signature)?,
+    )))();
+    let verified = decoded.and_then(|(client_data, authenticator_data, signature)| {
+        state.relying_party.verify_assertion(&credential.public_key, &client_data, &authenticator_data, &signature)
+    });
+    let (challenge, data) = match verified {
+        Ok(verified) => verified,
+        Err(e) => return invalid_passkey(e),
+    };
+
+    // Presence alone only shows someone is holding the authenticator.
+    if !data.user_verified() {
+        return invalid_passkey("The authenticator did not verify the user");
+    }
+
+    let now = chrono::Utc::now();
+    match state.db.take_webauthn_challenge(&challenge, WEBAUTHN_LOGIN, now).await {
+        Ok(Some(issued)) if issued.user_id.as_ref().is_none_or(|id| *id == credential.user_id) => {}
+        Ok(_) => return invalid_challenge(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to load passkey challenge", e),
+    }
+
+    // Authenticators that keep a counter must move it forward; one that goes backwards
+    // suggests the key was cloned. A counter that stays at zero means the authenticator has none.
+    let sign_count = i64::from(data.sign_count);
+    if (sign_count != 0 || credential.sign_count != 0) && sign_count <= credential.sign_count {
+        return invalid_passkey("Signature counter did not advance");
+    }
+
+    if let Err(e) = state.db.update_webauthn_credential_use(&credential.id, sign_count, now).await {
+        return db_error!("DB_ERROR", "Failed to record passkey use", e);
+    }
+
+    match state.db.get_user(&credential.user_id).await {
+        Ok(Some(user)) => issue_session(&state, &req, &user).await,
+        Ok(None) => invalid_passkey("Unknown passkey"),
+        Err(e) => db_error!("DB_ERROR", "Failed to retrieve user", e),
+    }
+}
+
+pub async fn list_passkeys_handler(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    match state.db.list_webauthn_credentials(&session.user_id).await {
+        Ok(credentials) => HttpResponse::Ok().json(json!({
+            "data": credentials.iter().map(passkey_json).collect::<Vec<_>>()
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to list passkeys", e),
+    }
+}
+
+pub async fn delete_passkey_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let passkey_id = path.into_inner();
+    match state.db.delete_webauthn_credential(&session.user_id, &passkey_id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "data": {
+                "message": "Passkey removed",
+                "passkey_id": passkey_id
+            }
+        })),
+        Ok(false) => error_response!(
+            HttpResponse::NotFound(),
+            "PASSKEY_NOT_FOUND",
+            "Passkey not found",
+            format!("No passkey {} for this user", passkey_id)
+        ),
+        Err(e) => db_error!("DB_ERROR", "Failed to delete passkey", e),
+    }
+}
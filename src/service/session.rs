@@ -1,15 +1,22 @@
 use crate::router::AppState;
-use crate::encrypt::Bcrypt;
+use crate::encrypt::SecretToken;
 use serde::Deserialize;
 use serde_json::json;
 use actix_web::{web, HttpRequest, HttpResponse};
-use crate::middleware::{get_client_ip, bearer_auth};
-use uuid::Uuid;
+use cf_turnstile::SiteVerifyRequest;
+use chrono::{DateTime, Utc};
+use crate::middleware::{get_client_ip, session_auth};
+use crate::middleware::throttle::FailureLimits;
+use crate::database::model::{LoginFailure, User, LOGIN_SCOPE_ACCOUNT, LOGIN_SCOPE_IP};
+use super::two_factor::start_login_challenge;
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
     email: String,
     password: String,
+    /// Only needed once the account or address has failed often enough to be challenged.
+    #[serde(default)]
+    turnstile: Option<String>,
 }
 
 #[derive(serde::Serialize)]
@@ -49,37 +56,186 @@ pub async fn login_handler(
     body: web::Json<LoginRequest>,
     req: HttpRequest,
 ) -> HttpResponse {
-    let user = match state.mysql.get_user_by_email(&body.email).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return auth_error!("Invalid email or password");
+    let now = Utc::now();
+    let policy = &state.login_policy;
+    let account_key = body.email.trim().to_lowercase();
+    let ip_key = get_client_ip(&state, &req);
+
+    let mut failures = 0;
+    for (scope, subject, limits) in [
+        (LOGIN_SCOPE_ACCOUNT, account_key.as_str(), policy.account),
+        (LOGIN_SCOPE_IP, ip_key.as_str(), policy.ip),
+    ] {
+        let record = match state.db.get_login_failure(scope, subject).await {
+            Ok(record) => record,
+            Err(e) => return db_error!("DB_ERROR", "Failed to check login attempts", e),
+        };
+        if let Some(record) = record {
+            if let Some(until) = record.blocked_until.filter(|until| *until > now) {
+                return throttled(policy.locked_out(limits, record.failures), until, now);
+            }
+            failures = failures.max(recent_failures(&record, now - policy.window));
         }
+    }
+
+    if policy.needs_captcha(failures) {
+        if let Some(response) = verify_turnstile(&state, body.turnstile.as_deref(), &ip_key).await {
+            return response;
+        }
+    }
+
+    let user = match state.db.get_user_by_email(&body.email).await {
+        Ok(user) => user,
         Err(e) => {
             return db_error!("DB_ERROR", "Failed to query user", e);
         }
     };
 
-    let password_valid = match Bcrypt::verify(&body.password, &user.password_hash) {
-        Ok(valid) => valid,
-        Err(_) => return auth_error!("Invalid email or password"),
+    // Unknown emails still pay for a hash comparison and count as failures, so they look
+    // exactly like a wrong password from the outside.
+    let hasher = &state.password_hasher;
+    let password_valid = match &user {
+        Some(user) => hasher.verify(&body.password, &user.password_hash).unwrap_or(false),
+        None => hasher.verify_dummy(&body.password),
+    };
+
+    let user = match user {
+        Some(user) if password_valid => user,
+        _ => {
+            for (scope, subject, limits) in [
+                (LOGIN_SCOPE_ACCOUNT, account_key.as_str(), policy.account),
+                (LOGIN_SCOPE_IP, ip_key.as_str(), policy.ip),
+            ] {
+                if let Err(e) = record_failure(&state, scope, subject, limits, now).await {
+                    return db_error!("DB_ERROR", "Failed to record login attempt", e);
+                }
+            }
+            return auth_error!("Invalid email or password");
+        }
     };
 
-    if !password_valid {
-        return auth_error!("Invalid email or password");
+    // Only the account is forgiven; an address that sprayed other accounts keeps its count.
+    if let Err(e) = state.db.clear_login_failures(LOGIN_SCOPE_ACCOUNT, &account_key).await {
+        return db_error!("DB_ERROR", "Failed to reset login attempts", e);
     }
 
-    let ip_address = get_client_ip(&req);
-    let session_key = format!("{}{}{}", Uuid::new_v4(), state.snowflake.generate().await, Uuid::new_v4()).replace("-", "");
-    let session_id = state.snowflake.generate().await.to_string();
-    let user_agent = req
-        .headers()
+    // The plaintext is only at hand now, so this is the moment to move off bcrypt or old parameters.
+    if hasher.needs_rehash(&user.password_hash) {
+        match hasher.rehash(&body.password) {
+            Ok(hash) => if let Err(e) = state.db.update_user_password(&user.id, &hash).await {
+                eprintln!("✗ Failed to store rehashed password for {}: {}", user.id, e);
+            },
+            Err(e) => eprintln!("✗ Failed to rehash password for {}: {}", user.id, e),
+        }
+    }
+
+    match state.db.get_totp(&user.id).await {
+        Ok(Some(totp)) if totp.enabled_at.is_some() => return start_login_challenge(&state, &req, &user).await,
+        Ok(_) => {}
+        Err(e) => return db_error!("DB_ERROR", "Failed to check two-factor status", e),
+    }
+
+    issue_session(&state, &req, &user).await
+}
+
+fn recent_failures(record: &LoginFailure, window_start: DateTime<Utc>) -> i32 {
+    if record.last_failed_at < window_start { 0 } else { record.failures }
+}
+
+async fn record_failure(
+    state: &AppState,
+    scope: &str,
+    subject: &str,
+    limits: FailureLimits,
+    now: DateTime<Utc>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let policy = &state.login_policy;
+    let failures = state.db.record_login_failure(scope, subject, now, now - policy.window).await?;
+    if let Some(until) = policy.blocked_until(limits, failures, now) {
+        state.db.block_login(scope, subject, until).await?;
+    }
+    Ok(())
+}
+
+fn throttled(locked_out: bool, until: DateTime<Utc>, now: DateTime<Utc>) -> HttpResponse {
+    let retry_after = ((until - now).num_milliseconds().max(0) as u64).div_ceil(1000);
+    let message = if locked_out {
+        "Too many failed logins; sign-in is temporarily locked"
+    } else {
+        "Too many failed logins; wait before trying again"
+    };
+
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.to_string()))
+        .json(json!({
+            "error": {
+                "code": "LOGIN_THROTTLED",
+                "message": message,
+                "detail": format!("Retry after {} seconds", retry_after)
+            }
+        }))
+}
+
+/// Returns the response to send when the Turnstile token is missing or rejected.
+async fn verify_turnstile(state: &AppState, token: Option<&str>, ip: &str) -> Option<HttpResponse> {
+    let token = match token {
+        Some(token) if !token.is_empty() => token,
+        _ => {
+            return Some(HttpResponse::BadRequest().json(json!({
+                "error": {
+                    "code": "CAPTCHA_REQUIRED",
+                    "message": "Turnstile token is required after repeated failed logins",
+                    "detail": state.turnstile_sitekey
+                }
+            })));
+        }
+    };
+
+    let request = SiteVerifyRequest {
+        response: token.to_string(),
+        secret: None,
+        remote_ip: Some(ip.to_string()),
+    };
+
+    match state.turnstile_client.siteverify(request).await {
+        Ok(response) if response.success => None,
+        Ok(response) => Some(HttpResponse::BadRequest().json(json!({
+            "error": {
+                "code": "TURNSTILE_INVALID",
+                "message": "Turnstile verification failed",
+                "detail": format!(
+                    "hostname: {:?}, timestamp: {:?}, action: {:?}, cdata: {:?}",
+                    response.hostname, response.timestamp, response.action, response.cdata
+                )
+            }
+        }))),
+        Err(e) => Some(HttpResponse::InternalServerError().json(json!({
+            "error": {
+                "code": "TURNSTILE_FAILED",
+                "message": "Failed to verify Turnstile",
+                "detail": e.to_string()
+            }
+        }))),
+    }
+}
+
+pub(crate) fn user_agent(req: &HttpRequest) -> Option<String> {
+    req.headers()
         .get("User-Agent")
         .and_then(|h| h.to_str().ok())
-        .map(|s| s.to_string());
+        .map(|s| s.to_string())
+}
+
+/// Creates a session for a user who has passed every login factor and answers with the login body.
+pub(crate) async fn issue_session(state: &web::Data<AppState>, req: &HttpRequest, user: &User) -> HttpResponse {
+    let ip_address = get_client_ip(state, req);
+    let session_key = SecretToken::generate();
+    let session_id = state.snowflake.generate().await.to_string();
+    let user_agent = user_agent(req);
 
-    match state.mysql.create_session(&session_id, &user.id, &session_key, Some(&ip_address), user_agent.as_deref()).await {
+    match state.db.create_session(&session_id, &user.id, &SecretToken::digest(&session_key), Some(&ip_address), user_agent.as_deref()).await {
         Ok(_) => {
-            let sessions = match state.mysql.get_user_sessions(&user.id).await {
+            let sessions = match state.db.get_user_sessions(&user.id).await {
                 Ok(sessions) => sessions,
                 Err(e) => {
                     return auth_error!(e.to_string());
@@ -100,6 +256,7 @@ pub async fn login_handler(
                 "data": {
                     "id": user.id,
                     "email": user.email,
+                    "email_verified": user.email_verified_at.is_some(),
                     "icon_url": user.icon_url,
                     "created_at": user.created_at,
                     "session_key": session_key,
@@ -121,12 +278,12 @@ pub async fn logout_handler(
 ) -> HttpResponse {
     let target_session_id = path.into_inner();
 
-    let session = match bearer_auth(state.clone(), &req).await {
+    let session = match session_auth(state.clone(), &req).await {
         Ok(session) => session,
-        Err(e) => return auth_error!(e.to_string()),
+        Err(e) => return e.to_response(),
     };
 
-    let sessions = match state.mysql.get_user_sessions(&session.user_id).await {
+    let sessions = match state.db.get_user_sessions(&session.user_id).await {
         Ok(sessions) => sessions,
         Err(e) => return db_error!("DB_ERROR", "Failed to get sessions", e),
     };
@@ -140,7 +297,7 @@ pub async fn logout_handler(
         }
     };
 
-    match state.mysql.delete_session(&target_session.session_key).await {
+    match state.db.delete_session(&target_session.key_hash).await {
         Ok(_) => HttpResponse::Ok().json(json!({
             "data": { "message": "Logged out successfully" }
         })),
@@ -152,16 +309,14 @@ pub async fn session_info_handler(
     state: web::Data<AppState>,
     req: HttpRequest,
 ) -> HttpResponse {
-    let session = match bearer_auth(state.clone(), &req).await {
+    let session = match session_auth(state.clone(), &req).await {
         Ok(session) => session,
         Err(e) => {
-            return auth_error!(e.to_string());
+            return e.to_response();
         }
     };
 
-    let _ = state.mysql.update_session_access_time(&session.session_key).await;
-
-    let sessions = match state.mysql.get_user_sessions(&session.user_id).await {
+    let sessions = match state.db.get_user_sessions(&session.user_id).await {
         Ok(sessions) => sessions,
         Err(e) => {
             return auth_error!(e.to_string());
@@ -178,14 +333,14 @@ pub async fn session_info_handler(
         })
         .collect();
 
-    match state.mysql.get_user(&session.user_id).await {
+    match state.db.get_user(&session.user_id).await {
         Ok(Some(user)) => HttpResponse::Ok().json(json!({
             "data": {
                 "id": user.id,
                 "email": user.email,
+                "email_verified": user.email_verified_at.is_some(),
                 "icon_url": user.icon_url,
                 "created_at": user.created_at,
-                "session_key": session.session_key,
                 "session_id": session.id,
                 "sessions": filtered_sessions
             }
@@ -0,0 +1,348 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::router::AppState;
+use crate::middleware::bearer_auth;
+use crate::encrypt::SecretToken;
+use crate::database::model::{File, Role, Scope, ShareLink, UPLOAD_COMPLETE};
+use super::content::require_role;
+use super::download::{stream_file, DownloadMode};
+use super::account::require_verified_email;
+use serde::Deserialize;
+use serde_json::json;
+
+const PASSWORD_HEADER: &str = "X-Share-Password";
+
+#[derive(Deserialize)]
+pub struct CreateShareLinkRequest {
+    #[serde(default)]
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    max_downloads: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SharePasswordForm {
+    password: String,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+fn share_url(req: &HttpRequest, token: &str) -> String {
+    let conn = req.connection_info();
+    format!("{}://{}/s/{}", conn.scheme(), conn.host(), token)
+}
+
+fn link_json(link: &ShareLink) -> serde_json::Value {
+    json!({
+        "id": link.id,
+        "file_id": link.file_id,
+        "expires_at": link.expires_at,
+        "max_downloads": link.max_downloads,
+        "download_count": link.download_count,
+        "has_password": link.password_hash.is_some(),
+        "created_by": link.created_by,
+        "created_at": link.created_at,
+        "revoked_at": link.revoked_at
+    })
+}
+
+/// Loads the file and makes sure the caller may manage its sharing, mapping every failure to its response.
+async fn shareable_file(state: &web::Data<AppState>, file_id: &str, user_id: &str) -> Result<File, HttpResponse> {
+    require_role(state, file_id, user_id, Role::can_share, "manage share links").await?;
+
+    match state.db.get_file(file_id).await {
+        Ok(Some(file)) => Ok(file),
+        Ok(None) => Err(error_response!(
+            HttpResponse::NotFound(),
+            "FILE_NOT_FOUND",
+            "File not found",
+            format!("File {} does not exist", file_id)
+        )),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve file", e)),
+    }
+}
+
+pub async fn create_share_link_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<CreateShareLinkRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::ShareManage).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    if let Err(response) = require_verified_email(&state, &principal.user_id).await {
+        return response;
+    }
+
+    let file_id = path.into_inner();
+    let file = match shareable_file(&state, &file_id, &principal.user_id).await {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+
+    if file.upload_state != UPLOAD_COMPLETE {
+        return error_response!(
+            HttpResponse::Conflict(),
+            "UPLOAD_INCOMPLETE",
+            "File upload has not been completed",
+            format!("File {} is still {}", file_id, file.upload_state)
+        );
+    }
+
+    let now = chrono::Utc::now();
+    if body.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "Expiry must be in the future",
+            format!("expires_at {:?} is not after {}", body.expires_at, now)
+        );
+    }
+
+    if body.max_downloads.is_some_and(|max| max < 1) {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "max_downloads must be at least 1",
+            format!("max_downloads is {:?}", body.max_downloads)
+        );
+    }
+
+    let password_hash = match body.password.as_deref() {
+        Some(password) => {
+            if let Err(e) = state.password_hasher.policy().check(password) {
+                return error_response!(
+                    HttpResponse::BadRequest(),
+                    "WEAK_PASSWORD",
+                    "Share link password was rejected",
+                    e.to_string()
+                );
+            }
+            match state.password_hasher.hash(password) {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    return error_response!(
+                        HttpResponse::InternalServerError(),
+                        "HASH_FAILED",
+                        "Failed to hash share link password",
+                        e.to_string()
+                    );
+                }
+            }
+        }
+        None => None,
+    };
+
+    let token = SecretToken::generate();
+    let link = ShareLink {
+        id: state.snowflake.generate().await.to_string(),
+        file_id: file_id.clone(),
+        token_hash: SecretToken::digest(&token),
+        password_hash,
+        expires_at: body.expires_at,
+        max_downloads: body.max_downloads,
+        download_count: 0,
+        created_by: Some(principal.user_id.clone()),
+        created_at: now,
+        revoked_at: None,
+    };
+
+    if let Err(e) = state.db.create_share_link(&link).await {
+        return db_error!("DB_ERROR", "Failed to create share link", e);
+    }
+
+    // The token is only stored as a digest, so this response is the one chance to read it.
+    let mut data = link_json(&link);
+    data["token"] = json!(token);
+    data["url"] = json!(share_url(&req, &token));
+
+    HttpResponse::Created().json(json!({ "data": data }))
+}
+
+pub async fn list_share_links_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::ShareManage).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let file_id = path.into_inner();
+    if let Err(response) = shareable_file(&state, &file_id, &principal.user_id).await {
+        return response;
+    }
+
+    match state.db.list_share_links(&file_id).await {
+        Ok(links) => HttpResponse::Ok().json(json!({
+            "data": {
+                "file_id": file_id,
+                "links": links.iter().map(link_json).collect::<Vec<_>>()
+            }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to list share links", e),
+    }
+}
+
+pub async fn revoke_share_link_handler(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::ShareManage).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let (file_id, link_id) = path.into_inner();
+    if let Err(response) = shareable_file(&state, &file_id, &principal.user_id).await {
+        return response;
+    }
+
+    match state.db.revoke_share_link(&file_id, &link_id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "data": {
+                "message": "Share link revoked",
+                "file_id": file_id,
+                "link_id": link_id
+            }
+        })),
+        Ok(false) => error_response!(
+            HttpResponse::NotFound(),
+            "SHARE_NOT_FOUND",
+            "Share link not found",
+            format!("No active share link {} for file {}", link_id, file_id)
+        ),
+        Err(e) => db_error!("DB_ERROR", "Failed to revoke share link", e),
+    }
+}
+
+pub async fn open_share_link_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let password = req
+        .headers()
+        .get(PASSWORD_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+
+    redeem_share_link(state, &req, &path.into_inner(), password.as_deref(), HttpResponse::Found).await
+}
+
+pub async fn unlock_share_link_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    form: web::Form<SharePasswordForm>,
+    req: HttpRequest,
+) -> HttpResponse {
+    redeem_share_link(state, &req, &path.into_inner(), Some(&form.password), HttpResponse::SeeOther).await
+}
+
+async fn redeem_share_link(
+    state: web::Data<AppState>,
+    req: &HttpRequest,
+    token: &str,
+    password: Option<&str>,
+    redirect: fn() -> actix_web::HttpResponseBuilder,
+) -> HttpResponse {
+    let not_found = || error_response!(
+        HttpResponse::NotFound(),
+        "SHARE_NOT_FOUND",
+        "Share link not found",
+        "The link does not exist or has been revoked"
+    );
+
+    let link = match state.db.get_share_link_by_token(&SecretToken::digest(token)).await {
+        Ok(Some(link)) if link.revoked_at.is_none() => link,
+        Ok(_) => return not_found(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to look up share link", e),
+    };
+
+    let now = chrono::Utc::now();
+    if link.is_expired(now) || link.is_exhausted() {
+        return error_response!(
+            HttpResponse::Gone(),
+            "SHARE_EXPIRED",
+            "Share link is no longer available",
+            format!("Link {} has expired or reached its download limit", link.id)
+        );
+    }
+
+    if let Some(password_hash) = &link.password_hash {
+        let Some(password) = password.filter(|p| !p.is_empty()) else {
+            return error_response!(
+                HttpResponse::Unauthorized(),
+                "SHARE_PASSWORD_REQUIRED",
+                "This link is password protected",
+                format!("Send the password in the {} header or as a form field", PASSWORD_HEADER)
+            );
+        };
+
+        match state.password_hasher.verify(password, password_hash) {
+            Ok(true) => {}
+            Ok(false) => {
+                return error_response!(
+                    HttpResponse::Forbidden(),
+                    "SHARE_PASSWORD_INVALID",
+                    "Incorrect share link password",
+                    format!("Password rejected for link {}", link.id)
+                );
+            }
+            Err(e) => return db_error!("VERIFY_FAILED", "Failed to verify share link password", e),
+        }
+    }
+
+    let file = match state.db.get_file(&link.file_id).await {
+        Ok(Some(file)) if file.upload_state == UPLOAD_COMPLETE => file,
+        Ok(_) => return not_found(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
+    };
+
+    match state.db.consume_share_link(&link.id, now).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return error_response!(
+                HttpResponse::Gone(),
+                "SHARE_EXPIRED",
+                "Share link is no longer available",
+                format!("Link {} has expired or reached its download limit", link.id)
+            );
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to record share link download", e),
+    }
+
+    if state.download_mode == DownloadMode::Proxy {
+        return stream_file(&state, req, &file).await;
+    }
+
+    match state.storage.download_url(&file.object_key()).await {
+        Ok(url) => redirect()
+            .insert_header(("Location", url))
+            .insert_header(("Cache-Control", "no-store"))
+            .finish(),
+        Err(e) => db_error!("DOWNLOAD_FAILED", "Failed to generate download URL", e),
+    }
+}
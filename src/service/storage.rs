@@ -0,0 +1,141 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::http::header::{self, HeaderValue};
+use actix_files::NamedFile;
+use crate::database::{LocalStorage, Storage};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct SignedUrlQuery {
+    expires: i64,
+    signature: String,
+    /// Set together with `part_number` on URLs for one part of a multipart upload.
+    #[serde(default)]
+    upload_id: Option<String>,
+    #[serde(default)]
+    part_number: Option<i32>,
+}
+
+macro_rules! signature_error {
+    ($key:expr) => {
+        HttpResponse::Forbidden().json(json!({
+            "error": {
+                "code": "INVALID_SIGNATURE",
+                "message": "Signed URL is invalid or has expired",
+                "detail": format!("Signature check failed for object {}", $key)
+            }
+        }))
+    };
+}
+
+macro_rules! storage_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        HttpResponse::InternalServerError().json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $e.to_string()
+            }
+        }))
+    };
+}
+
+fn not_enabled() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "error": {
+            "code": "STORAGE_NOT_LOCAL",
+            "message": "Local storage backend is not enabled"
+        }
+    }))
+}
+
+pub async fn local_upload_handler(
+    local: Option<web::Data<LocalStorage>>,
+    path: web::Path<String>,
+    query: web::Query<SignedUrlQuery>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> HttpResponse {
+    let Some(local) = local else {
+        return not_enabled();
+    };
+
+    let key = path.into_inner();
+
+    if let (Some(upload_id), Some(part_number)) = (&query.upload_id, query.part_number) {
+        let method = LocalStorage::part_method(upload_id, part_number);
+        if !local.verify_signature(&method, &key, query.expires, &query.signature) {
+            return signature_error!(key);
+        }
+
+        return match local.write_part(&key, upload_id, part_number, payload).await {
+            Ok(etag) => HttpResponse::Ok()
+                .insert_header((header::ETAG, format!("\"{}\"", etag)))
+                .finish(),
+            Err(e) => storage_error!("UPLOAD_FAILED", "Failed to store part", e),
+        };
+    }
+
+    if !local.verify_signature("PUT", &key, query.expires, &query.signature) {
+        return signature_error!(key);
+    }
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+
+    match local.write_stream(&key, content_type.as_deref(), payload).await {
+        Ok(meta) => HttpResponse::Ok()
+            .insert_header((header::ETAG, format!("\"{}\"", meta.etag.unwrap_or_default())))
+            .finish(),
+        Err(e) => storage_error!("UPLOAD_FAILED", "Failed to store object", e),
+    }
+}
+
+pub async fn local_download_handler(
+    local: Option<web::Data<LocalStorage>>,
+    path: web::Path<String>,
+    query: web::Query<SignedUrlQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(local) = local else {
+        return not_enabled();
+    };
+
+    let key = path.into_inner();
+
+    if !local.verify_signature("GET", &key, query.expires, &query.signature) {
+        return signature_error!(key);
+    }
+
+    let meta = match local.head_object(&key).await {
+        Ok(Some(meta)) => meta,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": {
+                    "code": "OBJECT_NOT_FOUND",
+                    "message": "Object not found"
+                }
+            }));
+        }
+        Err(e) => return storage_error!("DOWNLOAD_FAILED", "Failed to read object metadata", e),
+    };
+
+    let object_path = match local.object_path(&key) {
+        Ok(path) => path,
+        Err(e) => return storage_error!("DOWNLOAD_FAILED", "Failed to resolve object path", e),
+    };
+
+    match NamedFile::open_async(object_path).await {
+        Ok(file) => {
+            let mut response = file.use_etag(false).respond_to(&req).map_into_boxed_body();
+            if let Some(content_type) = meta.content_type.and_then(|ct| HeaderValue::from_str(&ct).ok()) {
+                response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+            }
+            response
+        }
+        Err(e) => storage_error!("DOWNLOAD_FAILED", "Failed to open object", e),
+    }
+}
@@ -0,0 +1,180 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::router::AppState;
+use crate::middleware::{session_auth, ACCESS_TOKEN_PREFIX};
+use crate::encrypt::SecretToken;
+use crate::database::model::{AccessToken, Scope};
+use serde::Deserialize;
+use serde_json::json;
+
+const MAX_TOKEN_NAME_LENGTH: usize = 255;
+
+#[derive(Deserialize)]
+pub struct CreateAccessTokenRequest {
+    name: String,
+    scopes: Vec<String>,
+    #[serde(default)]
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+fn token_json(token: &AccessToken) -> serde_json::Value {
+    json!({
+        "id": token.id,
+        "name": token.name,
+        "scopes": token.scopes().iter().map(Scope::as_str).collect::<Vec<_>>(),
+        "expires_at": token.expires_at,
+        "last_used_at": token.last_used_at,
+        "created_at": token.created_at
+    })
+}
+
+/// Parses the requested scope names, keeping their order and dropping repeats.
+fn parse_scopes(names: &[String]) -> Result<Vec<Scope>, HttpResponse> {
+    if names.is_empty() {
+        return Err(error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "At least one scope is required",
+            "scopes must not be empty"
+        ));
+    }
+
+    let mut scopes = Vec::new();
+    for name in names {
+        let Some(scope) = Scope::parse(name) else {
+            return Err(error_response!(
+                HttpResponse::BadRequest(),
+                "INVALID_SCOPE",
+                "Unknown scope",
+                format!("'{}' is not one of files:read, files:write, share:manage", name)
+            ));
+        };
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    Ok(scopes)
+}
+
+pub async fn create_access_token_handler(
+    state: web::Data<AppState>,
+    body: web::Json<CreateAccessTokenRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "Token name must be 1-255 characters",
+            format!("name has {} characters", name.chars().count())
+        );
+    }
+
+    let scopes = match parse_scopes(&body.scopes) {
+        Ok(scopes) => scopes,
+        Err(response) => return response,
+    };
+
+    let now = chrono::Utc::now();
+    if body.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return error_response!(
+            HttpResponse::BadRequest(),
+            "INVALID_INPUT",
+            "Expiry must be in the future",
+            format!("expires_at {:?} is not after {}", body.expires_at, now)
+        );
+    }
+
+    let token = format!("{}{}", ACCESS_TOKEN_PREFIX, SecretToken::generate());
+    let access_token = AccessToken {
+        id: state.snowflake.generate().await.to_string(),
+        user_id: session.user_id.clone(),
+        name: name.to_string(),
+        token_hash: SecretToken::digest(&token),
+        scopes: scopes.iter().map(Scope::as_str).collect::<Vec<_>>().join(" "),
+        expires_at: body.expires_at,
+        last_used_at: None,
+        created_at: now,
+    };
+
+    if let Err(e) = state.db.create_access_token(&access_token).await {
+        return db_error!("DB_ERROR", "Failed to create access token", e);
+    }
+
+    // Only the digest is stored, so this response is the one chance to read the token.
+    let mut data = token_json(&access_token);
+    data["token"] = json!(token);
+
+    HttpResponse::Created().json(json!({ "data": data }))
+}
+
+pub async fn list_access_tokens_handler(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    match state.db.list_access_tokens(&session.user_id).await {
+        Ok(tokens) => HttpResponse::Ok().json(json!({
+            "data": {
+                "tokens": tokens.iter().map(token_json).collect::<Vec<_>>()
+            }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to list access tokens", e),
+    }
+}
+
+pub async fn revoke_access_token_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let token_id = path.into_inner();
+    match state.db.delete_access_token(&session.user_id, &token_id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "data": {
+                "message": "Access token revoked",
+                "token_id": token_id
+            }
+        })),
+        Ok(false) => error_response!(
+            HttpResponse::NotFound(),
+            "TOKEN_NOT_FOUND",
+            "Access token not found",
+            format!("No access token {} for this user", token_id)
+        ),
+        Err(e) => db_error!("DB_ERROR", "Failed to revoke access token", e),
+    }
+}
@@ -0,0 +1,690 @@
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+use actix_web::http::header::{HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures_util::StreamExt;
+use serde_json::json;
+use sha1::Sha1;
+use sha2::digest::DynDigest;
+use sha2::Sha256;
+use uuid::Uuid;
+use crate::router::AppState;
+use crate::middleware::bearer_auth;
+use crate::database::model::{Role, Scope, TusUpload};
+use crate::database::storage::{MAX_PART_NUMBER, MIN_PART_SIZE};
+use super::content::finalize_upload;
+use super::folder::require_folder_role;
+
+pub const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,checksum";
+const TUS_CHECKSUM_ALGORITHMS: &str = "sha1,sha256";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Limits for uploads made through the tus endpoint.
+#[derive(Debug, Clone)]
+pub struct TusConfig {
+    pub max_size: i64,
+    /// Bytes per backend part; the upload is buffered up to this much before each part is sent.
+    pub part_size: i64,
+    /// How long a PATCH may go without progress before another request can take the upload over.
+    pub lock_timeout: Duration,
+}
+
+impl Default for TusConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10 * 1024 * 1024 * 1024,
+            part_size: 8 * 1024 * 1024,
+            lock_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl TusConfig {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let default = Self::default();
+        let config = Self {
+            max_size: match env::var("TUS_MAX_SIZE") {
+                Ok(value) => value.parse()?,
+                Err(_) => default.max_size,
+            },
+            part_size: match env::var("TUS_PART_SIZE") {
+                Ok(value) => value.parse()?,
+                Err(_) => default.part_size,
+            },
+            lock_timeout: match env::var("TUS_LOCK_TIMEOUT") {
+                Ok(value) => Duration::from_secs(value.parse()?),
+                Err(_) => default.lock_timeout,
+            },
+        };
+
+        if config.part_size < MIN_PART_SIZE {
+            return Err(format!("TUS_PART_SIZE must be at least {} bytes", MIN_PART_SIZE).into());
+        }
+        if config.max_size > config.part_size * MAX_PART_NUMBER as i64 {
+            return Err(format!(
+                "TUS_MAX_SIZE cannot exceed {} parts of TUS_PART_SIZE ({} bytes)",
+                MAX_PART_NUMBER,
+                config.part_size * MAX_PART_NUMBER as i64
+            ).into());
+        }
+
+        Ok(config)
+    }
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+/// Every tus response, errors included, names the protocol version it speaks.
+fn tus(mut response: HttpResponse) -> HttpResponse {
+    response.headers_mut().insert(HeaderName::from_static("tus-resumable"), HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::trim)
+}
+
+fn check_version(req: &HttpRequest) -> Result<(), HttpResponse> {
+    match header(req, "Tus-Resumable") {
+        Some(TUS_VERSION) => Ok(()),
+        version => Err(error_response!(
+            HttpResponse::PreconditionFailed().insert_header(("Tus-Version", TUS_VERSION)),
+            "TUS_VERSION_UNSUPPORTED",
+            format!("Only tus {} is supported", TUS_VERSION),
+            format!("Tus-Resumable was {}", version.unwrap_or("missing"))
+        )),
+    }
+}
+
+fn upload_not_found(id: &str) -> HttpResponse {
+    error_response!(
+        HttpResponse::NotFound(),
+        "UPLOAD_NOT_FOUND",
+        "Upload not found",
+        format!("No upload {} in progress", id)
+    )
+}
+
+fn upload_locked(id: &str) -> HttpResponse {
+    error_response!(
+        HttpResponse::build(StatusCode::LOCKED),
+        "UPLOAD_LOCKED",
+        "Another request is writing to this upload",
+        format!("Retry upload {} once the current request finishes", id)
+    )
+}
+
+/// Decodes `Upload-Metadata`: comma-separated keys, each followed by an optional base64 value.
+fn parse_metadata(raw: &str) -> Result<Vec<(String, String)>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, ' ');
+            let key = parts.next().unwrap_or_default().to_string();
+            let value = match parts.next() {
+                Some(encoded) => {
+                    let bytes = STANDARD.decode(encoded.trim()).map_err(|_| format!("Value for {} is not base64", key))?;
+                    String::from_utf8(bytes).map_err(|_| format!("Value for {} is not UTF-8", key))?
+                }
+                None => String::new(),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Parses `Upload-Checksum` into a running digest and the value it must end up at.
+fn parse_checksum(raw: &str) -> Result<(Box<dyn DynDigest>, Vec<u8>), HttpResponse> {
+    let (algorithm, encoded) = raw.split_once(' ').unwrap_or((raw, ""));
+    let digest: Box<dyn DynDigest> = match algorithm {
+        "sha1" => Box::new(Sha1::default()),
+        "sha256" => Box::new(Sha256::default()),
+        _ => {
+            return Err(error_response!(
+                HttpResponse::BadRequest(),
+                "CHECKSUM_UNSUPPORTED",
+                format!("Supported checksum algorithms are {}", TUS_CHECKSUM_ALGORITHMS),
+                format!("Upload-Checksum used {}", algorithm)
+            ));
+        }
+    };
+    let expected = STANDARD.decode(encoded.trim()).map_err(|_| error_response!(
+        HttpResponse::BadRequest(),
+        "INVALID_CHECKSUM",
+        "Upload-Checksum must be an algorithm and a base64 digest",
+        raw.to_string()
+    ))?;
+    Ok((digest, expected))
+}
+
+fn offset_mismatch(expected: i64, offset: i64) -> HttpResponse {
+    error_response!(
+        HttpResponse::Conflict(),
+        "OFFSET_MISMATCH",
+        "Upload-Offset does not match the bytes stored so far",
+        format!("Expected offset {}, got {}", expected, offset)
+    )
+}
+
+/// Loads an upload the caller started.
+async fn owned_upload(state: &web::Data<AppState>, req: &HttpRequest, id: &str) -> Result<TusUpload, HttpResponse> {
+    let principal = bearer_auth(state.clone(), req, Scope::FilesWrite).await.map_err(|e| e.to_response())?;
+
+    match state.db.get_tus_upload(id).await {
+        Ok(Some(upload)) if upload.user_id == principal.user_id => Ok(upload),
+        Ok(_) => Err(upload_not_found(id)),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve upload", e)),
+    }
+}
+
+pub async fn tus_options_handler(state: web::Data<AppState>) -> HttpResponse {
+    tus(HttpResponse::NoContent()
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .insert_header(("Tus-Max-Size", state.tus.max_size.to_string()))
+        .insert_header(("Tus-Checksum-Algorithm", TUS_CHECKSUM_ALGORITHMS))
+        .finish())
+}
+
+/// Creation extension: reserves an upload whose id doubles as the file id.
+pub async fn tus_create_handler(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    tus(create_upload(&state, &req).await)
+}
+
+async fn create_upload(state: &web::Data<AppState>, req: &HttpRequest) -> HttpResponse {
+    if let Err(response) = check_version(req) {
+        return response;
+    }
+
+    let principal = match bearer_auth(state.clone(), req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let upload_length = match header(req, "Upload-Length").map(str::parse::<i64>) {
+        Some(Ok(length)) if length >= 0 => length,
+        _ => {
+            return error_response!(
+                HttpResponse::BadRequest(),
+                "INVALID_UPLOAD_LENGTH",
+                "Upload-Length must be a non-negative integer",
+                "Deferred lengths are not supported"
+            );
+        }
+    };
+    if upload_length > state.tus.max_size {
+        return error_response!(
+            HttpResponse::PayloadTooLarge().insert_header(("Tus-Max-Size", state.tus.max_size.to_string())),
+            "UPLOAD_TOO_LARGE",
+            format!("Uploads are limited to {} bytes", state.tus.max_size),
+            format!("Upload-Length was {}", upload_length)
+        );
+    }
+
+    let metadata = match parse_metadata(header(req, "Upload-Metadata").unwrap_or_default()) {
+        Ok(metadata) => metadata,
+        Err(detail) => {
+            return error_response!(HttpResponse::BadRequest(), "INVALID_METADATA", "Upload-Metadata could not be decoded", detail);
+        }
+    };
+    // tus clients disagree on key names, so accept the common spellings of each.
+    let lookup = |keys: &[&str]| metadata
+        .iter()
+        .find(|(key, value)| keys.contains(&key.as_str()) && !value.is_empty())
+        .map(|(_, value)| value.clone());
+    let folder_id = lookup(&["folder_id"]);
+    let content_type = lookup(&["filetype", "type", "content_type"]);
+
+    if let Some(folder_id) = folder_id.as_deref() {
+        if let Err(response) = require_folder_role(state, folder_id, &principal.user_id, Role::can_edit, "upload into this folder").await {
+            return response;
+        }
+    }
+
+    let id = state.snowflake.generate().await.to_string();
+    let filename = lookup(&["filename", "name"]).unwrap_or_else(|| id.clone());
+    let location = format!("/api/v1/tus/{}", id);
+
+    // Nothing will ever be PATCHed to an empty upload, so it is finished on the spot.
+    if upload_length == 0 {
+        if let Err(e) = state.db.create_file(&id, &filename, &principal.user_id, folder_id.as_deref()).await {
+            return db_error!("DB_ERROR", "Failed to create file record in database", e);
+        }
+        if let Err(e) = state.storage.put_object(&id, Vec::new(), content_type.as_deref()).await {
+            return db_error!("STORAGE_ERROR", "Failed to store empty file", e);
+        }
+        let file = match state.db.get_file(&id).await {
+            Ok(Some(file)) => file,
+            Ok(None) => return upload_not_found(&id),
+            Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
+        };
+        let response = finalize_upload(state, &file, &principal.user_id).await;
+        if !response.status().is_success() {
+            return response;
+        }
+        return HttpResponse::Created()
+            .insert_header((LOCATION, location))
+            .insert_header(("Upload-Offset", "0"))
+            .finish();
+    }
+
+    let multipart_upload_id = match state.storage.create_multipart_upload(&id, content_type.as_deref()).await {
+        Ok(upload_id) => upload_id,
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to start upload", e),
+    };
+
+    let upload = TusUpload {
+        id: id.clone(),
+        user_id: principal.user_id,
+        folder_id,
+        filename,
+        content_type,
+        upload_length,
+        upload_offset: 0,
+        part_size: state.tus.part_size,
+        multipart_upload_id,
+        lock_token: None,
+        locked_until: None,
+        created_at: chrono::Utc::now(),
+    };
+    if let Err(e) = state.db.create_tus_upload(&upload).await {
+        if let Err(e) = state.storage.abort_multipart_upload(&id, &upload.multipart_upload_id).await {
+            eprintln!("✗ Failed to abort multipart upload {}: {}", upload.multipart_upload_id, e);
+        }
+        return db_error!("DB_ERROR", "Failed to record upload", e);
+    }
+
+    HttpResponse::Created()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+/// Reports how many bytes have been stored so a client can resume from there.
+pub async fn tus_head_handler(state: web::Data<AppState>, path: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let id = path.into_inner();
+    let response = match check_version(&req) {
+        Ok(()) => upload_offset(&state, &req, &id).await,
+        Err(response) => response,
+    };
+    let mut response = tus(response);
+    response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+async fn upload_offset(state: &web::Data<AppState>, req: &HttpRequest, id: &str) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let (offset, length) = match state.db.get_tus_upload(id).await {
+        Ok(Some(upload)) if upload.user_id == principal.user_id => (upload.upload_offset, upload.upload_length),
+        Ok(Some(_)) => return HttpResponse::NotFound().finish(),
+        // A client that lost the final response can still learn that its upload finished.
+        Ok(None) => match state.db.get_file(id).await {
+            Ok(Some(file)) if file.owner_id == principal.user_id => match file.size_bytes {
+                Some(size) => (size, size),
+                None => return HttpResponse::NotFound().finish(),
+            },
+            Ok(_) => return HttpResponse::NotFound().finish(),
+            Err(e) => return db_error!("DB_ERROR", "Failed to retrieve file", e),
+        },
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve upload", e),
+    };
+
+    HttpResponse::Ok()
+        .insert_header(("Upload-Offset", offset.to_string()))
+        .insert_header(("Upload-Length", length.to_string()))
+        .finish()
+}
+
+/// Appends the request body at `Upload-Offset`, finishing the file once every byte is in.
+pub async fn tus_patch_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> HttpResponse {
+    let id = path.into_inner();
+    tus(patch_upload(&state, &req, &id, payload).await)
+}
+
+async fn patch_upload(state: &web::Data<AppState>, req: &HttpRequest, id: &str, payload: web::Payload) -> HttpResponse {
+    if let Err(response) = check_version(req) {
+        return response;
+    }
+
+    if header(req, CONTENT_TYPE.as_str()) != Some(OFFSET_CONTENT_TYPE) {
+        return error_response!(
+            HttpResponse::UnsupportedMediaType(),
+            "INVALID_CONTENT_TYPE",
+            format!("PATCH requests must be sent as {}", OFFSET_CONTENT_TYPE),
+            format!("Content-Type was {}", header(req, CONTENT_TYPE.as_str()).unwrap_or("missing"))
+        );
+    }
+
+    let upload = match owned_upload(state, req, id).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    let offset = match header(req, "Upload-Offset").map(str::parse::<i64>) {
+        Some(Ok(offset)) => offset,
+        _ => {
+            return error_response!(
+                HttpResponse::BadRequest(),
+                "INVALID_UPLOAD_OFFSET",
+                "Upload-Offset must be a non-negative integer",
+                "Send the offset returned by HEAD"
+            );
+        }
+    };
+    if offset != upload.upload_offset {
+        return offset_mismatch(upload.upload_offset, offset);
+    }
+
+    let checksum = match header(req, "Upload-Checksum").map(parse_checksum) {
+        Some(Ok(checksum)) => Some(checksum),
+        Some(Err(response)) => return response,
+        None => None,
+    };
+
+    let token = Uuid::new_v4().to_string();
+    let lease = chrono::Duration::from_std(state.tus.lock_timeout).unwrap_or(chrono::Duration::seconds(60));
+    let now = chrono::Utc::now();
+    match state.db.lock_tus_upload(id, &token, now, now + lease).await {
+        Ok(true) => {}
+        Ok(false) => return upload_locked(id),
+        Err(e) => return db_error!("DB_ERROR", "Failed to lock upload", e),
+    }
+
+    // Another request may have advanced the upload between the read above and taking the lock.
+    let upload = match state.db.get_tus_upload(id).await {
+        Ok(Some(upload)) if upload.upload_offset == offset => Ok(upload),
+        Ok(Some(upload)) => Err(offset_mismatch(upload.upload_offset, offset)),
+        Ok(None) => Err(upload_not_found(id)),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve upload", e)),
+    };
+    let response = match upload {
+        Ok(upload) => write_chunk(state, &upload, &token, lease, checksum, payload).await,
+        Err(response) => response,
+    };
+    if !response.status().is_success() {
+        if let Err(e) = state.db.unlock_tus_upload(id, &token).await {
+            eprintln!("✗ Failed to unlock upload {}: {}", id, e);
+        }
+    }
+    response
+}
+
+/// Streams the body into full parts, keeping whatever is left over in the staging object.
+///
+/// Nothing is recorded until the body has been read and its checksum verified, so a failed
+/// request leaves the stored offset where it was; parts it wrote are simply written again.
+async fn write_chunk(
+    state: &web::Data<AppState>,
+    upload: &TusUpload,
+    token: &str,
+    lease: chrono::Duration,
+    checksum: Option<(Box<dyn DynDigest>, Vec<u8>)>,
+    mut payload: web::Payload,
+) -> HttpResponse {
+    let id = upload.id.as_str();
+    let first_part = upload.completed_parts() + 1;
+    let tail_size = upload.tail_size();
+    let part_size = upload.part_size as usize;
+
+    let mut buffer = Vec::with_capacity(part_size);
+    if tail_size > 0 {
+        match state.storage.get_object(&upload.tail_key(first_part)).await {
+            Ok(Some(tail)) if tail.len() as i64 >= tail_size => buffer.extend_from_slice(&tail[..tail_size as usize]),
+            Ok(_) => {
+                return error_response!(
+                    HttpResponse::InternalServerError(),
+                    "STORAGE_ERROR",
+                    "Buffered upload data is missing",
+                    format!("Staging object for upload {} was not found", id)
+                );
+            }
+            Err(e) => return db_error!("STORAGE_ERROR", "Failed to read buffered upload data", e),
+        }
+    }
+
+    let (mut digest, expected) = match checksum {
+        Some((digest, expected)) => (Some(digest), Some(expected)),
+        None => (None, None),
+    };
+    let mut next_part = first_part;
+    let mut received: i64 = 0;
+    let mut renew_at = chrono::Utc::now() + lease / 2;
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            // Without a checksum the bytes that did arrive can still be kept.
+            Err(_) if digest.is_none() => break,
+            Err(e) => {
+                return error_response!(
+                    HttpResponse::BadRequest(),
+                    "UPLOAD_INTERRUPTED",
+                    "The request body ended early",
+                    e.to_string()
+                );
+            }
+        };
+
+        if upload.upload_offset + received + chunk.len() as i64 > upload.upload_length {
+            return error_response!(
+                HttpResponse::BadRequest(),
+                "UPLOAD_LENGTH_EXCEEDED",
+                "The request body runs past Upload-Length",
+                format!("Upload {} is {} bytes long", id, upload.upload_length)
+            );
+        }
+        if let Some(digest) = digest.as_mut() {
+            digest.update(&chunk);
+        }
+        received += chunk.len() as i64;
+        buffer.extend_from_slice(&chunk);
+
+        while buffer.len() >= part_size {
+            let rest = buffer.split_off(part_size);
+            let part = std::mem::replace(&mut buffer, rest);
+            if let Err(e) = state.storage.put_part(id, &upload.multipart_upload_id, next_part, part).await {
+                return db_error!("STORAGE_ERROR", "Failed to store upload part", e);
+            }
+            next_part += 1;
+        }
+
+        let now = chrono::Utc::now();
+        if now >= renew_at {
+            match state.db.lock_tus_upload(id, token, now, now + lease).await {
+                Ok(true) => renew_at = now + lease / 2,
+                Ok(false) => return upload_locked(id),
+                Err(e) => return db_error!("DB_ERROR", "Failed to renew upload lock", e),
+            }
+        }
+    }
+
+    if let (Some(digest), Some(expected)) = (digest, expected) {
+        if *digest.finalize() != *expected {
+            return error_response!(
+                HttpResponse::build(StatusCode::from_u16(460).unwrap()),
+                "CHECKSUM_MISMATCH",
+                "The request body does not match Upload-Checksum",
+                format!("Nothing was stored for upload {}", id)
+            );
+        }
+    }
+
+    let offset = upload.upload_offset + received;
+    let old_tail = (tail_size > 0).then(|| upload.tail_key(first_part));
+
+    if offset == upload.upload_length {
+        return complete_upload(state, upload, buffer, next_part, old_tail).await;
+    }
+
+    let new_tail = upload.tail_key(next_part);
+    if !buffer.is_empty() {
+        if let Err(e) = state.storage.put_object(&new_tail, buffer, None).await {
+            return db_error!("STORAGE_ERROR", "Failed to buffer upload data", e);
+        }
+    }
+
+    match state.db.advance_tus_upload(id, token, upload.upload_offset, offset).await {
+        Ok(true) => {}
+        Ok(false) => return upload_locked(id),
+        Err(e) => return db_error!("DB_ERROR", "Failed to record upload progress", e),
+    }
+
+    if let Some(old_tail) = old_tail.filter(|key| *key != new_tail) {
+        if let Err(e) = state.storage.delete_object(&old_tail).await {
+            eprintln!("✗ Failed to delete staging object {}: {}", old_tail, e);
+        }
+    }
+
+    HttpResponse::NoContent()
+        .insert_header(("Upload-Offset", offset.to_string()))
+        .finish()
+}
+
+/// Sends the final part, assembles the object and turns the upload into a file.
+async fn complete_upload(
+    state: &web::Data<AppState>,
+    upload: &TusUpload,
+    last: Vec<u8>,
+    mut next_part: i32,
+    old_tail: Option<String>,
+) -> HttpResponse {
+    let id = upload.id.as_str();
+
+    if !last.is_empty() {
+        if let Err(e) = state.storage.put_part(id, &upload.multipart_upload_id, next_part, last).await {
+            return db_error!("STORAGE_ERROR", "Failed to store upload part", e);
+        }
+        next_part += 1;
+    }
+
+    // Parts past the end can linger from an earlier request that failed its checksum.
+    let parts: Vec<(i32, String)> = match state.storage.list_parts(id, &upload.multipart_upload_id).await {
+        Ok(parts) => parts
+            .into_iter()
+            .filter(|part| part.part_number < next_part)
+            .map(|part| (part.part_number, part.etag))
+            .collect(),
+        Err(e) => return db_error!("STORAGE_ERROR", "Failed to list uploaded parts", e),
+    };
+    if parts.len() as i32 != next_part - 1 {
+        return error_response!(
+            HttpResponse::InternalServerError(),
+            "STORAGE_ERROR",
+            "Some parts of the upload are missing",
+            format!("Expected {} parts for upload {}, found {}", next_part - 1, id, parts.len())
+        );
+    }
+
+    // The folder may have been deleted while the upload was running.
+    let folder_id = match upload.folder_id.as_deref() {
+        Some(folder_id) => match state.db.get_folder(folder_id).await {
+            Ok(folder) => folder.map(|f| f.id),
+            Err(e) => return db_error!("DB_ERROR", "Failed to retrieve folder", e),
+        },
+        None => None,
+    };
+
+    // The row goes in first so the reaper never sees the finished object as an orphan.
+    let file = match state.db.create_file(id, &upload.filename, &upload.user_id, folder_id.as_deref()).await {
+        Ok(file) => file,
+        Err(e) => return db_error!("DB_ERROR", "Failed to create file record in database", e),
+    };
+    if let Err(e) = state.storage.complete_multipart_upload(id, &upload.multipart_upload_id, &parts).await {
+        if let Err(e) = state.db.delete_file(id).await {
+            eprintln!("✗ Failed to remove file record {}: {}", id, e);
+        }
+        return db_error!("STORAGE_ERROR", "Failed to complete upload", e);
+    }
+
+    if let Err(e) = state.db.delete_tus_upload(id).await {
+        eprintln!("✗ Failed to delete finished upload {}: {}", id, e);
+    }
+    if let Some(old_tail) = old_tail {
+        if let Err(e) = state.storage.delete_object(&old_tail).await {
+            eprintln!("✗ Failed to delete staging object {}: {}", old_tail, e);
+        }
+    }
+
+    let response = finalize_upload(state, &file, &upload.user_id).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    HttpResponse::NoContent()
+        .insert_header(("Upload-Offset", upload.upload_length.to_string()))
+        .finish()
+}
+
+/// Termination extension: discards the upload and everything stored for it.
+pub async fn tus_delete_handler(state: web::Data<AppState>, path: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let id = path.into_inner();
+    tus(terminate_upload(&state, &req, &id).await)
+}
+
+async fn terminate_upload(state: &web::Data<AppState>, req: &HttpRequest, id: &str) -> HttpResponse {
+    if let Err(response) = check_version(req) {
+        return response;
+    }
+
+    let upload = match owned_upload(state, req, id).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    let token = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now();
+    let lease = chrono::Duration::from_std(state.tus.lock_timeout).unwrap_or(chrono::Duration::seconds(60));
+    match state.db.lock_tus_upload(id, &token, now, now + lease).await {
+        Ok(true) => {}
+        Ok(false) => return upload_locked(id),
+        Err(e) => return db_error!("DB_ERROR", "Failed to lock upload", e),
+    }
+
+    if let Err(e) = discard_upload(state, &upload).await {
+        if let Err(e) = state.db.unlock_tus_upload(id, &token).await {
+            eprintln!("✗ Failed to unlock upload {}: {}", id, e);
+        }
+        return db_error!("STORAGE_ERROR", "Failed to terminate upload", e);
+    }
+
+    HttpResponse::NoContent().finish()
+}
+
+/// Aborts the backend upload, drops its staging object and forgets it.
+async fn discard_upload(state: &web::Data<AppState>, upload: &TusUpload) -> Result<(), Box<dyn Error>> {
+    state.storage.abort_multipart_upload(&upload.id, &upload.multipart_upload_id).await?;
+    if upload.tail_size() > 0 {
+        state.storage.delete_object(&upload.tail_key(upload.completed_parts() + 1)).await?;
+    }
+    state.db.delete_tus_upload(&upload.id).await
+}
@@ -0,0 +1,364 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::router::AppState;
+use crate::middleware::{get_client_ip, session_auth};
+use crate::encrypt::{SecretToken, Totp};
+use crate::encrypt::totp::{generate_recovery_codes, normalize_recovery_code};
+use crate::database::model::{LoginChallenge, TotpEnrollment, User};
+use super::session::{issue_session, user_agent};
+use serde::Deserialize;
+use serde_json::json;
+
+const TOTP_ISSUER: &str = "Cloud File Storage";
+const RECOVERY_CODE_COUNT: usize = 10;
+const CHALLENGE_TTL_SECONDS: i64 = 300;
+const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// A second factor: either a current authenticator code or one of the recovery codes.
+#[derive(Deserialize)]
+pub struct SecondFactor {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    recovery_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct CompleteLoginRequest {
+    challenge_token: String,
+    #[serde(flatten)]
+    factor: SecondFactor,
+}
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+fn invalid_factor() -> HttpResponse {
+    error_response!(
+        HttpResponse::Unauthorized(),
+        "TOTP_INVALID",
+        "Invalid two-factor code",
+        "The code is wrong, expired or was already used"
+    )
+}
+
+/// Checks a second factor against an enabled enrollment and burns it so it cannot be replayed.
+async fn verify_second_factor(
+    state: &web::Data<AppState>,
+    totp: &TotpEnrollment,
+    factor: &SecondFactor,
+) -> Result<bool, HttpResponse> {
+    let now = chrono::Utc::now();
+
+    if let Some(code) = factor.code.as_deref() {
+        let secret = match Totp::from_base32(&totp.secret) {
+            Ok(secret) => secret,
+            Err(e) => return Err(db_error!("TOTP_CORRUPT", "Stored two-factor secret is unreadable", e)),
+        };
+        let Some(step) = secret.verify(code, now) else {
+            return Ok(false);
+        };
+        return match state.db.use_totp_step(&totp.user_id, step).await {
+            Ok(accepted) => Ok(accepted),
+            Err(e) => Err(db_error!("DB_ERROR", "Failed to record two-factor code", e)),
+        };
+    }
+
+    if let Some(recovery_code) = factor.recovery_code.as_deref() {
+        let hash = SecretToken::digest(&normalize_recovery_code(recovery_code));
+        return match state.db.use_recovery_code(&totp.user_id, &hash, now).await {
+            Ok(accepted) => Ok(accepted),
+            Err(e) => Err(db_error!("DB_ERROR", "Failed to record recovery code", e)),
+        };
+    }
+
+    Err(error_response!(
+        HttpResponse::BadRequest(),
+        "INVALID_INPUT",
+        "A two-factor code is required",
+        "Provide either code or recovery_code"
+    ))
+}
+
+async fn enabled_totp(state: &web::Data<AppState>, user_id: &str) -> Result<Option<TotpEnrollment>, HttpResponse> {
+    match state.db.get_totp(user_id).await {
+        Ok(totp) => Ok(totp.filter(|t| t.enabled_at.is_some())),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to load two-factor settings", e)),
+    }
+}
+
+/// Answers the password step of a login for a user with two-factor enabled.
+pub(crate) async fn start_login_challenge(state: &web::Data<AppState>, req: &HttpRequest, user: &User) -> HttpResponse {
+    let token = SecretToken::generate();
+    let now = chrono::Utc::now();
+    let challenge = LoginChallenge {
+        id: state.snowflake.generate().await.to_string(),
+        user_id: user.id.clone(),
+        token_hash: SecretToken::digest(&token),
+        ip_address: Some(get_client_ip(state, req)),
+        user_agent: user_agent(req),
+        attempts: 0,
+        expires_at: now + chrono::Duration::seconds(CHALLENGE_TTL_SECONDS),
+        created_at: now,
+    };
+
+    if let Err(e) = state.db.create_login_challenge(&challenge).await {
+        return db_error!("DB_ERROR", "Failed to start two-factor login", e);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "two_factor_required": true,
+            "challenge_token": token,
+            "methods": ["totp", "recovery_code"],
+            "expires_in": CHALLENGE_TTL_SECONDS
+        }
+    }))
+}
+
+pub async fn complete_login_handler(
+    state: web::Data<AppState>,
+    body: web::Json<CompleteLoginRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let invalid_challenge = || error_response!(
+        HttpResponse::Unauthorized(),
+        "CHALLENGE_INVALID",
+        "Login challenge is invalid or has expired",
+        "Start again by logging in with your password"
+    );
+
+    let challenge = match state.db.get_login_challenge(&SecretToken::digest(&body.challenge_token)).await {
+        Ok(Some(challenge)) => challenge,
+        Ok(None) => return invalid_challenge(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to load login challenge", e),
+    };
+
+    let ip_address = get_client_ip(&state, &req);
+    if challenge.expires_at <= chrono::Utc::now()
+        || !state.session_policy.ip_allowed(challenge.ip_address.as_deref(), &ip_address)
+    {
+        return invalid_challenge();
+    }
+
+    let totp = match enabled_totp(&state, &challenge.user_id).await {
+        Ok(Some(totp)) => totp,
+        Ok(None) => return invalid_challenge(),
+        Err(response) => return response,
+    };
+
+    // Each challenge only gets a handful of guesses before the password step must be redone. The
+    // guess is paid for before it is checked, so parallel requests cannot share one attempt.
+    match state.db.increment_login_challenge_attempts(&challenge.id, MAX_CHALLENGE_ATTEMPTS).await {
+        Ok(true) => {}
+        Ok(false) => {
+            if let Err(e) = state.db.delete_login_challenge(&challenge.id).await {
+                eprintln!("✗ Failed to delete exhausted login challenge {}: {}", challenge.id, e);
+            }
+            return invalid_challenge();
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to record login attempt", e),
+    }
+
+    match verify_second_factor(&state, &totp, &body.factor).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_factor(),
+        Err(response) => return response,
+    }
+
+    match state.db.delete_login_challenge(&challenge.id).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_challenge(),
+        Err(e) => return db_error!("DB_ERROR", "Failed to consume login challenge", e),
+    }
+
+    match state.db.get_user(&challenge.user_id).await {
+        Ok(Some(user)) => issue_session(&state, &req, &user).await,
+        Ok(None) => invalid_challenge(),
+        Err(e) => db_error!("DB_ERROR", "Failed to retrieve user", e),
+    }
+}
+
+pub async fn two_factor_status_handler(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let totp = match state.db.get_totp(&session.user_id).await {
+        Ok(totp) => totp,
+        Err(e) => return db_error!("DB_ERROR", "Failed to load two-factor settings", e),
+    };
+
+    let remaining = match totp.as_ref().filter(|t| t.enabled_at.is_some()) {
+        Some(_) => match state.db.count_recovery_codes(&session.user_id).await {
+            Ok(count) => count,
+            Err(e) => return db_error!("DB_ERROR", "Failed to count recovery codes", e),
+        },
+        None => 0,
+    };
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "totp_enabled": totp.as_ref().is_some_and(|t| t.enabled_at.is_some()),
+            "totp_pending": totp.as_ref().is_some_and(|t| t.enabled_at.is_none()),
+            "enabled_at": totp.as_ref().and_then(|t| t.enabled_at),
+            "recovery_codes_remaining": remaining
+        }
+    }))
+}
+
+pub async fn enroll_totp_handler(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    match enabled_totp(&state, &session.user_id).await {
+        Ok(Some(_)) => {
+            return error_response!(
+                HttpResponse::Conflict(),
+                "TOTP_ALREADY_ENABLED",
+                "Two-factor authentication is already enabled",
+                "Disable it first to enroll a new authenticator"
+            );
+        }
+        Ok(None) => {}
+        Err(response) => return response,
+    }
+
+    let user = match state.db.get_user(&session.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response!(HttpResponse::NotFound(), "USER_NOT_FOUND", "User not found", session.user_id),
+        Err(e) => return db_error!("DB_ERROR", "Failed to retrieve user", e),
+    };
+
+    let totp = Totp::generate();
+    if let Err(e) = state.db.save_totp_secret(&user.id, &totp.base32()).await {
+        return db_error!("DB_ERROR", "Failed to store two-factor secret", e);
+    }
+
+    HttpResponse::Created().json(json!({
+        "data": {
+            "secret": totp.base32(),
+            "otpauth_uri": totp.otpauth_uri(TOTP_ISSUER, &user.email)
+        }
+    }))
+}
+
+pub async fn confirm_totp_handler(
+    state: web::Data<AppState>,
+    body: web::Json<ConfirmTotpRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let pending = match state.db.get_totp(&session.user_id).await {
+        Ok(Some(totp)) if totp.enabled_at.is_none() => totp,
+        Ok(_) => {
+            return error_response!(
+                HttpResponse::Conflict(),
+                "TOTP_NOT_PENDING",
+                "No two-factor enrollment is waiting for confirmation",
+                "Start an enrollment first"
+            );
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to load two-factor settings", e),
+    };
+
+    let step = match Totp::from_base32(&pending.secret) {
+        Ok(totp) => totp.verify(&body.code, chrono::Utc::now()),
+        Err(e) => return db_error!("TOTP_CORRUPT", "Stored two-factor secret is unreadable", e),
+    };
+    let Some(step) = step else {
+        return invalid_factor();
+    };
+
+    let recovery_codes = generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| SecretToken::digest(&normalize_recovery_code(code)))
+        .collect();
+
+    match state.db.enable_totp(&session.user_id, step, &hashes, chrono::Utc::now()).await {
+        // Recovery codes are stored hashed, so this is the only time they can be shown.
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "data": {
+                "totp_enabled": true,
+                "recovery_codes": recovery_codes
+            }
+        })),
+        Ok(false) => error_response!(
+            HttpResponse::Conflict(),
+            "TOTP_NOT_PENDING",
+            "No two-factor enrollment is waiting for confirmation",
+            "The enrollment was confirmed or replaced in the meantime"
+        ),
+        Err(e) => db_error!("DB_ERROR", "Failed to enable two-factor authentication", e),
+    }
+}
+
+pub async fn disable_totp_handler(
+    state: web::Data<AppState>,
+    body: web::Json<SecondFactor>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let session = match session_auth(state.clone(), &req).await {
+        Ok(session) => session,
+        Err(e) => return e.to_response(),
+    };
+
+    let totp = match enabled_totp(&state, &session.user_id).await {
+        Ok(Some(totp)) => totp,
+        Ok(None) => {
+            return error_response!(
+                HttpResponse::NotFound(),
+                "TOTP_NOT_ENABLED",
+                "Two-factor authentication is not enabled",
+                session.user_id
+            );
+        }
+        Err(response) => return response,
+    };
+
+    match verify_second_factor(&state, &totp, &body).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_factor(),
+        Err(response) => return response,
+    }
+
+    match state.db.delete_totp(&session.user_id).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "data": { "totp_enabled": false }
+        })),
+        Err(e) => db_error!("DB_ERROR", "Failed to disable two-factor authentication", e),
+    }
+}
@@ -1,12 +1,11 @@
 use actix_web::{web, HttpRequest, HttpResponse};
 use crate::router::AppState;
-use crate::encrypt::Bcrypt;
+use crate::encrypt::SecretToken;
 use serde::Deserialize;
 use serde_json::json;
 use cf_turnstile::SiteVerifyRequest;
 use crate::middleware::get_client_ip;
-
-const MIN_PASSWORD_LENGTH: usize = 8;
+use super::account::send_verification_email;
 
 #[derive(Deserialize)]
 pub struct CreateUserRequest {
@@ -58,18 +57,6 @@ fn validate_create_user_input(req: &CreateUserRequest) -> Result<(), ValidationE
         });
     }
 
-    if req.password.len() < MIN_PASSWORD_LENGTH {
-        return Err(ValidationError {
-            code: "WEAK_PASSWORD",
-            message: "Password must be at least 8 characters",
-            detail: format!(
-                "Password length is {}, minimum required is {}",
-                req.password.len(),
-                MIN_PASSWORD_LENGTH
-            ),
-        });
-    }
-
     Ok(())
 }
 
@@ -98,7 +85,11 @@ pub async fn create_user_handler(
     let password = &req_body.password;
     let turnstile = &req_body.turnstile;
 
-    let hashed_password = match Bcrypt::hash(password) {
+    if let Err(e) = state.password_hasher.policy().check(password) {
+        return error_response!(HttpResponse::BadRequest(), "WEAK_PASSWORD", e.message(), e.to_string());
+    }
+
+    let hashed_password = match state.password_hasher.hash(password) {
         Ok(hash) => hash,
         Err(e) => {
             return error_response!(
@@ -140,7 +131,7 @@ pub async fn create_user_handler(
         }
     }
 
-    match state.mysql.get_user_by_email(email).await {
+    match state.db.get_user_by_email(email).await {
         Ok(Some(_)) => {
             return error_response!(
                 HttpResponse::Conflict(),
@@ -162,10 +153,12 @@ pub async fn create_user_handler(
 
     let user_id = state.snowflake.generate().await.to_string();
 
-    match state.mysql.create_user(&user_id, email, &hashed_password).await {
+    match state.db.create_user(&user_id, email, &hashed_password).await {
         Ok(user) => {
-            let ip_address = get_client_ip(&req);
-            let session_key = state.snowflake.generate().await.to_string();
+            send_verification_email(&state, &user).await;
+
+            let ip_address = get_client_ip(&state, &req);
+            let session_key = SecretToken::generate();
             let session_id = state.snowflake.generate().await.to_string();
             let user_agent = req
                 .headers()
@@ -173,11 +166,12 @@ pub async fn create_user_handler(
                 .and_then(|h| h.to_str().ok())
                 .map(|s| s.to_string());
 
-            match state.mysql.create_session(&session_id, &user.id, &session_key, Some(&ip_address), user_agent.as_deref()).await {
+            match state.db.create_session(&session_id, &user.id, &SecretToken::digest(&session_key), Some(&ip_address), user_agent.as_deref()).await {
                 Ok(_) => HttpResponse::Ok().json(json!({
                     "data": {
                         "id": user.id,
                         "email": user.email,
+                        "email_verified": false,
                         "icon_url": user.icon_url,
                         "created_at": user.created_at,
                         "session_key": session_key,
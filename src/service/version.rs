@@ -0,0 +1,225 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::{json, Value};
+use crate::router::AppState;
+use crate::middleware::bearer_auth;
+use crate::database::blob::release_blobs;
+use crate::database::model::{File, FileVersion, Role, Scope};
+use super::content::{file_response, require_role};
+use super::download::stream_file;
+
+macro_rules! error_response {
+    ($status:expr, $code:expr, $msg:expr, $detail:expr) => {
+        $status.json(json!({
+            "error": {
+                "code": $code,
+                "message": $msg,
+                "detail": $detail
+            }
+        }))
+    };
+}
+
+macro_rules! db_error {
+    ($code:expr, $msg:expr, $e:expr) => {
+        error_response!(HttpResponse::InternalServerError(), $code, $msg, $e.to_string())
+    };
+}
+
+fn version_json(version: &FileVersion, current: bool) -> Value {
+    json!({
+        "version": version.version_number,
+        "size_bytes": version.size_bytes,
+        "content_type": version.content_type,
+        "etag": version.etag,
+        "sha256": version.blob_sha256,
+        "uploaded_by": version.uploaded_by,
+        "created_at": version.created_at,
+        "current": current
+    })
+}
+
+fn version_not_found(file_id: &str, version_number: i32) -> HttpResponse {
+    error_response!(
+        HttpResponse::NotFound(),
+        "VERSION_NOT_FOUND",
+        "Version not found",
+        format!("File {} has no version {}", file_id, version_number)
+    )
+}
+
+async fn find_file(state: &web::Data<AppState>, file_id: &str) -> Result<File, HttpResponse> {
+    match state.db.get_file(file_id).await {
+        Ok(Some(file)) => Ok(file),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({
+            "error": {
+                "code": "FILE_NOT_FOUND",
+                "message": "File not found"
+            }
+        }))),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve file", e)),
+    }
+}
+
+async fn find_version(state: &web::Data<AppState>, file_id: &str, version_number: i32) -> Result<FileVersion, HttpResponse> {
+    match state.db.get_file_version(file_id, version_number).await {
+        Ok(Some(version)) => Ok(version),
+        Ok(None) => Err(version_not_found(file_id, version_number)),
+        Err(e) => Err(db_error!("DB_ERROR", "Failed to retrieve version", e)),
+    }
+}
+
+/// Lists the stored versions of a file, newest first.
+pub async fn list_versions_handler(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let file_id = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, |_| true, "view this file's versions").await {
+        return response;
+    }
+
+    let file = match find_file(&state, &file_id).await {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+
+    let versions = match state.db.list_file_versions(&file_id).await {
+        Ok(versions) => versions,
+        Err(e) => return db_error!("DB_ERROR", "Failed to list versions", e),
+    };
+
+    // Content that could not be deduplicated is not a version, and then none of them is current.
+    let versions: Vec<Value> = versions
+        .iter()
+        .enumerate()
+        .map(|(index, version)| version_json(version, index == 0 && file.blob_sha256.as_ref() == Some(&version.blob_sha256)))
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "file_id": file_id,
+            "versions": versions
+        }
+    }))
+}
+
+/// Streams one version's contents, with the same headers as the current file's download.
+pub async fn download_version_handler(
+    state: web::Data<AppState>,
+    path: web::Path<(String, i32)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesRead).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let (file_id, version_number) = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, |_| true, "download this file").await {
+        return response;
+    }
+
+    let file = match find_file(&state, &file_id).await {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+    let version = match find_version(&state, &file_id, version_number).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let file = File {
+        blob_sha256: Some(version.blob_sha256),
+        content_type: version.content_type,
+        ..file
+    };
+    stream_file(&state, &req, &file).await
+}
+
+/// Makes an older version current again by recording it as the newest version.
+pub async fn restore_version_handler(
+    state: web::Data<AppState>,
+    path: web::Path<(String, i32)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let (file_id, version_number) = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, Role::can_edit, "restore a version").await {
+        return response;
+    }
+
+    let version = match find_version(&state, &file_id, version_number).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    match state.db.attach_blob(&file_id, &version.blob_sha256, version.content_type.as_deref(), Some(&principal.user_id)).await {
+        Ok(true) => file_response(&state, &file_id).await,
+        Ok(false) => error_response!(
+            HttpResponse::Conflict(),
+            "VERSION_UNAVAILABLE",
+            "The version's content is no longer available",
+            format!("Blob {} of version {} is not live", version.blob_sha256, version_number)
+        ),
+        Err(e) => db_error!("DB_ERROR", "Failed to restore version", e),
+    }
+}
+
+/// Deletes an old version; its content goes once no other file or version shares it.
+pub async fn delete_version_handler(
+    state: web::Data<AppState>,
+    path: web::Path<(String, i32)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let principal = match bearer_auth(state.clone(), &req, Scope::FilesWrite).await {
+        Ok(principal) => principal,
+        Err(e) => return e.to_response(),
+    };
+
+    let (file_id, version_number) = path.into_inner();
+
+    if let Err(response) = require_role(&state, &file_id, &principal.user_id, Role::can_delete, "delete versions").await {
+        return response;
+    }
+
+    let version = match find_version(&state, &file_id, version_number).await {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    match state.db.delete_file_version(&file_id, version_number).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return error_response!(
+                HttpResponse::Conflict(),
+                "CURRENT_VERSION",
+                "The newest version cannot be deleted; restore another version first",
+                format!("Version {} is the newest version of file {}", version_number, file_id)
+            );
+        }
+        Err(e) => return db_error!("DB_ERROR", "Failed to delete version", e),
+    }
+
+    release_blobs(state.db.as_ref(), state.storage.as_ref(), &[version.blob_sha256]).await;
+
+    HttpResponse::Ok().json(json!({
+        "data": {
+            "message": "Version deleted successfully",
+            "file_id": file_id,
+            "version": version_number
+        }
+    }))
+}
@@ -0,0 +1,5 @@
+pub mod reaper;
+pub mod purge;
+
+pub use reaper::{Reaper, ReaperConfig};
+pub use purge::SessionPurger;
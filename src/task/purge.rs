@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+use crate::database::Repository;
+use crate::middleware::{LoginPolicy, SessionPolicy};
+
+/// Periodically deletes sessions that have outlived the configured lifetimes, along with stale login, passkey and single-sign-on challenges, mailed tokens and failed-login counters.
+pub struct SessionPurger {
+    db: Arc<dyn Repository>,
+    policy: SessionPolicy,
+    login_window: chrono::Duration,
+}
+
+impl SessionPurger {
+    pub fn new(db: Arc<dyn Repository>, policy: SessionPolicy) -> Self {
+        Self { db, policy, login_window: LoginPolicy::default().window }
+    }
+
+    pub fn with_login_window(mut self, login_window: chrono::Duration) -> Self {
+        self.login_window = login_window;
+        self
+    }
+
+    pub fn spawn(self) -> Option<JoinHandle<()>> {
+        if self.policy.purge_interval.is_zero() {
+            println!("✓ Session purge disabled");
+            return None;
+        }
+
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.policy.purge_interval);
+            loop {
+                ticker.tick().await;
+                match self.purge(Utc::now()).await {
+                    Ok(0) => {}
+                    Ok(count) => println!("✓ Session purge: {} expired sessions removed", count),
+                    Err(e) => eprintln!("✗ Session purge failed: {}", e),
+                }
+            }
+        }))
+    }
+
+    pub async fn purge(&self, now: DateTime<Utc>) -> Result<u64, Box<dyn Error>> {
+        let (created_before, idle_before) = self.policy.cutoffs(now);
+        let sessions = self.db.delete_expired_sessions(created_before, idle_before).await?;
+        self.db.delete_expired_login_challenges(now).await?;
+        self.db.delete_expired_webauthn_challenges(now).await?;
+        self.db.delete_expired_oidc_login_states(now).await?;
+        self.db.delete_expired_email_tokens(now).await?;
+        self.db.delete_stale_login_failures(now - self.login_window, now).await?;
+        Ok(sessions)
+    }
+}
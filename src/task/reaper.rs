@@ -0,0 +1,284 @@
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+use crate::database::{Repository, Storage};
+use crate::database::blob::{release_blob, store_as_blob};
+use crate::database::model::BLOB_DELETING;
+use crate::database::storage::{BLOB_PREFIX, STAGING_PREFIX};
+
+const PENDING_BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone)]
+pub struct ReaperConfig {
+    pub interval: Duration,
+    pub dry_run: bool,
+    pub delete_orphans: bool,
+    /// Multipart and tus uploads started longer ago than this are aborted along with their parts.
+    pub multipart_expire: Duration,
+    /// Versions kept per file, newest first; older ones are deleted. Zero keeps every version.
+    pub max_versions: i64,
+}
+
+impl ReaperConfig {
+    pub fn new_from_env() -> Result<Self, Box<dyn Error>> {
+        let interval = match env::var("REAPER_INTERVAL") {
+            Ok(value) => value.parse::<u64>()?,
+            Err(_) => 3600,
+        };
+
+        let dry_run = match env::var("REAPER_DRY_RUN") {
+            Ok(value) => value.parse::<bool>()?,
+            Err(_) => true,
+        };
+
+        let delete_orphans = match env::var("REAPER_DELETE_ORPHANS") {
+            Ok(value) => value.parse::<bool>()?,
+            Err(_) => false,
+        };
+
+        let multipart_expire = match env::var("REAPER_MULTIPART_EXPIRE") {
+            Ok(value) => value.parse::<u64>()?,
+            Err(_) => 7 * 24 * 3600,
+        };
+
+        let max_versions = match env::var("REAPER_MAX_VERSIONS") {
+            Ok(value) => value.parse::<u32>()?,
+            Err(_) => 10,
+        };
+
+        Ok(Self {
+            interval: Duration::from_secs(interval),
+            dry_run,
+            delete_orphans,
+            multipart_expire: Duration::from_secs(multipart_expire),
+            max_versions: max_versions as i64,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReaperReport {
+    pub aborted_uploads: Vec<String>,
+    pub finalized_files: Vec<String>,
+    pub deleted_files: Vec<String>,
+    /// File id and number of each version pruned past `max_versions`.
+    pub pruned_versions: Vec<(String, i32)>,
+    /// Hashes of blobs no file references any more.
+    pub deleted_blobs: Vec<String>,
+    pub orphaned_objects: Vec<String>,
+    pub deleted_objects: Vec<String>,
+}
+
+pub struct Reaper {
+    db: Arc<dyn Repository>,
+    storage: Arc<dyn Storage>,
+    config: ReaperConfig,
+}
+
+impl Reaper {
+    pub fn new(db: Arc<dyn Repository>, storage: Arc<dyn Storage>, config: ReaperConfig) -> Self {
+        Self { db, storage, config }
+    }
+
+    pub fn spawn(self) -> Option<JoinHandle<()>> {
+        if self.config.interval.is_zero() {
+            println!("✓ Reaper disabled");
+            return None;
+        }
+
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.interval);
+            loop {
+                ticker.tick().await;
+                match self.reconcile(Utc::now()).await {
+                    Ok(report) => println!(
+                        "✓ Reaper{}: {} multipart uploads aborted, {} finalized, {} abandoned uploads removed, {} old versions pruned, {} unreferenced blobs, {} orphaned objects ({} deleted)",
+                        if self.config.dry_run { " (dry run)" } else { "" },
+                        report.aborted_uploads.len(),
+                        report.finalized_files.len(),
+                        report.deleted_files.len(),
+                        report.pruned_versions.len(),
+                        report.deleted_blobs.len(),
+                        report.orphaned_objects.len(),
+                        report.deleted_objects.len(),
+                    ),
+                    Err(e) => eprintln!("✗ Reaper failed: {}", e),
+                }
+            }
+        }))
+    }
+
+    /// Reconciles `files` rows against the stored objects as of `now`.
+    ///
+    /// Anything younger than the storage upload window is left alone, since its
+    /// presigned PUT may still be in flight. Files with a multipart upload in progress are
+    /// only touched once that upload outlives `multipart_expire`.
+    pub async fn reconcile(&self, now: DateTime<Utc>) -> Result<ReaperReport, Box<dyn Error>> {
+        let cutoff = now - chrono::Duration::seconds(self.storage.upload_expire() as i64);
+        let mut report = ReaperReport::default();
+
+        let multipart_cutoff = now - chrono::Duration::from_std(self.config.multipart_expire)?;
+        self.reap_multipart_uploads(multipart_cutoff, &mut report).await?;
+        self.reap_tus_uploads(multipart_cutoff, &mut report).await?;
+        self.reap_pending_files(cutoff, &mut report).await?;
+        self.prune_versions(&mut report).await?;
+        self.reap_unreferenced_blobs(cutoff, &mut report).await?;
+        self.reap_orphaned_objects(cutoff, &mut report).await?;
+
+        Ok(report)
+    }
+
+    async fn reap_multipart_uploads(&self, cutoff: DateTime<Utc>, report: &mut ReaperReport) -> Result<(), Box<dyn Error>> {
+        let files = self.db.list_stale_multipart_uploads(cutoff, PENDING_BATCH_SIZE).await?;
+
+        for file in files {
+            let Some(upload_id) = file.multipart_upload_id else {
+                continue;
+            };
+            if !self.config.dry_run {
+                self.storage.abort_multipart_upload(&file.id, &upload_id).await?;
+                self.db.finish_multipart_upload(&file.id, &upload_id).await?;
+            }
+            println!("✓ Reaper: stale multipart upload {} for {}", upload_id, file.id);
+            report.aborted_uploads.push(file.id);
+        }
+
+        Ok(())
+    }
+
+    async fn reap_tus_uploads(&self, cutoff: DateTime<Utc>, report: &mut ReaperReport) -> Result<(), Box<dyn Error>> {
+        let uploads = self.db.list_stale_tus_uploads(cutoff, PENDING_BATCH_SIZE).await?;
+
+        for upload in uploads {
+            if !self.config.dry_run {
+                self.storage.abort_multipart_upload(&upload.id, &upload.multipart_upload_id).await?;
+                if upload.tail_size() > 0 {
+                    self.storage.delete_object(&upload.tail_key(upload.completed_parts() + 1)).await?;
+                }
+                self.db.delete_tus_upload(&upload.id).await?;
+            }
+            println!("✓ Reaper: stale tus upload {} ({}) by {}", upload.id, upload.filename, upload.user_id);
+            report.aborted_uploads.push(upload.id);
+        }
+
+        Ok(())
+    }
+
+    async fn reap_pending_files(&self, cutoff: DateTime<Utc>, report: &mut ReaperReport) -> Result<(), Box<dyn Error>> {
+        let files = self.db.list_stale_pending_files(cutoff, PENDING_BATCH_SIZE).await?;
+
+        for file in files {
+            let meta = self.storage.head_object(&file.id).await?;
+
+            match meta {
+                Some(meta) => {
+                    if !self.config.dry_run && !store_as_blob(self.db.as_ref(), self.storage.as_ref(), &file, &meta, Some(&file.owner_id)).await? {
+                        self.db.complete_file_upload(
+                            &file.id,
+                            meta.size,
+                            meta.content_type.as_deref(),
+                            meta.etag.as_deref(),
+                            meta.sha256.as_deref(),
+                        ).await?;
+                    }
+                    report.finalized_files.push(file.id);
+                }
+                None => {
+                    if !self.config.dry_run {
+                        self.db.delete_file(&file.id).await?;
+                    }
+                    println!("✓ Reaper: abandoned upload {} ({}) by {}", file.id, file.filename, file.owner_id);
+                    report.deleted_files.push(file.id);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn prune_versions(&self, report: &mut ReaperReport) -> Result<(), Box<dyn Error>> {
+        if self.config.max_versions == 0 {
+            return Ok(());
+        }
+
+        let versions = self.db.list_excess_file_versions(self.config.max_versions, PENDING_BATCH_SIZE).await?;
+
+        for version in versions {
+            if !self.config.dry_run {
+                if !self.db.delete_file_version(&version.file_id, version.version_number).await? {
+                    continue;
+                }
+                release_blob(self.db.as_ref(), self.storage.as_ref(), &version.blob_sha256).await?;
+            }
+            println!("✓ Reaper: pruned version {} of {}", version.version_number, version.file_id);
+            report.pruned_versions.push((version.file_id, version.version_number));
+        }
+
+        Ok(())
+    }
+
+    /// Deletes blobs whose last reference went away without the object being removed, pending
+    /// blobs whose upload never finished, and blobs stuck mid-deletion.
+    async fn reap_unreferenced_blobs(&self, cutoff: DateTime<Utc>, report: &mut ReaperReport) -> Result<(), Box<dyn Error>> {
+        let blobs = self.db.list_unreferenced_blobs(cutoff, PENDING_BATCH_SIZE).await?;
+
+        for blob in blobs {
+            if !self.config.dry_run {
+                if blob.state != BLOB_DELETING && !self.db.claim_blob_deletion(&blob.sha256, true).await? {
+                    continue;
+                }
+                self.storage.delete_object(&blob.key()).await?;
+                self.db.delete_blob(&blob.sha256).await?;
+            }
+            println!("✓ Reaper: unreferenced {} blob {}", blob.state, blob.sha256);
+            report.deleted_blobs.push(blob.sha256);
+        }
+
+        Ok(())
+    }
+
+    async fn reap_orphaned_objects(&self, cutoff: DateTime<Utc>, report: &mut ReaperReport) -> Result<(), Box<dyn Error>> {
+        let mut token = None;
+
+        loop {
+            let page = self.storage.list_objects(token).await?;
+
+            let keys: Vec<String> = page.objects
+                .into_iter()
+                .filter(|object| object.last_modified.is_none_or(|t| t < cutoff))
+                .filter(|object| !object.key.starts_with(STAGING_PREFIX))
+                .map(|object| object.key)
+                .collect();
+            let (blob_keys, file_keys): (Vec<String>, Vec<String>) = keys.into_iter().partition(|key| key.starts_with(BLOB_PREFIX));
+            let hashes: Vec<String> = blob_keys.iter().map(|key| key[BLOB_PREFIX.len()..].to_string()).collect();
+            let existing_hashes = self.db.existing_blob_hashes(&hashes).await?;
+            let existing = self.db.existing_file_ids(&file_keys).await?;
+
+            let orphans = blob_keys
+                .into_iter()
+                .zip(hashes)
+                .filter(|(_, sha256)| !existing_hashes.contains(sha256))
+                .map(|(key, _)| key)
+                .chain(file_keys.into_iter().filter(|key| !existing.contains(key)));
+
+            for key in orphans {
+                println!("✓ Reaper: orphaned object {}", key);
+                if self.config.delete_orphans && !self.config.dry_run {
+                    self.storage.delete_object(&key).await?;
+                    report.deleted_objects.push(key.clone());
+                }
+                report.orphaned_objects.push(key);
+            }
+
+            match page.next_token {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use actix_web::test::TestRequest;
+use serde_json::json;
+use crate::database::Repository;
+use crate::database::model::{EmailToken, EMAIL_TOKEN_PASSWORD_RESET};
+use crate::encrypt::SecretToken;
+use super::{call, context, context_with, request, signup, upload_file, PASSWORD};
+
+/// Pulls the token for `param` out of the most recent mail to `to`.
+fn mailed_token(ctx: &super::TestContext, to: &str, param: &str) -> String {
+    let mail = ctx.mailer.sent().into_iter().rev().find(|m| m.to == to).expect("no mail sent");
+    let marker = format!("{}=", param);
+    let start = mail.body.find(&marker).expect("no link in mail") + marker.len();
+    mail.body[start..].chars().take_while(|c| c.is_ascii_hexdigit()).collect()
+}
+
+fn login(email: &str, password: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": email, "password": password }))
+}
+
+#[actix_web::test]
+async fn password_change_revokes_other_sessions() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, first) = signup(&app, "alice@example.com").await;
+    let (_, body) = call(&app, request(login("alice@example.com", PASSWORD), None)).await;
+    let second = body["data"]["session_key"].as_str().unwrap().to_string();
+
+    let change = |current: &str, new: &str| TestRequest::put()
+        .uri("/api/v1/user/password")
+        .set_json(json!({ "current_password": current, "new_password": new }));
+
+    let (status, body) = call(&app, request(change("wrong-password", "a-new-password"), Some(&first))).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "INVALID_PASSWORD");
+    let (status, body) = call(&app, request(change(PASSWORD, "short"), Some(&first))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "WEAK_PASSWORD");
+
+    let (status, body) = call(&app, request(change(PASSWORD, "a-new-password"), Some(&first))).await;
+    assert_eq!(status, 200, "change failed: {}", body);
+    let fresh = body["data"]["session_key"].as_str().unwrap();
+    assert_eq!(body["data"]["sessions"].as_array().unwrap().len(), 1);
+
+    for old in [&first, &second] {
+        assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(old))).await.0, 401);
+    }
+    assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(fresh))).await.0, 200);
+    assert_eq!(call(&app, request(login("alice@example.com", PASSWORD), None)).await.0, 401);
+    assert_eq!(call(&app, request(login("alice@example.com", "a-new-password"), None)).await.0, 200);
+}
+
+#[actix_web::test]
+async fn password_reset_links_work_once() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, session_key) = signup(&app, "alice@example.com").await;
+    let sent_at_signup = ctx.mailer.sent().len();
+
+    let reset = |email: &str| TestRequest::post()
+        .uri("/api/v1/user/password/reset")
+        .set_json(json!({ "email": email }));
+    let confirm = |token: &str, password: &str| TestRequest::post()
+        .uri("/api/v1/user/password/reset/confirm")
+        .set_json(json!({ "token": token, "new_password": password }));
+
+    // Unknown addresses get the same answer but no mail.
+    let (status, unknown) = call(&app, request(reset("nobody@example.com"), None)).await;
+    assert_eq!(status, 202);
+    assert_eq!(ctx.mailer.sent().len(), sent_at_signup);
+
+    let (status, known) = call(&app, request(reset("alice@example.com"), None)).await;
+    assert_eq!(status, 202);
+    assert_eq!(known, unknown);
+    let superseded = mailed_token(&ctx, "alice@example.com", "reset_token");
+
+    // Asking again invalidates the earlier link.
+    call(&app, request(reset("alice@example.com"), None)).await;
+    let token = mailed_token(&ctx, "alice@example.com", "reset_token");
+    assert_ne!(token, superseded);
+    assert_eq!(call(&app, request(confirm(&superseded, "a-new-password"), None)).await.1["error"]["code"], "TOKEN_INVALID");
+
+    // A rejected password leaves the link usable.
+    assert_eq!(call(&app, request(confirm(&token, "short"), None)).await.0, 400);
+    let (status, body) = call(&app, request(confirm(&token, "a-new-password"), None)).await;
+    assert_eq!(status, 200, "reset failed: {}", body);
+    assert_eq!(call(&app, request(confirm(&token, "another-password"), None)).await.0, 400);
+
+    assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(&session_key))).await.0, 401);
+    let (status, body) = call(&app, request(login("alice@example.com", "a-new-password"), None)).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["email_verified"], true);
+
+    let expired = SecretToken::generate();
+    let now = chrono::Utc::now();
+    ctx.db.create_email_token(&EmailToken {
+        token_hash: SecretToken::digest(&expired),
+        user_id,
+        purpose: EMAIL_TOKEN_PASSWORD_RESET.to_string(),
+        expires_at: now - chrono::Duration::seconds(1),
+        created_at: now - chrono::Duration::hours(2),
+    }).await.unwrap();
+    assert_eq!(call(&app, request(confirm(&expired, "another-password"), None)).await.0, 400);
+}
+
+#[actix_web::test]
+async fn unverified_email_cannot_share() {
+    let ctx = context_with(|state| state.with_email_verification(true));
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, _) = signup(&app, "bob@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "notes.txt", b"hello").await;
+
+    let share = || TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(json!({ "accessible_user_ids": [bob_id] }));
+    let link = || TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/links", file_id))
+        .set_json(json!({}));
+
+    let (status, body) = call(&app, request(share(), Some(&alice))).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "EMAIL_NOT_VERIFIED");
+    assert_eq!(call(&app, request(link(), Some(&alice))).await.0, 403);
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(&alice))).await;
+    assert_eq!(body["data"]["email_verified"], false);
+
+    // Resending replaces the link mailed at signup.
+    let from_signup = mailed_token(&ctx, "alice@example.com", "verify_token");
+    assert_eq!(call(&app, request(TestRequest::post().uri("/api/v1/user/email/verify"), Some(&alice))).await.0, 202);
+    let token = mailed_token(&ctx, "alice@example.com", "verify_token");
+    let verify = |token: &str| TestRequest::post()
+        .uri("/api/v1/user/email/verify/confirm")
+        .set_json(json!({ "token": token }));
+    assert_eq!(call(&app, request(verify(&from_signup), None)).await.0, 400);
+
+    let (status, body) = call(&app, request(verify(&token), None)).await;
+    assert_eq!(status, 200, "verify failed: {}", body);
+    assert_eq!(call(&app, request(verify(&token), None)).await.0, 400);
+
+    assert_eq!(call(&app, request(share(), Some(&alice))).await.0, 200);
+    assert_eq!(call(&app, request(link(), Some(&alice))).await.0, 201);
+    let (status, body) = call(&app, request(TestRequest::post().uri("/api/v1/user/email/verify"), Some(&alice))).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "EMAIL_ALREADY_VERIFIED");
+}
@@ -0,0 +1,423 @@
+use actix_web::test::TestRequest;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde_json::{json, Value};
+use crate::database::{Repository, Storage};
+use super::{call, context, create_file, request, signup, upload_file};
+
+#[actix_web::test]
+async fn create_file_issues_upload_url() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, session_key) = signup(&app, "alice@example.com").await;
+
+    let req = TestRequest::post()
+        .uri("/api/v1/content")
+        .set_json(json!({ "filename": "report.pdf" }));
+    let (status, body) = call(&app, request(req, Some(&session_key))).await;
+
+    assert_eq!(status, 201);
+    let file_id = body["data"]["id"].as_str().unwrap();
+    assert_eq!(body["data"]["url"], format!("memory://upload/{}", file_id));
+    let file = ctx.db.get_file(file_id).await.unwrap().unwrap();
+    assert_eq!(file.filename, "report.pdf");
+    assert_eq!(file.owner_id, user_id);
+}
+
+#[actix_web::test]
+async fn create_file_requires_authentication() {
+    let ctx = context();
+    let app = test_app!(ctx);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/content")
+        .set_json(json!({ "filename": "report.pdf" }));
+    let (status, _) = call(&app, request(req, None)).await;
+
+    assert_eq!(status, 401);
+}
+
+#[actix_web::test]
+async fn sharing_grants_access_to_other_user() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+    let file_id = create_file(&app, &alice, "report.pdf").await;
+
+    let details = || TestRequest::get().uri(&format!("/api/v1/content/{}", file_id));
+    let (status, _) = call(&app, request(details(), Some(&bob))).await;
+    assert_eq!(status, 403);
+
+    let req = TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(json!({ "accessible_user_ids": [bob_id] }));
+    let (status, _) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200);
+
+    let (status, body) = call(&app, request(details(), Some(&bob))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["filename"], "report.pdf");
+
+    let req = TestRequest::get().uri(&format!("/api/v1/content/{}/share", file_id));
+    let (status, body) = call(&app, request(req, Some(&bob))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["url"], format!("memory://download/{}", file_id));
+}
+
+#[actix_web::test]
+async fn sharing_is_owner_only_and_validates_users() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (alice_id, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let file_id = create_file(&app, &alice, "report.pdf").await;
+
+    let req = TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(json!({ "accessible_user_ids": [alice_id] }));
+    let (status, _) = call(&app, request(req, Some(&bob))).await;
+    assert_eq!(status, 403);
+
+    let req = TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(json!({ "accessible_user_ids": ["missing"] }));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "USER_NOT_FOUND");
+}
+
+#[actix_web::test]
+async fn list_files_includes_owned_and_shared() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (alice_id, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+    let (_, carol) = signup(&app, "carol@example.com").await;
+
+    let shared = upload_file(&app, &ctx, &alice, "shared.txt", b"shared").await;
+    upload_file(&app, &ctx, &alice, "private.txt", b"private").await;
+    let own = upload_file(&app, &ctx, &bob, "own.txt", b"own").await;
+    ctx.db.add_user_to_file(&shared, &bob_id, &alice_id).await.unwrap();
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/content"), Some(&bob))).await;
+    assert_eq!(status, 200);
+    let mut ids: Vec<&str> = body["data"]["files"].as_array().unwrap()
+        .iter()
+        .map(|f| f["id"].as_str().unwrap())
+        .collect();
+    ids.sort();
+    let mut expected = vec![shared.as_str(), own.as_str()];
+    expected.sort();
+    assert_eq!(ids, expected);
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/content"), Some(&carol))).await;
+    assert!(body["data"]["files"].as_array().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn delete_file_removes_object_and_record() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let file_id = create_file(&app, &alice, "report.pdf").await;
+    ctx.storage.put_object(&file_id, b"hello".to_vec(), Some("text/plain")).await.unwrap();
+
+    let delete = || TestRequest::delete().uri(&format!("/api/v1/content/{}", file_id));
+    let (status, _) = call(&app, request(delete(), Some(&bob))).await;
+    assert_eq!(status, 403);
+
+    let (status, _) = call(&app, request(delete(), Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert!(ctx.db.get_file(&file_id).await.unwrap().is_none());
+    assert!(ctx.storage.head_object(&file_id).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn complete_upload_records_object_metadata() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = create_file(&app, &alice, "hello.txt").await;
+    ctx.storage.put_object(&file_id, b"hello".to_vec(), Some("text/plain")).await.unwrap();
+
+    let req = TestRequest::post().uri(&format!("/api/v1/content/{}/complete", file_id));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["upload_state"], "complete");
+    assert_eq!(body["data"]["size_bytes"], 5);
+    assert_eq!(body["data"]["content_type"], "text/plain");
+    assert_eq!(
+        body["data"]["sha256"],
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+}
+
+#[actix_web::test]
+async fn complete_upload_requires_stored_object() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let file_id = create_file(&app, &alice, "hello.txt").await;
+
+    let complete = || TestRequest::post().uri(&format!("/api/v1/content/{}/complete", file_id));
+    let (status, body) = call(&app, request(complete(), Some(&alice))).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "UPLOAD_NOT_FOUND");
+
+    ctx.storage.put_object(&file_id, b"hello".to_vec(), None).await.unwrap();
+    let (status, _) = call(&app, request(complete(), Some(&bob))).await;
+    assert_eq!(status, 403);
+}
+
+#[actix_web::test]
+async fn list_files_hides_pending_uploads() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    create_file(&app, &alice, "pending.txt").await;
+    let done = upload_file(&app, &ctx, &alice, "done.txt", b"done").await;
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/content"), Some(&alice))).await;
+    let files = body["data"]["files"].as_array().unwrap();
+
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["id"], done.as_str());
+}
+
+#[actix_web::test]
+async fn updating_access_replaces_permission_grants() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (alice_id, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+    let (carol_id, _) = signup(&app, "carol@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "report.pdf", b"report").await;
+
+    let share = |ids: Vec<&str>| TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(json!({ "accessible_user_ids": ids }));
+    let (status, _) = call(&app, request(share(vec![&bob_id, &carol_id]), Some(&alice))).await;
+    assert_eq!(status, 200);
+
+    let permissions = ctx.db.list_file_permissions(&file_id).await.unwrap();
+    assert_eq!(permissions.len(), 2);
+    assert!(permissions.iter().all(|p| p.role == "viewer" && p.granted_by.as_deref() == Some(alice_id.as_str())));
+
+    let (status, _) = call(&app, request(share(vec![&carol_id]), Some(&alice))).await;
+    assert_eq!(status, 200);
+
+    let details = TestRequest::get().uri(&format!("/api/v1/content/{}", file_id));
+    let (_, body) = call(&app, request(details, Some(&alice))).await;
+    assert_eq!(body["data"]["accessible_user_ids"], json!([carol_id]));
+    assert!(!ctx.db.check_user_can_access(&file_id, &bob_id).await.unwrap());
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/content"), Some(&bob))).await;
+    assert!(body["data"]["files"].as_array().unwrap().is_empty());
+}
+
+fn listed_names(body: &Value) -> Vec<&str> {
+    body["data"]["files"].as_array().unwrap()
+        .iter()
+        .map(|f| f["filename"].as_str().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn list_files_paginates_with_cursor() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    for name in ["e.txt", "a.txt", "d.txt", "b.txt", "c.txt"] {
+        upload_file(&app, &ctx, &alice, name, name.as_bytes()).await;
+    }
+
+    let mut seen = Vec::new();
+    let mut uri = "/api/v1/content?sort=name&limit=2".to_string();
+    loop {
+        let (status, body) = call(&app, request(TestRequest::get().uri(&uri), Some(&alice))).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["data"]["total"], 5);
+        seen.extend(listed_names(&body).into_iter().map(String::from));
+        match body["data"]["next_cursor"].as_str() {
+            Some(cursor) => uri = format!("/api/v1/content?sort=name&limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, vec!["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"]);
+
+    let (status, body) = call(&app, request(TestRequest::get().uri(&format!("{}&order=desc", uri)), Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_QUERY");
+
+    // A cursor whose key does not fit its sort is rejected before it reaches the database.
+    let forged = URL_SAFE_NO_PAD.encode(json!({ "sort": "date", "desc": true, "key": "a.txt", "id": "1" }).to_string());
+    let (status, body) = call(&app, request(TestRequest::get().uri(&format!("/api/v1/content?cursor={}", forged)), Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_QUERY");
+}
+
+#[actix_web::test]
+async fn list_files_sorts_by_size_and_date() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    upload_file(&app, &ctx, &alice, "medium.txt", b"12345").await;
+    upload_file(&app, &ctx, &alice, "large.txt", b"1234567890").await;
+    upload_file(&app, &ctx, &alice, "small.txt", b"1").await;
+
+    let list = |query: &str| TestRequest::get().uri(&format!("/api/v1/content?{}", query));
+
+    let (_, body) = call(&app, request(list("sort=size"), Some(&alice))).await;
+    assert_eq!(listed_names(&body), vec!["large.txt", "medium.txt", "small.txt"]);
+
+    let (_, body) = call(&app, request(list("sort=size&order=asc"), Some(&alice))).await;
+    assert_eq!(listed_names(&body), vec!["small.txt", "medium.txt", "large.txt"]);
+
+    let (_, body) = call(&app, request(list("sort=date&order=asc"), Some(&alice))).await;
+    assert_eq!(listed_names(&body), vec!["medium.txt", "large.txt", "small.txt"]);
+}
+
+#[actix_web::test]
+async fn list_files_filters_by_scope_and_search() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (alice_id, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+
+    let shared = upload_file(&app, &ctx, &alice, "report-2024.pdf", b"shared").await;
+    ctx.db.add_user_to_file(&shared, &bob_id, &alice_id).await.unwrap();
+    upload_file(&app, &ctx, &bob, "notes.txt", b"notes").await;
+    upload_file(&app, &ctx, &bob, "annual-report.txt", b"annual").await;
+
+    let list = |query: &str| TestRequest::get().uri(&format!("/api/v1/content?sort=name&{}", query));
+
+    let (_, body) = call(&app, request(list("scope=owned"), Some(&bob))).await;
+    assert_eq!(listed_names(&body), vec!["annual-report.txt", "notes.txt"]);
+    assert_eq!(body["data"]["total"], 2);
+
+    let (_, body) = call(&app, request(list("scope=shared"), Some(&bob))).await;
+    assert_eq!(listed_names(&body), vec!["report-2024.pdf"]);
+
+    let (_, body) = call(&app, request(list("q=report"), Some(&bob))).await;
+    assert_eq!(listed_names(&body), vec!["annual-report.txt", "report-2024.pdf"]);
+
+    let (_, body) = call(&app, request(list("q=report&match=prefix"), Some(&bob))).await;
+    assert_eq!(listed_names(&body), vec!["report-2024.pdf"]);
+
+    let (status, body) = call(&app, request(list("scope=everyone"), Some(&bob))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_QUERY");
+}
+
+#[actix_web::test]
+async fn roles_gate_content_operations() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (viewer_id, viewer) = signup(&app, "viewer@example.com").await;
+    let (editor_id, editor) = signup(&app, "editor@example.com").await;
+    let (co_owner_id, co_owner) = signup(&app, "co-owner@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "plan.txt", b"v1").await;
+
+    let share = TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(json!({ "permissions": [
+            { "user_id": viewer_id, "role": "viewer" },
+            { "user_id": editor_id, "role": "editor" },
+            { "user_id": co_owner_id, "role": "co_owner" }
+        ] }));
+    let (status, body) = call(&app, request(share, Some(&alice))).await;
+    assert_eq!(status, 200, "{}", body);
+
+    let download = || TestRequest::get().uri(&format!("/api/v1/content/{}/share", file_id));
+    let rename = |name: &str| TestRequest::patch()
+        .uri(&format!("/api/v1/content/{}", file_id))
+        .set_json(json!({ "filename": name }));
+    let replace = || TestRequest::post().uri(&format!("/api/v1/content/{}/replace", file_id));
+    let complete = || TestRequest::post().uri(&format!("/api/v1/content/{}/complete", file_id));
+    let links = || TestRequest::get().uri(&format!("/api/v1/content/{}/links", file_id));
+    let delete = || TestRequest::delete().uri(&format!("/api/v1/content/{}", file_id));
+
+    assert_eq!(call(&app, request(download(), Some(&viewer))).await.0, 200);
+    assert_eq!(call(&app, request(rename("x.txt"), Some(&viewer))).await.0, 403);
+    assert_eq!(call(&app, request(replace(), Some(&viewer))).await.0, 403);
+    assert_eq!(call(&app, request(complete(), Some(&viewer))).await.0, 403);
+
+    let (status, body) = call(&app, request(replace(), Some(&editor))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["url"], format!("memory://upload/{}", file_id));
+    ctx.storage.put_object(&file_id, b"version two".to_vec(), Some("text/plain")).await.unwrap();
+    let (status, body) = call(&app, request(complete(), Some(&editor))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["size_bytes"], 11);
+    let (status, body) = call(&app, request(rename("plan-v2.txt"), Some(&editor))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["filename"], "plan-v2.txt");
+    assert_eq!(call(&app, request(links(), Some(&editor))).await.0, 403);
+
+    assert_eq!(call(&app, request(links(), Some(&co_owner))).await.0, 200);
+    assert_eq!(call(&app, request(delete(), Some(&co_owner))).await.0, 403);
+    let demote = TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(json!({ "permissions": [
+            { "user_id": editor_id, "role": "viewer" },
+            { "user_id": co_owner_id, "role": "co_owner" }
+        ] }));
+    assert_eq!(call(&app, request(demote, Some(&co_owner))).await.0, 200);
+    assert_eq!(call(&app, request(rename("again.txt"), Some(&editor))).await.0, 403);
+    assert_eq!(call(&app, request(download(), Some(&viewer))).await.0, 403);
+
+    assert_eq!(call(&app, request(delete(), Some(&alice))).await.0, 200);
+}
+
+#[actix_web::test]
+async fn details_report_grantee_roles() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (alice_id, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+    let (carol_id, _) = signup(&app, "carol@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "plan.txt", b"plan").await;
+
+    let share = |body: Value| TestRequest::put()
+        .uri(&format!("/api/v1/content/{}/share", file_id))
+        .set_json(body);
+    let (status, _) = call(&app, request(share(json!({ "permissions": [
+        { "user_id": bob_id, "role": "editor" }
+    ] })), Some(&alice))).await;
+    assert_eq!(status, 200);
+
+    // The legacy id list keeps existing roles and adds newcomers as viewers.
+    let (status, _) = call(&app, request(share(json!({ "accessible_user_ids": [bob_id, carol_id] })), Some(&alice))).await;
+    assert_eq!(status, 200);
+
+    let details = || TestRequest::get().uri(&format!("/api/v1/content/{}", file_id));
+    let (_, body) = call(&app, request(details(), Some(&bob))).await;
+    assert_eq!(body["data"]["role"], "editor");
+    let mut roles: Vec<(String, String)> = body["data"]["permissions"].as_array().unwrap()
+        .iter()
+        .map(|p| (p["user_id"].as_str().unwrap().to_string(), p["role"].as_str().unwrap().to_string()))
+        .collect();
+    roles.sort();
+    let mut expected = vec![(bob_id.clone(), "editor".to_string()), (carol_id.clone(), "viewer".to_string())];
+    expected.sort();
+    assert_eq!(roles, expected);
+
+    let (_, body) = call(&app, request(details(), Some(&alice))).await;
+    assert_eq!(body["data"]["role"], "owner");
+
+    let (status, body) = call(&app, request(share(json!({ "permissions": [
+        { "user_id": bob_id, "role": "owner" }
+    ] })), Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_ROLE");
+
+    let (status, _) = call(&app, request(share(json!({ "permissions": [
+        { "user_id": alice_id, "role": "viewer" }
+    ] })), Some(&alice))).await;
+    assert_eq!(status, 400);
+}
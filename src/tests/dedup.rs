@@ -0,0 +1,116 @@
+use actix_web::test::{self, TestRequest};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use crate::database::{Repository, Storage};
+use crate::database::storage::blob_key;
+use crate::task::{Reaper, ReaperConfig};
+use super::{call, context, request, signup, upload_file, TestContext};
+
+async fn object_keys(ctx: &TestContext) -> Vec<String> {
+    let mut keys: Vec<String> = ctx.storage.list_objects(None).await.unwrap().objects.into_iter().map(|o| o.key).collect();
+    keys.sort();
+    keys
+}
+
+#[actix_web::test]
+async fn identical_uploads_share_one_blob_until_the_last_delete() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let sha256 = hex::encode(Sha256::digest(b"installer bytes"));
+
+    let first = upload_file(&app, &ctx, &alice, "setup.exe", b"installer bytes").await;
+    let second = upload_file(&app, &ctx, &bob, "installer.exe", b"installer bytes").await;
+
+    for file_id in [&first, &second] {
+        let file = ctx.db.get_file(file_id).await.unwrap().unwrap();
+        assert_eq!(file.blob_sha256.as_deref(), Some(sha256.as_str()));
+        assert_eq!(file.sha256.as_deref(), Some(sha256.as_str()));
+        assert_eq!(file.size_bytes, Some(15));
+    }
+    assert_eq!(object_keys(&ctx).await, vec![blob_key(&sha256)]);
+    assert_eq!(ctx.db.get_blob(&sha256).await.unwrap().unwrap().ref_count, 2);
+
+    let res = test::call_service(&app, request(TestRequest::get().uri(&format!("/api/v1/content/{}/data", second)), Some(&bob))).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(test::read_body(res).await, "installer bytes");
+
+    let (status, _) = call(&app, request(TestRequest::delete().uri(&format!("/api/v1/content/{}", first)), Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(ctx.db.get_blob(&sha256).await.unwrap().unwrap().ref_count, 1);
+    assert_eq!(object_keys(&ctx).await, vec![blob_key(&sha256)]);
+
+    let (status, _) = call(&app, request(TestRequest::delete().uri(&format!("/api/v1/content/{}", second)), Some(&bob))).await;
+    assert_eq!(status, 200);
+    assert!(ctx.db.get_blob(&sha256).await.unwrap().is_none());
+    assert!(object_keys(&ctx).await.is_empty());
+}
+
+#[actix_web::test]
+async fn replacing_content_moves_the_file_to_a_new_blob_and_keeps_the_old_one() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let old_sha256 = hex::encode(Sha256::digest(b"draft"));
+    let new_sha256 = hex::encode(Sha256::digest(b"final version"));
+
+    let file_id = upload_file(&app, &ctx, &alice, "notes.txt", b"draft").await;
+    let complete = || request(TestRequest::post().uri(&format!("/api/v1/content/{}/complete", file_id)), Some(&alice));
+
+    // Completing again without new content keeps the file where it is.
+    let (status, body) = call(&app, complete()).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["data"]["sha256"], old_sha256);
+
+    let req = TestRequest::post().uri(&format!("/api/v1/content/{}/replace", file_id));
+    let (status, _) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200);
+    ctx.storage.put_object(&file_id, b"final version".to_vec(), Some("text/plain")).await.unwrap();
+    let (status, body) = call(&app, complete()).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["data"]["sha256"], new_sha256);
+    assert_eq!(body["data"]["size_bytes"], 13);
+
+    // The old content lives on as the file's first version.
+    assert_eq!(ctx.db.get_blob(&old_sha256).await.unwrap().unwrap().ref_count, 1);
+    assert_eq!(ctx.db.get_blob(&new_sha256).await.unwrap().unwrap().ref_count, 1);
+    let mut expected = vec![blob_key(&old_sha256), blob_key(&new_sha256)];
+    expected.sort();
+    assert_eq!(object_keys(&ctx).await, expected);
+}
+
+#[actix_web::test]
+async fn reaper_collects_unreferenced_blobs_and_orphaned_blob_objects() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "kept.txt", b"kept").await;
+    let kept = ctx.db.get_file(&file_id).await.unwrap().unwrap().object_key();
+
+    // An upload that died between writing the blob and activating it.
+    let abandoned = hex::encode(Sha256::digest(b"abandoned"));
+    assert!(ctx.db.create_blob(&abandoned, 9).await.unwrap());
+    ctx.storage.put_object(&blob_key(&abandoned), b"abandoned".to_vec(), None).await.unwrap();
+    // A blob object whose row is gone.
+    let stray = blob_key(&hex::encode(Sha256::digest(b"stray")));
+    ctx.storage.put_object(&stray, b"stray".to_vec(), None).await.unwrap();
+
+    let reaper = Reaper::new(ctx.db.clone(), ctx.storage.clone(), ReaperConfig {
+        interval: std::time::Duration::from_secs(60),
+        dry_run: false,
+        delete_orphans: true,
+        multipart_expire: std::time::Duration::from_secs(3600),
+        max_versions: 0,
+    });
+
+    let report = reaper.reconcile(Utc::now()).await.unwrap();
+    assert!(report.deleted_blobs.is_empty());
+    assert!(report.orphaned_objects.is_empty());
+
+    let report = reaper.reconcile(Utc::now() + Duration::hours(1)).await.unwrap();
+    assert_eq!(report.deleted_blobs, vec![abandoned.clone()]);
+    assert_eq!(report.orphaned_objects, vec![stray]);
+    assert!(ctx.db.get_blob(&abandoned).await.unwrap().is_none());
+    assert_eq!(object_keys(&ctx).await, vec![kept]);
+}
@@ -0,0 +1,110 @@
+use actix_web::http::Method;
+use actix_web::test::{self, TestRequest};
+use futures_util::StreamExt;
+use serde_json::json;
+use crate::database::{LocalStorage, Storage};
+use crate::service::download::DownloadMode;
+use super::{call, context, context_with, request, signup, upload_file};
+
+#[actix_web::test]
+async fn download_streams_ranges_and_honours_conditional_headers() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "résumé 2024.txt", b"hello world").await;
+    let uri = format!("/api/v1/content/{}/data", file_id);
+
+    let res = test::call_service(&app, request(TestRequest::get().uri(&uri), Some(&alice))).await;
+    assert_eq!(res.status(), 200);
+    let header = |res: &actix_web::dev::ServiceResponse, name: &str| res.headers().get(name).map(|v| v.to_str().unwrap().to_string());
+    assert_eq!(header(&res, "Content-Length").as_deref(), Some("11"));
+    assert_eq!(header(&res, "Content-Type").as_deref(), Some("text/plain"));
+    assert_eq!(header(&res, "Accept-Ranges").as_deref(), Some("bytes"));
+    let disposition = header(&res, "Content-Disposition").unwrap();
+    assert!(disposition.starts_with("attachment"), "{}", disposition);
+    assert!(disposition.contains("filename=\"r_sum_ 2024.txt\""), "{}", disposition);
+    assert!(disposition.contains("filename*=UTF-8''r%C3%A9sum%C3%A9%202024.txt"), "{}", disposition);
+    let etag = header(&res, "ETag").unwrap();
+    assert_eq!(test::read_body(res).await, "hello world");
+
+    for range in ["bytes=6-", "bytes=6-100", "bytes=-5"] {
+        let req = TestRequest::get().uri(&uri).insert_header(("Range", range));
+        let res = test::call_service(&app, request(req, Some(&alice))).await;
+        assert_eq!(res.status(), 206, "{}", range);
+        assert_eq!(header(&res, "Content-Range").as_deref(), Some("bytes 6-10/11"));
+        assert_eq!(test::read_body(res).await, "world");
+    }
+
+    let req = TestRequest::get().uri(&uri).insert_header(("Range", "bytes=11-"));
+    let res = test::call_service(&app, request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 416);
+    assert_eq!(header(&res, "Content-Range").as_deref(), Some("bytes */11"));
+
+    let req = TestRequest::get().uri(&uri).insert_header(("Range", "bytes=0-4")).insert_header(("If-Range", etag.clone()));
+    let res = test::call_service(&app, request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 206);
+    assert_eq!(test::read_body(res).await, "hello");
+
+    // The file changed since the client's partial copy, so it gets the whole thing again.
+    let req = TestRequest::get().uri(&uri).insert_header(("Range", "bytes=0-4")).insert_header(("If-Range", "\"stale\""));
+    let res = test::call_service(&app, request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(test::read_body(res).await, "hello world");
+
+    let req = TestRequest::get().uri(&uri).insert_header(("If-None-Match", etag.clone()));
+    let res = test::call_service(&app, request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 304);
+
+    let res = test::call_service(&app, request(TestRequest::default().method(Method::HEAD).uri(&uri), Some(&alice))).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(header(&res, "Content-Length").as_deref(), Some("11"));
+    assert_eq!(header(&res, "ETag"), Some(etag));
+    assert!(test::read_body(res).await.is_empty());
+
+    let (status, body) = call(&app, request(TestRequest::get().uri(&uri), Some(&bob))).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "ACCESS_DENIED");
+}
+
+#[actix_web::test]
+async fn proxy_mode_never_hands_out_storage_urls() {
+    let ctx = context_with(|state| state.with_download_mode(DownloadMode::Proxy));
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "report.pdf", b"report").await;
+
+    let req = TestRequest::get().uri(&format!("/api/v1/content/{}/share", file_id));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["url"], format!("http://localhost:9000/api/v1/content/{}/data", file_id));
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/links", file_id))
+        .set_json(json!({}));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 201);
+    let share_path = format!("/s/{}", body["data"]["token"].as_str().unwrap());
+
+    let res = test::call_service(&app, request(TestRequest::get().uri(&share_path), None)).await;
+    assert_eq!(res.status(), 200);
+    assert!(res.headers().get("Location").is_none());
+    assert_eq!(test::read_body(res).await, "report");
+}
+
+#[actix_web::test]
+async fn local_storage_reads_byte_ranges() {
+    let root = std::env::temp_dir().join(format!("cfs-download-{}", uuid::Uuid::new_v4()));
+    let local = LocalStorage::new(root.clone(), "http://localhost:9000".to_string(), b"secret".to_vec(), 300, 300);
+    local.put_object("report", b"hello world".to_vec(), None).await.unwrap();
+
+    let mut stream = local.read_object("report", 3..8).await.unwrap().unwrap();
+    let mut body = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        body.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(body, b"lo wo");
+    assert!(local.read_object("missing", 0..1).await.unwrap().is_none());
+
+    tokio::fs::remove_dir_all(root).await.unwrap();
+}
@@ -0,0 +1,275 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::TestRequest;
+use serde_json::{json, Value};
+use crate::database::{Repository, Storage};
+use super::{call, context, request, signup, TestContext};
+
+async fn create_folder<S, B>(app: &S, session_key: &str, name: &str, parent_id: Option<&str>) -> String
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/v1/folders")
+        .set_json(json!({ "name": name, "parent_id": parent_id }));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 201, "create folder failed: {}", body);
+
+    body["data"]["id"].as_str().unwrap().to_string()
+}
+
+async fn upload_into<S, B>(app: &S, ctx: &TestContext, session_key: &str, folder_id: &str, filename: &str) -> String
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/v1/content")
+        .set_json(json!({ "filename": filename, "folder_id": folder_id }));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 201, "create file failed: {}", body);
+    let file_id = body["data"]["id"].as_str().unwrap().to_string();
+
+    ctx.storage.put_object(&file_id, filename.as_bytes().to_vec(), Some("text/plain")).await.unwrap();
+    let complete = TestRequest::post().uri(&format!("/api/v1/content/{}/complete", file_id));
+    let (status, body) = call(app, request(complete, Some(session_key))).await;
+    assert_eq!(status, 200, "complete upload failed: {}", body);
+
+    file_id
+}
+
+fn names(items: &Value, field: &str) -> Vec<String> {
+    items.as_array().unwrap()
+        .iter()
+        .map(|item| item[field].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[actix_web::test]
+async fn folders_nest_with_unique_names_and_breadcrumbs() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+
+    let projects = create_folder(&app, &alice, "Projects", None).await;
+    let acme = create_folder(&app, &alice, "Acme", Some(&projects)).await;
+    create_folder(&app, &alice, "Acme", None).await;
+
+    let duplicate = TestRequest::post()
+        .uri("/api/v1/folders")
+        .set_json(json!({ "name": "acme", "parent_id": projects }));
+    let (status, body) = call(&app, request(duplicate, Some(&alice))).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "FOLDER_EXISTS");
+
+    upload_into(&app, &ctx, &alice, &acme, "brief.txt").await;
+
+    let (status, body) = call(&app, request(TestRequest::get().uri(&format!("/api/v1/folders/{}", acme)), Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(names(&body["data"]["breadcrumbs"], "name"), vec!["Projects", "Acme"]);
+    assert_eq!(body["data"]["role"], "owner");
+
+    let (_, body) = call(&app, request(TestRequest::get().uri(&format!("/api/v1/folders/{}", projects)), Some(&alice))).await;
+    assert_eq!(names(&body["data"]["folders"], "name"), vec!["Acme"]);
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/folders"), Some(&alice))).await;
+    assert_eq!(names(&body["data"]["folders"], "name"), vec!["Acme", "Projects"]);
+
+    let (_, body) = call(&app, request(TestRequest::get().uri(&format!("/api/v1/content?folder={}", acme)), Some(&alice))).await;
+    assert_eq!(names(&body["data"]["files"], "filename"), vec!["brief.txt"]);
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/content?folder=root"), Some(&alice))).await;
+    assert_eq!(body["data"]["total"], 0);
+}
+
+#[actix_web::test]
+async fn folder_sharing_is_inherited_by_contents() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+
+    let clients = create_folder(&app, &alice, "Clients", None).await;
+    let acme = create_folder(&app, &alice, "Acme", Some(&clients)).await;
+    let deliverables = create_folder(&app, &alice, "Deliverables", Some(&acme)).await;
+    let file_id = upload_into(&app, &ctx, &alice, &deliverables, "final.pdf").await;
+
+    let download = || TestRequest::get().uri(&format!("/api/v1/content/{}/share", file_id));
+    assert_eq!(call(&app, request(download(), Some(&bob))).await.0, 403);
+
+    let share = TestRequest::put()
+        .uri(&format!("/api/v1/folders/{}/share", acme))
+        .set_json(json!({ "permissions": [{ "user_id": bob_id, "role": "viewer" }] }));
+    assert_eq!(call(&app, request(share, Some(&alice))).await.0, 200);
+
+    assert_eq!(call(&app, request(download(), Some(&bob))).await.0, 200);
+    let rename = TestRequest::patch()
+        .uri(&format!("/api/v1/content/{}", file_id))
+        .set_json(json!({ "filename": "renamed.pdf" }));
+    assert_eq!(call(&app, request(rename, Some(&bob))).await.0, 403);
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/content?scope=shared"), Some(&bob))).await;
+    assert_eq!(names(&body["data"]["files"], "filename"), vec!["final.pdf"]);
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/folders"), Some(&bob))).await;
+    assert_eq!(names(&body["data"]["folders"], "name"), vec!["Acme"]);
+
+    let (status, body) = call(&app, request(TestRequest::get().uri(&format!("/api/v1/folders/{}", deliverables)), Some(&bob))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["role"], "viewer");
+    assert_eq!(names(&body["data"]["breadcrumbs"], "name"), vec!["Acme", "Deliverables"]);
+
+    let (status, _) = call(&app, request(TestRequest::get().uri(&format!("/api/v1/folders/{}", clients)), Some(&bob))).await;
+    assert_eq!(status, 403);
+
+    let upload = TestRequest::post()
+        .uri("/api/v1/content")
+        .set_json(json!({ "filename": "notes.txt", "folder_id": deliverables }));
+    assert_eq!(call(&app, request(upload, Some(&bob))).await.0, 403);
+}
+
+#[actix_web::test]
+async fn editors_cannot_move_files_into_another_users_folder() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+
+    let shared = create_folder(&app, &alice, "Shared", None).await;
+    let archive = create_folder(&app, &alice, "Archive", Some(&shared)).await;
+    let mine = create_folder(&app, &bob, "Mine", None).await;
+    let share = TestRequest::put()
+        .uri(&format!("/api/v1/folders/{}/share", shared))
+        .set_json(json!({ "permissions": [{ "user_id": bob_id, "role": "editor" }] }));
+    assert_eq!(call(&app, request(share, Some(&alice))).await.0, 200);
+
+    let file_id = upload_into(&app, &ctx, &alice, &shared, "report.pdf").await;
+    let move_to = |folder_id: &str| TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/move", file_id))
+        .set_json(json!({ "folder_id": folder_id }));
+
+    let (status, body) = call(&app, request(move_to(&mine), Some(&bob))).await;
+    assert_eq!(status, 403, "{}", body);
+    let delete = || TestRequest::delete().uri(&format!("/api/v1/content/{}", file_id));
+    assert_eq!(call(&app, request(delete(), Some(&bob))).await.0, 403);
+    assert!(ctx.db.get_file(&file_id).await.unwrap().is_some());
+
+    // Moving within the same owner's folders is still an editor's job.
+    assert_eq!(call(&app, request(move_to(&archive), Some(&bob))).await.0, 200);
+
+    // Owning the folder does not make Alice the owner of what Bob puts in it.
+    let bobs_file = upload_into(&app, &ctx, &bob, &shared, "draft.txt").await;
+    let delete = TestRequest::delete().uri(&format!("/api/v1/content/{}", bobs_file));
+    assert_eq!(call(&app, request(delete, Some(&alice))).await.0, 403);
+    let move_out = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/move", bobs_file))
+        .set_json(json!({ "folder_id": mine }));
+    assert_eq!(call(&app, request(move_out, Some(&bob))).await.0, 200);
+}
+
+#[actix_web::test]
+async fn folders_move_without_cycles_or_name_clashes() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+
+    let archive = create_folder(&app, &alice, "Archive", None).await;
+    let work = create_folder(&app, &alice, "Work", None).await;
+    let reports = create_folder(&app, &alice, "Reports", Some(&work)).await;
+    create_folder(&app, &alice, "Reports", Some(&archive)).await;
+    let bobs = create_folder(&app, &bob, "Bob", None).await;
+
+    let mv = |folder: &str, parent: Option<&str>| TestRequest::post()
+        .uri(&format!("/api/v1/folders/{}/move", folder))
+        .set_json(json!({ "parent_id": parent }));
+
+    let (status, body) = call(&app, request(mv(&work, Some(&reports)), Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_MOVE");
+
+    let (status, _) = call(&app, request(mv(&reports, Some(&archive)), Some(&alice))).await;
+    assert_eq!(status, 409);
+
+    let rename = TestRequest::patch()
+        .uri(&format!("/api/v1/folders/{}", reports))
+        .set_json(json!({ "name": "Reports 2024" }));
+    assert_eq!(call(&app, request(rename, Some(&alice))).await.0, 200);
+
+    let (status, body) = call(&app, request(mv(&reports, Some(&archive)), Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["parent_id"], archive);
+
+    assert_eq!(call(&app, request(mv(&reports, Some(&bobs)), Some(&alice))).await.0, 403);
+    assert_eq!(call(&app, request(mv(&reports, None), Some(&alice))).await.0, 200);
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/folders"), Some(&alice))).await;
+    assert_eq!(names(&body["data"]["folders"], "name"), vec!["Archive", "Reports 2024", "Work"]);
+}
+
+#[actix_web::test]
+async fn deleting_a_folder_removes_its_whole_tree() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+
+    let root = create_folder(&app, &alice, "Root", None).await;
+    let child = create_folder(&app, &alice, "Child", Some(&root)).await;
+    let top = upload_into(&app, &ctx, &alice, &root, "top.txt").await;
+    let nested = upload_into(&app, &ctx, &alice, &child, "nested.txt").await;
+
+    let share = TestRequest::put()
+        .uri(&format!("/api/v1/folders/{}/share", root))
+        .set_json(json!({ "permissions": [{ "user_id": bob_id, "role": "co_owner" }] }));
+    assert_eq!(call(&app, request(share, Some(&alice))).await.0, 200);
+
+    let delete = || TestRequest::delete().uri(&format!("/api/v1/folders/{}", root));
+    assert_eq!(call(&app, request(delete(), Some(&bob))).await.0, 403);
+
+    let (status, body) = call(&app, request(delete(), Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["deleted_file_ids"].as_array().unwrap().len(), 2);
+
+    for file_id in [&top, &nested] {
+        assert!(ctx.db.get_file(file_id).await.unwrap().is_none());
+        assert!(ctx.storage.head_object(file_id).await.unwrap().is_none());
+    }
+    assert!(ctx.db.get_folder(&child).await.unwrap().is_none());
+    assert!(ctx.db.list_folder_permissions(&root).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn deleting_a_shared_folder_keeps_files_other_users_own() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (bob_id, bob) = signup(&app, "bob@example.com").await;
+
+    let shared = create_folder(&app, &alice, "Shared", None).await;
+    let child = create_folder(&app, &alice, "Child", Some(&shared)).await;
+    let share = TestRequest::put()
+        .uri(&format!("/api/v1/folders/{}/share", shared))
+        .set_json(json!({ "permissions": [{ "user_id": bob_id, "role": "editor" }] }));
+    assert_eq!(call(&app, request(share, Some(&alice))).await.0, 200);
+
+    let alices = upload_into(&app, &ctx, &alice, &shared, "plan.txt").await;
+    let bobs = upload_into(&app, &ctx, &bob, &child, "draft.txt").await;
+    let bobs_key = ctx.db.get_file(&bobs).await.unwrap().unwrap().object_key();
+
+    let (status, body) = call(&app, request(TestRequest::delete().uri(&format!("/api/v1/folders/{}", shared)), Some(&alice))).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["data"]["deleted_file_ids"], json!([alices]));
+    assert_eq!(body["data"]["moved_file_ids"], json!([bobs]));
+    assert!(ctx.db.get_file(&alices).await.unwrap().is_none());
+
+    // Bob's file survives at his top level, content and all.
+    let file = ctx.db.get_file(&bobs).await.unwrap().unwrap();
+    assert_eq!(file.folder_id, None);
+    let sha256 = file.blob_sha256.clone().unwrap();
+    assert_eq!(ctx.db.get_blob(&sha256).await.unwrap().unwrap().ref_count, 1);
+    assert_eq!(ctx.storage.get_object(&bobs_key).await.unwrap().unwrap(), b"draft.txt");
+    let (status, _) = call(&app, request(TestRequest::get().uri(&format!("/api/v1/content/{}/share", bobs)), Some(&alice))).await;
+    assert_eq!(status, 403);
+}
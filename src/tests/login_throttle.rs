@@ -0,0 +1,141 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::{self, TestRequest};
+use serde_json::{json, Value};
+use chrono::{Duration, Utc};
+use crate::database::Repository;
+use crate::database::model::{LOGIN_SCOPE_ACCOUNT, LOGIN_SCOPE_IP};
+use crate::middleware::{LoginPolicy, SessionPolicy};
+use crate::middleware::throttle::FailureLimits;
+use crate::task::SessionPurger;
+use super::{context_with, request, signup, PASSWORD, PEER_ADDR};
+
+const NO_LIMIT: FailureLimits = FailureLimits { backoff_after: 0, lockout_after: 0 };
+
+fn policy(account: FailureLimits, ip: FailureLimits, captcha_after: Option<i32>) -> LoginPolicy {
+    LoginPolicy {
+        account,
+        ip,
+        base_delay: Duration::seconds(30),
+        max_delay: Duration::minutes(2),
+        lockout_duration: Duration::minutes(15),
+        captcha_after,
+        window: Duration::hours(1),
+    }
+}
+
+async fn login<S, B>(app: &S, email: &str, password: &str, turnstile: Option<&str>) -> (u16, Option<String>, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": email, "password": password, "turnstile": turnstile }));
+    let res = test::call_service(app, request(req, None)).await;
+    let status = res.status().as_u16();
+    let retry_after = res.headers().get("Retry-After").map(|v| v.to_str().unwrap().to_string());
+    let body = serde_json::from_slice(&test::read_body(res).await).unwrap_or(Value::Null);
+    (status, retry_after, body)
+}
+
+#[actix_web::test]
+async fn failures_back_off_exponentially_and_success_resets_the_account() {
+    let limits = FailureLimits { backoff_after: 2, lockout_after: 0 };
+    let ctx = context_with(|s| s.with_login_policy(policy(limits, NO_LIMIT, None)));
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    let (status, _, _) = login(&app, "alice@example.com", "wrong", None).await;
+    assert_eq!(status, 401);
+    let (status, _, _) = login(&app, "alice@example.com", "wrong", None).await;
+    assert_eq!(status, 401);
+
+    // Even the right password waits out the delay.
+    let (status, retry_after, body) = login(&app, "alice@example.com", PASSWORD, None).await;
+    assert_eq!(status, 429);
+    assert_eq!(retry_after.as_deref(), Some("30"));
+    assert_eq!(body["error"]["code"], "LOGIN_THROTTLED");
+
+    // Each further failure doubles the delay, up to the cap.
+    let now = Utc::now();
+    ctx.db.block_login(LOGIN_SCOPE_ACCOUNT, "alice@example.com", now - Duration::seconds(1)).await.unwrap();
+    login(&app, "alice@example.com", "wrong", None).await;
+    let record = ctx.db.get_login_failure(LOGIN_SCOPE_ACCOUNT, "alice@example.com").await.unwrap().unwrap();
+    assert_eq!(record.failures, 3);
+    assert!(record.blocked_until.unwrap() > now + Duration::seconds(55));
+
+    ctx.db.block_login(LOGIN_SCOPE_ACCOUNT, "alice@example.com", now - Duration::seconds(1)).await.unwrap();
+    let (status, _, _) = login(&app, "alice@example.com", PASSWORD, None).await;
+    assert_eq!(status, 200);
+    assert!(ctx.db.get_login_failure(LOGIN_SCOPE_ACCOUNT, "alice@example.com").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn unknown_emails_are_locked_out_like_real_accounts() {
+    let limits = FailureLimits { backoff_after: 0, lockout_after: 3 };
+    let ctx = context_with(|s| s.with_login_policy(policy(limits, NO_LIMIT, None)));
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    let mut responses = Vec::new();
+    for email in ["alice@example.com", "nobody@example.com"] {
+        for _ in 0..3 {
+            let (status, _, body) = login(&app, email, "wrong", None).await;
+            assert_eq!(status, 401);
+            assert_eq!(body["error"]["detail"], "Invalid email or password");
+        }
+        let (status, retry_after, body) = login(&app, email, PASSWORD, None).await;
+        assert_eq!(status, 429);
+        assert_eq!(retry_after.as_deref(), Some("900"));
+        responses.push(body);
+    }
+
+    assert_eq!(responses[0], responses[1]);
+}
+
+#[actix_web::test]
+async fn turnstile_is_required_after_repeated_failures() {
+    let ctx = context_with(|s| s.with_login_policy(policy(NO_LIMIT, NO_LIMIT, Some(2))));
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    login(&app, "alice@example.com", "wrong", None).await;
+    let (status, _, _) = login(&app, "alice@example.com", "wrong", None).await;
+    assert_eq!(status, 401);
+
+    let (status, _, body) = login(&app, "alice@example.com", PASSWORD, None).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "CAPTCHA_REQUIRED");
+    assert_eq!(body["error"]["detail"], "test-sitekey");
+
+    let (status, _, _) = login(&app, "alice@example.com", PASSWORD, Some("token")).await;
+    assert_eq!(status, 200);
+}
+
+#[actix_web::test]
+async fn address_limits_span_accounts_and_stale_counters_are_purged() {
+    let limits = FailureLimits { backoff_after: 0, lockout_after: 3 };
+    let ctx = context_with(|s| s.with_login_policy(policy(NO_LIMIT, limits, None)));
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    for email in ["a@example.com", "b@example.com", "c@example.com"] {
+        login(&app, email, "wrong", None).await;
+    }
+
+    let (status, _, body) = login(&app, "alice@example.com", PASSWORD, None).await;
+    assert_eq!(status, 429);
+    assert_eq!(body["error"]["code"], "LOGIN_THROTTLED");
+
+    let ip = PEER_ADDR.split(':').next().unwrap();
+    let purger = SessionPurger::new(ctx.db.clone(), SessionPolicy::default()).with_login_window(Duration::minutes(5));
+    purger.purge(Utc::now() + Duration::minutes(10)).await.unwrap();
+    assert!(ctx.db.get_login_failure(LOGIN_SCOPE_ACCOUNT, "a@example.com").await.unwrap().is_none());
+    // Still locked out, so the address record survives.
+    assert!(ctx.db.get_login_failure(LOGIN_SCOPE_IP, ip).await.unwrap().is_some());
+
+    purger.purge(Utc::now() + Duration::minutes(20)).await.unwrap();
+    assert!(ctx.db.get_login_failure(LOGIN_SCOPE_IP, ip).await.unwrap().is_none());
+}
@@ -0,0 +1,55 @@
+use crate::database::migration::{pending, rollback_plan, verify, AppliedMigration, Migration, MIGRATIONS};
+
+fn applied(migration: &Migration) -> AppliedMigration {
+    AppliedMigration {
+        version: migration.version,
+        name: migration.name.to_string(),
+        checksum: migration.checksum(),
+        applied_at: chrono::Utc::now(),
+    }
+}
+
+#[test]
+fn migrations_are_numbered_in_order() {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        assert_eq!(migration.version, index as i64 + 1);
+        assert!(migration.name.starts_with(&format!("{:04}_", migration.version)));
+        assert!(!migration.up.trim().is_empty());
+    }
+}
+
+#[test]
+fn pending_skips_applied_and_respects_target() {
+    let done = vec![applied(&MIGRATIONS[0])];
+
+    let all: Vec<i64> = pending(MIGRATIONS, &done, None).iter().map(|m| m.version).collect();
+    assert_eq!(all, (2..=MIGRATIONS.len() as i64).collect::<Vec<_>>());
+
+    let limited: Vec<i64> = pending(MIGRATIONS, &done, Some(2)).iter().map(|m| m.version).collect();
+    assert_eq!(limited, vec![2]);
+}
+
+#[test]
+fn verify_rejects_modified_or_unknown_migrations() {
+    let mut done = vec![applied(&MIGRATIONS[0])];
+    assert!(verify(MIGRATIONS, &done).is_ok());
+
+    done[0].checksum = "0".repeat(64);
+    assert!(verify(MIGRATIONS, &done).unwrap_err().to_string().contains("Checksum mismatch"));
+
+    let unknown = AppliedMigration {
+        version: 9999,
+        name: "9999_future".to_string(),
+        checksum: String::new(),
+        applied_at: chrono::Utc::now(),
+    };
+    assert!(verify(MIGRATIONS, &[unknown]).unwrap_err().to_string().contains("unknown"));
+}
+
+#[test]
+fn rollback_plan_reverts_newest_first() {
+    let done: Vec<AppliedMigration> = MIGRATIONS.iter().map(applied).collect();
+
+    let plan: Vec<i64> = rollback_plan(MIGRATIONS, &done, 1).unwrap().iter().map(|m| m.version).collect();
+    assert_eq!(plan, (2..=MIGRATIONS.len() as i64).rev().collect::<Vec<_>>());
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::{self, TestRequest};
+use actix_web::web;
+use serde_json::{json, Value};
+use crate::captcha::StaticCaptcha;
+use crate::database::{MemoryRepository, MemoryStorage, Storage};
+use crate::mail::MemoryMailer;
+use crate::router::AppState;
+use crate::unique::Snowflake;
+
+macro_rules! test_app {
+    ($ctx:expr) => {
+        actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data($ctx.state.clone())
+                .configure(crate::router::configure)
+        ).await
+    };
+}
+
+mod user;
+mod session;
+mod content;
+mod reaper;
+mod share;
+mod folder;
+mod token;
+mod two_factor;
+mod passkey;
+mod oidc;
+mod account;
+mod login_throttle;
+mod password;
+mod multipart;
+mod tus;
+mod download;
+mod dedup;
+mod version;
+mod migration;
+
+pub const PEER_ADDR: &str = "127.0.0.1:40000";
+pub const PASSWORD: &str = "correct-horse-battery";
+
+pub struct TestContext {
+    pub state: web::Data<AppState>,
+    pub db: Arc<MemoryRepository>,
+    pub storage: Arc<MemoryStorage>,
+    pub mailer: Arc<MemoryMailer>,
+}
+
+pub fn context() -> TestContext {
+    context_with_captcha(true)
+}
+
+pub fn context_with_captcha(captcha_success: bool) -> TestContext {
+    build_context(captcha_success, |state| state)
+}
+
+/// Builds a context whose `AppState` is adjusted by `configure`, e.g. to swap in a session policy.
+pub fn context_with(configure: impl FnOnce(AppState) -> AppState) -> TestContext {
+    build_context(true, configure)
+}
+
+fn build_context(captcha_success: bool, configure: impl FnOnce(AppState) -> AppState) -> TestContext {
+    let db = Arc::new(MemoryRepository::new());
+    let storage = Arc::new(MemoryStorage::new());
+    let snowflake = Snowflake::new(1288834974657, 1).unwrap();
+    let mailer = Arc::new(MemoryMailer::new());
+    // Most tests share straight after signup; the account tests turn verification back on.
+    let state = AppState::new(
+        db.clone(),
+        storage.clone(),
+        snowflake,
+        Arc::new(StaticCaptcha::new(captcha_success)),
+        "test-sitekey".to_string(),
+    )
+    .with_mailer(mailer.clone(), "http://localhost:9000".to_string())
+    .with_email_verification(false);
+
+    TestContext {
+        state: web::Data::new(configure(state)),
+        db,
+        storage,
+        mailer,
+    }
+}
+
+pub fn request(req: TestRequest, session_key: Option<&str>) -> Request {
+    let req = req.peer_addr(PEER_ADDR.parse().unwrap());
+    match session_key {
+        Some(key) => req.insert_header(("Authorization", format!("Bearer {}", key))).to_request(),
+        None => req.to_request(),
+    }
+}
+
+pub async fn call<S, B>(app: &S, req: Request) -> (u16, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let res = test::call_service(app, req).await;
+    let status = res.status().as_u16();
+    let body = test::read_body(res).await;
+    let value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, value)
+}
+
+pub async fn signup<S, B>(app: &S, email: &str) -> (String, String)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/v1/user")
+        .set_json(json!({ "email": email, "password": PASSWORD, "turnstile": "token" }));
+    let (status, body) = call(app, request(req, None)).await;
+    assert_eq!(status, 200, "signup failed: {}", body);
+
+    (
+        body["data"]["id"].as_str().unwrap().to_string(),
+        body["data"]["session_key"].as_str().unwrap().to_string(),
+    )
+}
+
+pub async fn create_file<S, B>(app: &S, session_key: &str, filename: &str) -> String
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/v1/content")
+        .set_json(json!({ "filename": filename }));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 201, "create file failed: {}", body);
+
+    body["data"]["id"].as_str().unwrap().to_string()
+}
+
+pub async fn upload_file<S, B>(app: &S, ctx: &TestContext, session_key: &str, filename: &str, body: &[u8]) -> String
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let file_id = create_file(app, session_key, filename).await;
+    ctx.storage.put_object(&file_id, body.to_vec(), Some("text/plain")).await.unwrap();
+
+    let req = TestRequest::post().uri(&format!("/api/v1/content/{}/complete", file_id));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 200, "complete upload failed: {}", body);
+
+    file_id
+}
@@ -0,0 +1,199 @@
+use actix_web::test::TestRequest;
+use actix_web::web::Bytes;
+use chrono::{Duration, Utc};
+use futures_util::stream;
+use serde_json::json;
+use crate::database::{LocalStorage, Repository, Storage};
+use crate::task::{Reaper, ReaperConfig};
+use super::{call, context, create_file, request, signup};
+
+#[actix_web::test]
+async fn multipart_upload_resumes_from_listed_parts_and_completes() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = create_file(&app, &alice, "master.mov").await;
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart", file_id))
+        .set_json(json!({ "content_type": "video/quicktime" }));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 201, "{}", body);
+    let upload_id = body["data"]["upload_id"].as_str().unwrap().to_string();
+    assert_eq!(body["data"]["max_parts"], 10000);
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart/parts", file_id))
+        .set_json(json!({ "part_numbers": [1, 2] }));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["parts"].as_array().unwrap().len(), 2);
+    assert_eq!(body["data"]["parts"][1]["part_number"], 2);
+
+    // The connection drops after the first part; the client asks what made it through.
+    let first = ctx.storage.upload_part(&upload_id, 1, b"hello ".to_vec()).unwrap();
+    let req = TestRequest::get().uri(&format!("/api/v1/content/{}/multipart", file_id));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["uploaded_bytes"], 6);
+    assert_eq!(body["data"]["parts"], json!([{ "part_number": 1, "etag": first, "size": 6 }]));
+
+    let second = ctx.storage.upload_part(&upload_id, 2, b"world".to_vec()).unwrap();
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart/complete", file_id))
+        .set_json(json!({ "parts": [
+            { "part_number": 1, "etag": format!("\"{}\"", first) },
+            { "part_number": 2, "etag": second }
+        ] }));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["data"]["upload_state"], "complete");
+    assert_eq!(body["data"]["size_bytes"], 11);
+    assert_eq!(body["data"]["content_type"], "video/quicktime");
+
+    let file = ctx.db.get_file(&file_id).await.unwrap().unwrap();
+    assert_eq!(ctx.storage.get_object(&file.object_key()).await.unwrap().unwrap(), b"hello world");
+    assert!(file.multipart_upload_id.is_none());
+    assert_eq!(ctx.storage.multipart_upload_count(), 0);
+}
+
+#[actix_web::test]
+async fn multipart_rejects_concurrent_uploads_and_bad_part_lists() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let file_id = create_file(&app, &alice, "master.mov").await;
+
+    let initiate = || TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart", file_id))
+        .set_json(json!({}));
+    let (status, _) = call(&app, request(initiate(), Some(&bob))).await;
+    assert_eq!(status, 403);
+
+    let (status, body) = call(&app, request(initiate(), Some(&alice))).await;
+    assert_eq!(status, 201);
+    let upload_id = body["data"]["upload_id"].as_str().unwrap().to_string();
+
+    let (status, body) = call(&app, request(initiate(), Some(&alice))).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "MULTIPART_IN_PROGRESS");
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart/parts", file_id))
+        .set_json(json!({ "part_numbers": [0] }));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_PART");
+
+    let etag = ctx.storage.upload_part(&upload_id, 1, b"data".to_vec()).unwrap();
+    for parts in [
+        json!([{ "part_number": 1, "etag": "wrong" }]),
+        json!([{ "part_number": 1, "etag": etag }, { "part_number": 2, "etag": etag }]),
+        json!([{ "part_number": 1, "etag": etag }, { "part_number": 1, "etag": etag }]),
+    ] {
+        let req = TestRequest::post()
+            .uri(&format!("/api/v1/content/{}/multipart/complete", file_id))
+            .set_json(json!({ "parts": parts }));
+        let (status, body) = call(&app, request(req, Some(&alice))).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"]["code"], "INVALID_PART");
+    }
+
+    // Nothing was assembled, so the upload can still be finished.
+    assert_eq!(ctx.storage.multipart_upload_count(), 1);
+    assert!(ctx.storage.head_object(&file_id).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn aborting_discards_parts_and_allows_a_fresh_start() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = create_file(&app, &alice, "master.mov").await;
+
+    let initiate = || TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart", file_id))
+        .set_json(json!({}));
+    let (_, body) = call(&app, request(initiate(), Some(&alice))).await;
+    let upload_id = body["data"]["upload_id"].as_str().unwrap().to_string();
+    ctx.storage.upload_part(&upload_id, 1, b"data".to_vec()).unwrap();
+
+    let req = TestRequest::delete().uri(&format!("/api/v1/content/{}/multipart", file_id));
+    let (status, _) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(ctx.storage.multipart_upload_count(), 0);
+
+    let req = TestRequest::get().uri(&format!("/api/v1/content/{}/multipart", file_id));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "MULTIPART_NOT_FOUND");
+
+    let (status, _) = call(&app, request(initiate(), Some(&alice))).await;
+    assert_eq!(status, 201);
+
+    // Deleting the file takes the upload in progress with it.
+    let req = TestRequest::delete().uri(&format!("/api/v1/content/{}", file_id));
+    let (status, _) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(ctx.storage.multipart_upload_count(), 0);
+}
+
+#[actix_web::test]
+async fn reaper_spares_active_multipart_uploads_and_aborts_stale_ones() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = create_file(&app, &alice, "master.mov").await;
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart", file_id))
+        .set_json(json!({}));
+    call(&app, request(req, Some(&alice))).await;
+
+    let reaper = Reaper::new(ctx.db.clone(), ctx.storage.clone(), ReaperConfig {
+        interval: std::time::Duration::from_secs(60),
+        dry_run: false,
+        delete_orphans: false,
+        multipart_expire: std::time::Duration::from_secs(24 * 3600),
+        max_versions: 0,
+    });
+
+    // Well past the single-PUT window, but the multipart upload is still live.
+    let report = reaper.reconcile(Utc::now() + Duration::hours(2)).await.unwrap();
+    assert!(report.deleted_files.is_empty());
+    assert!(ctx.db.get_file(&file_id).await.unwrap().is_some());
+
+    let report = reaper.reconcile(Utc::now() + Duration::days(2)).await.unwrap();
+    assert_eq!(report.aborted_uploads, vec![file_id.clone()]);
+    assert_eq!(report.deleted_files, vec![file_id.clone()]);
+    assert_eq!(ctx.storage.multipart_upload_count(), 0);
+}
+
+#[actix_web::test]
+async fn local_storage_assembles_parts_in_order() {
+    let root = std::env::temp_dir().join(format!("cfs-multipart-{}", uuid::Uuid::new_v4()));
+    let local = LocalStorage::new(root.clone(), "http://localhost:9000".to_string(), b"secret".to_vec(), 300, 300);
+
+    let upload_id = local.create_multipart_upload("video", Some("video/mp4")).await.unwrap();
+    let url = local.upload_part_url("video", &upload_id, 2).await.unwrap();
+    assert!(url.contains(&format!("upload_id={}&part_number=2", upload_id)));
+
+    let mut etags = Vec::new();
+    for (part_number, body) in [(2, "second"), (1, "first-")] {
+        let chunks = stream::iter(vec![Ok::<_, std::io::Error>(Bytes::from(body))]);
+        etags.push((part_number, local.write_part("video", &upload_id, part_number, chunks).await.unwrap()));
+    }
+    etags.reverse();
+
+    let parts = local.list_parts("video", &upload_id).await.unwrap();
+    assert_eq!(parts.iter().map(|p| p.part_number).collect::<Vec<_>>(), vec![1, 2]);
+    assert!(local.list_parts("other", &upload_id).await.is_err());
+
+    local.complete_multipart_upload("video", &upload_id, &etags).await.unwrap();
+    assert_eq!(local.get_object("video").await.unwrap().unwrap(), b"first-second");
+    assert_eq!(local.head_object("video").await.unwrap().unwrap().content_type.as_deref(), Some("video/mp4"));
+    assert!(local.list_parts("video", &upload_id).await.is_err());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::TestRequest;
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use p256::ecdsa::SigningKey;
+use p256::pkcs8::EncodePrivateKey;
+use rand::RngCore;
+use serde_json::{json, Value};
+use url::Url;
+use crate::database::Repository;
+use crate::oidc::{HttpClient, OidcConfig, OidcProvider};
+use crate::oidc::provider::code_challenge;
+use super::{call, context, context_with, request, signup, TestContext, PASSWORD};
+
+const ISSUER: &str = "https://idp.example.com";
+const CLIENT_ID: &str = "cloud-file-storage";
+const REDIRECT_URI: &str = "http://localhost:9000/api/v1/session/oidc/callback";
+
+struct Grant {
+    code_challenge: String,
+    nonce: String,
+    claims: Value,
+}
+
+/// An identity provider that lives in memory: it serves discovery and JWKS, and redeems codes
+/// it handed out through `authorize` for ES256-signed ID tokens.
+struct MockIdp {
+    keys: Mutex<Vec<(String, SigningKey)>>,
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+impl MockIdp {
+    fn new() -> Arc<Self> {
+        let idp = Arc::new(Self { keys: Mutex::new(Vec::new()), grants: Mutex::new(HashMap::new()) });
+        idp.rotate_key();
+        idp
+    }
+
+    /// Starts signing with a new key, publishing it alongside the old ones.
+    fn rotate_key(&self) {
+        let mut secret = [0u8; 32];
+        rand::rng().fill_bytes(&mut secret);
+        let mut keys = self.keys.lock().unwrap();
+        let kid = format!("key-{}", keys.len() + 1);
+        keys.push((kid, SigningKey::from_slice(&secret).unwrap()));
+    }
+
+    /// Plays the browser's visit to the authorization endpoint and returns the code it redirects back with.
+    fn authorize(&self, authorization_url: &str, claims: Value) -> (String, String) {
+        let url = Url::parse(authorization_url).unwrap();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["redirect_uri"], REDIRECT_URI);
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        let code = format!("code-{}", self.grants.lock().unwrap().len());
+        self.grants.lock().unwrap().insert(code.clone(), Grant {
+            code_challenge: params["code_challenge"].clone(),
+            nonce: params["nonce"].clone(),
+            claims,
+        });
+        (code, params["state"].clone())
+    }
+
+    fn id_token(&self, nonce: &str, overrides: &Value) -> String {
+        let now = chrono::Utc::now().timestamp();
+        let mut claims = json!({
+            "iss": ISSUER,
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": nonce
+        });
+        for (name, value) in overrides.as_object().unwrap() {
+            claims[name] = value.clone();
+        }
+
+        let keys = self.keys.lock().unwrap();
+        let (kid, key) = keys.last().unwrap();
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(kid.clone());
+        let der = key.to_pkcs8_der().unwrap();
+        encode(&header, &claims, &EncodingKey::from_ec_der(der.as_bytes())).unwrap()
+    }
+}
+
+#[async_trait]
+impl HttpClient for MockIdp {
+    async fn get_json(&self, url: &str) -> Result<Value, Box<dyn Error>> {
+        if url == format!("{}/.well-known/openid-configuration", ISSUER) {
+            return Ok(json!({
+                "issuer": ISSUER,
+                "authorization_endpoint": format!("{}/authorize", ISSUER),
+                "token_endpoint": format!("{}/token", ISSUER),
+                "jwks_uri": format!("{}/jwks", ISSUER)
+            }));
+        }
+
+        if url == format!("{}/jwks", ISSUER) {
+            let keys: Vec<Value> = self.keys.lock().unwrap().iter().map(|(kid, key)| {
+                let point = key.verifying_key().to_encoded_point(false);
+                json!({
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "use": "sig",
+                    "alg": "ES256",
+                    "kid": kid,
+                    "x": URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+                    "y": URL_SAFE_NO_PAD.encode(point.y().unwrap())
+                })
+            }).collect();
+            return Ok(json!({ "keys": keys }));
+        }
+
+        Err(format!("{} answered 404", url).into())
+    }
+
+    async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Value, Box<dyn Error>> {
+        assert_eq!(url, format!("{}/token", ISSUER));
+        let form: HashMap<&str, &str> = form.iter().copied().collect();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["client_id"], CLIENT_ID);
+
+        let grant = self.grants.lock().unwrap().remove(form["code"]).ok_or("invalid_grant")?;
+        if code_challenge(form["code_verifier"]) != grant.code_challenge {
+            return Err("invalid_grant: PKCE verification failed".into());
+        }
+
+        Ok(json!({
+            "access_token": "unused",
+            "token_type": "Bearer",
+            "id_token": self.id_token(&grant.nonce, &grant.claims)
+        }))
+    }
+}
+
+fn oidc_context(idp: &Arc<MockIdp>, auto_provision: bool) -> TestContext {
+    let config = OidcConfig {
+        issuer: ISSUER.to_string(),
+        client_id: CLIENT_ID.to_string(),
+        client_secret: None,
+        redirect_uri: REDIRECT_URI.to_string(),
+        scopes: "openid email".to_string(),
+        auto_provision,
+    };
+    let provider = OidcProvider::new(config, idp.clone());
+    context_with(|state| state.with_oidc(provider))
+}
+
+/// Runs the whole redirect dance and returns the callback's answer.
+async fn sign_in<S, B>(app: &S, idp: &MockIdp, claims: Value) -> (u16, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let (status, body) = call(app, request(TestRequest::get().uri("/api/v1/session/oidc/authorize"), None)).await;
+    assert_eq!(status, 200, "authorize failed: {}", body);
+
+    let (code, state) = idp.authorize(body["data"]["authorization_url"].as_str().unwrap(), claims);
+    call(app, callback(&code, &state)).await
+}
+
+fn callback(code: &str, state: &str) -> Request {
+    let uri = format!("/api/v1/session/oidc/callback?code={}&state={}", code, state);
+    request(TestRequest::get().uri(&uri), None)
+}
+
+#[actix_web::test]
+async fn oidc_provisions_then_recognises_account() {
+    let idp = MockIdp::new();
+    let ctx = oidc_context(&idp, true);
+    let app = test_app!(ctx);
+
+    let claims = json!({ "sub": "carol-1", "email": "carol@example.com", "email_verified": true });
+    let (status, body) = sign_in(&app, &idp, claims.clone()).await;
+    assert_eq!(status, 200, "sign-in failed: {}", body);
+    assert_eq!(body["data"]["email"], "carol@example.com");
+    let user_id = body["data"]["id"].clone();
+    let session_key = body["data"]["session_key"].as_str().unwrap();
+    assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(session_key))).await.0, 200);
+
+    // A rotated signing key is picked up by refetching the JWKS.
+    idp.rotate_key();
+    let (status, body) = sign_in(&app, &idp, claims).await;
+    assert_eq!(status, 200, "sign-in after rotation failed: {}", body);
+    assert_eq!(body["data"]["id"], user_id);
+
+    // The provisioned account has no password of its own.
+    let login = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "carol@example.com", "password": "!" }));
+    assert_eq!(call(&app, request(login, None)).await.0, 401);
+}
+
+#[actix_web::test]
+async fn oidc_links_existing_account_by_verified_email() {
+    let idp = MockIdp::new();
+    let ctx = oidc_context(&idp, false);
+    let app = test_app!(ctx);
+    let (alice_id, _) = signup(&app, "alice@example.com").await;
+
+    let (status, body) = sign_in(&app, &idp, json!({ "sub": "mallory", "email": "alice@example.com", "email_verified": false })).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "OIDC_EMAIL_UNVERIFIED");
+
+    let (status, body) = sign_in(&app, &idp, json!({ "sub": "alice-1", "email": "alice@example.com", "email_verified": "true" })).await;
+    assert_eq!(status, 200, "sign-in failed: {}", body);
+    assert_eq!(body["data"]["id"], alice_id);
+
+    // Once linked, the subject alone identifies the account even if the email changes upstream.
+    let (status, body) = sign_in(&app, &idp, json!({ "sub": "alice-1", "email": "alice@corp.example.com" })).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["id"], alice_id);
+
+    let (status, body) = sign_in(&app, &idp, json!({ "sub": "dave-1", "email": "dave@example.com", "email_verified": true })).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "OIDC_NO_ACCOUNT");
+
+    // Password login keeps working for the linked account.
+    let login = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }));
+    assert_eq!(call(&app, request(login, None)).await.0, 200);
+
+    // With TOTP enabled, signing in through the provider still ends at the second factor.
+    ctx.db.save_totp_secret(&alice_id, "JBSWY3DPEHPK3PXP").await.unwrap();
+    assert!(ctx.db.enable_totp(&alice_id, 0, &[], chrono::Utc::now()).await.unwrap());
+    let (status, body) = sign_in(&app, &idp, json!({ "sub": "alice-1", "email": "alice@example.com" })).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["data"]["two_factor_required"], true);
+    assert!(body["data"].get("session_key").is_none());
+}
+
+#[actix_web::test]
+async fn oidc_rejects_untrusted_callbacks() {
+    let idp = MockIdp::new();
+    let ctx = oidc_context(&idp, true);
+    let app = test_app!(ctx);
+    let claims = json!({ "sub": "carol-1", "email": "carol@example.com", "email_verified": true });
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/session/oidc/authorize"), None)).await;
+    let (code, state) = idp.authorize(body["data"]["authorization_url"].as_str().unwrap(), claims.clone());
+    assert_eq!(call(&app, callback(&code, "forged-state")).await.1["error"]["code"], "OIDC_STATE_INVALID");
+    assert_eq!(call(&app, callback(&code, &state)).await.0, 200);
+    // Each state is good for one callback.
+    assert_eq!(call(&app, callback(&code, &state)).await.1["error"]["code"], "OIDC_STATE_INVALID");
+
+    for overrides in [json!({ "aud": "someone-else" }), json!({ "iss": "https://evil.example" }), json!({ "nonce": "replayed" }), json!({ "exp": 1 })] {
+        let mut claims = claims.clone();
+        claims.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+        let (status, body) = sign_in(&app, &idp, claims).await;
+        assert_eq!(status, 401, "accepted {}", overrides);
+        assert_eq!(body["error"]["code"], "OIDC_TOKEN_INVALID");
+    }
+
+    let denied = TestRequest::get().uri("/api/v1/session/oidc/callback?error=access_denied&state=x");
+    let (status, body) = call(&app, request(denied, None)).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "OIDC_DENIED");
+}
+
+#[actix_web::test]
+async fn oidc_is_off_without_configuration() {
+    let ctx = context();
+    let app = test_app!(ctx);
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/session/oidc/authorize"), None)).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "OIDC_DISABLED");
+}
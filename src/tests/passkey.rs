@@ -0,0 +1,271 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::TestRequest;
+use ciborium::Value as Cbor;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::ecdsa::signature::Signer;
+use rand::RngCore;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use crate::database::Repository;
+use crate::encrypt::RelyingParty;
+use crate::encrypt::webauthn::{decode_b64url, encode_b64url};
+use super::{call, context, request, signup, TestContext};
+
+/// A software authenticator holding a single ES256 credential.
+struct Authenticator {
+    key: SigningKey,
+    credential_id: Vec<u8>,
+    counter: u32,
+    origin: String,
+    verified: bool,
+}
+
+impl Authenticator {
+    fn new(rp: &RelyingParty) -> Self {
+        let mut secret = [0u8; 32];
+        rand::rng().fill_bytes(&mut secret);
+        let mut credential_id = vec![0u8; 16];
+        rand::rng().fill_bytes(&mut credential_id);
+
+        Self {
+            key: SigningKey::from_slice(&secret).unwrap(),
+            credential_id,
+            counter: 0,
+            origin: rp.origin.clone(),
+            verified: true,
+        }
+    }
+
+    fn credential_id(&self) -> String {
+        encode_b64url(&self.credential_id)
+    }
+
+    fn client_data(&self, kind: &str, challenge: &str) -> Vec<u8> {
+        serde_json::to_vec(&json!({ "type": kind, "challenge": challenge, "origin": self.origin })).unwrap()
+    }
+
+    fn auth_data(&self, rp: &RelyingParty, attested: bool) -> Vec<u8> {
+        let mut data = Sha256::digest(rp.id.as_bytes()).to_vec();
+        let flags = if self.verified { 0x05 } else { 0x01 };
+        data.push(if attested { flags | 0x40 } else { flags });
+        data.extend_from_slice(&self.counter.to_be_bytes());
+
+        if attested {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            let cose = Cbor::Map(vec![
+                (Cbor::from(1), Cbor::from(2)),
+                (Cbor::from(3), Cbor::from(-7)),
+                (Cbor::from(-1), Cbor::from(1)),
+                (Cbor::from(-2), Cbor::Bytes(point.x().unwrap().to_vec())),
+                (Cbor::from(-3), Cbor::Bytes(point.y().unwrap().to_vec())),
+            ]);
+            data.extend_from_slice(&[0u8; 16]);
+            data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(&self.credential_id);
+            ciborium::into_writer(&cose, &mut data).unwrap();
+        }
+
+        data
+    }
+
+    fn attest(&self, rp: &RelyingParty, options: &Value) -> Value {
+        let challenge = options["data"]["challenge"].as_str().unwrap();
+        let attestation = Cbor::Map(vec![
+            (Cbor::from("fmt"), Cbor::from("none")),
+            (Cbor::from("attStmt"), Cbor::Map(vec![])),
+            (Cbor::from("authData"), Cbor::Bytes(self.auth_data(rp, true))),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::into_writer(&attestation, &mut attestation_object).unwrap();
+
+        json!({
+            "name": "Test key",
+            "client_data_json": encode_b64url(&self.client_data("webauthn.create", challenge)),
+            "attestation_object": encode_b64url(&attestation_object)
+        })
+    }
+
+    fn assert(&mut self, rp: &RelyingParty, options: &Value, user_id: &str) -> Value {
+        self.counter += 1;
+        let client_data = self.client_data("webauthn.get", options["data"]["challenge"].as_str().unwrap());
+        let auth_data = self.auth_data(rp, false);
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&signed);
+
+        json!({
+            "credential_id": self.credential_id(),
+            "client_data_json": encode_b64url(&client_data),
+            "authenticator_data": encode_b64url(&auth_data),
+            "signature": encode_b64url(signature.to_der().as_bytes()),
+            "user_handle": encode_b64url(user_id.as_bytes())
+        })
+    }
+}
+
+async fn register<S, B>(app: &S, ctx: &TestContext, session_key: &str) -> Authenticator
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let authenticator = Authenticator::new(&ctx.state.relying_party);
+    let (status, options) = call(app, request(TestRequest::post().uri("/api/v1/session/passkey/register/options"), Some(session_key))).await;
+    assert_eq!(status, 200, "register options failed: {}", options);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/session/passkey/register")
+        .set_json(authenticator.attest(&ctx.state.relying_party, &options));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 201, "register failed: {}", body);
+    assert_eq!(body["data"]["credential_id"], authenticator.credential_id());
+
+    authenticator
+}
+
+async fn login_options<S, B>(app: &S, body: Value) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post().uri("/api/v1/session/passkey/login/options").set_json(body);
+    let (status, options) = call(app, request(req, None)).await;
+    assert_eq!(status, 200, "login options failed: {}", options);
+    options
+}
+
+fn login(body: Value) -> Request {
+    request(TestRequest::post().uri("/api/v1/session/passkey/login").set_json(body), None)
+}
+
+#[actix_web::test]
+async fn passkey_registers_and_logs_in() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+
+    let (_, options) = call(&app, request(TestRequest::post().uri("/api/v1/session/passkey/register/options"), Some(&alice))).await;
+    assert_eq!(options["data"]["rp"]["id"], "localhost");
+    assert_eq!(decode_b64url(options["data"]["user"]["id"].as_str().unwrap()).unwrap(), user_id.as_bytes());
+    assert_eq!(options["data"]["pubKeyCredParams"][0]["alg"], -7);
+
+    let mut authenticator = register(&app, &ctx, &alice).await;
+
+    // Registering again lists the existing key so the browser can skip it.
+    let (_, options) = call(&app, request(TestRequest::post().uri("/api/v1/session/passkey/register/options"), Some(&alice))).await;
+    assert_eq!(options["data"]["excludeCredentials"][0]["id"], authenticator.credential_id());
+
+    let options = login_options(&app, json!({})).await;
+    assert_eq!(options["data"]["allowCredentials"], json!([]));
+    let assertion = authenticator.assert(&ctx.state.relying_party, &options, &user_id);
+    let (status, body) = call(&app, login(assertion.clone())).await;
+    assert_eq!(status, 200, "login failed: {}", body);
+    assert_eq!(body["data"]["id"], user_id);
+    let session_key = body["data"]["session_key"].as_str().unwrap();
+    assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(session_key))).await.0, 200);
+
+    let stored = ctx.db.get_webauthn_credential(&authenticator.credential_id()).await.unwrap().unwrap();
+    assert_eq!(stored.sign_count, 1);
+    assert!(stored.last_used_at.is_some());
+
+    // The challenge is spent, so the same assertion cannot be replayed.
+    let (status, body) = call(&app, login(assertion)).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "CHALLENGE_INVALID");
+}
+
+#[actix_web::test]
+async fn passkey_login_options_scope_to_account() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (alice_id, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let mut alice_key = register(&app, &ctx, &alice).await;
+    let mut bob_key = register(&app, &ctx, &bob).await;
+
+    let options = login_options(&app, json!({ "email": "alice@example.com" })).await;
+    assert_eq!(options["data"]["allowCredentials"], json!([{ "type": "public-key", "id": alice_key.credential_id() }]));
+
+    // A challenge issued for alice cannot be answered with bob's passkey.
+    let assertion = bob_key.assert(&ctx.state.relying_party, &options, &alice_id);
+    let (status, body) = call(&app, login(assertion)).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "PASSKEY_INVALID");
+
+    let assertion = alice_key.assert(&ctx.state.relying_party, &options, &alice_id);
+    assert_eq!(call(&app, login(assertion)).await.0, 200);
+
+    // Unknown accounts look the same as accounts without passkeys.
+    let options = login_options(&app, json!({ "email": "nobody@example.com" })).await;
+    assert_eq!(options["data"]["allowCredentials"], json!([]));
+}
+
+#[actix_web::test]
+async fn passkey_rejects_forged_assertions() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+    let mut authenticator = register(&app, &ctx, &alice).await;
+    let rp = ctx.state.relying_party.clone();
+
+    let options = login_options(&app, json!({})).await;
+    let mut assertion = authenticator.assert(&rp, &options, &user_id);
+    let mut signature = decode_b64url(assertion["signature"].as_str().unwrap()).unwrap();
+    let last = signature.len() - 1;
+    signature[last] ^= 1;
+    assertion["signature"] = json!(encode_b64url(&signature));
+    assert_eq!(call(&app, login(assertion)).await.0, 401);
+
+    let options = login_options(&app, json!({})).await;
+    authenticator.origin = "https://evil.example".to_string();
+    let assertion = authenticator.assert(&rp, &options, &user_id);
+    assert_eq!(call(&app, login(assertion)).await.0, 401);
+    authenticator.origin = rp.origin.clone();
+
+    // Touching the key without a PIN or biometric is not enough to sign in.
+    authenticator.verified = false;
+    let options = login_options(&app, json!({})).await;
+    assert_eq!(options["data"]["userVerification"], "required");
+    let assertion = authenticator.assert(&rp, &options, &user_id);
+    let (status, body) = call(&app, login(assertion)).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["detail"], "The authenticator did not verify the user");
+    authenticator.verified = true;
+
+    let options = login_options(&app, json!({})).await;
+    let assertion = authenticator.assert(&rp, &options, &user_id);
+    assert_eq!(call(&app, login(assertion)).await.0, 200);
+
+    // A counter that fails to advance points at a cloned authenticator.
+    authenticator.counter = 0;
+    let options = login_options(&app, json!({})).await;
+    let assertion = authenticator.assert(&rp, &options, &user_id);
+    let (status, body) = call(&app, login(assertion)).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["detail"], "Signature counter did not advance");
+}
+
+#[actix_web::test]
+async fn passkey_removal_is_per_user() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let mut authenticator = register(&app, &ctx, &alice).await;
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/session/passkeys"), Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    assert_eq!(body["data"][0]["name"], "Test key");
+    assert!(body["data"][0].get("public_key").is_none());
+    let passkey_id = body["data"][0]["id"].as_str().unwrap().to_string();
+
+    let uri = format!("/api/v1/session/passkeys/{}", passkey_id);
+    assert_eq!(call(&app, request(TestRequest::delete().uri(&uri), Some(&bob))).await.0, 404);
+    assert_eq!(call(&app, request(TestRequest::delete().uri(&uri), Some(&alice))).await.0, 200);
+
+    let options = login_options(&app, json!({})).await;
+    let assertion = authenticator.assert(&ctx.state.relying_party, &options, &user_id);
+    assert_eq!(call(&app, login(assertion)).await.0, 401);
+}
@@ -0,0 +1,102 @@
+use actix_web::test::TestRequest;
+use argon2::Params;
+use serde_json::json;
+use crate::database::Repository;
+use crate::encrypt::PasswordHasher;
+use crate::encrypt::password::PasswordPolicy;
+use super::{call, context, context_with, request, signup, PASSWORD};
+
+#[actix_web::test]
+async fn login_upgrades_legacy_bcrypt_hashes() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, _) = signup(&app, "alice@example.com").await;
+    let user = ctx.db.get_user(&user_id).await.unwrap().unwrap();
+    assert!(user.password_hash.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+
+    ctx.db.update_user_password(&user_id, &bcrypt::hash(PASSWORD, 4).unwrap()).await.unwrap();
+
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }));
+    let (status, _) = call(&app, request(req, None)).await;
+    assert_eq!(status, 200);
+
+    let user = ctx.db.get_user(&user_id).await.unwrap().unwrap();
+    assert!(user.password_hash.starts_with("$argon2id$"));
+    assert!(ctx.state.password_hasher.verify(PASSWORD, &user.password_hash).unwrap());
+    assert!(!ctx.state.password_hasher.needs_rehash(&user.password_hash));
+}
+
+#[actix_web::test]
+async fn login_rehashes_when_argon2_parameters_change() {
+    let ctx = context_with(|s| s.with_password_hasher(PasswordHasher::new(
+        Params::new(8 * 1024, 1, 1, None).unwrap(),
+        PasswordPolicy::default(),
+    )));
+    let app = test_app!(ctx);
+    let (user_id, _) = signup(&app, "alice@example.com").await;
+
+    let old_hash = PasswordHasher::default().rehash(PASSWORD).unwrap();
+    ctx.db.update_user_password(&user_id, &old_hash).await.unwrap();
+
+    // A wrong password leaves the old hash alone.
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": "not-the-password" }));
+    let (status, _) = call(&app, request(req, None)).await;
+    assert_eq!(status, 401);
+    assert_eq!(ctx.db.get_user(&user_id).await.unwrap().unwrap().password_hash, old_hash);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }));
+    let (status, _) = call(&app, request(req, None)).await;
+    assert_eq!(status, 200);
+    let user = ctx.db.get_user(&user_id).await.unwrap().unwrap();
+    assert!(user.password_hash.starts_with("$argon2id$v=19$m=8192,t=1,p=1$"));
+}
+
+#[actix_web::test]
+async fn passwords_are_not_truncated_at_72_bytes() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let prefix = "x".repeat(72);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/user")
+        .set_json(json!({ "email": "alice@example.com", "password": format!("{}-first", prefix), "turnstile": "token" }));
+    let (status, _) = call(&app, request(req, None)).await;
+    assert_eq!(status, 200);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": format!("{}-second", prefix) }));
+    let (status, _) = call(&app, request(req, None)).await;
+    assert_eq!(status, 401);
+}
+
+#[actix_web::test]
+async fn breached_passwords_are_rejected_wherever_a_password_is_set() {
+    let ctx = context_with(|s| s.with_password_hasher(PasswordHasher::new(
+        Params::default(),
+        PasswordPolicy::default().with_breached("password123\nqwertyuiop\n".lines()),
+    )));
+    let app = test_app!(ctx);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/user")
+        .set_json(json!({ "email": "bob@example.com", "password": "qwertyuiop", "turnstile": "token" }));
+    let (status, body) = call(&app, request(req, None)).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "WEAK_PASSWORD");
+
+    let (_, session_key) = signup(&app, "alice@example.com").await;
+    let req = TestRequest::put()
+        .uri("/api/v1/user/password")
+        .set_json(json!({ "current_password": PASSWORD, "new_password": "password123" }));
+    let (status, body) = call(&app, request(req, Some(&session_key))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "WEAK_PASSWORD");
+    assert_eq!(body["error"]["message"], "Password appears in a list of breached passwords");
+}
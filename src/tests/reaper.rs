@@ -0,0 +1,82 @@
+use chrono::{Duration, Utc};
+use crate::database::{Repository, Storage};
+use crate::task::{Reaper, ReaperConfig};
+use super::{context, create_file, signup, TestContext};
+
+fn reaper(ctx: &TestContext, dry_run: bool, delete_orphans: bool) -> Reaper {
+    Reaper::new(ctx.db.clone(), ctx.storage.clone(), ReaperConfig {
+        interval: std::time::Duration::from_secs(60),
+        dry_run,
+        delete_orphans,
+        multipart_expire: std::time::Duration::from_secs(3600),
+        max_versions: 0,
+    })
+}
+
+#[actix_web::test]
+async fn reaper_removes_abandoned_uploads_and_finalizes_forgotten_ones() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let abandoned = create_file(&app, &alice, "abandoned.txt").await;
+    let forgotten = create_file(&app, &alice, "forgotten.txt").await;
+    ctx.storage.put_object(&forgotten, b"data".to_vec(), None).await.unwrap();
+
+    let report = reaper(&ctx, false, false).reconcile(Utc::now() + Duration::hours(1)).await.unwrap();
+
+    assert_eq!(report.deleted_files, vec![abandoned.clone()]);
+    assert_eq!(report.finalized_files, vec![forgotten.clone()]);
+    assert!(ctx.db.get_file(&abandoned).await.unwrap().is_none());
+    assert_eq!(ctx.db.get_file(&forgotten).await.unwrap().unwrap().size_bytes, Some(4));
+}
+
+#[actix_web::test]
+async fn reaper_leaves_uploads_inside_the_window() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let pending = create_file(&app, &alice, "pending.txt").await;
+    ctx.storage.put_object("orphan", b"data".to_vec(), None).await.unwrap();
+
+    let report = reaper(&ctx, false, true).reconcile(Utc::now()).await.unwrap();
+
+    assert!(report.deleted_files.is_empty());
+    assert!(report.orphaned_objects.is_empty());
+    assert!(ctx.db.get_file(&pending).await.unwrap().is_some());
+}
+
+#[actix_web::test]
+async fn reaper_dry_run_only_reports() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let abandoned = create_file(&app, &alice, "abandoned.txt").await;
+    ctx.storage.put_object("orphan", b"data".to_vec(), None).await.unwrap();
+
+    let report = reaper(&ctx, true, true).reconcile(Utc::now() + Duration::hours(1)).await.unwrap();
+
+    assert_eq!(report.deleted_files, vec![abandoned.clone()]);
+    assert_eq!(report.orphaned_objects, vec!["orphan".to_string()]);
+    assert!(report.deleted_objects.is_empty());
+    assert!(ctx.db.get_file(&abandoned).await.unwrap().is_some());
+    assert!(ctx.storage.head_object("orphan").await.unwrap().is_some());
+}
+
+#[actix_web::test]
+async fn reaper_deletes_orphaned_objects_when_enabled() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let kept = create_file(&app, &alice, "kept.txt").await;
+    ctx.storage.put_object(&kept, b"kept".to_vec(), None).await.unwrap();
+    ctx.storage.put_object("orphan", b"data".to_vec(), None).await.unwrap();
+
+    let report = reaper(&ctx, false, true).reconcile(Utc::now() + Duration::hours(1)).await.unwrap();
+
+    assert_eq!(report.deleted_objects, vec!["orphan".to_string()]);
+    assert!(ctx.storage.head_object("orphan").await.unwrap().is_none());
+    // The forgotten upload was finalized into a blob, which the orphan scan leaves alone.
+    let kept = ctx.db.get_file(&kept).await.unwrap().unwrap();
+    assert!(kept.blob_sha256.is_some());
+    assert!(ctx.storage.head_object(&kept.object_key()).await.unwrap().is_some());
+}
@@ -0,0 +1,240 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::TestRequest;
+use serde_json::json;
+use chrono::{Duration, Utc};
+use crate::database::Repository;
+use crate::encrypt::SecretToken;
+use crate::middleware::{SessionPolicy, TrustedProxies};
+use crate::middleware::proxy::Cidr;
+use crate::middleware::session::IpBinding;
+use crate::task::SessionPurger;
+use super::{call, context, context_with, request, signup, TestContext, PASSWORD};
+
+#[actix_web::test]
+async fn login_issues_new_session() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, _) = signup(&app, "alice@example.com").await;
+
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }));
+    let (status, body) = call(&app, request(req, None)).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["id"], user_id.as_str());
+    let session_key = body["data"]["session_key"].as_str().unwrap();
+    assert!(ctx.db.get_session(&SecretToken::digest(session_key)).await.unwrap().is_some());
+    assert_eq!(body["data"]["sessions"].as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn login_rejects_wrong_password() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": "not-the-password" }));
+    let (status, body) = call(&app, request(req, None)).await;
+
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "AUTH_FAILED");
+}
+
+#[actix_web::test]
+async fn session_info_requires_bearer_token() {
+    let ctx = context();
+    let app = test_app!(ctx);
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/session"), None)).await;
+
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "AUTH_FAILED");
+}
+
+#[actix_web::test]
+async fn logout_deletes_session() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, session_key) = signup(&app, "alice@example.com").await;
+    let session = ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().unwrap();
+
+    let req = TestRequest::delete().uri(&format!("/api/v1/session/{}", session.id));
+    let (status, _) = call(&app, request(req, Some(&session_key))).await;
+    assert_eq!(status, 200);
+
+    let (status, _) = call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(&session_key))).await;
+    assert_eq!(status, 401);
+}
+
+#[actix_web::test]
+async fn idle_sessions_expire_with_distinct_code() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, session_key) = signup(&app, "alice@example.com").await;
+    ctx.db.update_session_access_time(&SecretToken::digest(&session_key), Utc::now() - Duration::days(8)).await.unwrap();
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/content"), Some(&session_key))).await;
+
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "SESSION_EXPIRED");
+    assert!(ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn sessions_expire_after_absolute_lifetime() {
+    let ctx = context_with(|state| state.with_session_policy(SessionPolicy {
+        absolute_ttl: Some(Duration::zero()),
+        ..SessionPolicy::default()
+    }));
+    let app = test_app!(ctx);
+    let (_, session_key) = signup(&app, "alice@example.com").await;
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(&session_key))).await;
+
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "SESSION_EXPIRED");
+}
+
+#[actix_web::test]
+async fn access_time_is_touched_at_most_once_per_interval() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, session_key) = signup(&app, "alice@example.com").await;
+    let list = || request(TestRequest::get().uri("/api/v1/content"), Some(&session_key));
+
+    let recent = Utc::now() - Duration::seconds(30);
+    ctx.db.update_session_access_time(&SecretToken::digest(&session_key), recent).await.unwrap();
+    assert_eq!(call(&app, list()).await.0, 200);
+    assert_eq!(ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().unwrap().last_accessed_at, recent);
+
+    let stale = Utc::now() - Duration::hours(1);
+    ctx.db.update_session_access_time(&SecretToken::digest(&session_key), stale).await.unwrap();
+    assert_eq!(call(&app, list()).await.0, 200);
+    assert!(ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().unwrap().last_accessed_at > recent);
+}
+
+#[actix_web::test]
+async fn purge_removes_only_expired_sessions() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, idle) = signup(&app, "alice@example.com").await;
+    let (_, active) = signup(&app, "bob@example.com").await;
+    ctx.db.update_session_access_time(&SecretToken::digest(&idle), Utc::now() - Duration::days(8)).await.unwrap();
+
+    let purger = SessionPurger::new(ctx.db.clone(), SessionPolicy::default());
+    assert_eq!(purger.purge(Utc::now()).await.unwrap(), 1);
+    assert!(ctx.db.get_session(&SecretToken::digest(&idle)).await.unwrap().is_none());
+    assert!(ctx.db.get_session(&SecretToken::digest(&active)).await.unwrap().is_some());
+
+    assert_eq!(purger.purge(Utc::now() + Duration::days(31)).await.unwrap(), 1);
+    assert!(ctx.db.get_session(&SecretToken::digest(&active)).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn session_keys_are_stored_as_digests_and_never_echoed() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, session_key) = signup(&app, "alice@example.com").await;
+
+    assert_eq!(session_key.len(), 64);
+    assert!(ctx.db.get_session(&session_key).await.unwrap().is_none());
+    let session = ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().unwrap();
+    assert_ne!(session.key_hash, session_key);
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(&session_key))).await;
+    assert_eq!(status, 200);
+    assert!(body["data"].get("session_key").is_none());
+    assert_eq!(body["data"]["session_id"], session.id.as_str());
+
+    // Presenting the stored digest itself as a bearer token must not authenticate.
+    let (status, _) = call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(&session.key_hash))).await;
+    assert_eq!(status, 401);
+}
+
+fn behind_proxy(binding: IpBinding) -> TestContext {
+    context_with(|state| state
+        .with_session_policy(SessionPolicy { ip_binding: binding, ..SessionPolicy::default() })
+        .with_trusted_proxies(TrustedProxies::new(vec![
+            "127.0.0.1/32".parse::<Cidr>().unwrap(),
+            "10.0.0.0/8".parse::<Cidr>().unwrap(),
+        ])))
+}
+
+fn from_client(req: TestRequest, client: &str, session_key: Option<&str>) -> Request {
+    request(req.insert_header(("X-Forwarded-For", client)), session_key)
+}
+
+async fn login_from<S, B>(app: &S, req: Request) -> String
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let (status, body) = call(app, req).await;
+    assert_eq!(status, 200, "login failed: {}", body);
+    body["data"]["session_key"].as_str().unwrap().to_string()
+}
+
+fn login_request() -> TestRequest {
+    TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }))
+}
+
+#[actix_web::test]
+async fn forwarding_headers_from_untrusted_peers_are_ignored() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    let session_key = login_from(&app, from_client(login_request(), "203.0.113.9", None)).await;
+
+    let session = ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().unwrap();
+    assert_eq!(session.ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[actix_web::test]
+async fn trusted_proxies_resolve_the_rightmost_untrusted_hop() {
+    let ctx = behind_proxy(IpBinding::Strict);
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    let spoofed = from_client(login_request(), "1.2.3.4, 198.51.100.7, 10.0.0.2", None);
+    let session_key = login_from(&app, spoofed).await;
+    let session = ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().unwrap();
+    assert_eq!(session.ip_address.as_deref(), Some("198.51.100.7"));
+
+    let forwarded = request(
+        login_request()
+            .insert_header(("Forwarded", "for=192.0.2.60;proto=http, for=\"[2001:db8::17]:4711\""))
+            .insert_header(("X-Forwarded-For", "198.51.100.7")),
+        None,
+    );
+    let session_key = login_from(&app, forwarded).await;
+    let session = ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().unwrap();
+    assert_eq!(session.ip_address.as_deref(), Some("2001:db8::17"));
+}
+
+#[actix_web::test]
+async fn ip_binding_policy_controls_address_changes() {
+    let list = || TestRequest::get().uri("/api/v1/content");
+
+    for (binding, same_subnet, other_subnet) in [
+        (IpBinding::Strict, 401, 401),
+        (IpBinding::SameSubnet, 200, 401),
+        (IpBinding::Off, 200, 200),
+    ] {
+        let ctx = behind_proxy(binding);
+        let app = test_app!(ctx);
+        signup(&app, "alice@example.com").await;
+        let session_key = login_from(&app, from_client(login_request(), "198.51.100.7", None)).await;
+
+        assert_eq!(call(&app, from_client(list(), "198.51.100.7", Some(&session_key))).await.0, 200);
+        assert_eq!(call(&app, from_client(list(), "198.51.100.200", Some(&session_key))).await.0, same_subnet, "{:?}", binding);
+        assert_eq!(call(&app, from_client(list(), "198.51.101.7", Some(&session_key))).await.0, other_subnet, "{:?}", binding);
+    }
+}
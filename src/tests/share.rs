@@ -0,0 +1,126 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::{self, TestRequest};
+use serde_json::{json, Value};
+use crate::database::Repository;
+use super::{call, context, create_file, request, signup, upload_file};
+
+async fn create_link<S, B>(app: &S, session_key: &str, file_id: &str, body: Value) -> (u16, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/links", file_id))
+        .set_json(body);
+    call(app, request(req, Some(session_key))).await
+}
+
+fn share_path(body: &Value) -> String {
+    format!("/s/{}", body["data"]["token"].as_str().unwrap())
+}
+
+#[actix_web::test]
+async fn share_link_redirects_without_authentication() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "report.pdf", b"report").await;
+
+    let (status, body) = create_link(&app, &alice, &file_id, json!({})).await;
+    assert_eq!(status, 201, "{}", body);
+    assert!(body["data"]["url"].as_str().unwrap().ends_with(&share_path(&body)));
+    assert_eq!(body["data"]["has_password"], false);
+
+    let res = test::call_service(&app, request(TestRequest::get().uri(&share_path(&body)), None)).await;
+    assert_eq!(res.status().as_u16(), 302);
+    assert_eq!(
+        res.headers().get("Location").unwrap().to_str().unwrap(),
+        format!("memory://download/{}", ctx.db.get_file(&file_id).await.unwrap().unwrap().object_key())
+    );
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/s/not-a-token"), None)).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "SHARE_NOT_FOUND");
+}
+
+#[actix_web::test]
+async fn share_link_enforces_download_limit_and_revocation() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "report.pdf", b"report").await;
+
+    let (_, limited) = create_link(&app, &alice, &file_id, json!({ "max_downloads": 1 })).await;
+    let open = || request(TestRequest::get().uri(&share_path(&limited)), None);
+    assert_eq!(test::call_service(&app, open()).await.status().as_u16(), 302);
+    let (status, body) = call(&app, open()).await;
+    assert_eq!(status, 410);
+    assert_eq!(body["error"]["code"], "SHARE_EXPIRED");
+
+    let (_, link) = create_link(&app, &alice, &file_id, json!({})).await;
+    let link_id = link["data"]["id"].as_str().unwrap();
+    let revoke = || TestRequest::delete().uri(&format!("/api/v1/content/{}/links/{}", file_id, link_id));
+    let (status, _) = call(&app, request(revoke(), Some(&alice))).await;
+    assert_eq!(status, 200);
+    let (status, _) = call(&app, request(revoke(), Some(&alice))).await;
+    assert_eq!(status, 404);
+
+    let (status, _) = call(&app, request(TestRequest::get().uri(&share_path(&link)), None)).await;
+    assert_eq!(status, 404);
+
+    let list = TestRequest::get().uri(&format!("/api/v1/content/{}/links", file_id));
+    let (status, body) = call(&app, request(list, Some(&alice))).await;
+    assert_eq!(status, 200);
+    let links = body["data"]["links"].as_array().unwrap();
+    assert_eq!(links.len(), 2);
+    assert_eq!(links[0]["download_count"], 1);
+    assert!(links[1]["revoked_at"].is_string());
+    assert!(links.iter().all(|l| l.get("token").is_none()));
+}
+
+#[actix_web::test]
+async fn share_link_password_and_expiry() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "report.pdf", b"report").await;
+
+    let (status, body) = create_link(&app, &alice, &file_id, json!({ "password": "partner-secret" })).await;
+    assert_eq!(status, 201);
+    assert_eq!(body["data"]["has_password"], true);
+    let path = share_path(&body);
+
+    let (status, body) = call(&app, request(TestRequest::get().uri(&path), None)).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "SHARE_PASSWORD_REQUIRED");
+
+    let wrong = TestRequest::get().uri(&path).insert_header(("X-Share-Password", "guess-guess"));
+    let (status, _) = call(&app, request(wrong, None)).await;
+    assert_eq!(status, 403);
+
+    let form = TestRequest::post().uri(&path).set_form([("password", "partner-secret")]);
+    assert_eq!(test::call_service(&app, request(form, None)).await.status().as_u16(), 303);
+
+    let expired = json!({ "expires_at": chrono::Utc::now() - chrono::Duration::minutes(1) });
+    let (status, _) = create_link(&app, &alice, &file_id, expired).await;
+    assert_eq!(status, 400);
+}
+
+#[actix_web::test]
+async fn share_links_are_owner_only_and_need_completed_upload() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "report.pdf", b"report").await;
+    let pending = create_file(&app, &alice, "pending.txt").await;
+
+    let (status, _) = create_link(&app, &bob, &file_id, json!({})).await;
+    assert_eq!(status, 403);
+
+    let (status, body) = create_link(&app, &alice, &pending, json!({})).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "UPLOAD_INCOMPLETE");
+}
@@ -0,0 +1,115 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::TestRequest;
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use crate::database::Repository;
+use crate::database::model::AccessToken;
+use crate::encrypt::SecretToken;
+use crate::middleware::ACCESS_TOKEN_PREFIX;
+use super::{call, context, request, signup, upload_file};
+
+async fn create_token<S, B>(app: &S, session_key: &str, scopes: &[&str]) -> (String, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/v1/tokens")
+        .set_json(json!({ "name": "ci", "scopes": scopes }));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 201, "create token failed: {}", body);
+
+    (body["data"]["token"].as_str().unwrap().to_string(), body["data"].clone())
+}
+
+#[actix_web::test]
+async fn access_tokens_are_hashed_and_listed_without_secret() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+
+    let (token, data) = create_token(&app, &alice, &["files:write", "files:read", "files:write"]).await;
+    assert!(token.starts_with(ACCESS_TOKEN_PREFIX));
+    assert_eq!(data["scopes"], json!(["files:write", "files:read"]));
+
+    let stored = ctx.db.get_access_token_by_hash(&SecretToken::digest(&token)).await.unwrap().unwrap();
+    assert_eq!(stored.user_id, user_id);
+
+    let (status, body) = call(&app, request(TestRequest::get().uri("/api/v1/tokens"), Some(&alice))).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["tokens"].as_array().unwrap().len(), 1);
+    assert!(body["data"]["tokens"][0].get("token").is_none());
+
+    let bad = TestRequest::post()
+        .uri("/api/v1/tokens")
+        .set_json(json!({ "name": "ci", "scopes": ["files:delete"] }));
+    let (status, body) = call(&app, request(bad, Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_SCOPE");
+}
+
+#[actix_web::test]
+async fn access_token_scopes_are_enforced_per_handler() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "build.tar", b"artifact").await;
+    let (reader, _) = create_token(&app, &alice, &["files:read"]).await;
+    let (writer, _) = create_token(&app, &alice, &["files:write"]).await;
+
+    let list = || TestRequest::get().uri("/api/v1/content");
+    let upload = || TestRequest::post().uri("/api/v1/content").set_json(json!({ "filename": "next.tar" }));
+    let links = || TestRequest::post().uri(&format!("/api/v1/content/{}/links", file_id)).set_json(json!({}));
+
+    assert_eq!(call(&app, request(list(), Some(&reader))).await.0, 200);
+    let (status, body) = call(&app, request(upload(), Some(&reader))).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "INSUFFICIENT_SCOPE");
+
+    assert_eq!(call(&app, request(upload(), Some(&writer))).await.0, 201);
+    assert_eq!(call(&app, request(list(), Some(&writer))).await.0, 403);
+    assert_eq!(call(&app, request(links(), Some(&writer))).await.0, 403);
+    assert_eq!(call(&app, request(links(), Some(&alice))).await.0, 201);
+
+    // Tokens are not bound to the address they were created from.
+    let elsewhere = list()
+        .peer_addr("198.51.100.20:50000".parse().unwrap())
+        .insert_header(("Authorization", format!("Bearer {}", reader)))
+        .to_request();
+    assert_eq!(call(&app, elsewhere).await.0, 200);
+}
+
+#[actix_web::test]
+async fn access_tokens_expire_and_can_be_revoked() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+    let (token, data) = create_token(&app, &alice, &["files:read"]).await;
+    let list = || TestRequest::get().uri("/api/v1/content");
+
+    // Access tokens cannot manage credentials themselves.
+    assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/tokens"), Some(&token))).await.0, 401);
+    assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(&token))).await.0, 401);
+
+    let revoke = TestRequest::delete().uri(&format!("/api/v1/tokens/{}", data["id"].as_str().unwrap()));
+    assert_eq!(call(&app, request(revoke, Some(&alice))).await.0, 200);
+    assert_eq!(call(&app, request(list(), Some(&token))).await.0, 401);
+
+    let expired = format!("{}{}", ACCESS_TOKEN_PREFIX, SecretToken::generate());
+    ctx.db.create_access_token(&AccessToken {
+        id: "expired".to_string(),
+        user_id,
+        name: "old".to_string(),
+        token_hash: SecretToken::digest(&expired),
+        scopes: "files:read".to_string(),
+        expires_at: Some(Utc::now() - Duration::minutes(1)),
+        last_used_at: None,
+        created_at: Utc::now() - Duration::days(1),
+    }).await.unwrap();
+
+    let (status, body) = call(&app, request(list(), Some(&expired))).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "TOKEN_EXPIRED");
+}
@@ -0,0 +1,209 @@
+use actix_http::Request;
+use actix_web::test::{self, TestRequest};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{Duration, Utc};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use crate::database::{Repository, Storage};
+use crate::service::tus::TusConfig;
+use crate::task::{Reaper, ReaperConfig};
+use super::{call, context_with, request, signup, TestContext};
+
+fn tus_context() -> TestContext {
+    context_with(|state| state.with_tus(TusConfig {
+        max_size: 1000,
+        part_size: 4,
+        lock_timeout: std::time::Duration::from_secs(60),
+    }))
+}
+
+fn tus_request(req: TestRequest, session_key: Option<&str>) -> Request {
+    request(req.insert_header(("Tus-Resumable", "1.0.0")), session_key)
+}
+
+fn patch(location: &str, offset: i64, body: &'static [u8]) -> TestRequest {
+    TestRequest::patch()
+        .uri(location)
+        .insert_header(("Content-Type", "application/offset+octet-stream"))
+        .insert_header(("Upload-Offset", offset.to_string()))
+        .set_payload(body)
+}
+
+fn header(res: &actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>, name: &str) -> Option<String> {
+    res.headers().get(name).map(|value| value.to_str().unwrap().to_string())
+}
+
+#[actix_web::test]
+async fn tus_upload_resumes_across_patches_and_creates_file() {
+    let ctx = tus_context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+
+    let res = test::call_service(&app, request(TestRequest::default().method(actix_web::http::Method::OPTIONS).uri("/api/v1/tus"), None)).await;
+    assert_eq!(res.status(), 204);
+    assert_eq!(header(&res, "Tus-Version").as_deref(), Some("1.0.0"));
+    assert_eq!(header(&res, "Tus-Extension").as_deref(), Some("creation,termination,checksum"));
+    assert_eq!(header(&res, "Tus-Max-Size").as_deref(), Some("1000"));
+
+    let metadata = format!("filename {},filetype {}", STANDARD.encode("notes.txt"), STANDARD.encode("text/plain"));
+    let req = TestRequest::post()
+        .uri("/api/v1/tus")
+        .insert_header(("Upload-Length", "11"))
+        .insert_header(("Upload-Metadata", metadata));
+    let res = test::call_service(&app, tus_request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 201);
+    assert_eq!(header(&res, "Tus-Resumable").as_deref(), Some("1.0.0"));
+    let location = header(&res, "Location").unwrap();
+    let id = location.rsplit('/').next().unwrap().to_string();
+
+    let res = test::call_service(&app, tus_request(patch(&location, 0, b"hello "), Some(&alice))).await;
+    assert_eq!(res.status(), 204);
+    assert_eq!(header(&res, "Upload-Offset").as_deref(), Some("6"));
+
+    // A client that missed that response retries from the start and is told where to resume.
+    let (status, body) = call(&app, tus_request(patch(&location, 0, b"hello "), Some(&alice))).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "OFFSET_MISMATCH");
+
+    // A retry that took the lock with a stale snapshot cannot move the offset back.
+    let now = Utc::now();
+    assert!(ctx.db.lock_tus_upload(&id, "stale", now, now + Duration::minutes(1)).await.unwrap());
+    assert!(!ctx.db.advance_tus_upload(&id, "stale", 0, 4).await.unwrap());
+    ctx.db.unlock_tus_upload(&id, "stale").await.unwrap();
+
+    let res = test::call_service(&app, tus_request(TestRequest::default().method(actix_web::http::Method::HEAD).uri(&location), Some(&alice))).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(header(&res, "Upload-Offset").as_deref(), Some("6"));
+    assert_eq!(header(&res, "Upload-Length").as_deref(), Some("11"));
+    assert_eq!(header(&res, "Cache-Control").as_deref(), Some("no-store"));
+    assert!(ctx.db.get_file(&id).await.unwrap().is_none());
+
+    let checksum = format!("sha1 {}", STANDARD.encode(Sha1::digest(b"world")));
+    let req = patch(&location, 6, b"world").insert_header(("Upload-Checksum", checksum));
+    let res = test::call_service(&app, tus_request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 204);
+    assert_eq!(header(&res, "Upload-Offset").as_deref(), Some("11"));
+
+    let file = ctx.db.get_file(&id).await.unwrap().unwrap();
+    assert_eq!(file.filename, "notes.txt");
+    assert_eq!(file.upload_state, "complete");
+    assert_eq!(file.size_bytes, Some(11));
+    assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(ctx.storage.get_object(&file.object_key()).await.unwrap().unwrap(), b"hello world");
+    assert!(ctx.db.get_tus_upload(&id).await.unwrap().is_none());
+    assert_eq!(ctx.storage.multipart_upload_count(), 0);
+    let keys: Vec<String> = ctx.storage.list_objects(None).await.unwrap().objects.into_iter().map(|o| o.key).collect();
+    assert_eq!(keys, vec![file.object_key()]);
+
+    let res = test::call_service(&app, tus_request(TestRequest::default().method(actix_web::http::Method::HEAD).uri(&location), Some(&alice))).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(header(&res, "Upload-Offset").as_deref(), Some("11"));
+}
+
+#[actix_web::test]
+async fn tus_rejects_bad_checksums_and_foreign_requests_then_terminates() {
+    let ctx = tus_context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+
+    let req = TestRequest::post().uri("/api/v1/tus").insert_header(("Upload-Length", "10"));
+    let res = test::call_service(&app, request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 412);
+    assert_eq!(header(&res, "Tus-Version").as_deref(), Some("1.0.0"));
+
+    let req = TestRequest::post().uri("/api/v1/tus").insert_header(("Upload-Length", "1001"));
+    let (status, body) = call(&app, tus_request(req, Some(&alice))).await;
+    assert_eq!(status, 413);
+    assert_eq!(body["error"]["code"], "UPLOAD_TOO_LARGE");
+
+    let req = TestRequest::post().uri("/api/v1/tus").insert_header(("Upload-Length", "10"));
+    let res = test::call_service(&app, tus_request(req, Some(&alice))).await;
+    assert_eq!(res.status(), 201);
+    let location = header(&res, "Location").unwrap();
+    let id = location.rsplit('/').next().unwrap().to_string();
+
+    let checksum = format!("sha256 {}", STANDARD.encode(Sha256::digest(b"other")));
+    let req = patch(&location, 0, b"abcde").insert_header(("Upload-Checksum", checksum));
+    let (status, body) = call(&app, tus_request(req, Some(&alice))).await;
+    assert_eq!(status, 460);
+    assert_eq!(body["error"]["code"], "CHECKSUM_MISMATCH");
+    assert_eq!(ctx.db.get_tus_upload(&id).await.unwrap().unwrap().upload_offset, 0);
+
+    let req = TestRequest::patch()
+        .uri(&location)
+        .insert_header(("Content-Type", "application/octet-stream"))
+        .insert_header(("Upload-Offset", "0"))
+        .set_payload(&b"abcde"[..]);
+    let (status, _) = call(&app, tus_request(req, Some(&alice))).await;
+    assert_eq!(status, 415);
+
+    let (status, body) = call(&app, tus_request(patch(&location, 0, b"abcde"), Some(&bob))).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "UPLOAD_NOT_FOUND");
+
+    let (status, _) = call(&app, tus_request(patch(&location, 0, b"abcde"), Some(&alice))).await;
+    assert_eq!(status, 204);
+    let upload = ctx.db.get_tus_upload(&id).await.unwrap().unwrap();
+    assert_eq!(upload.upload_offset, 5);
+    let tail = upload.tail_key(2);
+    assert_eq!(ctx.storage.get_object(&tail).await.unwrap().unwrap(), b"e");
+
+    let (status, body) = call(&app, tus_request(patch(&location, 5, b"abcdefghij"), Some(&alice))).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "UPLOAD_LENGTH_EXCEEDED");
+
+    // Another request mid-PATCH holds the lease.
+    let now = Utc::now();
+    assert!(ctx.db.lock_tus_upload(&id, "other-request", now, now + Duration::seconds(60)).await.unwrap());
+    let (status, body) = call(&app, tus_request(patch(&location, 5, b"fg"), Some(&alice))).await;
+    assert_eq!(status, 423);
+    assert_eq!(body["error"]["code"], "UPLOAD_LOCKED");
+    ctx.db.unlock_tus_upload(&id, "other-request").await.unwrap();
+
+    let (status, _) = call(&app, tus_request(TestRequest::delete().uri(&location), Some(&bob))).await;
+    assert_eq!(status, 404);
+    let (status, _) = call(&app, tus_request(TestRequest::delete().uri(&location), Some(&alice))).await;
+    assert_eq!(status, 204);
+
+    assert!(ctx.db.get_tus_upload(&id).await.unwrap().is_none());
+    assert!(ctx.storage.get_object(&tail).await.unwrap().is_none());
+    assert_eq!(ctx.storage.multipart_upload_count(), 0);
+    let res = test::call_service(&app, tus_request(TestRequest::default().method(actix_web::http::Method::HEAD).uri(&location), Some(&alice))).await;
+    assert_eq!(res.status(), 404);
+}
+
+#[actix_web::test]
+async fn reaper_aborts_stale_tus_uploads_without_flagging_staging_objects() {
+    let ctx = tus_context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+
+    let req = TestRequest::post().uri("/api/v1/tus").insert_header(("Upload-Length", "10"));
+    let res = test::call_service(&app, tus_request(req, Some(&alice))).await;
+    let location = header(&res, "Location").unwrap();
+    let id = location.rsplit('/').next().unwrap().to_string();
+    let (status, _) = call(&app, tus_request(patch(&location, 0, b"abcdef"), Some(&alice))).await;
+    assert_eq!(status, 204);
+    let tail = ctx.db.get_tus_upload(&id).await.unwrap().unwrap().tail_key(2);
+
+    let reaper = Reaper::new(ctx.db.clone(), ctx.storage.clone(), ReaperConfig {
+        interval: std::time::Duration::from_secs(60),
+        dry_run: false,
+        delete_orphans: true,
+        multipart_expire: std::time::Duration::from_secs(24 * 3600),
+        max_versions: 0,
+    });
+
+    let report = reaper.reconcile(Utc::now() + Duration::hours(1)).await.unwrap();
+    assert!(report.aborted_uploads.is_empty());
+    assert!(report.orphaned_objects.is_empty());
+    assert!(ctx.storage.get_object(&tail).await.unwrap().is_some());
+
+    let report = reaper.reconcile(Utc::now() + Duration::days(2)).await.unwrap();
+    assert_eq!(report.aborted_uploads, vec![id.clone()]);
+    assert!(ctx.db.get_tus_upload(&id).await.unwrap().is_none());
+    assert!(ctx.storage.get_object(&tail).await.unwrap().is_none());
+    assert_eq!(ctx.storage.multipart_upload_count(), 0);
+}
@@ -0,0 +1,164 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::TestRequest;
+use chrono::Utc;
+use serde_json::{json, Value};
+use crate::database::Repository;
+use crate::encrypt::{SecretToken, Totp};
+use super::{call, context, request, signup, PASSWORD};
+
+fn code(totp: &Totp, offset: i64) -> String {
+    totp.code_at_step(Totp::step_at(Utc::now()) + offset)
+}
+
+/// Enrolls and confirms TOTP for the session's user, returning the authenticator and recovery codes.
+async fn enable_totp<S, B>(app: &S, session_key: &str) -> (Totp, Vec<String>)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let (status, body) = call(app, request(TestRequest::post().uri("/api/v1/user/2fa/totp"), Some(session_key))).await;
+    assert_eq!(status, 201, "enroll failed: {}", body);
+    let totp = Totp::from_base32(body["data"]["secret"].as_str().unwrap()).unwrap();
+
+    let confirm = TestRequest::post()
+        .uri("/api/v1/user/2fa/totp/confirm")
+        .set_json(json!({ "code": code(&totp, 0) }));
+    let (status, body) = call(app, request(confirm, Some(session_key))).await;
+    assert_eq!(status, 200, "confirm failed: {}", body);
+
+    let recovery_codes = body["data"]["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c.as_str().unwrap().to_string())
+        .collect();
+    (totp, recovery_codes)
+}
+
+async fn password_step<S, B>(app: &S) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/v1/session")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD }));
+    let (status, body) = call(app, request(req, None)).await;
+    assert_eq!(status, 200, "login failed: {}", body);
+    body
+}
+
+fn second_step(challenge: &Value, factor: Value) -> Request {
+    let mut body = factor;
+    body["challenge_token"] = challenge["data"]["challenge_token"].clone();
+    request(TestRequest::post().uri("/api/v1/session/2fa").set_json(body), None)
+}
+
+#[actix_web::test]
+async fn totp_enrollment_requires_confirmation() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+
+    let (status, body) = call(&app, request(TestRequest::post().uri("/api/v1/user/2fa/totp"), Some(&alice))).await;
+    assert_eq!(status, 201);
+    let secret = body["data"]["secret"].as_str().unwrap();
+    let uri = body["data"]["otpauth_uri"].as_str().unwrap();
+    assert!(uri.starts_with("otpauth://totp/Cloud%20File%20Storage%3Aalice%40example.com?"));
+    assert!(uri.contains(&format!("secret={}", secret)));
+
+    let totp = Totp::from_base32(secret).unwrap();
+    let stale = TestRequest::post()
+        .uri("/api/v1/user/2fa/totp/confirm")
+        .set_json(json!({ "code": code(&totp, -5) }));
+    assert_eq!(call(&app, request(stale, Some(&alice))).await.0, 401);
+    assert!(ctx.db.get_totp(&user_id).await.unwrap().unwrap().enabled_at.is_none());
+
+    // A password login still goes straight through while enrollment is pending.
+    assert!(password_step(&app).await["data"]["session_key"].is_string());
+
+    let (_, recovery_codes) = enable_totp(&app, &alice).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    let (_, body) = call(&app, request(TestRequest::get().uri("/api/v1/user/2fa"), Some(&alice))).await;
+    assert_eq!(body["data"]["totp_enabled"], true);
+    assert_eq!(body["data"]["recovery_codes_remaining"], 10);
+
+    let (status, body) = call(&app, request(TestRequest::post().uri("/api/v1/user/2fa/totp"), Some(&alice))).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "TOTP_ALREADY_ENABLED");
+}
+
+#[actix_web::test]
+async fn login_with_totp_exchanges_challenge_for_session() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+    let (totp, _) = enable_totp(&app, &alice).await;
+
+    let challenge = password_step(&app).await;
+    assert_eq!(challenge["data"]["two_factor_required"], true);
+    assert!(challenge["data"].get("session_key").is_none());
+    assert_eq!(ctx.db.get_user_sessions(&user_id).await.unwrap().len(), 1);
+
+    // The confirmation already used its step, so that code cannot be replayed.
+    let used = ctx.db.get_totp(&user_id).await.unwrap().unwrap().last_used_step.unwrap();
+    let (status, body) = call(&app, second_step(&challenge, json!({ "code": totp.code_at_step(used) }))).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "TOTP_INVALID");
+
+    let next = totp.code_at_step(used + 1);
+    let (status, body) = call(&app, second_step(&challenge, json!({ "code": next }))).await;
+    assert_eq!(status, 200, "{}", body);
+    let session_key = body["data"]["session_key"].as_str().unwrap();
+    assert_eq!(call(&app, request(TestRequest::get().uri("/api/v1/session"), Some(session_key))).await.0, 200);
+
+    // Challenges are single use.
+    let (status, body) = call(&app, second_step(&challenge, json!({ "code": totp.code_at_step(used + 1) }))).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "CHALLENGE_INVALID");
+}
+
+#[actix_web::test]
+async fn recovery_codes_work_once_and_challenges_lock_after_failures() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (user_id, alice) = signup(&app, "alice@example.com").await;
+    let (totp, recovery_codes) = enable_totp(&app, &alice).await;
+
+    let challenge = password_step(&app).await;
+    let recovery = recovery_codes[0].to_uppercase();
+    assert_eq!(call(&app, second_step(&challenge, json!({ "recovery_code": recovery }))).await.0, 200);
+    assert_eq!(ctx.db.count_recovery_codes(&user_id).await.unwrap(), 9);
+
+    let challenge = password_step(&app).await;
+    for _ in 0..5 {
+        let (status, _) = call(&app, second_step(&challenge, json!({ "recovery_code": recovery_codes[0] }))).await;
+        assert_eq!(status, 401);
+    }
+    let (status, body) = call(&app, second_step(&challenge, json!({ "recovery_code": recovery_codes[1] }))).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "CHALLENGE_INVALID");
+
+    // Once parallel guesses have spent the budget, even a correct code is no longer checked.
+    let challenge = password_step(&app).await;
+    let token_hash = SecretToken::digest(challenge["data"]["challenge_token"].as_str().unwrap());
+    let challenge_id = ctx.db.get_login_challenge(&token_hash).await.unwrap().unwrap().id;
+    for _ in 0..5 {
+        assert!(ctx.db.increment_login_challenge_attempts(&challenge_id, 5).await.unwrap());
+    }
+    assert!(!ctx.db.increment_login_challenge_attempts(&challenge_id, 5).await.unwrap());
+    let (status, body) = call(&app, second_step(&challenge, json!({ "recovery_code": recovery_codes[1] }))).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "CHALLENGE_INVALID");
+    assert_eq!(ctx.db.count_recovery_codes(&user_id).await.unwrap(), 9);
+
+    let disable = TestRequest::delete()
+        .uri("/api/v1/user/2fa/totp")
+        .set_json(json!({ "code": code(&totp, 1) }));
+    assert_eq!(call(&app, request(disable, Some(&alice))).await.0, 200);
+    assert!(ctx.db.get_totp(&user_id).await.unwrap().is_none());
+    assert!(password_step(&app).await["data"]["session_key"].is_string());
+}
@@ -0,0 +1,62 @@
+use actix_web::test::TestRequest;
+use serde_json::json;
+use crate::database::Repository;
+use crate::encrypt::SecretToken;
+use super::{call, context, context_with_captcha, request, signup, PASSWORD};
+
+#[actix_web::test]
+async fn signup_creates_user_and_session() {
+    let ctx = context();
+    let app = test_app!(ctx);
+
+    let (user_id, session_key) = signup(&app, "alice@example.com").await;
+
+    let user = ctx.db.get_user(&user_id).await.unwrap().unwrap();
+    assert_eq!(user.email, "alice@example.com");
+    assert_ne!(user.password_hash, PASSWORD);
+    assert!(ctx.db.get_session(&SecretToken::digest(&session_key)).await.unwrap().is_some());
+}
+
+#[actix_web::test]
+async fn signup_rejects_duplicate_email() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    signup(&app, "alice@example.com").await;
+
+    let req = TestRequest::post()
+        .uri("/api/v1/user")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD, "turnstile": "token" }));
+    let (status, body) = call(&app, request(req, None)).await;
+
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "EMAIL_EXISTS");
+}
+
+#[actix_web::test]
+async fn signup_rejects_weak_password() {
+    let ctx = context();
+    let app = test_app!(ctx);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/user")
+        .set_json(json!({ "email": "alice@example.com", "password": "short", "turnstile": "token" }));
+    let (status, body) = call(&app, request(req, None)).await;
+
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "WEAK_PASSWORD");
+}
+
+#[actix_web::test]
+async fn signup_rejects_failed_turnstile() {
+    let ctx = context_with_captcha(false);
+    let app = test_app!(ctx);
+
+    let req = TestRequest::post()
+        .uri("/api/v1/user")
+        .set_json(json!({ "email": "alice@example.com", "password": PASSWORD, "turnstile": "token" }));
+    let (status, body) = call(&app, request(req, None)).await;
+
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "TURNSTILE_INVALID");
+    assert!(ctx.db.get_user_by_email("alice@example.com").await.unwrap().is_none());
+}
@@ -0,0 +1,182 @@
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::test::{self, TestRequest};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use crate::database::{Repository, Storage};
+use crate::task::{Reaper, ReaperConfig};
+use super::{call, context, create_file, request, signup, upload_file, TestContext};
+
+async fn replace<S, B>(app: &S, ctx: &TestContext, session_key: &str, file_id: &str, body: &[u8]) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post().uri(&format!("/api/v1/content/{}/replace", file_id));
+    let (status, body_json) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 200, "replace failed: {}", body_json);
+    ctx.storage.put_object(file_id, body.to_vec(), Some("text/plain")).await.unwrap();
+
+    let req = TestRequest::post().uri(&format!("/api/v1/content/{}/complete", file_id));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 200, "complete upload failed: {}", body);
+    body
+}
+
+async fn versions<S, B>(app: &S, session_key: &str, file_id: &str) -> Vec<Value>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::get().uri(&format!("/api/v1/content/{}/versions", file_id));
+    let (status, body) = call(app, request(req, Some(session_key))).await;
+    assert_eq!(status, 200, "list versions failed: {}", body);
+    body["data"]["versions"].as_array().unwrap().clone()
+}
+
+#[actix_web::test]
+async fn uploads_create_versions_that_can_be_downloaded_and_restored() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (alice_id, alice) = signup(&app, "alice@example.com").await;
+    let (_, bob) = signup(&app, "bob@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "notes.txt", b"first draft").await;
+    replace(&app, &ctx, &alice, &file_id, b"second draft").await;
+
+    let listed = versions(&app, &alice, &file_id).await;
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["version"], 2);
+    assert_eq!(listed[0]["current"], true);
+    assert_eq!(listed[0]["size_bytes"], 12);
+    assert_eq!(listed[0]["uploaded_by"], alice_id);
+    assert_eq!(listed[1]["version"], 1);
+    assert_eq!(listed[1]["current"], false);
+
+    let uri = format!("/api/v1/content/{}/versions/1/data", file_id);
+    let res = test::call_service(&app, request(TestRequest::get().uri(&uri), Some(&alice))).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(test::read_body(res).await, "first draft");
+    let (status, _) = call(&app, request(TestRequest::get().uri(&uri), Some(&bob))).await;
+    assert_eq!(status, 403);
+    let missing = format!("/api/v1/content/{}/versions/9/data", file_id);
+    let (status, body) = call(&app, request(TestRequest::get().uri(&missing), Some(&alice))).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "VERSION_NOT_FOUND");
+
+    let req = TestRequest::post().uri(&format!("/api/v1/content/{}/versions/1/restore", file_id));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["data"]["size_bytes"], 11);
+
+    let listed = versions(&app, &alice, &file_id).await;
+    assert_eq!(listed.len(), 3);
+    assert_eq!(listed[0]["version"], 3);
+    assert_eq!(listed[0]["sha256"], listed[2]["sha256"]);
+    let res = test::call_service(&app, request(TestRequest::get().uri(&format!("/api/v1/content/{}/data", file_id)), Some(&alice))).await;
+    assert_eq!(test::read_body(res).await, "first draft");
+    // Both versions with the first draft share one blob.
+    let sha256 = listed[0]["sha256"].as_str().unwrap();
+    assert_eq!(ctx.db.get_blob(sha256).await.unwrap().unwrap().ref_count, 2);
+}
+
+#[actix_web::test]
+async fn deleting_versions_keeps_the_current_one_and_shared_content() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "notes.txt", b"shared").await;
+    let other = upload_file(&app, &ctx, &alice, "copy.txt", b"shared").await;
+    replace(&app, &ctx, &alice, &file_id, b"unique").await;
+    replace(&app, &ctx, &alice, &file_id, b"latest").await;
+    let listed = versions(&app, &alice, &file_id).await;
+    let shared = listed[2]["sha256"].as_str().unwrap().to_string();
+    let unique = listed[1]["sha256"].as_str().unwrap().to_string();
+
+    let delete = |version: i32| request(TestRequest::delete().uri(&format!("/api/v1/content/{}/versions/{}", file_id, version)), Some(&alice));
+    let (status, body) = call(&app, delete(3)).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "CURRENT_VERSION");
+
+    let (status, _) = call(&app, delete(2)).await;
+    assert_eq!(status, 200);
+    assert!(ctx.db.get_blob(&unique).await.unwrap().is_none());
+    assert!(ctx.storage.head_object(&format!("blobs/{}", unique)).await.unwrap().is_none());
+
+    // The other file still holds the first version's content.
+    let (status, _) = call(&app, delete(1)).await;
+    assert_eq!(status, 200);
+    assert_eq!(ctx.db.get_blob(&shared).await.unwrap().unwrap().ref_count, 1);
+    let (status, _) = call(&app, delete(1)).await;
+    assert_eq!(status, 404);
+
+    let listed = versions(&app, &alice, &file_id).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["version"], 3);
+    assert_eq!(versions(&app, &alice, &other).await.len(), 1);
+}
+
+#[actix_web::test]
+async fn reaper_prunes_versions_past_the_retention_limit() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+    let file_id = upload_file(&app, &ctx, &alice, "notes.txt", b"one").await;
+    for body in [&b"two"[..], b"three", b"four"] {
+        replace(&app, &ctx, &alice, &file_id, body).await;
+    }
+
+    let reaper = |dry_run| Reaper::new(ctx.db.clone(), ctx.storage.clone(), ReaperConfig {
+        interval: std::time::Duration::from_secs(60),
+        dry_run,
+        delete_orphans: false,
+        multipart_expire: std::time::Duration::from_secs(3600),
+        max_versions: 2,
+    });
+
+    let report = reaper(true).reconcile(Utc::now() + Duration::hours(1)).await.unwrap();
+    assert_eq!(report.pruned_versions.len(), 2);
+    assert_eq!(versions(&app, &alice, &file_id).await.len(), 4);
+
+    let report = reaper(false).reconcile(Utc::now() + Duration::hours(1)).await.unwrap();
+    assert_eq!(report.pruned_versions, vec![(file_id.clone(), 1), (file_id.clone(), 2)]);
+    let numbers: Vec<i64> = versions(&app, &alice, &file_id).await.iter().map(|v| v["version"].as_i64().unwrap()).collect();
+    assert_eq!(numbers, vec![4, 3]);
+    let objects = ctx.storage.list_objects(None).await.unwrap().objects;
+    assert_eq!(objects.len(), 2);
+}
+
+#[actix_web::test]
+async fn multipart_replace_keeps_content_that_never_became_a_blob() {
+    let ctx = context();
+    let app = test_app!(ctx);
+    let (_, alice) = signup(&app, "alice@example.com").await;
+
+    // A file completed before deduplication still has its content under the file id.
+    let file_id = create_file(&app, &alice, "legacy.txt").await;
+    ctx.storage.put_object(&file_id, b"legacy content".to_vec(), Some("text/plain")).await.unwrap();
+    ctx.db.complete_file_upload(&file_id, 14, Some("text/plain"), None, None).await.unwrap();
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart", file_id))
+        .set_json(json!({ "content_type": "text/plain" }));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 201, "{}", body);
+    let upload_id = body["data"]["upload_id"].as_str().unwrap().to_string();
+
+    let etag = ctx.storage.upload_part(&upload_id, 1, b"new content".to_vec()).unwrap();
+    let req = TestRequest::post()
+        .uri(&format!("/api/v1/content/{}/multipart/complete", file_id))
+        .set_json(json!({ "parts": [{ "part_number": 1, "etag": etag }] }));
+    let (status, body) = call(&app, request(req, Some(&alice))).await;
+    assert_eq!(status, 200, "{}", body);
+
+    let listed = versions(&app, &alice, &file_id).await;
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[1]["size_bytes"], 14);
+    let res = test::call_service(&app, request(TestRequest::get().uri(&format!("/api/v1/content/{}/versions/1/data", file_id)), Some(&alice))).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(test::read_body(res).await, "legacy content");
+    let res = test::call_service(&app, request(TestRequest::get().uri(&format!("/api/v1/content/{}/data", file_id)), Some(&alice))).await;
+    assert_eq!(test::read_body(res).await, "new content");
+}
@@ -14,7 +14,11 @@ impl Snowflake {
         let worker_id_num = env::var("SNOWFLAKE_WORKER_ID")?
             .parse::<u16>()?;
         
-        let generator = SnowflakeGenerator::new(epoch_num, worker_id_num)?;
+        Self::new(epoch_num, worker_id_num)
+    }
+
+    pub fn new(epoch: i64, worker_id: u16) -> Result<Self, Box<dyn Error>> {
+        let generator = SnowflakeGenerator::new(epoch, worker_id)?;
         
         Ok(Self {
             generator,